@@ -0,0 +1,245 @@
+//! Multi-frame image files opened as sequences: the frames of an animated GIF or PNG,
+//! or the pages of a multi-page TIFF. Frame (or page) N of the file is index N, from 0.
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::error::{DecodingError, ImageFormatHint};
+use image::{AnimationDecoder, DynamicImage, ImageBuffer, ImageError, ImageFormat};
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+use tracing::debug_span;
+
+use crate::error::CacheError;
+use crate::frame::{finish_decode, DecodeOptions, FramePixels};
+use crate::orientation::Orientation;
+
+/// Pattern naming the frames of a multi-frame file; `zapvis fetch` writes them as PNGs
+pub const ANIMATION_PATTERN: &str = "frame_######.png";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+    Tiff,
+}
+
+/// What a multi-frame file holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationInfo {
+    pub format: AnimationFormat,
+    pub frames: u64,
+    /// Average delay between frames of an animation (TIFF pages have none)
+    pub delay_ms: Option<u32>,
+}
+
+impl AnimationInfo {
+    pub fn contains(&self, idx: i64) -> bool {
+        u64::try_from(idx).is_ok_and(|idx| idx < self.frames)
+    }
+
+    /// Playback rate matching the animation's delays
+    pub fn fps(&self) -> Option<f64> {
+        self.delay_ms.filter(|&ms| ms > 0).map(|ms| 1000.0 / ms as f64)
+    }
+}
+
+/// Format and frame count of `path` if it is a GIF, PNG or TIFF with more than one
+/// frame; `Ok(None)` for single images and other formats. Animations are decoded once
+/// to count their frames.
+pub fn probe_animation(path: &Path) -> Result<Option<AnimationInfo>, CacheError> {
+    let Some(format) = animation_format(path)? else {
+        return Ok(None);
+    };
+    let _span = debug_span!("probe_animation", path = %path.display()).entered();
+    let (frames, delay_ms) = match format {
+        AnimationFormat::Gif | AnimationFormat::Apng => {
+            let (mut frames, mut total_ms) = (0u64, 0f64);
+            for frame in animation_frames(path, format)? {
+                let frame = frame.map_err(|e| decode_error(path, e))?;
+                let (num, den) = frame.delay().numer_denom_ms();
+                total_ms += num as f64 / den.max(1) as f64;
+                frames += 1;
+            }
+            (frames, (frames > 0).then(|| (total_ms / frames as f64).round() as u32))
+        }
+        AnimationFormat::Tiff => {
+            let mut decoder = tiff_decoder(path)?;
+            let mut pages = 1;
+            while decoder.more_images() {
+                decoder.next_image().map_err(|e| tiff_error(path, e))?;
+                pages += 1;
+            }
+            (pages, None)
+        }
+    };
+    Ok((frames > 1).then_some(AnimationInfo {
+        format,
+        frames,
+        delay_ms,
+    }))
+}
+
+/// Decode frame `idx` of a multi-frame file. Animation frames are composited onto the
+/// ones before them, so reaching a late frame decodes all earlier ones too.
+pub fn load_animation_frame(path: &Path, info: AnimationInfo, idx: i64, opts: DecodeOptions) -> Result<FramePixels, CacheError> {
+    Ok(finish_decode(decode_animation_frame(path, info, idx)?, Orientation::default(), None, opts))
+}
+
+/// Frame `idx` of a multi-frame file encoded as PNG, for copying it out
+pub fn read_animation_frame(path: &Path, info: AnimationInfo, idx: i64) -> Result<Vec<u8>, CacheError> {
+    let img = decode_animation_frame(path, info, idx)?;
+    let mut png = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).map_err(|e| decode_error(path, e))?;
+    Ok(png)
+}
+
+fn decode_animation_frame(path: &Path, info: AnimationInfo, idx: i64) -> Result<DynamicImage, CacheError> {
+    let not_found = || CacheError::NotFound(format!("{} frame {}", path.display(), idx));
+    if !info.contains(idx) {
+        return Err(not_found());
+    }
+    let _span = debug_span!("decode", path = %path.display(), idx).entered();
+    match info.format {
+        AnimationFormat::Gif | AnimationFormat::Apng => {
+            let frame = animation_frames(path, info.format)?.nth(idx as usize).ok_or_else(not_found)?;
+            Ok(DynamicImage::ImageRgba8(frame.map_err(|e| decode_error(path, e))?.into_buffer()))
+        }
+        AnimationFormat::Tiff => {
+            let mut decoder = tiff_decoder(path)?;
+            decoder.seek_to_image(idx as usize).map_err(|e| tiff_error(path, e))?;
+            tiff_page(&mut decoder).map_err(|e| tiff_error(path, e))
+        }
+    }
+}
+
+/// GIFs, PNGs that are animated and TIFFs, told apart by their contents
+fn animation_format(path: &Path) -> Result<Option<AnimationFormat>, CacheError> {
+    let format = match image::ImageReader::open(path).and_then(|r| r.with_guessed_format()) {
+        Ok(reader) => reader.format(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(CacheError::NotFound(path.display().to_string())),
+        Err(source) => {
+            return Err(CacheError::Read {
+                path: path.display().to_string(),
+                source,
+            })
+        }
+    };
+    Ok(match format {
+        Some(ImageFormat::Gif) => Some(AnimationFormat::Gif),
+        Some(ImageFormat::Tiff) => Some(AnimationFormat::Tiff),
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(open(path)?).map_err(|e| decode_error(path, e))?;
+            decoder.is_apng().map_err(|e| decode_error(path, e))?.then_some(AnimationFormat::Apng)
+        }
+        _ => None,
+    })
+}
+
+fn animation_frames(path: &Path, format: AnimationFormat) -> Result<image::Frames<'static>, CacheError> {
+    let reader = open(path)?;
+    let frames = match format {
+        AnimationFormat::Gif => GifDecoder::new(reader).map(|d| d.into_frames()),
+        _ => PngDecoder::new(reader).and_then(|d| d.apng()).map(|d| d.into_frames()),
+    };
+    frames.map_err(|e| decode_error(path, e))
+}
+
+fn tiff_decoder(path: &Path) -> Result<tiff::decoder::Decoder<BufReader<File>>, CacheError> {
+    tiff::decoder::Decoder::new(open(path)?).map_err(|e| tiff_error(path, e))
+}
+
+/// The page the decoder is at, for the 8 and 16 bit gray and RGB(A) layouts
+fn tiff_page(decoder: &mut tiff::decoder::Decoder<BufReader<File>>) -> tiff::TiffResult<DynamicImage> {
+    use tiff::decoder::DecodingResult::{U16, U8};
+    use tiff::ColorType::{Gray, GrayA, RGB, RGBA};
+    let (w, h) = decoder.dimensions()?;
+    let color = decoder.colortype()?;
+    let img = match (color, decoder.read_image()?) {
+        (Gray(8), U8(data)) => ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageLuma8),
+        (GrayA(8), U8(data)) => ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageLumaA8),
+        (RGB(8), U8(data)) => ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageRgb8),
+        (RGBA(8), U8(data)) => ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageRgba8),
+        (Gray(16), U16(data)) => ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageLuma16),
+        (GrayA(16), U16(data)) => ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageLumaA16),
+        (RGB(16), U16(data)) => ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageRgb16),
+        (RGBA(16), U16(data)) => ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageRgba16),
+        _ => None,
+    };
+    img.ok_or(tiff::TiffError::UnsupportedError(tiff::TiffUnsupportedError::UnsupportedColorType(color)))
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, CacheError> {
+    File::open(path).map(BufReader::new).map_err(|source| CacheError::Read {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+fn decode_error(path: &Path, source: ImageError) -> CacheError {
+    CacheError::Decode {
+        path: path.display().to_string(),
+        source,
+    }
+}
+
+fn tiff_error(path: &Path, e: tiff::TiffError) -> CacheError {
+    decode_error(path, ImageError::Decoding(DecodingError::new(ImageFormatHint::Exact(ImageFormat::Tiff), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame, Rgba, RgbaImage};
+
+    fn temp_file(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("zapvis-animation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn gif_frames_are_a_sequence() {
+        let path = temp_file("blink.gif");
+        let mut encoder = GifEncoder::new(File::create(&path).unwrap());
+        for shade in [0u8, 100, 200] {
+            let img = RgbaImage::from_pixel(4, 2, Rgba([shade, shade, shade, 255]));
+            encoder.encode_frame(Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(40, 1))).unwrap();
+        }
+        drop(encoder);
+
+        let info = probe_animation(&path).unwrap().unwrap();
+        assert_eq!((info.format, info.frames, info.delay_ms), (AnimationFormat::Gif, 3, Some(40)));
+        assert_eq!(info.fps(), Some(25.0));
+        let opts = DecodeOptions::raw();
+        let frame = load_animation_frame(&path, info, 2, opts).unwrap();
+        assert_eq!(frame.dimensions(), (4, 2));
+        assert!(frame.pixel_rgba(0, 0).is_some_and(|p| p[0] > 150));
+        assert!(matches!(load_animation_frame(&path, info, 3, opts), Err(CacheError::NotFound(_))));
+    }
+
+    #[test]
+    fn tiff_pages_are_a_sequence() {
+        let path = temp_file("stack.tiff");
+        let mut encoder = tiff::encoder::TiffEncoder::new(File::create(&path).unwrap()).unwrap();
+        for value in [1000u16, 2000] {
+            encoder.write_image::<tiff::encoder::colortype::Gray16>(3, 1, &[value; 3]).unwrap();
+        }
+        drop(encoder);
+
+        let info = probe_animation(&path).unwrap().unwrap();
+        assert_eq!((info.format, info.frames, info.fps()), (AnimationFormat::Tiff, 2, None));
+        let page = decode_animation_frame(&path, info, 1).unwrap();
+        assert_eq!(page.as_luma16().map(|p| p.get_pixel(2, 0).0), Some([2000]));
+        let png = read_animation_frame(&path, info, 0).unwrap();
+        let copy = image::load_from_memory(&png).unwrap();
+        assert_eq!((copy.width(), copy.height()), (3, 1));
+    }
+
+    #[test]
+    fn single_images_are_not_animations() {
+        let path = temp_file("still.png");
+        RgbaImage::new(2, 2).save(&path).unwrap();
+        assert_eq!(probe_animation(&path).unwrap(), None);
+    }
+}
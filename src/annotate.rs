@@ -0,0 +1,173 @@
+use eframe::egui;
+use zapvis::annotations::{arrow_head, AnnotationFile, Shape, ANNOTATION_COLOR};
+use zapvis::orientation::Orientation;
+
+/// What a drag (or, for text, a click) on the frame draws
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    Rect,
+    Arrow,
+    Text,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 3] = [Tool::Rect, Tool::Arrow, Tool::Text];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Tool::Rect => "Rectangle",
+            Tool::Arrow => "Arrow",
+            Tool::Text => "Text",
+        }
+    }
+}
+
+/// Annotations of the open sequence and the shape being drawn (E)
+pub struct Annotator {
+    pub file: AnnotationFile,
+    pub tool: Tool,
+    /// Label placed by the text tool
+    pub text: String,
+    /// Start of the drag drawing a shape, in frame pixels
+    drag_start: Option<[f32; 2]>,
+    /// Shape under construction, painted but not part of `file` yet
+    pub drawing: Option<Shape>,
+}
+
+impl Annotator {
+    pub fn new(file: AnnotationFile) -> Self {
+        Self {
+            file,
+            tool: Tool::Rect,
+            text: String::new(),
+            drag_start: None,
+            drawing: None,
+        }
+    }
+
+    pub fn is_drawing(&self) -> bool {
+        self.drag_start.is_some()
+    }
+
+    /// Begin a rectangle or arrow at `at` (frame pixels)
+    pub fn start(&mut self, at: [f32; 2]) {
+        if self.tool != Tool::Text {
+            self.drag_start = Some(at);
+        }
+    }
+
+    /// Stretch the shape being drawn to `to`; on `release` it is finished and returned.
+    pub fn drag_to(&mut self, to: [f32; 2], release: bool) -> Option<Shape> {
+        let from = self.drag_start?;
+        self.drawing = match self.tool {
+            Tool::Rect => Some(Shape::Rect {
+                min: [from[0].min(to[0]), from[1].min(to[1])],
+                max: [from[0].max(to[0]), from[1].max(to[1])],
+            }),
+            Tool::Arrow => Some(Shape::Arrow { from, to }),
+            Tool::Text => None,
+        };
+        if !release {
+            return None;
+        }
+        self.drag_start = None;
+        // A click without movement draws nothing
+        self.drawing.take().filter(|_| (to[0] - from[0]).abs() + (to[1] - from[1]).abs() >= 2.0)
+    }
+
+    /// The typed label at `at`, if the text tool is active and something was typed
+    pub fn place_text(&self, at: [f32; 2]) -> Option<Shape> {
+        let text = self.text.trim();
+        (self.tool == Tool::Text && !text.is_empty()).then(|| Shape::Text {
+            at,
+            text: text.to_string(),
+        })
+    }
+}
+
+/// Where the frame is on screen, for converting between frame pixels and screen points
+pub struct FrameMapping {
+    pub image_rect: egui::Rect,
+    /// Frame size in pixels
+    pub size: egui::Vec2,
+    pub orientation: Orientation,
+}
+
+impl FrameMapping {
+    pub fn to_screen(&self, p: [f32; 2]) -> egui::Pos2 {
+        let [u, v] = self.orientation.source_to_display_uv(p[0] / self.size.x, p[1] / self.size.y);
+        self.image_rect.min + egui::vec2(u * self.image_rect.width(), v * self.image_rect.height())
+    }
+
+    /// Frame pixel under `pos`, clamped to the frame
+    pub fn to_frame(&self, pos: egui::Pos2) -> [f32; 2] {
+        let u = ((pos.x - self.image_rect.min.x) / self.image_rect.width()).clamp(0.0, 1.0);
+        let v = ((pos.y - self.image_rect.min.y) / self.image_rect.height()).clamp(0.0, 1.0);
+        let [su, sv] = self.orientation.display_to_source_uv(u, v);
+        [su * self.size.x, sv * self.size.y]
+    }
+
+    /// Screen points per frame pixel
+    fn scale(&self) -> f32 {
+        (self.image_rect.area() / (self.size.x * self.size.y)).sqrt()
+    }
+}
+
+/// Paint `shapes` over the frame, sized like they come out of `burn_in`
+pub fn paint_annotations<'a>(painter: &egui::Painter, map: &FrameMapping, shapes: impl IntoIterator<Item = &'a Shape>) {
+    let [r, g, b, _] = ANNOTATION_COLOR;
+    let color = egui::Color32::from_rgb(r, g, b);
+    let short_side = map.size.x.min(map.size.y);
+    let width = (short_side / 400.0).max(2.0);
+    let stroke = egui::Stroke::new((width * map.scale()).max(1.5), color);
+    for shape in shapes {
+        match shape {
+            Shape::Rect { min, max } => {
+                let corners = [*min, [max[0], min[1]], *max, [min[0], max[1]]].map(|p| map.to_screen(p));
+                painter.add(egui::Shape::closed_line(corners.to_vec(), stroke));
+            }
+            Shape::Arrow { from, to } => {
+                painter.line_segment([map.to_screen(*from), map.to_screen(*to)], stroke);
+                for [a, b] in arrow_head(*from, *to, width * 6.0) {
+                    painter.line_segment([map.to_screen(a), map.to_screen(b)], stroke);
+                }
+            }
+            Shape::Text { at, text } => {
+                let px = (short_side / 30.0).max(14.0) * map.scale();
+                let galley = painter.layout_no_wrap(text.clone(), egui::FontId::proportional(px.max(8.0)), color);
+                let pad = px * 0.2;
+                let min = map.to_screen(*at);
+                let rect = egui::Rect::from_min_size(min, galley.size() + egui::vec2(2.0 * pad, 2.0 * pad));
+                painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(160));
+                painter.galley(min + egui::vec2(pad, pad), galley, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drags_become_shapes() {
+        let mut annotator = Annotator::new(AnnotationFile::default());
+        annotator.start([40.0, 10.0]);
+        assert_eq!(annotator.drag_to([10.0, 30.0], false), None);
+        assert!(annotator.drawing.is_some(), "shown while dragging");
+        let rect = annotator.drag_to([10.0, 30.0], true);
+        assert_eq!(rect, Some(Shape::Rect { min: [10.0, 10.0], max: [40.0, 30.0] }));
+        assert!(!annotator.is_drawing());
+
+        annotator.start([5.0, 5.0]);
+        assert_eq!(annotator.drag_to([5.5, 5.0], true), None, "a click is not a shape");
+
+        annotator.tool = Tool::Text;
+        assert_eq!(annotator.place_text([1.0, 1.0]), None, "nothing typed yet");
+        annotator.text = " scratch ".to_string();
+        assert_eq!(
+            annotator.place_text([1.0, 1.0]),
+            Some(Shape::Text { at: [1.0, 1.0], text: "scratch".to_string() })
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! Frame annotations (rectangles, arrows, text) flagging issues, stored in a sidecar
+//! per sequence and burned into exported frames.
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use directories::ProjectDirs;
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::ConfigError;
+use crate::sequence::{SequenceSource, SequenceSpec};
+
+/// A shape in pixels of the decoded frame (after EXIF orientation and `max_decode_dim`,
+/// before the view's rotation and flips), like crops and color samples.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Shape {
+    Rect { min: [f32; 2], max: [f32; 2] },
+    Arrow { from: [f32; 2], to: [f32; 2] },
+    /// Label whose top-left corner is at `at`
+    Text { at: [f32; 2], text: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub index: i64,
+    #[serde(flatten)]
+    pub shape: Shape,
+}
+
+impl Shape {
+    /// The shape on the frame resized by `factor`
+    pub fn scaled(&self, factor: f32) -> Shape {
+        let s = |p: &[f32; 2]| [p[0] * factor, p[1] * factor];
+        match self {
+            Shape::Rect { min, max } => Shape::Rect { min: s(min), max: s(max) },
+            Shape::Arrow { from, to } => Shape::Arrow { from: s(from), to: s(to) },
+            Shape::Text { at, text } => Shape::Text { at: s(at), text: text.clone() },
+        }
+    }
+}
+
+/// Contents of an annotation sidecar (TOML).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnnotationFile {
+    /// `SequenceSpec::sequence_key` the annotations were made on (informational)
+    #[serde(default)]
+    pub sequence: String,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+impl AnnotationFile {
+    /// Shapes on frame `index`, oldest first
+    pub fn at(&self, index: i64) -> impl Iterator<Item = &Shape> {
+        self.annotations.iter().filter(move |a| a.index == index).map(|a| &a.shape)
+    }
+
+    pub fn add(&mut self, index: i64, shape: Shape) {
+        self.annotations.push(Annotation { index, shape });
+    }
+
+    /// Remove the newest annotation on `index`. Returns false if it had none.
+    pub fn remove_last(&mut self, index: i64) -> bool {
+        match self.annotations.iter().rposition(|a| a.index == index) {
+            Some(i) => {
+                self.annotations.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Sidecar for a sequence's annotations: a hidden file next to the frames of a local
+/// sequence, else one under the data directory.
+pub fn annotation_path(seq: &SequenceSpec, pattern: &str) -> Result<PathBuf, ConfigError> {
+    let sanitize = |s: &str| -> String {
+        s.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+            .collect()
+    };
+    match &seq.source {
+        SequenceSource::Local(dir) => Ok(dir.join(format!(".{}.annotations.toml", sanitize(pattern)))),
+        // Every video or animation has the same frame pattern; name the sidecar after the file instead
+        SequenceSource::Video { path, .. } | SequenceSource::Animation { path, .. } => {
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            Ok(path.with_file_name(format!(".{}.annotations.toml", sanitize(&name))))
+        }
+        SequenceSource::Remote { .. } | SequenceSource::Http { .. } => {
+            let proj = ProjectDirs::from("dev", "zapvis", "zapvis").ok_or(ConfigError::NoConfigDir)?;
+            let name = sanitize(&seq.sequence_key(pattern));
+            Ok(proj.data_dir().join("annotations").join(format!("{name}.toml")))
+        }
+    }
+}
+
+/// Read an annotation sidecar; a missing one is empty.
+pub fn load_annotation_file(path: &Path) -> Result<AnnotationFile, ConfigError> {
+    if !path.exists() {
+        return Ok(AnnotationFile::default());
+    }
+    let txt = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    toml::from_str(&txt).map_err(|source| ConfigError::Parse {
+        name: format!("annotations {}", path.display()),
+        source,
+    })
+}
+
+pub fn save_annotation_file(path: &Path, file: &AnnotationFile) -> Result<(), ConfigError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    let txt = toml::to_string_pretty(file).map_err(|source| ConfigError::Serialize {
+        name: "annotations".to_string(),
+        source,
+    })?;
+    fs::write(path, txt).map_err(|source| ConfigError::Write {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Color of burned-in annotations (the overlay uses the same)
+pub const ANNOTATION_COLOR: [u8; 4] = [255, 64, 64, 255];
+
+/// Draw `shapes` into `img`, with strokes and text sized relative to the image.
+pub fn burn_in<'a>(img: &mut RgbaImage, shapes: impl IntoIterator<Item = &'a Shape>) {
+    let short_side = img.width().min(img.height()) as f32;
+    let width = (short_side / 400.0).max(2.0);
+    for shape in shapes {
+        match shape {
+            Shape::Rect { min, max } => {
+                let corners = [*min, [max[0], min[1]], *max, [min[0], max[1]]];
+                for i in 0..4 {
+                    draw_line(img, corners[i], corners[(i + 1) % 4], width);
+                }
+            }
+            Shape::Arrow { from, to } => {
+                draw_line(img, *from, *to, width);
+                for [a, b] in arrow_head(*from, *to, width * 6.0) {
+                    draw_line(img, a, b, width);
+                }
+            }
+            Shape::Text { at, text } => draw_text(img, *at, text, (short_side / 30.0).max(14.0)),
+        }
+    }
+}
+
+/// The two strokes of an arrow head at `to`, `size` long
+pub fn arrow_head(from: [f32; 2], to: [f32; 2], size: f32) -> [[[f32; 2]; 2]; 2] {
+    let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+    let angle = dy.atan2(dx);
+    let size = size.min((dx * dx + dy * dy).sqrt() * 0.5);
+    let barb = |spread: f32| [to[0] - size * (angle + spread).cos(), to[1] - size * (angle + spread).sin()];
+    let spread = 25f32.to_radians();
+    [[to, barb(spread)], [to, barb(-spread)]]
+}
+
+fn blend(img: &mut RgbaImage, x: i64, y: i64, color: [u8; 4], coverage: f32) {
+    if x < 0 || y < 0 || x >= img.width() as i64 || y >= img.height() as i64 {
+        return;
+    }
+    let px = img.get_pixel_mut(x as u32, y as u32);
+    let a = coverage.clamp(0.0, 1.0) * color[3] as f32 / 255.0;
+    for c in 0..3 {
+        px[c] = (px[c] as f32 * (1.0 - a) + color[c] as f32 * a).round() as u8;
+    }
+    px[3] = px[3].max((a * 255.0) as u8);
+}
+
+/// Stroke `width` wide from `a` to `b`, with round caps and antialiased edges
+fn draw_line(img: &mut RgbaImage, a: [f32; 2], b: [f32; 2], width: f32) {
+    let r = width / 2.0;
+    let len = ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt();
+    let (x0, x1) = ((a[0].min(b[0]) - r).floor() as i64, (a[0].max(b[0]) + r).ceil() as i64);
+    let (y0, y1) = ((a[1].min(b[1]) - r).floor() as i64, (a[1].max(b[1]) + r).ceil() as i64);
+    // Distance of each pixel in the bounding box to the segment decides its coverage
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let t = if len > 0.0 {
+                (((px - a[0]) * (b[0] - a[0]) + (py - a[1]) * (b[1] - a[1])) / (len * len)).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let (cx, cy) = (a[0] + t * (b[0] - a[0]), a[1] + t * (b[1] - a[1]));
+            let d = ((px - cx).powi(2) + (py - cy).powi(2)).sqrt();
+            let coverage = r + 0.5 - d;
+            if coverage > 0.0 {
+                blend(img, x, y, ANNOTATION_COLOR, coverage);
+            }
+        }
+    }
+}
+
+/// Label on a dark box, top-left corner at `at`
+fn draw_text(img: &mut RgbaImage, at: [f32; 2], text: &str, px: f32) {
+    let Ok(font) = FontRef::try_from_slice(epaint_default_fonts::UBUNTU_LIGHT) else {
+        return;
+    };
+    let scale = PxScale::from(px);
+    let scaled = font.as_scaled(scale);
+    let pad = px * 0.2;
+    let mut caret = at[0] + pad;
+    let baseline = at[1] + pad + scaled.ascent();
+    let mut glyphs = Vec::new();
+    let mut prev = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(prev) = prev {
+            caret += scaled.kern(prev, id);
+        }
+        glyphs.push(id.with_scale_and_position(scale, point(caret, baseline)));
+        caret += scaled.h_advance(id);
+        prev = Some(id);
+    }
+    let (x1, y1) = (caret + pad, at[1] + 2.0 * pad + scaled.height());
+    for y in at[1].floor() as i64..y1.ceil() as i64 {
+        for x in at[0].floor() as i64..x1.ceil() as i64 {
+            blend(img, x, y, [0, 0, 0, 160], 1.0);
+        }
+    }
+    for glyph in glyphs {
+        if let Some(outline) = font.outline_glyph(glyph) {
+            let bounds = outline.px_bounds();
+            outline.draw(|x, y, coverage| {
+                blend(img, bounds.min.x as i64 + x as i64, bounds.min.y as i64 + y as i64, ANNOTATION_COLOR, coverage);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn sidecar_round_trip_and_burn_in() {
+        let mut file = AnnotationFile::default();
+        file.add(4, Shape::Rect { min: [2.0, 2.0], max: [20.0, 12.0] });
+        file.add(4, Shape::Text { at: [1.0, 30.0], text: "dust".to_string() });
+        file.add(9, Shape::Arrow { from: [0.0, 0.0], to: [30.0, 30.0] });
+        let txt = toml::to_string_pretty(&file).unwrap();
+        let back: AnnotationFile = toml::from_str(&txt).unwrap();
+        assert_eq!(back.annotations, file.annotations);
+        assert_eq!(back.at(4).count(), 2);
+
+        assert!(file.remove_last(4));
+        assert_eq!(file.at(4).collect::<Vec<_>>(), [&Shape::Rect { min: [2.0, 2.0], max: [20.0, 12.0] }]);
+        assert!(!file.remove_last(5));
+
+        let mut img = RgbaImage::from_pixel(40, 40, Rgba([0, 0, 0, 255]));
+        burn_in(&mut img, file.at(4));
+        assert_eq!(img.get_pixel(2, 6).0, ANNOTATION_COLOR, "left edge of the rectangle");
+        assert_eq!(img.get_pixel(10, 7).0, [0, 0, 0, 255], "inside stays untouched");
+    }
+}
@@ -1,108 +1,3115 @@
+use anyhow::Result;
 use eframe::egui;
-use std::sync::mpsc::Sender;
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
 
+use crate::annotate::{paint_annotations, Annotator, FrameMapping, Tool};
+use crate::audio::{open_audio, AudioPlayer};
+use crate::blink::{blink_interval, Blink};
+use crate::channel_view::ChannelView;
+use crate::export::export_cropped;
+use crate::colormap::{Colormap, FalseColor};
+use crate::defects::{detect_stuck_pixels, StuckPixel};
 use crate::image_cache::ImageCache;
-use crate::remote_worker::{RemoteRange, RemoteWorkerRequest};
-use crate::sequence::{SequenceSource, SequenceSpec};
+use crate::image_util::rgba_to_texture;
+use crate::input::{spawn_source_worker, OpenedInput};
+use crate::grade::{Grade, GradeStage};
+use crate::interpolate::{crossfade, fade_position};
+use crate::measure::{paint_measurements, MeasureTool, Measurer};
+use crate::onion::{BlendMode, OnionSkin};
+use crate::playback::{Playback, PlaybackFilter};
+use crate::samples::{Sample, SampleList};
+use crate::stats::{region_stats, ChannelStats, PixelRect};
+use crate::tiles::TILED_ABOVE;
+use crate::viewport::{MouseSteps, Viewport};
+use zapvis::annotations::{annotation_path, burn_in, load_annotation_file, save_annotation_file, AnnotationFile, Shape};
+use zapvis::bookmarks::{bookmark_path, load_bookmark_file, save_bookmark_file, BookmarkFile, Bookmarks};
+use zapvis::bounds::SequenceBounds;
+use zapvis::color::ToneMap;
+use zapvis::config::{maybe_add_pattern, save_config, AudioTrack, Background, Config, CropRect};
+use zapvis::dir_config::DirConfig;
+use zapvis::error::{FailureKind, SshError};
+use zapvis::follow::Follower;
+use zapvis::frame::{DecodeOptions, FramePixels, SourceValues};
+use zapvis::frame_cache::{LoadError, LoadTiming};
+use zapvis::guides::{Guide, GuideShape};
+use zapvis::lut::Lut3d;
+use zapvis::mirror::{mirror_range, mirrored_sequence, MirrorProgress, MirrorSnapshot};
+use zapvis::orientation::Orientation;
+use zapvis::region::{FrameRegion, MAX_REGION_SHARE};
+use zapvis::remote_worker::{ConnectionState, Download, RemoteRange, RemoteStats, RemoteWorkerRequest};
+use zapvis::sequence::{sibling_sequences, SequenceSource, SequenceSpec, MAX_INDEX};
+use zapvis::session::{load_session_file, record_session, session_path, SessionState};
+
+/// Statistics of the visible region, tagged with what they were computed from
+struct RegionStats {
+    idx: i64,
+    source: Arc<FramePixels>,
+    rect: PixelRect,
+    channels: [ChannelStats; 4],
+}
+
+/// Stuck-pixel scan of the cached frames, tagged with what it covered
+struct DefectScan {
+    frames: usize,
+    size: (u32, u32),
+    /// `None` while too few frames were loaded to tell
+    pixels: Option<Vec<StuckPixel>>,
+}
+
+/// Second sequence shown beside the main one (split view), stepping in lockstep with it
+struct Compare {
+    pattern: String,
+    seq: SequenceSpec,
+    cache: ImageCache,
+    /// Compare index minus main index, fixed when opened so the two inputs stay paired
+    offset: i64,
+    /// Opened for the pattern's `[paired_patterns]` partner rather than from a second
+    /// input, so it is reopened for every sequence switched to
+    paired: bool,
+    channel_tex: Option<(Arc<FramePixels>, ChannelView, egui::TextureHandle)>,
+}
+
+impl Compare {
+    /// Move to the frame paired with `main_index` and load around it
+    fn follow(&mut self, main_index: i64, ctx: &egui::Context) {
+        self.seq.index = main_index.saturating_add(self.offset).max(self.seq.min_index());
+        self.cache.update_for_index(self.seq.index, &self.seq, ctx);
+    }
+}
+
+/// Composite of the onion skin, tagged with the frames and settings it was built from
+struct OnionTexture {
+    base: Arc<FramePixels>,
+    over: Arc<FramePixels>,
+    onion: OnionSkin,
+    view: ChannelView,
+    tex: egui::TextureHandle,
+}
+
+/// Cross-fade shown for a missing frame, tagged with what it was built from
+struct InterpolatedTexture {
+    idx: i64,
+    before: (i64, Arc<FramePixels>),
+    after: (i64, Arc<FramePixels>),
+    view: ChannelView,
+    tex: egui::TextureHandle,
+}
+
+/// Frame range being copied to local disk in the background (see [`zapvis::mirror`])
+struct Fetch {
+    pattern: String,
+    /// The sequence as it was when the fetch started, to open its copy later
+    seq: SequenceSpec,
+    dest: PathBuf,
+    total: u64,
+    progress: MirrorProgress,
+    thread: Option<JoinHandle<MirrorSnapshot>>,
+    /// Final counts once the thread is done
+    finished: Option<MirrorSnapshot>,
+    /// Frames done when the status notice was last refreshed
+    shown: u64,
+}
+
+/// Remote step that may land in a gap; continued when the frame turns out missing
+struct GapSearch {
+    direction: i64,
+    step: u64,
+    skipped: u64,
+}
+
+/// How long zoom/pan must be idle before region statistics are recomputed
+const STATS_SETTLE: Duration = Duration::from_millis(250);
+
+/// A click in the diagnostics panel
+enum DiagnosticsAction {
+    Jump(i64),
+    /// Retry one failed frame, or every one
+    Retry(Option<i64>),
+}
+
+/// Most frames buffered ahead for playback, however long the preroll
+const MAX_LOOKAHEAD: u64 = 100;
+
+/// Repaint interval while frames are loading, in case no load settles for a while
+const PENDING_POLL: Duration = Duration::from_millis(500);
+
+/// Space between the two halves of the split view
+const SPLIT_GAP: f32 = 4.0;
+
+/// Zoom (screen points per image pixel, i.e. 800%) from which nearest-neighbor
+/// filtering outlines the pixels
+const PIXEL_GRID_MIN_ZOOM: f32 = 8.0;
+
+/// Side of one square of the checkerboard background, in points
+const CHECKER_SIZE: f32 = 8.0;
+
+/// A sequence Tab switched away from, kept with its cache to come back to
+struct ParkedSequence {
+    seq: SequenceSpec,
+    cache: ImageCache,
+    bounds: SequenceBounds,
+}
 
 pub struct ZapVisApp {
+    cfg: Config,
     pattern: String,
+    /// Pattern was inferred from the filename and is not in the config yet
+    pattern_inferred: bool,
+    /// Every configured pattern matching the opened file, with the sequence it yields
+    pattern_candidates: Vec<(String, SequenceSpec)>,
+    /// Sequences of the directory that Tab cycles through, found on the first press
+    siblings: Option<Vec<(String, SequenceSpec)>>,
+    /// Sibling sequences switched away from, by sequence key
+    parked: BTreeMap<String, ParkedSequence>,
     seq: SequenceSpec,
     cache: ImageCache,
+    /// Handle the loader threads wake the UI through (kept for caches opened later)
+    egui_ctx: egui::Context,
+    /// Notices at the end of the status bar (download progress, skipped frames, messages)
     status: String,
+    /// The first cache update has been made
+    cache_started: bool,
+    /// Displayed size over the frame's size, as of the last paint
+    display_zoom: Option<f32>,
+    /// Viewport zoom last set by the 1:1 physical pixel mode (Shift+Z); any other zoom
+    /// change ends the mode
+    one_to_one: Option<f32>,
     step_size: u64,
+    /// Maximized (F) or fullscreen (F11)
     is_fullscreen: bool,
+    /// OS fullscreen without title bar and taskbar (F11) rather than maximized
+    exclusive_fullscreen: bool,
     saved_window_pos: Option<egui::Pos2>,
     saved_window_size: Option<egui::Vec2>,
+    /// Inner size of the window as of the last frame outside fullscreen, saved with the session
+    window_size: Option<egui::Vec2>,
+    /// Outer position of the window as of the last frame outside fullscreen, so that it
+    /// reopens on the same monitor
+    window_pos: Option<egui::Pos2>,
+    orientation: Orientation,
+    remote_stats: Option<RemoteStats>,
+    remote_tx: Option<Sender<RemoteWorkerRequest>>,
+    remote_range: Option<RemoteRange>,
+    show_diagnostics: bool,
+    /// Gain, offset, gamma and LUT applied on the GPU, tuned in the grade panel (F4)
+    grade: GradeStage,
+    show_grade: bool,
+    /// Performance overlay (F12): cache window, load timings, SSH queue, texture memory
+    show_perf: bool,
+    last_loaded: usize,
+    last_evicted: usize,
+    channel_view: ChannelView,
+    /// Remapped texture for the channel view, tagged with the pixels and view it was built from
+    channel_tex: Option<(Arc<FramePixels>, ChannelView, egui::TextureHandle)>,
+    viewport: Viewport,
+    /// Wheel/drag distance not yet turned into frame steps
+    mouse_steps: MouseSteps,
+    /// Color picker active: clicks on the image append to `samples`
+    picking: bool,
+    samples: SampleList,
+    sample_export_path: String,
+    /// The file's values of a frame (`None` inside for 8-bit ones), read by the picker
+    source_values: Option<(i64, Option<Arc<SourceValues>>)>,
+    source_values_rx: Option<Receiver<(i64, Option<Arc<SourceValues>>)>>,
+    show_stats: bool,
+    stats: Option<RegionStats>,
+    /// Framing guides overlay, remembered per sequence
+    show_guides: bool,
+    /// Configured guides turned off for this sequence (Alt+1..9)
+    hidden_guides: Vec<Guide>,
+    /// Nearest-neighbor filtering with a pixel grid when zoomed in far, remembered per sequence
+    nearest: bool,
+    /// Two-by-two check pattern repeated under transparent images
+    checker: egui::TextureHandle,
+    /// Crop preview: everything outside `crop` is masked
+    show_crop: bool,
+    /// Crop of this sequence, from the config or drawn with Shift+drag
+    crop: Option<CropRect>,
+    /// Where the Shift+drag drawing a new crop started
+    crop_drag: Option<egui::Pos2>,
+    /// ROI lock (Alt+K): every frame is shown zoomed to the crop
+    roi_lock: bool,
+    /// Cropped PNG export of a frame range running in the background: written and
+    /// missing frames once it is done
+    roi_export: Option<Receiver<Result<(u64, u64), String>>>,
+    /// Fetch panel (M): mirror a frame range to local disk
+    show_fetch: bool,
+    fetch_range: (i64, i64),
+    fetch_dest: String,
+    fetch: Option<Fetch>,
+    /// Annotation mode (E): drags and clicks on the frame draw with the chosen tool
+    annotating: bool,
+    annotator: Annotator,
+    /// Measure mode (Shift+M): clicks on the frame add points to distances and angles
+    measuring: bool,
+    measurer: Measurer,
+    /// Opened on the first image copy and kept: on X11 the copied image is only
+    /// available while the clipboard that set it is alive
+    clipboard: Option<arboard::Clipboard>,
+    show_defects: bool,
+    defects: Option<DefectScan>,
+    defects_rx: Option<Receiver<DefectScan>>,
+    bookmarks: Bookmarks,
+    /// Sidecars from other reviewers, re-merged when the sequence changes
+    bookmark_imports: Vec<BookmarkFile>,
+    playback: Option<Playback>,
+    playback_filter: PlaybackFilter,
+    /// In and out points of the loop region (Ctrl+I / Ctrl+O); a missing one is the
+    /// sequence's end on that side
+    loop_in: Option<i64>,
+    loop_out: Option<i64>,
+    /// First/last frame, from the background discovery and from frames found missing
+    bounds: SequenceBounds,
+    bounds_rx: Option<Receiver<Result<SequenceBounds, SshError>>>,
+    skip_gaps: bool,
+    /// Missing frames are shown as a cross-fade of their loaded neighbors (Shift+G)
+    interpolate_gaps: bool,
+    interpolated_tex: Option<InterpolatedTexture>,
+    /// Frames around the current one are loaded ahead of time (Q pauses it)
+    prefetch: bool,
+    /// Frames are converted to sRGB (U shows the raw values)
+    color_manage: bool,
+    /// Exposure, operator and gamma for linear float frames, saved per sequence
+    tone_map: ToneMap,
+    gap_search: Option<GapSearch>,
+    /// Missing frames skipped by the last step, shown in the status bar
+    gap_skipped: u64,
+    /// Opened on first playback when the sequence has an audio track
+    audio: Option<AudioPlayer>,
+    playback_fps: f64,
+    /// Watch for frames appended to the sequence (L)
+    follow: Option<Follower>,
+    /// Jump to each new last frame while following (Shift+L)
+    follow_advance: bool,
+    /// Settings from the sequence directory's `.zapvis.toml` (empty if it has none)
+    dir_config: DirConfig,
+    /// Blink comparison between two pinned frames (T)
+    blink: Option<Blink>,
+    /// First frame of a blink comparison, waiting for T on the second
+    blink_mark: Option<i64>,
+    compare: Option<Compare>,
+    /// Another frame composited over the current one (Shift+O)
+    onion: Option<OnionSkin>,
+    onion_tex: Option<OnionTexture>,
+    /// Show the `[paired_patterns]` partner beside each sequence (Shift+S)
+    show_pairs: bool,
+    /// Inputs later invocations handed over, opened and ready to switch to
+    forwarded: Option<Receiver<OpenedInput>>,
 }
 
 impl ZapVisApp {
     pub fn new(
-        _cc: &eframe::CreationContext<'_>,
+        cc: &eframe::CreationContext<'_>,
+        cfg: Config,
         pattern: String,
         seq: SequenceSpec,
         request_tx: Option<Sender<RemoteWorkerRequest>>,
         remote_range: RemoteRange,
+        remote_stats: RemoteStats,
     ) -> Self {
-        let cache_remote_range = match &seq.source {
-            SequenceSource::Remote { .. } => Some(remote_range),
-            SequenceSource::Local(_) => None,
+        let (cache_remote_range, remote_stats) = if seq.source.is_remote() {
+            (Some(remote_range), Some(remote_stats))
+        } else {
+            (None, None)
+        };
+        let remote_tx = request_tx.clone();
+        let tone_map = cfg.tone_map_for(&seq.sequence_key(&pattern));
+        let decode_opts = DecodeOptions {
+            tone: tone_map,
+            ..DecodeOptions::from_config(&cfg)
         };
-        let cache = ImageCache::new(10, seq.source.clone(), request_tx, cache_remote_range);
+        let cache = Self::open_cache(&cc.egui_ctx, &cfg, decode_opts, &pattern, &seq, request_tx, cache_remote_range.clone());
 
-        Self {
+        let orientation = cfg.orientation_for(&seq.sequence_key(&pattern));
+        let show_guides = cfg.guides_shown_for(&seq.sequence_key(&pattern));
+        let hidden_guides = cfg.hidden_guides_for(&seq.sequence_key(&pattern));
+        let nearest = cfg.nearest_filtering_for(&seq.sequence_key(&pattern));
+        let crop = cfg.crop_for(&seq.sequence_key(&pattern));
+        let playback_fps = cfg.playback_fps;
+
+        let mut app = Self {
+            egui_ctx: cc.egui_ctx.clone(),
+            cfg,
             pattern,
+            pattern_inferred: false,
+            pattern_candidates: Vec::new(),
+            siblings: None,
+            parked: BTreeMap::new(),
             seq,
             cache,
             status: String::new(),
+            cache_started: false,
+            display_zoom: None,
+            one_to_one: None,
             step_size: 1,
             is_fullscreen: false,
+            exclusive_fullscreen: false,
             saved_window_pos: None,
             saved_window_size: None,
+            window_size: None,
+            window_pos: None,
+            orientation,
+            remote_stats,
+            remote_tx,
+            remote_range: cache_remote_range,
+            show_diagnostics: false,
+            grade: GradeStage::default(),
+            show_grade: false,
+            show_perf: false,
+            last_loaded: 0,
+            last_evicted: 0,
+            channel_view: ChannelView::default(),
+            channel_tex: None,
+            viewport: Viewport::default(),
+            mouse_steps: MouseSteps::default(),
+            picking: false,
+            samples: SampleList::default(),
+            sample_export_path: "samples.csv".to_string(),
+            source_values: None,
+            source_values_rx: None,
+            show_stats: false,
+            stats: None,
+            show_guides,
+            hidden_guides,
+            nearest,
+            checker: checker_texture(&cc.egui_ctx),
+            show_crop: false,
+            crop,
+            crop_drag: None,
+            roi_lock: false,
+            roi_export: None,
+            show_fetch: false,
+            fetch_range: (0, 0),
+            fetch_dest: String::new(),
+            fetch: None,
+            annotating: false,
+            annotator: Annotator::new(AnnotationFile::default()),
+            measuring: false,
+            measurer: Measurer::default(),
+            clipboard: None,
+            show_defects: false,
+            defects: None,
+            defects_rx: None,
+            bookmarks: Bookmarks::default(),
+            bookmark_imports: Vec::new(),
+            playback: None,
+            playback_filter: PlaybackFilter::All,
+            loop_in: None,
+            loop_out: None,
+            audio: None,
+            bounds: SequenceBounds::default(),
+            bounds_rx: None,
+            skip_gaps: false,
+            interpolate_gaps: false,
+            interpolated_tex: None,
+            prefetch: true,
+            color_manage: true,
+            tone_map,
+            gap_search: None,
+            gap_skipped: 0,
+            playback_fps,
+            follow: None,
+            follow_advance: false,
+            blink: None,
+            blink_mark: None,
+            dir_config: DirConfig::default(),
+            compare: None,
+            onion: None,
+            onion_tex: None,
+            show_pairs: true,
+            forwarded: None,
+        };
+        app.skip_gaps = app.cfg.skip_gaps;
+        app.interpolate_gaps = app.cfg.interpolate_gaps;
+        app.prefetch = app.cfg.prefetch;
+        app.cache.set_prefetch(app.prefetch);
+        app.color_manage = app.cfg.color_management;
+        app.cache.set_texture_options(&cc.egui_ctx, app.texture_options());
+        app.load_annotations();
+        app.start_bounds_discovery();
+        app
+    }
+
+    /// Probe for the first/last frame in the background (done by the SSH worker for remote sequences)
+    fn start_bounds_discovery(&mut self) {
+        let (tx, rx) = channel();
+        let seq = self.seq.clone();
+        let request_tx = self.remote_tx.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send(seq.find_bounds(request_tx.as_ref()));
+        });
+        self.bounds = SequenceBounds::default();
+        self.bounds_rx = Some(rx);
+    }
+
+    /// Take in discovered bounds and narrow them by frames the loader found missing
+    fn update_bounds(&mut self, ctx: &egui::Context) {
+        if let Some(rx) = &self.bounds_rx {
+            if let Ok(result) = rx.try_recv() {
+                self.bounds_rx = None;
+                match result {
+                    Ok(found) => {
+                        info!("sequence spans frames {:?}..={:?}", found.first, found.last);
+                        self.bounds = found;
+                    }
+                    Err(e) => warn!("bounds discovery failed: {e:#}"),
+                }
+                self.refresh_status();
+            }
+        }
+        let current = self.seq.index;
+        let missing = self.cache.take_missing();
+        if self.skip_gaps {
+            // Holes are expected, so a missing frame says nothing about the ends
+            if missing.contains(&current) {
+                self.continue_gap_search(ctx);
+            }
+        } else {
+            for idx in missing {
+                // While following, the end is the follower's to move
+                if self.follow.is_none() || idx < current {
+                    self.bounds.learn_missing(idx, current);
+                }
+            }
+        }
+        self.cache.set_bounds(self.bounds);
+    }
+
+    /// Start or stop following frames appended to the sequence. With `advance`, also
+    /// jump to each new last frame (while following, `advance` just toggles that).
+    fn toggle_follow(&mut self, advance: bool) {
+        if self.follow.is_some() {
+            if advance {
+                self.follow_advance = !self.follow_advance;
+            } else {
+                self.follow = None;
+                info!("follow mode off");
+            }
+            return;
+        }
+        let Some(last) = self.bounds.last else {
+            self.status = "Sequence ends not known yet".to_string();
+            return;
+        };
+        let poll = Duration::from_millis(self.cfg.follow_poll_ms.max(100));
+        match Follower::start(self.seq.clone(), last, self.remote_tx.clone(), poll) {
+            Ok(follower) => {
+                info!("following frames after {}", last);
+                self.follow = Some(follower);
+                self.follow_advance = advance;
+            }
+            Err(e) => self.status = format!("Cannot follow the sequence: {e:#}"),
+        }
+    }
+
+    /// Extend the bounds by newly appeared frames, jumping to the last one in auto-advance
+    fn update_follow(&mut self, ctx: &egui::Context) {
+        let Some(follower) = &self.follow else {
+            return;
+        };
+        // Nothing else repaints while the sequence sits still on screen
+        ctx.request_repaint_after(Duration::from_millis(250));
+        let Some(last) = follower.latest() else {
+            return;
+        };
+        self.bounds.last = Some(last);
+        self.cache.set_bounds(self.bounds);
+        if self.follow_advance && self.playback.is_none() {
+            self.jump_to(ctx, last);
+        } else {
+            self.refresh_status();
+        }
+    }
+
+    /// Blink comparison (T): the first press marks the current frame, the second (on
+    /// another frame) starts flipping between the two; after that T flips
+    fn blink_key(&mut self, ctx: &egui::Context) {
+        if let Some(blink) = &mut self.blink {
+            let idx = blink.flip();
+            self.show_blink_frame(idx);
+            return;
+        }
+        match self.blink_mark.take() {
+            Some(a) if a != self.seq.index => self.start_blink(ctx, a),
+            // T twice on the same frame drops the mark
+            Some(_) => {}
+            None => self.blink_mark = Some(self.seq.index),
+        }
+    }
+
+    /// Shift+T: automatic flipping on/off at `blink_hz`, or drop a pending mark
+    fn toggle_blink_auto(&mut self) {
+        let Some(blink) = &mut self.blink else {
+            self.blink_mark = None;
+            return;
+        };
+        let interval = (!blink.is_auto()).then(|| blink_interval(self.cfg.blink_hz));
+        info!("blink flips automatically: {}", interval.is_some());
+        blink.set_auto(interval);
+    }
+
+    fn start_blink(&mut self, ctx: &egui::Context, a: i64) {
+        if self.onion.take().is_some() {
+            self.onion_tex = None;
+        }
+        let blink = Blink::new(a, self.seq.index);
+        info!("blinking between {} and {}", a, self.seq.index);
+        self.cache.set_pinned(&blink.frames());
+        if let Some(compare) = &mut self.compare {
+            compare.cache.set_pinned(&blink.frames().map(|i| i.saturating_add(compare.offset).max(compare.seq.min_index())));
+        }
+        self.blink = Some(blink);
+        // Requests the pinned frames
+        self.update_cache_and_status(ctx);
+    }
+
+    /// Leave blink comparison, releasing the two frames to normal eviction
+    fn stop_blink(&mut self) {
+        self.blink_mark = None;
+        if self.blink.take().is_none() {
+            return;
+        }
+        info!("blink stopped");
+        self.cache.set_pinned(&[]);
+        if let Some(compare) = &mut self.compare {
+            compare.cache.set_pinned(&[]);
+        }
+    }
+
+    /// Put one of the blink frames on screen. Both are pinned, so the cache is not recentered.
+    fn show_blink_frame(&mut self, idx: i64) {
+        self.seq.index = idx;
+        if let Some(compare) = &mut self.compare {
+            compare.seq.index = idx.saturating_add(compare.offset).max(compare.seq.min_index());
+        }
+        self.refresh_status();
+    }
+
+    fn update_blink(&mut self, ctx: &egui::Context) {
+        let Some(blink) = &mut self.blink else {
+            return;
+        };
+        let Some((flipped, wait)) = blink.tick() else {
+            return;
+        };
+        if flipped {
+            let idx = blink.current();
+            self.show_blink_frame(idx);
+        }
+        ctx.request_repaint_after(wait);
+    }
+
+    /// Nearest existing local frame from `start` onwards in `direction`, within `max_gap` steps.
+    /// Returns the frame and how many missing frames were passed over.
+    fn find_local_frame(&self, start: i64, direction: i64, step: u64) -> Option<(i64, u64)> {
+        let mut idx = start;
+        for skipped in 0..=self.cfg.max_gap {
+            if !self.local_frame_missing(idx) {
+                return Some((idx, skipped));
+            }
+            idx = if direction > 0 { idx.checked_add_unsigned(step)? } else { idx.checked_sub_unsigned(step)? };
+            if idx < self.seq.min_index() || !self.bounds.contains(idx) {
+                return None;
+            }
+        }
+        None
+    }
+
+    /// The remote frame a step landed on is missing: move on in the same direction
+    fn continue_gap_search(&mut self, ctx: &egui::Context) {
+        let Some(search) = &mut self.gap_search else {
+            return;
+        };
+        search.skipped += 1;
+        let (direction, step, skipped) = (search.direction, search.step, search.skipped);
+        let next = if direction > 0 {
+            self.seq.index.checked_add_unsigned(step)
+        } else {
+            self.seq.index.checked_sub_unsigned(step)
+        };
+        match next.filter(|&n| skipped <= self.cfg.max_gap && n >= self.seq.min_index() && self.bounds.contains(n)) {
+            Some(next) => {
+                debug!("frame {} missing, skipping to {}", self.seq.index, next);
+                self.gap_skipped = skipped;
+                self.seq.index = next;
+                self.restart_playback_clock();
+                self.update_cache_and_status(ctx);
+            }
+            None => {
+                info!("no frame within {} steps", self.cfg.max_gap);
+                self.gap_search = None;
+            }
+        }
+    }
+
+    /// Jump to the first or last frame (Home/End)
+    fn jump_to_end(&mut self, ctx: &egui::Context, last: bool) {
+        let target = if last { self.bounds.last } else { self.bounds.first };
+        match target {
+            Some(idx) => self.jump_to(ctx, idx),
+            None => self.status = "Sequence ends not known yet".to_string(),
+        }
+    }
+
+    fn open_cache(
+        ctx: &egui::Context,
+        cfg: &Config,
+        decode_opts: DecodeOptions,
+        pattern: &str,
+        seq: &SequenceSpec,
+        request_tx: Option<Sender<RemoteWorkerRequest>>,
+        remote_range: Option<RemoteRange>,
+    ) -> ImageCache {
+        let mut cache = ImageCache::new(
+            ctx,
+            cfg.cache_radius,
+            seq.source.clone(),
+            request_tx,
+            remote_range,
+            cfg.preview_max_dim,
+            decode_opts,
+        );
+        cache.set_stall_timeout(cfg.stall_timeout());
+        cache.set_reload_threshold(cfg.reload_threshold);
+        if cfg.adaptive_cache_radius {
+            cache.set_adaptive_radius(cfg.max_cache_radius.max(cfg.cache_radius), cfg.cache_memory_budget());
+        }
+        cache.set_loader_command(cfg.loader_command_for(pattern));
+        if seq.source.is_remote() {
+            // One loader thread per transfer channel keeps them all busy
+            cache.set_loader_threads(cfg.remote_channels);
+        } else if matches!(seq.source, SequenceSource::Video { .. }) {
+            // Every video frame is an ffmpeg run of its own; a few side by side keep up with scrubbing
+            cache.set_loader_threads(4);
+        }
+        cache
+    }
+
+    /// Mark the pattern as inferred, offering to save it to the config.
+    pub fn with_inferred_pattern(mut self) -> Self {
+        self.pattern_inferred = true;
+        self
+    }
+
+    /// Bookmark as `author`, merging the given sidecars from other reviewers.
+    pub fn with_bookmarks(mut self, author: String, imports: Vec<BookmarkFile>) -> Self {
+        self.bookmarks = Bookmarks::new(author);
+        self.bookmark_imports = imports;
+        self.reload_bookmarks();
+        self
+    }
+
+    /// Play at `fps` instead of the configured rate (not saved).
+    pub fn with_playback_fps(mut self, fps: f64) -> Self {
+        self.playback_fps = fps;
+        self
+    }
+
+    /// Use known sequence ends instead of probing for them.
+    pub fn with_bounds(mut self, bounds: SequenceBounds) -> Self {
+        self.bounds_rx = None;
+        self.bounds = bounds;
+        self.cache.set_bounds(bounds);
+        self
+    }
+
+    /// Open with the display settings from the sequence directory's `.zapvis.toml`.
+    /// The viewer's own saved orientation and tone mapping for the sequence still win.
+    pub fn with_dir_config(mut self, dir_config: DirConfig) -> Self {
+        self.apply_dir_config(dir_config);
+        self
+    }
+
+    fn apply_dir_config(&mut self, dir_config: DirConfig) {
+        match dir_config.fps {
+            Some(fps) if fps > 0.0 && fps.is_finite() => self.playback_fps = fps,
+            Some(fps) => warn!("ignoring fps {} in the directory config", fps),
+            None => {}
+        }
+        if let Some(name) = &dir_config.channels {
+            match ChannelView::from_name(name) {
+                Some(view) => self.channel_view = view,
+                None => warn!("unknown channel view {:?} in the directory config", name),
+            }
+        }
+        let key = self.seq.sequence_key(&self.pattern);
+        match dir_config.exposure {
+            Some(ev) if !ev.is_finite() => warn!("ignoring exposure {} in the directory config", ev),
+            Some(ev) if !self.cfg.tone_maps.contains_key(&key) => {
+                self.tone_map.exposure = ev.clamp(-16.0, 16.0);
+                let opts = self.decode_options();
+                self.cache.set_decode_options(opts);
+            }
+            _ => {}
+        }
+        self.dir_config = dir_config;
+        self.orientation = self.sequence_orientation();
+    }
+
+    /// Show `pixels` (piped in on stdin) for the current frame instead of its file, until
+    /// the frame leaves the cache.
+    pub fn with_initial_frame(mut self, pixels: FramePixels) -> Self {
+        self.cache.insert(self.seq.index, pixels);
+        self
+    }
+
+    /// Switch to the inputs later invocations hand over (see `single_instance`).
+    pub fn with_forwarded_inputs(mut self, rx: Receiver<OpenedInput>) -> Self {
+        self.forwarded = Some(rx);
+        self
+    }
+
+    /// Show an input another invocation handed over in place of the current sequence,
+    /// with its own remote worker; the old sequence's worker goes with its cache
+    fn open_forwarded(&mut self, ctx: &egui::Context, opened: OpenedInput) {
+        let (pattern, seq) = opened.candidates[0].clone();
+        info!("switching to {} for another invocation", seq.path_display(seq.index));
+        let remote = seq.source.is_remote();
+        self.remote_tx = opened.remote_tx;
+        self.remote_range = remote.then_some(opened.remote_range);
+        self.remote_stats = remote.then_some(opened.remote_stats);
+        self.pattern_candidates = opened.candidates;
+        self.pattern_inferred = opened.inferred;
+        self.siblings = None;
+        self.parked.clear();
+        self.compare = None;
+        self.playback_fps = seq.source.native_fps().or(opened.manifest.as_ref().and_then(|m| m.fps)).unwrap_or(self.cfg.playback_fps);
+        self.dir_config = DirConfig::default();
+        self.switch_sequence(ctx, pattern, seq);
+        if let Some(dir_config) = opened.dir_config {
+            self.apply_dir_config(dir_config);
+        }
+        if let Some(bounds) = opened.manifest.as_ref().and_then(|m| m.bounds()) {
+            self.bounds_rx = None;
+            self.bounds = bounds;
+            self.cache.set_bounds(bounds);
+        }
+        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+    }
+
+    /// Resume at the frame, zoom, step size and window of the last session on this sequence.
+    pub fn with_session(mut self, state: SessionState) -> Self {
+        info!("resuming the session at frame {}", state.index);
+        self.seq.index = state.index;
+        self.step_size = state.step_size.max(1);
+        self.cache.set_step_size(self.step_size);
+        if state.zoom.is_finite() && state.zoom > 0.0 {
+            self.viewport.zoom = state.zoom;
+            self.viewport.pan = egui::vec2(state.pan[0], state.pan[1]);
+        }
+        // The window opens maximized or fullscreen already (see `main`); this is the size
+        // and position to go back to
+        self.is_fullscreen = state.fullscreen || state.exclusive_fullscreen;
+        self.exclusive_fullscreen = state.exclusive_fullscreen;
+        self.saved_window_size = state.window_size.map(|[w, h]| egui::vec2(w, h));
+        self.saved_window_pos = state.window_pos.map(|[x, y]| egui::pos2(x, y));
+        (self.loop_in, self.loop_out) = (state.loop_in, state.loop_out);
+        self.start_bounds_discovery();
+        self
+    }
+
+    fn session_state(&self) -> SessionState {
+        let (window_size, window_pos) = match self.is_fullscreen {
+            true => (self.saved_window_size, self.saved_window_pos),
+            false => (self.window_size, self.window_pos),
+        };
+        SessionState {
+            index: self.seq.index,
+            zoom: self.viewport.zoom,
+            pan: [self.viewport.pan.x, self.viewport.pan.y],
+            fullscreen: self.is_fullscreen && !self.exclusive_fullscreen,
+            exclusive_fullscreen: self.exclusive_fullscreen,
+            step_size: self.step_size,
+            window_size: window_size.map(|s| [s.x, s.y]),
+            window_pos: window_pos.map(|p| [p.x, p.y]),
+            loop_in: self.loop_in,
+            loop_out: self.loop_out,
+        }
+    }
+
+    /// Remember where this sequence was left, for the next time it is opened
+    fn save_session(&self) {
+        if !self.cfg.remember_sessions {
+            return;
+        }
+        let key = self.seq.sequence_key(&self.pattern);
+        if let Err(e) = session_path().and_then(|path| record_session(&path, &key, self.session_state())) {
+            error!("could not save the session: {e}");
+        }
+    }
+
+    /// Loop markers saved with the last session on this sequence
+    fn saved_loop_markers(&self) -> (Option<i64>, Option<i64>) {
+        if !self.cfg.remember_sessions {
+            return (None, None);
+        }
+        let key = self.seq.sequence_key(&self.pattern);
+        match session_path().and_then(|path| load_session_file(&path)) {
+            Ok(file) => file.sessions.get(&key).map_or((None, None), |s| (s.loop_in, s.loop_out)),
+            Err(e) => {
+                warn!("could not read the sessions file: {e}");
+                (None, None)
+            }
+        }
+    }
+
+    /// Orientation saved for this sequence, else the one its directory asks for
+    fn sequence_orientation(&self) -> Orientation {
+        let key = self.seq.sequence_key(&self.pattern);
+        match self.cfg.orientations.get(&key) {
+            Some(orientation) => *orientation,
+            None => self.dir_config.orientation.unwrap_or_default(),
+        }
+    }
+
+    /// Show a second sequence beside this one, its frames paired by the opened indices.
+    pub fn with_compare(mut self, opened: OpenedInput) -> Self {
+        self.set_compare(opened, false);
+        self
+    }
+
+    /// Show the `[paired_patterns]` partner of the sequence beside it, if one is configured.
+    pub fn with_pair(mut self) -> Self {
+        self.open_pair();
+        self
+    }
+
+    fn set_compare(&mut self, opened: OpenedInput, paired: bool) {
+        let (pattern, seq) = opened.candidates[0].clone();
+        let remote_range = seq.source.is_remote().then_some(opened.remote_range);
+        let mut cache = Self::open_cache(&self.egui_ctx, &self.cfg, self.decode_options(), &pattern, &seq, opened.remote_tx, remote_range);
+        cache.set_step_size(self.step_size);
+        cache.set_prefetch(self.prefetch);
+        cache.set_texture_options(&self.egui_ctx, self.texture_options());
+        info!("comparing {} beside {}", seq.path_display(seq.index), self.seq.path_display(self.seq.index));
+        self.compare = Some(Compare {
+            pattern,
+            offset: seq.index - self.seq.index,
+            paired,
+            seq,
+            cache,
+            channel_tex: None,
+        });
+    }
+
+    /// Open the partner sequence of `[paired_patterns]` beside this one, at the same index
+    /// and with a worker of its own. False if the pattern has no usable partner.
+    fn open_pair(&mut self) -> bool {
+        let Some(partner) = self.cfg.pair_for(&self.pattern).map(str::to_string) else {
+            return false;
+        };
+        let seq = SequenceSpec::from_pattern(&partner, self.seq.source.clone(), self.seq.index).and_then(|seq| match self.cfg.shards.get(&partner) {
+            Some(rule) if seq.shard.is_none() => seq.with_shard(rule),
+            _ => Ok(seq),
+        });
+        let seq = match seq {
+            Ok(seq) => seq,
+            Err(e) => {
+                warn!("cannot pair {:?} with {:?}: {}", self.pattern, partner, e);
+                self.status = format!("Paired pattern {partner:?}: {e}");
+                return false;
+            }
+        };
+        let (remote_range, remote_stats) = (RemoteRange::new(), RemoteStats::new());
+        let remote_tx = spawn_source_worker(&self.cfg, &seq.source, remote_range.clone(), remote_stats.clone());
+        let opened = OpenedInput {
+            manifest: None,
+            candidates: vec![(partner, seq)],
+            inferred: false,
+            dir_config: None,
+            remote_tx,
+            remote_range,
+            remote_stats,
+        };
+        self.set_compare(opened, true);
+        true
+    }
+
+    /// Show or hide the paired sequence (Shift+S). A second input given on the command
+    /// line stays.
+    fn toggle_pair(&mut self, ctx: &egui::Context) {
+        if self.compare.as_ref().is_some_and(|c| !c.paired) {
+            self.status = "The split view shows the second input".to_string();
+            return;
+        }
+        self.show_pairs = !self.show_pairs;
+        if !self.show_pairs {
+            self.compare = None;
+        } else if self.open_pair() {
+            self.update_cache_and_status(ctx);
+        } else {
+            self.show_pairs = false;
+            self.status = format!("No paired pattern for {:?} in the config", self.pattern);
+        }
+    }
+
+    /// Offer the other matching patterns in the top bar.
+    pub fn with_pattern_candidates(mut self, candidates: Vec<(String, SequenceSpec)>) -> Self {
+        self.pattern_candidates = candidates;
+        self
+    }
+
+    /// Rebuild the bookmarks from the local reviewer's sidecar for this sequence plus the imports
+    fn reload_bookmarks(&mut self) {
+        let mut bookmarks = Bookmarks::new(self.bookmarks.author());
+        if let Ok(path) = bookmark_path(&self.seq.sequence_key(&self.pattern)) {
+            if path.exists() {
+                match load_bookmark_file(&path) {
+                    Ok(file) => bookmarks.merge(file),
+                    Err(e) => warn!("could not load bookmarks: {e:#}"),
+                }
+            }
+        }
+        for file in &self.bookmark_imports {
+            bookmarks.merge(file.clone());
+        }
+        self.bookmarks = bookmarks;
+    }
+
+    /// Switch to another matching pattern, reopening the sequence at the originally opened file
+    fn switch_pattern(&mut self, ctx: &egui::Context, i: usize) {
+        let Some((pattern, seq)) = self.pattern_candidates.get(i).cloned() else {
+            return;
+        };
+        info!("switching to pattern {:?}", pattern);
+        self.switch_sequence(ctx, pattern, seq);
+    }
+
+    /// Replace the shown sequence, resetting everything that belonged to the old one. A
+    /// sequence parked by Tab gets its cache, frame and bounds back. Returns the old cache.
+    fn switch_sequence(&mut self, ctx: &egui::Context, pattern: String, seq: SequenceSpec) -> ImageCache {
+        self.save_session();
+        let parked = self.parked.remove(&seq.sequence_key(&pattern));
+        self.pattern = pattern;
+        self.seq = seq;
+        self.tone_map = self.cfg.tone_map_for(&self.seq.sequence_key(&self.pattern));
+        let old_cache = match parked {
+            Some(parked) => {
+                self.seq.index = parked.seq.index;
+                let opts = self.decode_options();
+                let mut cache = parked.cache;
+                // Color management may have been toggled meanwhile
+                if cache.decode_options() != opts {
+                    cache.set_decode_options(opts);
+                }
+                self.bounds_rx = None;
+                self.bounds = parked.bounds;
+                std::mem::replace(&mut self.cache, cache)
+            }
+            None => {
+                let cache = Self::open_cache(ctx, &self.cfg, self.decode_options(), &self.pattern, &self.seq, self.remote_tx.clone(), self.remote_range.clone());
+                self.start_bounds_discovery();
+                std::mem::replace(&mut self.cache, cache)
+            }
+        };
+        self.cache.set_step_size(self.step_size);
+        self.cache.set_prefetch(self.prefetch);
+        self.orientation = self.sequence_orientation();
+        self.show_guides = self.cfg.guides_shown_for(&self.seq.sequence_key(&self.pattern));
+        self.hidden_guides = self.cfg.hidden_guides_for(&self.seq.sequence_key(&self.pattern));
+        self.nearest = self.cfg.nearest_filtering_for(&self.seq.sequence_key(&self.pattern));
+        self.cache.set_texture_options(ctx, self.texture_options());
+        self.crop = self.cfg.crop_for(&self.seq.sequence_key(&self.pattern));
+        self.channel_tex = None;
+        self.measurer.clear();
+        self.source_values = None;
+        self.source_values_rx = None;
+        self.onion_tex = None;
+        if let Some(onion) = &mut self.onion {
+            onion.pinned = None;
+        }
+        self.stats = None;
+        self.viewport.reset();
+        self.playback = None;
+        (self.loop_in, self.loop_out) = self.saved_loop_markers();
+        self.follow = None;
+        self.stop_blink();
+        self.audio = None;
+        self.reload_bookmarks();
+        self.load_annotations();
+        if self.compare.as_ref().is_none_or(|c| c.paired) {
+            self.compare = None;
+            if self.show_pairs {
+                self.open_pair();
+            }
+        }
+        self.update_cache_and_status(ctx);
+        old_cache
+    }
+
+    /// Show the next (or previous) sequence of the directory, keeping the current one's
+    /// cache and frame for when Tab comes back to it
+    fn cycle_sequence(&mut self, ctx: &egui::Context, forward: bool) {
+        let siblings = self.siblings.get_or_insert_with(|| discover_siblings(&self.cfg, &self.pattern, &self.seq));
+        let key = self.seq.sequence_key(&self.pattern);
+        let Some(pos) = siblings.iter().position(|(p, s)| s.sequence_key(p) == key).filter(|_| siblings.len() > 1) else {
+            self.status = "No other sequence in this directory".to_string();
+            return;
+        };
+        let next = (if forward { pos + 1 } else { pos + siblings.len() - 1 }) % siblings.len();
+        let (pattern, seq) = siblings[next].clone();
+        info!("switching to sequence {:?} ({}/{})", pattern, next + 1, siblings.len());
+        let (old_seq, old_bounds) = (self.seq.clone(), self.bounds);
+        let old_cache = self.switch_sequence(ctx, pattern, seq);
+        self.parked.insert(key, ParkedSequence {
+            seq: old_seq,
+            cache: old_cache,
+            bounds: old_bounds,
+        });
+    }
+
+    /// Read the annotation sidecar of the current sequence
+    fn load_annotations(&mut self) {
+        let file = annotation_path(&self.seq, &self.pattern).and_then(|path| load_annotation_file(&path));
+        let file = file.unwrap_or_else(|e| {
+            error!("could not load annotations: {e}");
+            AnnotationFile::default()
+        });
+        self.annotator = Annotator::new(file);
+    }
+
+    fn save_annotations(&mut self) {
+        self.annotator.file.sequence = self.seq.sequence_key(&self.pattern);
+        let saved = annotation_path(&self.seq, &self.pattern).and_then(|path| save_annotation_file(&path, &self.annotator.file));
+        if let Err(e) = saved {
+            self.status = format!("Annotations not saved: {e}");
+        }
+    }
+
+    fn add_annotation(&mut self, shape: Shape) {
+        debug!("annotation on frame {}: {:?}", self.seq.index, shape);
+        self.annotator.file.add(self.seq.index, shape);
+        self.save_annotations();
+        self.refresh_status();
+    }
+
+    /// Remove the newest annotation on the current frame (Backspace while annotating)
+    fn remove_last_annotation(&mut self) {
+        if self.annotator.file.remove_last(self.seq.index) {
+            self.save_annotations();
+            self.refresh_status();
+        }
+    }
+
+    /// Drags (and text-tool clicks) over the image draw annotations while annotating.
+    /// Returns true while one is being drawn, so the drag neither pans nor scrubs.
+    fn annotation_input(&mut self, ui: &egui::Ui, response: &egui::Response, panel: egui::Rect) -> bool {
+        let Some(size) = self.cache.display_size(self.seq.index) else {
+            return false;
+        };
+        let map = FrameMapping {
+            image_rect: self.viewport.image_rect(panel, self.fit_size(size, panel.size())),
+            size,
+            orientation: self.orientation,
+        };
+        let Some(pos) = response.interact_pointer_pos() else {
+            return false;
+        };
+        if response.clicked() && self.annotator.tool == Tool::Text {
+            match self.annotator.place_text(map.to_frame(pos)) {
+                Some(shape) => self.add_annotation(shape),
+                None => self.status = "Type the label in the top bar first".to_string(),
+            }
+            return true;
+        }
+        if response.drag_started() && !ui.input(|i| i.modifiers.shift) {
+            self.annotator.start(map.to_frame(pos));
+        }
+        if !self.annotator.is_drawing() {
+            return false;
+        }
+        if let Some(shape) = self.annotator.drag_to(map.to_frame(pos), response.drag_stopped()) {
+            self.add_annotation(shape);
+        }
+        true
+    }
+
+    /// Save the current frame with its annotations burned in as `<name>_annotated.png` in
+    /// the working directory (Shift+E)
+    fn export_annotated(&mut self) {
+        let idx = self.seq.index;
+        if self.cache.is_preview(idx) {
+            self.status = "Wait for the full-resolution frame to export it".to_string();
+            return;
+        }
+        if self.cache.region(idx).is_some() {
+            self.status = "Zoom out to export the whole frame (only part of it is decoded)".to_string();
+            return;
+        }
+        let Some(pixels) = self.cache.pixels(idx) else {
+            return;
+        };
+        let mut img = pixels.to_rgba().into_owned();
+        burn_in(&mut img, self.annotator.file.at(idx));
+        let name = self.seq.file_name_for(idx);
+        let stem = std::path::Path::new(&name).file_stem().and_then(|s| s.to_str()).unwrap_or(&name);
+        let out = std::path::PathBuf::from(format!("{stem}_annotated.png"));
+        self.status = match img.save_with_format(&out, image::ImageFormat::Png) {
+            Ok(()) => format!("Saved {}", out.display()),
+            Err(e) => format!("Could not save {}: {e}", out.display()),
+        };
+    }
+
+    /// Put the current frame's full path (`user@host:/path` for remote frames) on the
+    /// clipboard (Ctrl+C)
+    fn copy_path(&mut self, ctx: &egui::Context) {
+        let path = self.seq.path_display(self.seq.index);
+        ctx.copy_text(path.clone());
+        self.status = format!("Copied {path}");
+    }
+
+    /// The current frame as displayed: full resolution with the channel view, rotation
+    /// and flips applied. None (with a status to `action` it later) while only a preview
+    /// or nothing is cached.
+    fn displayed_frame(&mut self, action: &str) -> Option<image::RgbaImage> {
+        let idx = self.seq.index;
+        if self.cache.is_preview(idx) {
+            self.status = format!("Wait for the full-resolution frame to {action} it");
+            return None;
+        }
+        if self.cache.region(idx).is_some() {
+            self.status = format!("Zoom out to {action} the whole frame (only part of it is decoded)");
+            return None;
+        }
+        let rgba = self.cache.pixels(idx)?.to_rgba();
+        let img = match self.channel_view.remap(&rgba) {
+            Some(remapped) => self.orientation.apply_to_image(&remapped),
+            None => self.orientation.apply_to_image(&rgba),
+        };
+        Some(img)
+    }
+
+    /// Put the current frame's pixels, as displayed, on the clipboard (Ctrl+Shift+C)
+    fn copy_image(&mut self) {
+        let idx = self.seq.index;
+        let Some(img) = self.displayed_frame("copy") else {
+            return;
+        };
+        let image = arboard::ImageData {
+            width: img.width() as usize,
+            height: img.height() as usize,
+            bytes: img.into_raw().into(),
+        };
+        let copied = match &mut self.clipboard {
+            Some(clipboard) => clipboard.set_image(image),
+            None => arboard::Clipboard::new().and_then(|clipboard| self.clipboard.insert(clipboard).set_image(image)),
+        };
+        self.status = match copied {
+            Ok(()) => format!("Copied {} to the clipboard", self.seq.file_name_for(idx)),
+            Err(e) => format!("Could not copy the frame: {e}"),
+        };
+    }
+
+    /// Gain, offset and gamma sliders and the LUT file of the GPU grade (F4)
+    fn grade_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Grade");
+        ui.label("Applied on the GPU while drawing, to the values as displayed");
+        if let Some(e) = self.grade.unavailable() {
+            ui.colored_label(ui.visuals().error_fg_color, format!("Unavailable: {e}"));
+        }
+        let grade = &mut self.grade.grade;
+        ui.add(egui::Slider::new(&mut grade.gain, 0.0..=8.0).logarithmic(true).text("gain"));
+        ui.add(egui::Slider::new(&mut grade.offset, -0.5..=0.5).text("offset"));
+        ui.add(egui::Slider::new(&mut grade.gamma, 0.2..=5.0).logarithmic(true).text("gamma"));
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("Load LUT…").clicked() {
+                let picked = rfd::FileDialog::new().set_title("Load 3D LUT").add_filter("Cube LUT", &["cube"]).pick_file();
+                if let Some(path) = picked {
+                    match Lut3d::load(&path) {
+                        Ok(lut) => {
+                            info!("loaded LUT {} ({}³)", path.display(), lut.size);
+                            grade.lut = Some((path, Arc::new(lut)));
+                        }
+                        Err(e) => self.status = format!("Could not load {}: {e}", path.display()),
+                    }
+                }
+            }
+            if grade.lut.is_some() && ui.button("Clear").clicked() {
+                grade.lut = None;
+            }
+        });
+        match &grade.lut {
+            Some((path, lut)) => {
+                let name = path.file_name().map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().into_owned());
+                ui.label(format!("LUT: {name} ({}³)", lut.size)).on_hover_text(path.display().to_string());
+            }
+            None => {
+                ui.weak("No LUT");
+            }
+        }
+        ui.separator();
+        if ui.button("Reset").clicked() {
+            *grade = Grade::default();
+        }
+    }
+
+    /// Lowest and highest value of the false-colored channel in the current frame
+    fn channel_range(&self) -> Option<(u8, u8)> {
+        let pick = self.channel_view.mapped_channel().pick()?;
+        let rgba = self.cache.pixels(self.seq.index)?.to_rgba();
+        rgba.pixels().map(pick).fold(None, |range, v| match range {
+            None => Some((v, v)),
+            Some((low, high)) => Some((low.min(v), high.max(v))),
+        })
+    }
+
+    /// Save the current frame as displayed to a path picked in a native dialog (Ctrl+S);
+    /// the format follows the extension
+    fn save_frame_as(&mut self) {
+        let Some(img) = self.displayed_frame("save") else {
+            return;
+        };
+        let name = self.seq.file_name_for(self.seq.index);
+        let stem = std::path::Path::new(&name).file_stem().and_then(|s| s.to_str()).unwrap_or(&name);
+        let picked = rfd::FileDialog::new()
+            .set_title("Save frame")
+            .set_file_name(format!("{stem}.png"))
+            .add_filter("PNG", &["png"])
+            .add_filter("JPEG", &["jpg", "jpeg"])
+            .add_filter("TIFF", &["tif", "tiff"])
+            .save_file();
+        let Some(out) = picked else {
+            return;
+        };
+        // JPEG has no alpha channel
+        let saved = match image::ImageFormat::from_path(&out) {
+            Ok(image::ImageFormat::Jpeg) => image::DynamicImage::ImageRgba8(img).to_rgb8().save(&out),
+            _ => img.save(&out),
+        };
+        self.status = match saved {
+            Ok(()) => format!("Saved {}", out.display()),
+            Err(e) => format!("Could not save {}: {e}", out.display()),
+        };
+    }
+
+    /// Selector for what is drawn under the image, with the custom color's picker
+    fn background_picker(&mut self, ui: &mut egui::Ui) {
+        let mut background = self.cfg.background;
+        egui::ComboBox::from_id_salt("background_picker")
+            .selected_text(format!("Background: {}", background.label()))
+            .show_ui(ui, |ui| {
+                for choice in Background::ALL {
+                    ui.selectable_value(&mut background, choice, choice.label());
+                }
+            });
+        let mut color = self.cfg.background_color;
+        if background == Background::Custom {
+            ui.color_edit_button_srgb(&mut color);
+        }
+        if background != self.cfg.background || color != self.cfg.background_color {
+            self.cfg.background_color = color;
+            self.set_background(background);
+        }
+    }
+
+    fn set_background(&mut self, background: Background) {
+        self.cfg.background = background;
+        info!("background: {}", background.label());
+        save_config(&self.cfg).ok(); // ignore save errors (the background still changes)
+    }
+
+    /// Fill the visible part of `image_rect` with the background, so transparent pixels show it
+    fn paint_background(&self, painter: &egui::Painter, image_rect: egui::Rect) {
+        let rect = painter.clip_rect().intersect(image_rect);
+        let fill = match self.cfg.background {
+            Background::Checkerboard => {
+                // Checks are anchored to the image corner so they pan with it
+                let uv_at = |p: egui::Pos2| ((p - image_rect.min) / (2.0 * CHECKER_SIZE)).to_pos2();
+                let uv = egui::Rect::from_min_max(uv_at(rect.min), uv_at(rect.max));
+                painter.image(self.checker.id(), rect, uv, egui::Color32::WHITE);
+                return;
+            }
+            Background::Black => egui::Color32::BLACK,
+            Background::White => egui::Color32::WHITE,
+            Background::Custom => {
+                let [r, g, b] = self.cfg.background_color;
+                egui::Color32::from_rgb(r, g, b)
+            }
+        };
+        painter.rect_filled(rect, 0.0, fill);
+    }
+
+    /// Selector for the matching patterns, each previewing the filenames around the opened frame
+    fn pattern_picker(&mut self, ui: &mut egui::Ui) -> Option<usize> {
+        if self.pattern_candidates.len() < 2 {
+            return None;
+        }
+        let mut picked = None;
+        egui::ComboBox::from_id_salt("pattern_picker")
+            .selected_text(&self.pattern)
+            .show_ui(ui, |ui| {
+                for (i, (pattern, seq)) in self.pattern_candidates.iter().enumerate() {
+                    let idx = seq.index;
+                    let prev = if idx > seq.min_index() { seq.file_name_for(idx - 1) } else { "-".to_string() };
+                    let next = seq.file_name_for(idx + 1);
+                    let selected = *pattern == self.pattern;
+                    let text = format!("{pattern}    ({prev} | {next})");
+                    if ui.selectable_label(selected, text).clicked() && !selected {
+                        picked = Some(i);
+                    }
+                }
+            });
+        picked
+    }
+
+    /// Toggle the local reviewer's bookmark on the current frame and save their sidecar
+    fn toggle_bookmark(&mut self) {
+        let idx = self.seq.index;
+        let added = self.bookmarks.toggle(idx);
+        info!("bookmark {} on frame {}", if added { "added" } else { "removed" }, idx);
+        let key = self.seq.sequence_key(&self.pattern);
+        let saved = bookmark_path(&key).and_then(|path| save_bookmark_file(&path, &self.bookmarks.own_file(&key)));
+        if let Err(e) = saved {
+            error!("failed to save bookmarks: {e}");
+        }
+        self.refresh_status();
+    }
+
+    fn jump_to(&mut self, ctx: &egui::Context, idx: i64) {
+        debug!("jumping from {} to {}", self.seq.index, idx);
+        self.stop_blink();
+        self.seq.index = idx;
+        self.restart_playback_clock();
+        self.update_cache_and_status(ctx);
+    }
+
+    /// Pause or resume loading the frames around the current one (not saved)
+    fn toggle_prefetch(&mut self, ctx: &egui::Context) {
+        self.prefetch = !self.prefetch;
+        info!("prefetch: {}", self.prefetch);
+        self.cache.set_prefetch(self.prefetch);
+        if let Some(compare) = &mut self.compare {
+            compare.cache.set_prefetch(self.prefetch);
+        }
+        self.update_cache_and_status(ctx);
+    }
+
+    /// Switch between frames converted to sRGB and their raw values (not saved), decoding
+    /// the cached frames again
+    fn toggle_color_management(&mut self, ctx: &egui::Context) {
+        self.color_manage = !self.color_manage;
+        info!("color management: {}", self.color_manage);
+        let opts = self.decode_options();
+        self.cache.set_decode_options(opts);
+        if let Some(compare) = &mut self.compare {
+            compare.cache.set_decode_options(opts);
+        }
+        self.update_cache_and_status(ctx);
+    }
+
+    /// Apply a tone-mapping change, decoding the cached frames again, and persist it for
+    /// this sequence
+    fn change_tone_map(&mut self, ctx: &egui::Context, f: impl FnOnce(&mut ToneMap)) {
+        f(&mut self.tone_map);
+        self.tone_map.exposure = self.tone_map.exposure.clamp(-16.0, 16.0);
+        self.tone_map.gamma = ((self.tone_map.gamma * 10.0).round() / 10.0).clamp(0.1, 5.0);
+        info!("tone map: {}", self.tone_map.label());
+        let opts = self.decode_options();
+        self.cache.set_decode_options(opts);
+        if let Some(compare) = &mut self.compare {
+            compare.cache.set_decode_options(opts);
+        }
+        let key = self.seq.sequence_key(&self.pattern);
+        self.cfg.set_tone_map(&key, self.tone_map);
+        save_config(&self.cfg).ok(); // ignore save errors (the tone map still applies)
+        self.update_cache_and_status(ctx);
+    }
+
+    /// Decoding options of the config, with the color management toggled at runtime and
+    /// the sequence's tone mapping
+    fn decode_options(&self) -> DecodeOptions {
+        DecodeOptions {
+            color_manage: self.color_manage,
+            tone: self.tone_map,
+            ..DecodeOptions::from_config(&self.cfg)
+        }
+    }
+
+    /// True if a local sequence has no file for `idx` (remote frames are not checked up front)
+    fn local_frame_missing(&self, idx: i64) -> bool {
+        match &self.seq.source {
+            SequenceSource::Local(dir) => !dir.join(self.seq.relative_path_for(idx)).exists(),
+            SequenceSource::Video { info, .. } => !info.contains(idx),
+            SequenceSource::Animation { info, .. } => !info.contains(idx),
+            SequenceSource::Remote { .. } | SequenceSource::Http { .. } => false,
+        }
+    }
+
+    fn audio_track(&self) -> Option<AudioTrack> {
+        self.cfg.audio_track_for(&self.seq.sequence_key(&self.pattern)).cloned()
+    }
+
+    /// Start or stop playback (Space)
+    fn toggle_playback(&mut self, ctx: &egui::Context) {
+        if self.playback.take().is_some() {
+            info!("playback stopped at frame {}", self.seq.index);
+            self.set_lookahead(0);
+            if let Some(audio) = &mut self.audio {
+                audio.sync(0.0, false);
+            }
+            return;
+        }
+        self.stop_blink();
+        let fps = self.playback_fps;
+        let Some(playback) = self.begin_playback(fps) else {
+            self.status = format!("No {} frames from frame {} on", self.playback_filter.label(), self.seq.index);
+            return;
+        };
+        info!(
+            "playing {} from frame {} at {} fps",
+            self.playback_filter.label(),
+            self.seq.index,
+            fps
+        );
+        self.playback = Some(playback);
+        self.update_cache_and_status(ctx);
+        if self.audio.is_none() {
+            self.audio = self.audio_track().and_then(|track| open_audio(&track));
+        }
+    }
+
+    /// Playback from the current frame through the frames the filter selects, after the
+    /// preroll. Unfiltered playback buffers the frames the preroll covers before starting.
+    fn begin_playback(&mut self, fps: f64) -> Option<Playback> {
+        let preroll = Duration::from_millis(self.cfg.playback_preroll_ms);
+        let (playback, lookahead) = match self.playback_filter.playlist(&self.bookmarks, self.seq.index) {
+            None => {
+                let frames = (preroll.as_secs_f64() * fps).ceil() as u64;
+                let playback = match self.loop_range() {
+                    Some((first, last)) => {
+                        // Outside the loop region, playback starts over at its in point
+                        if !(first..=last).contains(&self.seq.index) {
+                            self.seq.index = first;
+                        }
+                        Playback::start(self.seq.index, fps).looping(first, last)
+                    }
+                    None => Playback::start(self.seq.index, fps),
+                };
+                (playback, frames.min(MAX_LOOKAHEAD))
+            }
+            Some(frames) if frames.is_empty() => return None,
+            Some(frames) => (Playback::start_playlist(frames, fps), 0),
+        };
+        self.set_lookahead(lookahead);
+        Some(playback.with_preroll(preroll))
+    }
+
+    /// Frames the loop region spans, once a marker is set and the ends it falls back
+    /// to are known
+    fn loop_range(&self) -> Option<(i64, i64)> {
+        if self.loop_in.is_none() && self.loop_out.is_none() {
+            return None;
+        }
+        let first = self.loop_in.or(self.bounds.first)?;
+        let last = self.loop_out.or(self.bounds.last)?;
+        (first <= last).then_some((first, last))
+    }
+
+    /// Set the loop's in (or out) point to the current frame, or clear it when it is
+    /// there already. A marker on the wrong side of the other one replaces that one.
+    fn set_loop_marker(&mut self, ctx: &egui::Context, out: bool) {
+        let idx = self.seq.index;
+        let (marker, other) = if out { (&mut self.loop_out, &mut self.loop_in) } else { (&mut self.loop_in, &mut self.loop_out) };
+        if *marker == Some(idx) {
+            *marker = None;
+        } else {
+            *marker = Some(idx);
+            if other.is_some_and(|o| if out { o > idx } else { o < idx }) {
+                *other = None;
+            }
+        }
+        self.status = match (self.loop_in, self.loop_out) {
+            (None, None) => "Loop region cleared".to_string(),
+            (first, last) => {
+                let first = first.map_or("start".to_string(), |i| i.to_string());
+                let last = last.map_or("end".to_string(), |i| i.to_string());
+                format!("Loop region {first}..={last}")
+            }
+        };
+        info!("{}", self.status);
+        self.restart_playback_clock();
+        ctx.request_repaint();
+    }
+
+    fn set_lookahead(&mut self, frames: u64) {
+        self.cache.set_lookahead(frames);
+        if let Some(compare) = &mut self.compare {
+            compare.cache.set_lookahead(frames);
+        }
+    }
+
+    /// Manual navigation while playing continues playback from the new frame
+    fn restart_playback_clock(&mut self) {
+        if let Some(playback) = &self.playback {
+            self.playback = self.begin_playback(playback.fps());
+            if self.playback.is_none() {
+                self.set_lookahead(0);
+                info!("playback stopped: no {} frames ahead", self.playback_filter.label());
+                if let Some(audio) = &mut self.audio {
+                    audio.sync(0.0, false);
+                }
+            }
+        }
+    }
+
+    /// Advance to the frame due on the playback clock and keep the audio in sync
+    fn advance_playback(&mut self, ctx: &egui::Context) {
+        let Some(playback) = &mut self.playback else {
+            return;
+        };
+        // Buffering ahead: start early once the frames the preroll covers are in
+        let ready = self.cache.lookahead_ready(self.seq.index)
+            && self.compare.as_ref().is_none_or(|c| c.cache.lookahead_ready(c.seq.index));
+        let prerolling = playback.is_prerolling() && !ready;
+        if !prerolling {
+            playback.end_preroll();
+        }
+        let position = playback.position();
+        let fps = playback.fps();
+        let wait = playback.until_next_frame();
+        let filtered = playback.is_filtered();
+        let Some(target) = playback.frame() else {
+            info!("playback reached the end of the {} frames", self.playback_filter.label());
+            self.toggle_playback(ctx);
+            return;
+        };
+
+        if target != self.seq.index {
+            if filtered {
+                // Filtered frames were picked from bookmarks; let the loader report gaps
+                self.seq.index = target;
+                self.update_cache_and_status(ctx);
+            } else if
+            !self.bounds.contains(target) || (!self.skip_gaps && !self.interpolate_gaps && self.local_frame_missing(target)) {
+                info!("playback stopped: no frame {}", target);
+                self.toggle_playback(ctx);
+                return;
+            } else if self.interpolate_gaps || !self.local_frame_missing(target) {
+                // With gap skipping on, the last frame stays up through a hole; with
+                // interpolation the hole is shown cross-faded
+                self.seq.index = target;
+                self.update_cache_and_status(ctx);
+            }
+        }
+        let track = self.audio_track();
+        if let (Some(audio), Some(track)) = (self.audio.as_mut(), track) {
+            audio.sync(track.time_at(position, fps), !prerolling);
+        }
+        ctx.request_repaint_after(wait);
+    }
+
+    /// Bookmark list with authors; returns the index to jump to when one is clicked
+    fn bookmarks_section(&self, ui: &mut egui::Ui) -> Option<i64> {
+        let mut jump = None;
+        egui::CollapsingHeader::new(format!("Bookmarks ({})", self.bookmarks.len()))
+            .default_open(true)
+            .show(ui, |ui| {
+                if self.bookmarks.is_empty() {
+                    ui.label("None yet (B bookmarks the current frame)");
+                }
+                for mark in self.bookmarks.iter() {
+                    let mut text = format!("{}  {}", mark.index, mark.author);
+                    if !mark.note.is_empty() {
+                        text.push_str(&format!(": {}", mark.note));
+                    }
+                    let current = mark.index == self.seq.index;
+                    if ui.selectable_label(current, text).clicked() {
+                        jump = Some(mark.index);
+                    }
+                }
+            });
+        jump
+    }
+
+    /// Offer to keep an inferred pattern
+    fn inferred_pattern_prompt(&mut self, ui: &mut egui::Ui) {
+        if !self.pattern_inferred {
+            return;
+        }
+        ui.label(format!("Inferred pattern: {}", self.pattern));
+        if ui.button("Save to config").clicked() {
+            maybe_add_pattern(&mut self.cfg, self.pattern.clone());
+            match save_config(&self.cfg) {
+                Ok(()) => info!("saved pattern {:?} to the config", self.pattern),
+                Err(e) => error!("failed to save the config: {e}"),
+            }
+            self.pattern_inferred = false;
+        }
+        if ui.small_button("Dismiss").clicked() {
+            self.pattern_inferred = false;
+        }
+        ui.separator();
+    }
+
+    /// Zoom (Ctrl+wheel / pinch, +/-), pan (drag while zoomed), reset (Z) and 1:1 physical
+    /// pixels (Shift+Z) for the image area
+    fn handle_viewport_input(&mut self, ui: &egui::Ui, response: &egui::Response, panel: egui::Rect) {
+        if response.dragged() && !self.viewport.is_fit() {
+            self.viewport.pan_by(response.drag_delta());
+        }
+        if response.hovered() {
+            let zoom = ui.input(|i| i.zoom_delta());
+            if zoom != 1.0 {
+                if let Some(pos) = response.hover_pos() {
+                    self.viewport.zoom_at(zoom, pos, panel);
+                }
+            }
+        }
+        if ui.ctx().wants_keyboard_input() {
+            return;
+        }
+        let (zoom_in, zoom_out, reset) = ui.input(|i| {
+            (
+                i.key_pressed(egui::Key::Plus) || i.key_pressed(egui::Key::Equals),
+                i.key_pressed(egui::Key::Minus),
+                i.key_pressed(egui::Key::Z),
+            )
+        });
+        let shift = ui.input(|i| i.modifiers.shift);
+        if zoom_in {
+            self.viewport.zoom_at(1.25, panel.center(), panel);
+        }
+        if zoom_out {
+            self.viewport.zoom_at(0.8, panel.center(), panel);
+        }
+        if reset && shift {
+            if self.one_to_one.take().is_some() {
+                self.viewport.reset();
+            } else {
+                // Applied when the frame is laid out, where its fitted size is known
+                self.one_to_one = Some(self.viewport.zoom);
+            }
+        } else if reset {
+            self.one_to_one = None;
+            self.viewport.reset();
+        }
+    }
+
+    /// In 1:1 mode, zoom so that one frame pixel covers one physical screen pixel, also
+    /// after a resize or a move to a monitor with another scale factor. The image's corner
+    /// is snapped to the physical pixel grid, so that fractional scale factors (125%,
+    /// 150%) do not resample it.
+    fn keep_one_to_one(&mut self, ctx: &egui::Context, panel: egui::Rect, full_size: egui::Vec2) {
+        let Some(applied) = self.one_to_one else {
+            return;
+        };
+        if self.viewport.zoom != applied {
+            self.one_to_one = None;
+            return;
+        }
+        let ppp = ctx.pixels_per_point();
+        let fit = self.fit_size(full_size, panel.size());
+        let fit_scale = (fit.x * fit.y / (full_size.x * full_size.y)).sqrt();
+        let target = 1.0 / (ppp * fit_scale);
+        if (target - self.viewport.zoom).abs() > target * 1e-6 {
+            self.viewport.zoom_at(target / self.viewport.zoom, panel.center(), panel);
+        }
+        let corner = ((panel.min + self.viewport.pan).to_vec2() * ppp).round() / ppp;
+        self.viewport.pan = corner - panel.min.to_vec2();
+        self.one_to_one = Some(self.viewport.zoom);
+    }
+
+    /// Frame steps from the mouse over the image area: wheel = ±1 frame, Shift+wheel = ±step
+    /// size, horizontal drag at fit zoom scrubs frame by frame. Returns (count, step).
+    fn mouse_navigation(&mut self, ui: &egui::Ui, response: &egui::Response) -> Option<(i64, u64)> {
+        if response.drag_started() {
+            self.mouse_steps.reset_scrub();
+        }
+        if response.dragged() && (self.viewport.is_fit() || self.roi_locked()) {
+            let n = self.mouse_steps.scrub(response.drag_delta().x);
+            if n != 0 {
+                return Some((n, 1));
+            }
+        }
+        if !response.hovered() {
+            return None;
+        }
+        let (delta, modifiers) = ui.input(|i| (i.raw_scroll_delta, i.modifiers));
+        // Ctrl/Cmd+wheel zooms; with Shift, egui reports the wheel as horizontal scrolling
+        if modifiers.command || modifiers.ctrl || delta == egui::Vec2::ZERO {
+            return None;
+        }
+        let n = self.mouse_steps.wheel(delta.x + delta.y);
+        let step = if modifiers.shift { self.step_size } else { 1 };
+        (n != 0).then_some((n, step))
+    }
+
+    /// Pixel rect of a frame of `size` that is visible in `panel`, accounting for orientation
+    fn visible_pixel_rect(&self, panel: egui::Rect, image_rect: egui::Rect, size: (u32, u32)) -> Option<PixelRect> {
+        let vis = image_rect.intersect(panel);
+        if vis.width() <= 0.0 || vis.height() <= 0.0 {
+            return None;
+        }
+        let u0 = (vis.min.x - image_rect.min.x) / image_rect.width();
+        let u1 = (vis.max.x - image_rect.min.x) / image_rect.width();
+        let v0 = (vis.min.y - image_rect.min.y) / image_rect.height();
+        let v1 = (vis.max.y - image_rect.min.y) / image_rect.height();
+        let a = self.orientation.display_to_source_uv(u0, v0);
+        let b = self.orientation.display_to_source_uv(u1, v1);
+        let (w, h) = (size.0 as f32, size.1 as f32);
+        Some(PixelRect {
+            x0: (a[0].min(b[0]) * w).floor().max(0.0) as u32,
+            y0: (a[1].min(b[1]) * h).floor().max(0.0) as u32,
+            x1: (a[0].max(b[0]) * w).ceil() as u32,
+            y1: (a[1].max(b[1]) * h).ceil() as u32,
+        })
+    }
+
+    /// Scan the cached frames for stuck pixels in the background; rescan when more frames
+    /// of the current size are cached than the last scan covered
+    fn update_defects(&mut self) {
+        if let Some(rx) = &self.defects_rx {
+            if let Ok(scan) = rx.try_recv() {
+                self.defects_rx = None;
+                if let Some(pixels) = &scan.pixels {
+                    let hot = pixels.iter().filter(|p| p.hot).count();
+                    info!("stuck pixels: {} hot, {} dead in {} frames", hot, pixels.len() - hot, scan.frames);
+                }
+                self.defects = Some(scan);
+            }
+            return;
+        }
+        let Some(size) = self.cache.pixels(self.seq.index).filter(|_| !self.cache.is_preview(self.seq.index)).map(|p| p.dimensions()) else {
+            return;
+        };
+        let frames: Vec<Arc<FramePixels>> = self.cache.full_frames().filter(|f| f.dimensions() == size).cloned().collect();
+        let covered = self.defects.as_ref().filter(|d| d.size == size).map_or(0, |d| d.frames);
+        if frames.len() <= covered {
+            return;
+        }
+        let (tx, rx) = channel();
+        std::thread::spawn(move || {
+            let pixels = detect_stuck_pixels(&frames);
+            let _ = tx.send(DefectScan { frames: frames.len(), size, pixels });
+        });
+        self.defects_rx = Some(rx);
+    }
+
+    /// Mark stuck pixels on the displayed frame: red rings for hot, cyan for dead
+    fn paint_defects(&self, painter: &egui::Painter, image_rect: egui::Rect) {
+        let Some(DefectScan { size: (w, h), pixels: Some(pixels), .. }) = &self.defects else {
+            return;
+        };
+        if self.cache.display_size(self.seq.index) != Some(egui::vec2(*w as f32, *h as f32)) {
+            return;
+        }
+        let pixel_size = image_rect.width() / if self.orientation.swaps_axes() { *h } else { *w } as f32;
+        let radius = (pixel_size * 0.75).max(4.0);
+        for p in pixels {
+            let s = (p.x as f32 + 0.5) / *w as f32;
+            let t = (p.y as f32 + 0.5) / *h as f32;
+            let [u, v] = self.orientation.source_to_display_uv(s, t);
+            let pos = image_rect.min + egui::vec2(u * image_rect.width(), v * image_rect.height());
+            let color = if p.hot { egui::Color32::RED } else { egui::Color32::from_rgb(0, 220, 255) };
+            painter.circle_stroke(pos, radius, egui::Stroke::new(1.5, color));
+        }
+    }
+
+    /// Append the pixel under `pos` to the sample list (full-resolution frames only)
+    fn pick_sample(&mut self, pos: egui::Pos2, image_rect: egui::Rect) {
+        let idx = self.seq.index;
+        if self.cache.is_preview(idx) {
+            self.status = "Wait for the full-resolution frame to sample it".to_string();
+            return;
+        }
+        let (Some(pixels), Some(size)) = (self.cache.pixels(idx), self.cache.full_size(idx)) else {
+            return;
+        };
+        let u = (pos.x - image_rect.min.x) / image_rect.width();
+        let v = (pos.y - image_rect.min.y) / image_rect.height();
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return;
+        }
+        let [su, sv] = self.orientation.display_to_source_uv(u, v);
+        let x = ((su * size.0 as f32) as u32).min(size.0.saturating_sub(1));
+        let y = ((sv * size.1 as f32) as u32).min(size.1.saturating_sub(1));
+        // Region decodes hold the pixels from the region's corner on
+        let [x0, y0, ..] = self.cache.region(idx).map_or([0; 4], |r| r.bounds);
+        if let Some(rgba) = x.checked_sub(x0).zip(y.checked_sub(y0)).and_then(|(rx, ry)| pixels.pixel_rgba(rx, ry)) {
+            // The file's values are not downscaled by max_decode_dim
+            let value = self.source_values.as_ref().filter(|(i, _)| *i == idx).and_then(|(_, v)| v.as_deref()).and_then(|v| {
+                let (w, h) = v.dimensions();
+                v.value(((su * w as f32) as u32).min(w.saturating_sub(1)), ((sv * h as f32) as u32).min(h.saturating_sub(1)))
+            });
+            debug!("sample on frame {} at ({}, {}) = {:?} {:?}", idx, x, y, rgba, value);
+            self.samples.push(Sample { index: idx, x, y, rgba, value });
+        }
+    }
+
+    /// Decode the current frame again in the background, keeping the file's 16-bit or
+    /// float values for the picker (not while playing)
+    fn update_source_values(&mut self, ctx: &egui::Context) {
+        if let Some(rx) = &self.source_values_rx {
+            if let Ok(values) = rx.try_recv() {
+                self.source_values_rx = None;
+                self.source_values = Some(values);
+            }
+            return;
+        }
+        let idx = self.seq.index;
+        if self.playback.is_some() || self.source_values.as_ref().is_some_and(|(i, _)| *i == idx) {
+            return;
+        }
+        let (tx, rx) = channel();
+        let seq = self.seq.clone();
+        let request_tx = self.remote_tx.clone();
+        let honor_exif = self.cache.decode_options().honor_exif_orientation;
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let values = seq
+                .fetch_bytes(idx, request_tx.as_ref())
+                .and_then(|bytes| SourceValues::decode(&bytes, &seq.path_display(idx), honor_exif))
+                .unwrap_or_else(|e| {
+                    debug!("no source values for frame {}: {}", idx, e);
+                    None
+                });
+            let _ = tx.send((idx, values.map(Arc::new)));
+            ctx.request_repaint();
+        });
+        self.source_values_rx = Some(rx);
+    }
+
+    /// Open the fetch panel (M), offering the known range and a folder named after the source
+    fn toggle_fetch_panel(&mut self) {
+        self.show_fetch = !self.show_fetch;
+        if !self.show_fetch || self.fetch.is_some() {
+            return;
+        }
+        let idx = self.seq.index;
+        self.fetch_range = (self.bounds.first.unwrap_or(idx), self.bounds.last.unwrap_or(idx));
+        if self.fetch_dest.is_empty() {
+            let dir = match &self.seq.source {
+                SequenceSource::Local(dir) => dir.display().to_string(),
+                SequenceSource::Remote { dir, .. } => dir.clone(),
+                SequenceSource::Http { base_url } => base_url.clone(),
+                SequenceSource::Video { path, .. } | SequenceSource::Animation { path, .. } => path.with_extension("").display().to_string(),
+            };
+            let name = std::path::Path::new(&dir).file_name().and_then(|n| n.to_str()).unwrap_or("frames");
+            self.fetch_dest = name.to_string();
+        }
+    }
+
+    /// Copy the range picked in the fetch panel to its folder on a thread of its own. Remote
+    /// frames come through a separate remote worker, so the fetch neither waits behind
+    /// nor gets cancelled by the viewer's cache window.
+    fn start_fetch(&mut self) {
+        let (first, last) = (self.fetch_range.0.min(self.fetch_range.1), self.fetch_range.0.max(self.fetch_range.1));
+        let dest = PathBuf::from(self.fetch_dest.trim());
+        if let Err(e) = std::fs::create_dir_all(&dest) {
+            self.status = format!("Could not create {}: {e}", dest.display());
+            return;
+        }
+        let range = RemoteRange::new();
+        range.set(first, last);
+        let request_tx = spawn_source_worker(&self.cfg, &self.seq.source, range, RemoteStats::new());
+        info!("fetching frames {}..={} of {} to {}", first, last, self.seq.source.location(), dest.display());
+        let progress = MirrorProgress::new();
+        let thread = {
+            let (seq, dest, progress, threads) = (self.seq.clone(), dest.clone(), progress.clone(), self.cfg.remote_channels);
+            // The worker's sender goes with the thread; its SSH sessions close when it ends
+            std::thread::spawn(move || mirror_range(&seq, first..=last, &dest, request_tx.as_ref(), threads, &progress))
+        };
+        self.fetch = Some(Fetch {
+            pattern: self.pattern.clone(),
+            seq: self.seq.clone(),
+            dest,
+            total: last.abs_diff(first) + 1,
+            progress,
+            thread: Some(thread),
+            finished: None,
+            shown: 0,
+        });
+    }
+
+    /// Collect the result of a fetch whose thread is done; keeps repainting while one runs
+    fn poll_fetch(&mut self, ctx: &egui::Context) {
+        let Some(fetch) = &mut self.fetch else {
+            return;
+        };
+        if !fetch.thread.as_ref().is_some_and(|t| t.is_finished()) {
+            if fetch.thread.is_some() {
+                let done = fetch.progress.snapshot().done;
+                if done != fetch.shown {
+                    fetch.shown = done;
+                    self.refresh_status();
+                }
+                ctx.request_repaint_after(Duration::from_millis(250));
+            }
+            return;
+        }
+        let done = fetch.thread.take().and_then(|t| t.join().ok()).unwrap_or_default();
+        info!("fetch finished: {:?}", done);
+        self.status = format!("Fetched {} frames to {}", done.written + done.present, fetch.dest.display());
+        fetch.finished = Some(done);
+    }
+
+    /// Fetch panel: range, folder and progress of the mirror to local disk (shown after M)
+    fn fetch_panel(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        ui.heading("Fetch to local disk");
+        ui.label("Copies frames as they are. Frames already in the folder are kept, so a stopped fetch can be resumed. M closes the panel.");
+        let running = self.fetch.as_ref().is_some_and(|f| f.thread.is_some());
+        let frames = self.seq.min_index()..=MAX_INDEX;
+        ui.add_enabled_ui(!running, |ui| {
+            egui::Grid::new("fetch").num_columns(2).show(ui, |ui| {
+                ui.label("From");
+                ui.add(egui::DragValue::new(&mut self.fetch_range.0).range(frames.clone()));
+                ui.end_row();
+                ui.label("To");
+                ui.add(egui::DragValue::new(&mut self.fetch_range.1).range(frames));
+                ui.end_row();
+                ui.label("Folder");
+                ui.text_edit_singleline(&mut self.fetch_dest);
+                ui.end_row();
+            });
+        });
+        if running {
+            if ui.button("Cancel").clicked() {
+                if let Some(fetch) = &self.fetch {
+                    fetch.progress.cancel();
+                }
+            }
+        } else if ui.add_enabled(!self.fetch_dest.trim().is_empty(), egui::Button::new("Fetch")).clicked() {
+            self.start_fetch();
+        }
+        let Some(fetch) = &self.fetch else {
+            return;
+        };
+        ui.separator();
+        let snap = fetch.finished.clone().unwrap_or_else(|| fetch.progress.snapshot());
+        ui.add(
+            egui::ProgressBar::new(snap.done as f32 / fetch.total.max(1) as f32)
+                .text(format!("{} / {}", snap.done, fetch.total)),
+        );
+        ui.label(format!(
+            "{} written ({:.1} MB), {} already there, {} missing, {} failed",
+            snap.written,
+            snap.bytes as f64 / 1_000_000.0,
+            snap.present,
+            snap.missing,
+            snap.failed
+        ));
+        if let Some(e) = &snap.last_error {
+            ui.colored_label(egui::Color32::RED, e);
+        }
+        if fetch.finished.is_some() && snap.written + snap.present > 0 {
+            let local = matches!(&self.seq.source, SequenceSource::Local(dir) if *dir == fetch.dest);
+            if !local && ui.button("Open local copy").clicked() {
+                let mut seq = mirrored_sequence(&fetch.seq, &fetch.dest);
+                seq.index = self.seq.index;
+                info!("switching to the fetched copy in {}", fetch.dest.display());
+                self.switch_sequence(ctx, fetch.pattern.clone(), seq);
+            }
+        }
+    }
+
+    /// Picked samples with CSV export (shown while the picker is active)
+    fn samples_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading(format!("Samples ({})", self.samples.len()));
+        ui.label("Click the image to sample a pixel. I closes the picker.");
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.sample_export_path);
+            if ui.button("Export CSV").clicked() {
+                let path = std::path::Path::new(&self.sample_export_path);
+                self.status = match self.samples.save_csv(path) {
+                    Ok(()) => format!("Saved {} samples to {}", self.samples.len(), path.display()),
+                    Err(e) => format!("{e:#}"),
+                };
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Copy CSV").clicked() {
+                ui.ctx().copy_text(self.samples.to_csv());
+            }
+            if ui.button("Clear").clicked() {
+                self.samples.clear();
+            }
+        });
+        ui.separator();
+        if self.samples.is_empty() {
+            ui.weak("No samples yet.");
+        }
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("samples").num_columns(5).striped(true).show(ui, |ui| {
+                for s in self.samples.iter().rev() {
+                    let [r, g, b, a] = s.rgba;
+                    let (swatch, _) = ui.allocate_exact_size(egui::vec2(14.0, 14.0), egui::Sense::hover());
+                    ui.painter().rect_filled(swatch, 2.0, egui::Color32::from_rgba_unmultiplied(r, g, b, a));
+                    ui.label(s.index.to_string());
+                    ui.label(format!("{}, {}", s.x, s.y));
+                    ui.monospace(format!("{r:3} {g:3} {b:3} {a:3}"));
+                    match s.value {
+                        Some(value) => ui.monospace(value.to_string()).on_hover_text("Value in the file"),
+                        None => ui.label(""),
+                    };
+                    ui.end_row();
+                }
+            });
+        });
+    }
+
+    /// Recompute visible-region statistics once zoom/pan has settled
+    fn update_stats(&mut self, ctx: &egui::Context, panel: egui::Rect, image_rect: egui::Rect) {
+        if !self.viewport.settled(STATS_SETTLE) {
+            ctx.request_repaint_after(STATS_SETTLE);
+            return;
+        }
+        let idx = self.seq.index;
+        let (Some(pixels), Some(size)) = (self.cache.pixels(idx).cloned(), self.cache.full_size(idx)) else {
+            self.stats = None;
+            return;
+        };
+        let Some(rect) = self.visible_pixel_rect(panel, image_rect, size) else {
+            self.stats = None;
+            return;
+        };
+        let fresh = self
+            .stats
+            .as_ref()
+            .is_some_and(|s| s.idx == idx && s.rect == rect && Arc::ptr_eq(&s.source, &pixels));
+        if fresh {
+            return;
+        }
+        // Region decodes hold the pixels from the region's corner on
+        let [x0, y0, ..] = self.cache.region(idx).map_or([0; 4], |r| r.bounds);
+        let local = PixelRect {
+            x0: rect.x0.saturating_sub(x0),
+            y0: rect.y0.saturating_sub(y0),
+            x1: rect.x1.saturating_sub(x0),
+            y1: rect.y1.saturating_sub(y0),
+        };
+        self.stats = region_stats(&pixels.to_rgba(), local).map(|channels| RegionStats {
+            idx,
+            source: pixels,
+            rect,
+            channels,
+        });
+    }
+
+    fn stats_overlay(&self, ctx: &egui::Context, panel: egui::Rect) {
+        let Some(stats) = &self.stats else {
+            return;
+        };
+        let preview = self.cache.is_preview(stats.idx);
+        egui::Area::new(egui::Id::new("stats_overlay"))
+            .order(egui::Order::Foreground)
+            .pivot(egui::Align2::RIGHT_TOP)
+            .fixed_pos(panel.right_top() + egui::vec2(-8.0, 8.0))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(format!(
+                        "Region {}x{} at ({}, {}){}",
+                        stats.rect.width(),
+                        stats.rect.height(),
+                        stats.rect.x0,
+                        stats.rect.y0,
+                        if preview { " (preview)" } else { "" }
+                    ));
+                    egui::Grid::new("stats_grid").num_columns(5).striped(true).show(ui, |ui| {
+                        for h in ["", "min", "max", "mean", "std"] {
+                            ui.strong(h);
+                        }
+                        ui.end_row();
+                        for (name, s) in ["R", "G", "B", "A"].iter().zip(stats.channels.iter()) {
+                            ui.strong(*name);
+                            ui.monospace(s.min.to_string());
+                            ui.monospace(s.max.to_string());
+                            ui.monospace(format!("{:.2}", s.mean));
+                            ui.monospace(format!("{:.2}", s.stddev));
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+    }
+
+    /// Cache window, load timings with a graph of the latest ones, SSH queue and texture
+    /// memory, in the bottom-left corner of the image area
+    fn perf_overlay(&self, ctx: &egui::Context, area: egui::Rect) {
+        let ms = |d: Duration| format!("{:.1} ms", d.as_secs_f64() * 1000.0);
+        let timings: Vec<LoadTiming> = self.cache.timings().copied().collect();
+        let textures = self.cache.texture_bytes() + self.compare.as_ref().map_or(0, |c| c.cache.texture_bytes());
+        egui::Area::new(egui::Id::new("perf_overlay"))
+            .order(egui::Order::Foreground)
+            .pivot(egui::Align2::LEFT_BOTTOM)
+            .fixed_pos(area.left_bottom() + egui::vec2(8.0, -8.0))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    egui::Grid::new("perf_grid").num_columns(2).show(ui, |ui| {
+                        ui.label("Window");
+                        ui.monospace(match self.cache.window() {
+                            Some(w) => format!("{}..={} around {}", w.start(), w.end(), self.seq.index),
+                            None => "-".to_string(),
+                        });
+                        ui.end_row();
+                        ui.label("Radius");
+                        ui.monospace(if self.cache.is_radius_adaptive() {
+                            format!("{} (adaptive, from {})", self.cache.radius(), self.cfg.cache_radius)
+                        } else {
+                            self.cache.radius().to_string()
+                        });
+                        ui.end_row();
+                        ui.label("Cache");
+                        ui.monospace(format!(
+                            "{} loaded, {} previews, {} pending",
+                            self.cache.loaded_count(),
+                            self.cache.preview_count(),
+                            self.cache.pending_count()
+                        ));
+                        ui.end_row();
+                        ui.label("Textures");
+                        ui.monospace(format!("{:.1} MB", textures as f64 / 1_000_000.0));
+                        ui.end_row();
+                        if let Some(last) = timings.last() {
+                            ui.label("Last load");
+                            ui.monospace(match last.fetch {
+                                Some(fetch) => format!("{}: fetch {}, decode {}", last.idx, ms(fetch), ms(last.decode)),
+                                None => format!("{}: read + decode {}", last.idx, ms(last.decode)),
+                            });
+                            ui.end_row();
+                            let n = timings.len() as u32;
+                            let fetch: Duration = timings.iter().filter_map(|t| t.fetch).sum();
+                            let decode: Duration = timings.iter().map(|t| t.decode).sum();
+                            ui.label(format!("Mean of {}", n));
+                            ui.monospace(if fetch.is_zero() {
+                                format!("decode {}", ms(decode / n))
+                            } else {
+                                format!("fetch {}, decode {}", ms(fetch / n), ms(decode / n))
+                            });
+                            ui.end_row();
+                        }
+                        if let Some(stats) = &self.remote_stats {
+                            let s = stats.snapshot();
+                            ui.label("SSH queue");
+                            ui.monospace(format!("{} waiting, {} downloading", s.queued, s.downloads.len()));
+                            ui.end_row();
+                        }
+                    });
+                    if !timings.is_empty() {
+                        timing_graph(ui, &timings);
+                    }
+                });
+            });
+        // The SSH queue changes without anything else asking for a repaint
+        if self.remote_stats.is_some() {
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+    }
+
+    /// Texture to draw for the current frame, applying the channel view (CPU remap) if active
+    fn display_texture(&mut self, ctx: &egui::Context) -> Option<egui::TextureId> {
+        if let Some(tex) = self.onion_texture(ctx) {
+            return Some(tex);
+        }
+        let (view, options) = (self.channel_view, self.texture_options());
+        channel_texture(ctx, &self.cache, self.seq.index, view, options, &mut self.channel_tex, "zapvis_channel_view")
+    }
+
+    /// The current frame with the onion skin's other frame composited over it, when both
+    /// are loaded whole and have the same size
+    fn onion_texture(&mut self, ctx: &egui::Context) -> Option<egui::TextureId> {
+        let onion = self.onion?;
+        let other = onion.other_frame(self.seq.index, self.step_size);
+        if other == self.seq.index || self.cache.region(self.seq.index) != self.cache.region(other) {
+            return None;
+        }
+        let base = self.cache.texture_pixels(self.seq.index)?.clone();
+        let over = self.cache.texture_pixels(other)?.clone();
+        let view = self.channel_view;
+        let stale = self
+            .onion_tex
+            .as_ref()
+            .is_none_or(|t| !Arc::ptr_eq(&t.base, &base) || !Arc::ptr_eq(&t.over, &over) || t.onion != onion || t.view != view);
+        if stale {
+            let composite = onion.composite(&base.to_rgba(), &over.to_rgba())?;
+            let composite = view.remap(&composite).unwrap_or(composite);
+            let tex = rgba_to_texture(ctx, "zapvis_onion", &composite, self.texture_options()).ok()?;
+            self.onion_tex = Some(OnionTexture { base, over, onion, view, tex });
+        }
+        self.onion_tex.as_ref().map(|t| t.tex.id())
+    }
+
+    /// The missing current frame as a cross-fade of the nearest frames loaded whole
+    /// before and after it (within the cache window), with the frame's size
+    fn interpolated_texture(&mut self, ctx: &egui::Context) -> Option<(egui::TextureId, egui::Vec2)> {
+        let idx = self.seq.index;
+        if !self.interpolate_gaps || self.cache.failure(idx).is_none_or(|e| e.kind != FailureKind::NotFound) {
+            return None;
+        }
+        let (Some(before), Some(after)) = self.cache.loaded_around(idx) else {
+            return None;
+        };
+        let (a, b) = (self.cache.texture_pixels(before)?.clone(), self.cache.texture_pixels(after)?.clone());
+        let view = self.channel_view;
+        let stale = self.interpolated_tex.as_ref().is_none_or(|t| {
+            t.idx != idx || t.view != view || !Arc::ptr_eq(&t.before.1, &a) || !Arc::ptr_eq(&t.after.1, &b)
+        });
+        if stale {
+            let blended = crossfade(&a.to_rgba(), &b.to_rgba(), fade_position(before, after, idx))?;
+            let blended = view.remap(&blended).unwrap_or(blended);
+            let tex = rgba_to_texture(ctx, "zapvis_interpolated", &blended, self.texture_options()).ok()?;
+            self.interpolated_tex = Some(InterpolatedTexture {
+                idx,
+                before: (before, a),
+                after: (after, b),
+                view,
+                tex,
+            });
+        }
+        let (w, h) = self.interpolated_tex.as_ref()?.before.1.dimensions();
+        Some((self.interpolated_tex.as_ref()?.tex.id(), egui::vec2(w as f32, h as f32)))
+    }
+
+    /// Turn the onion skin on or off (Shift+O); it starts on the frame a step back
+    fn toggle_onion(&mut self, ctx: &egui::Context) {
+        if self.onion.is_some() {
+            self.set_onion_pin(None);
+            self.onion = None;
+            self.onion_tex = None;
+            return;
+        }
+        self.stop_blink();
+        self.onion = Some(OnionSkin::default());
+        self.update_cache_and_status(ctx);
+    }
+
+    /// Lay `pinned` over every frame, or (`None`) the frame a step back. A pinned frame
+    /// is kept in the cache wherever the index goes.
+    fn set_onion_pin(&mut self, pinned: Option<i64>) {
+        let Some(onion) = &mut self.onion else {
+            return;
+        };
+        onion.pinned = pinned;
+        self.cache.set_pinned(&Vec::from_iter(pinned));
+    }
+
+    /// Same as `display_texture`, for the compared sequence
+    fn compare_texture(&mut self, ctx: &egui::Context) -> Option<egui::TextureId> {
+        let (view, options) = (self.channel_view, self.texture_options());
+        let c = self.compare.as_mut()?;
+        channel_texture(ctx, &c.cache, c.seq.index, view, options, &mut c.channel_tex, "zapvis_compare_channel_view")
+    }
+
+    /// Size of the image laid out to fit `avail`, accounting for rotation
+    fn fit_size(&self, full_size: egui::Vec2, avail: egui::Vec2) -> egui::Vec2 {
+        let mut tex_size = full_size;
+        if self.orientation.swaps_axes() {
+            tex_size = egui::vec2(tex_size.y, tex_size.x);
+        }
+        // In fullscreen mode, allow scaling up to fill the window
+        // In normal mode, cap at 1.0x to avoid upscaling
+        let scale = if self.is_fullscreen {
+            (avail.x / tex_size.x).min(avail.y / tex_size.y)
+        } else {
+            (avail.x / tex_size.x).min(avail.y / tex_size.y).min(1.0)
+        };
+        tex_size * scale
+    }
+
+    /// Right half of the split view: the compared sequence with the same zoom, pan and
+    /// orientation, with `tiles` over its texture if the frame is tiled
+    fn paint_compare(&self, ui: &egui::Ui, panel: egui::Rect, tex: Option<egui::TextureId>, tiles: &[([u32; 4], egui::TextureId)]) {
+        let Some(compare) = &self.compare else {
+            return;
+        };
+        let idx = compare.seq.index;
+        let painter = ui.painter_at(panel);
+        let path = compare.seq.path_display(idx);
+        match (tex, compare.cache.display_size(idx)) {
+            (Some(tex), Some(size)) => {
+                let rect = self.viewport.image_rect(panel, self.fit_size(size, panel.size()));
+                let map = self.frame_mapping(rect, size);
+                self.paint_background(&painter, rect);
+                paint_frame(&painter, &map, tex, compare.cache.region(idx), &self.grade);
+                paint_tiles(&painter, &map, tiles, &self.grade);
+                if self.nearest {
+                    self.paint_pixel_grid(&painter, rect, size);
+                }
+                if self.show_guides {
+                    paint_guides(&painter, rect, self.visible_guides());
+                }
+            }
+            _ => {
+                let text = if compare.cache.is_pending(idx) {
+                    "Loading…".to_string()
+                } else if compare.cache.is_tiled(idx) {
+                    "Preparing overview…".to_string()
+                } else {
+                    compare.cache.failure(idx).map_or_else(|| "Not found / failed".to_string(), failure_text)
+                };
+                placeholder(ui, panel, &text);
+            }
+        }
+        caption(&painter, panel, &path);
+    }
+
+    /// Decode only the part of large TIFFs around what `map` shows in `panel`, once zoom
+    /// and pan have settled (see [`zapvis::frame_cache::FrameCache::set_decode_region`]).
+    /// The compared sequence follows when its frames have the same size.
+    fn update_decode_region(&mut self, ctx: &egui::Context, map: &FrameMapping, panel: egui::Rect) {
+        if !self.cfg.roi_decoding {
+            return;
+        }
+        if !self.viewport.settled(STATS_SETTLE) {
+            ctx.request_repaint_after(STATS_SETTLE);
+            return;
+        }
+        let max_side = TILED_ABOVE.min(ctx.input(|i| i.max_texture_side) as u32);
+        let region = decode_region(visible_frame_bounds(map, panel), map.size, self.cache.decode_region(), max_side);
+        self.cache.set_decode_region(region);
+        if let Some(c) = &mut self.compare {
+            let same_size = c.cache.display_size(c.seq.index) == Some(map.size);
+            c.cache.set_decode_region(region.filter(|_| same_size));
+        }
+    }
+
+    /// Where a frame of `size` laid out in `image_rect` lands on screen
+    fn frame_mapping(&self, image_rect: egui::Rect, size: egui::Vec2) -> FrameMapping {
+        FrameMapping {
+            image_rect,
+            size,
+            orientation: self.orientation,
+        }
+    }
+
+    /// Apply an orientation change and persist it for this sequence.
+    fn change_orientation(&mut self, f: impl FnOnce(&mut Orientation)) {
+        f(&mut self.orientation);
+        info!("orientation now {}", self.orientation.describe());
+        let key = self.seq.sequence_key(&self.pattern);
+        // Only a change from what the directory asks for is saved as the viewer's own
+        if self.orientation == self.dir_config.orientation.unwrap_or_default() {
+            self.cfg.orientations.remove(&key);
+        } else {
+            self.cfg.orientations.insert(key, self.orientation);
+        }
+        save_config(&self.cfg).ok(); // ignore save errors (orientation still applies)
+    }
+
+    /// Toggle the guides overlay and remember the choice for this sequence.
+    fn toggle_guides(&mut self) {
+        self.show_guides = !self.show_guides;
+        let key = self.seq.sequence_key(&self.pattern);
+        self.cfg.set_guides_shown(&key, self.show_guides);
+        save_config(&self.cfg).ok(); // ignore save errors (the overlay still toggles)
+    }
+
+    /// The configured guides not turned off for this sequence
+    fn visible_guides(&self) -> impl Iterator<Item = &Guide> {
+        self.cfg.guides.iter().filter(|g| !self.hidden_guides.contains(g))
+    }
+
+    /// Turn the `n`-th configured guide off or back on for this sequence (Alt+1..9),
+    /// showing the overlay if it is off
+    fn toggle_guide(&mut self, n: usize) {
+        let Some(&guide) = self.cfg.guides.get(n) else {
+            return;
+        };
+        match self.hidden_guides.iter().position(|g| *g == guide) {
+            Some(i) => {
+                self.hidden_guides.remove(i);
+            }
+            None if self.show_guides => self.hidden_guides.push(guide),
+            None => {}
+        }
+        let key = self.seq.sequence_key(&self.pattern);
+        self.cfg.set_hidden_guides(&key, self.hidden_guides.clone());
+        if self.show_guides {
+            save_config(&self.cfg).ok(); // ignore save errors (the overlay still toggles)
+        } else {
+            self.toggle_guides();
+        }
+    }
+
+    /// Switch between linear and nearest-neighbor filtering and remember the choice for
+    /// this sequence; the textures are uploaded again with the new filter.
+    fn toggle_nearest(&mut self, ctx: &egui::Context) {
+        self.nearest = !self.nearest;
+        info!("nearest-neighbor filtering: {}", self.nearest);
+        let options = self.texture_options();
+        self.cache.set_texture_options(ctx, options);
+        self.channel_tex = None;
+        if let Some(compare) = &mut self.compare {
+            compare.cache.set_texture_options(ctx, options);
+            compare.channel_tex = None;
+        }
+        let key = self.seq.sequence_key(&self.pattern);
+        self.cfg.set_nearest_filtering(&key, self.nearest);
+        save_config(&self.cfg).ok(); // ignore save errors (the filter still changes)
+    }
+
+    /// Outline the pixels of a `full_size` frame laid out in `image_rect` once each is at
+    /// least [`PIXEL_GRID_MIN_ZOOM`] points across. Only the visible lines are drawn.
+    fn paint_pixel_grid(&self, painter: &egui::Painter, image_rect: egui::Rect, full_size: egui::Vec2) {
+        let (cols, rows) = if self.orientation.swaps_axes() { (full_size.y, full_size.x) } else { (full_size.x, full_size.y) };
+        let pixel_size = image_rect.width() / cols;
+        if pixel_size < PIXEL_GRID_MIN_ZOOM || !pixel_size.is_finite() {
+            return;
+        }
+        let stroke = egui::Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(128, 128, 128, 110));
+        let clip = painter.clip_rect().intersect(image_rect);
+        let lines = |lo: f32, hi: f32, start: f32, n: f32| {
+            let first = ((lo - start) / pixel_size).floor().max(0.0) as u32;
+            let last = ((hi - start) / pixel_size).ceil().clamp(0.0, n) as u32;
+            (first..=last).map(move |k| start + k as f32 * pixel_size)
+        };
+        for x in lines(clip.left(), clip.right(), image_rect.left(), cols) {
+            painter.vline(x, clip.y_range(), stroke);
+        }
+        for y in lines(clip.top(), clip.bottom(), image_rect.top(), rows) {
+            painter.hline(clip.x_range(), y, stroke);
+        }
+    }
+
+    fn texture_options(&self) -> egui::TextureOptions {
+        if self.nearest {
+            egui::TextureOptions::NEAREST
+        } else {
+            egui::TextureOptions::LINEAR
+        }
+    }
+
+    /// Replace this sequence's crop and persist it (`None` clears it).
+    fn set_crop(&mut self, crop: Option<CropRect>) {
+        self.crop = crop;
+        info!("crop {:?}", crop);
+        let key = self.seq.sequence_key(&self.pattern);
+        self.cfg.set_crop(&key, crop);
+        save_config(&self.cfg).ok(); // ignore save errors (the preview still applies)
+    }
+
+    /// Shift+drag over the image draws a new crop while the crop preview is on. Returns
+    /// true while one is being drawn, so the drag neither pans nor scrubs.
+    fn crop_input(&mut self, ui: &egui::Ui, response: &egui::Response, panel: egui::Rect) -> bool {
+        if response.drag_started() && ui.input(|i| i.modifiers.shift) {
+            self.crop_drag = response.interact_pointer_pos();
+        }
+        let Some(start) = self.crop_drag else {
+            return false;
+        };
+        let size = self.cache.display_size(self.seq.index);
+        if let (Some(size), Some(end)) = (size, response.interact_pointer_pos()) {
+            let image_rect = self.viewport.image_rect(panel, self.fit_size(size, panel.size()));
+            self.crop = self.display_to_crop(egui::Rect::from_two_pos(start, end), image_rect, size);
+        }
+        if response.drag_stopped() {
+            self.crop_drag = None;
+            self.set_crop(self.crop);
+        }
+        true
+    }
+
+    /// Crop in frame pixels for a selection on screen (clipped to the image)
+    fn display_to_crop(&self, selection: egui::Rect, image_rect: egui::Rect, size: egui::Vec2) -> Option<CropRect> {
+        let source_uv = |p: egui::Pos2| {
+            let u = ((p.x - image_rect.min.x) / image_rect.width()).clamp(0.0, 1.0);
+            let v = ((p.y - image_rect.min.y) / image_rect.height()).clamp(0.0, 1.0);
+            self.orientation.display_to_source_uv(u, v)
+        };
+        let (a, b) = (source_uv(selection.min), source_uv(selection.max));
+        let x0 = (a[0].min(b[0]) * size.x).round() as u32;
+        let x1 = (a[0].max(b[0]) * size.x).round() as u32;
+        let y0 = (a[1].min(b[1]) * size.y).round() as u32;
+        let y1 = (a[1].max(b[1]) * size.y).round() as u32;
+        (x1 > x0 && y1 > y0).then_some(CropRect {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        })
+    }
+
+    /// Letterbox everything outside the crop on the displayed frame
+    fn paint_crop(&self, painter: &egui::Painter, image_rect: egui::Rect) {
+        let (Some(crop), Some(size)) = (self.crop, self.cache.display_size(self.seq.index)) else {
+            return;
+        };
+        let at = |s: f32, t: f32| {
+            let [u, v] = self.orientation.source_to_display_uv(s, t);
+            image_rect.min + egui::vec2(u * image_rect.width(), v * image_rect.height())
+        };
+        let a = at(crop.x as f32 / size.x, crop.y as f32 / size.y);
+        let b = at((crop.x + crop.width) as f32 / size.x, (crop.y + crop.height) as f32 / size.y);
+        let inner = egui::Rect::from_two_pos(a, b).intersect(image_rect);
+        let outer = image_rect;
+        let mask = [
+            egui::Rect::from_min_max(outer.min, egui::pos2(outer.max.x, inner.min.y)),
+            egui::Rect::from_min_max(egui::pos2(outer.min.x, inner.max.y), outer.max),
+            egui::Rect::from_min_max(egui::pos2(outer.min.x, inner.min.y), egui::pos2(inner.min.x, inner.max.y)),
+            egui::Rect::from_min_max(egui::pos2(inner.max.x, inner.min.y), egui::pos2(outer.max.x, inner.max.y)),
+        ];
+        for rect in mask.into_iter().filter(|r| r.is_positive()) {
+            painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(220));
+        }
+        painter.rect_stroke(inner, 0.0, egui::Stroke::new(1.0, egui::Color32::WHITE));
+    }
+
+    /// True while the view follows the crop instead of zoom and pan
+    fn roi_locked(&self) -> bool {
+        self.roi_lock && self.crop.is_some()
+    }
+
+    /// Lock the view to the crop (or unlock it back to the fitted frame)
+    fn toggle_roi_lock(&mut self) {
+        if !self.roi_lock && self.crop.is_none() {
+            self.status = "No crop to lock to: draw one with K and Shift+drag".to_string();
+            return;
+        }
+        self.roi_lock = !self.roi_lock;
+        self.viewport.reset();
+    }
+
+    /// While the ROI lock is on, zoom and pan so the crop fills the panel, whatever the
+    /// frame and view orientation (not while a new crop is being drawn)
+    fn keep_roi_lock(&mut self, panel: egui::Rect, full_size: egui::Vec2) {
+        let Some(crop) = self.crop.filter(|_| self.roi_lock && self.crop_drag.is_none()) else {
+            return;
+        };
+        let fit = self.fit_size(full_size, panel.size());
+        let a = self.orientation.source_to_display_uv(crop.x as f32 / full_size.x, crop.y as f32 / full_size.y);
+        let b = self.orientation.source_to_display_uv(
+            (crop.x + crop.width) as f32 / full_size.x,
+            (crop.y + crop.height) as f32 / full_size.y,
+        );
+        let (u0, u1, v0, v1) = (a[0].min(b[0]), a[0].max(b[0]), a[1].min(b[1]), a[1].max(b[1]));
+        let roi = egui::vec2((u1 - u0) * fit.x, (v1 - v0) * fit.y);
+        if roi.x <= 0.0 || roi.y <= 0.0 {
+            return;
+        }
+        let zoom = (panel.width() / roi.x).min(panel.height() / roi.y);
+        let center = egui::vec2((u0 + u1) / 2.0 * fit.x, (v0 + v1) / 2.0 * fit.y) * zoom;
+        self.viewport.zoom = zoom;
+        self.viewport.pan = panel.size() / 2.0 - center;
+    }
+
+    /// Write the loop region (else the known frames) cropped to the ROI as PNGs into a
+    /// picked folder, in the background with a remote worker of its own
+    fn start_roi_export(&mut self, ctx: &egui::Context) {
+        let Some(crop) = self.crop else {
+            return;
+        };
+        let idx = self.seq.index;
+        let (first, last) = self
+            .loop_range()
+            .unwrap_or((self.bounds.first.unwrap_or(idx), self.bounds.last.unwrap_or(idx)));
+        let Some(dest) = rfd::FileDialog::new().set_title("Export ROI to folder").pick_folder() else {
+            return;
+        };
+        let range = RemoteRange::new();
+        range.set(first, last);
+        let request_tx = spawn_source_worker(&self.cfg, &self.seq.source, range, RemoteStats::new());
+        let (tx, rx) = channel();
+        let (seq, opts, command) = (self.seq.clone(), self.cache.decode_options(), self.cfg.loader_command_for(&self.pattern).map(str::to_string));
+        let ctx = ctx.clone();
+        info!("exporting frames {}..={} cropped to {:?} to {}", first, last, crop, dest.display());
+        self.status = format!("Exporting the ROI of frames {first}..={last} to {}", dest.display());
+        std::thread::spawn(move || {
+            let done = export_cropped(&seq, first..=last, crop, &dest, request_tx.as_ref(), command.as_deref(), opts)
+                .map_err(|e| format!("ROI export failed: {e:#}"));
+            let _ = tx.send(done);
+            ctx.request_repaint();
+        });
+        self.roi_export = Some(rx);
+    }
+
+    fn poll_roi_export(&mut self) {
+        let Some(done) = self.roi_export.as_ref().and_then(|rx| rx.try_recv().ok()) else {
+            return;
+        };
+        self.roi_export = None;
+        self.status = match done {
+            Ok((written, missing)) => format!("Exported the ROI of {written} frames ({missing} missing)"),
+            Err(e) => e,
+        };
+    }
+
+    fn update_cache_and_status(&mut self, ctx: &egui::Context) {
+        self.cache_started = true;
+        let (loaded, evicted) = self.cache.update_for_index(self.seq.index, &self.seq, ctx);
+        self.last_loaded = loaded;
+        self.last_evicted = evicted;
+        if let Some(compare) = &mut self.compare {
+            compare.follow(self.seq.index, ctx);
+        }
+        self.refresh_status();
+    }
+
+    /// Notices for the status bar; the frame's name, position and state are separate fields there
+    fn refresh_status(&mut self) {
+        let idx = self.seq.index;
+        let mut notices = Vec::new();
+        if let Some(d) = self.current_download() {
+            notices.push(format!(
+                "downloading {}% of {:.1} MB",
+                d.received * 100 / d.total.max(1),
+                d.total as f64 / 1_000_000.0
+            ));
+        }
+
+        if let Some(fetch) = self.fetch.as_ref().filter(|f| f.thread.is_some()) {
+            notices.push(format!("fetching {}/{}", fetch.progress.snapshot().done, fetch.total));
+        }
+
+        if self.gap_skipped > 0 {
+            notices.push(format!("skipped {} missing", self.gap_skipped));
+        }
+
+        if let Some(siblings) = self.siblings.as_ref().filter(|s| s.len() > 1) {
+            let key = self.seq.sequence_key(&self.pattern);
+            if let Some(pos) = siblings.iter().position(|(p, s)| s.sequence_key(p) == key) {
+                notices.push(format!("sequence {}/{}", pos + 1, siblings.len()));
+            }
+        }
+
+        if !self.prefetch {
+            notices.push("prefetch paused".to_string());
+        }
+
+        if !self.color_manage {
+            notices.push("raw colors".to_string());
+        } else if !self.tone_map.is_identity() {
+            notices.push(self.tone_map.label());
+        }
+
+        let authors: Vec<&str> = self.bookmarks.at(idx).map(|b| b.author.as_str()).collect();
+        if !authors.is_empty() {
+            notices.push(format!("bookmarked by {}", authors.join(", ")));
+        }
+
+        match self.annotator.file.at(idx).count() {
+            0 => {}
+            1 => notices.push("1 annotation".to_string()),
+            n => notices.push(format!("{} annotations", n)),
+        }
+        self.status = notices.join("  |  ");
+    }
+
+    /// Bottom bar: file name (full path on hover), position, resolution, zoom, cache fill,
+    /// step size and SSH connection, then the notices
+    fn status_bar(&mut self, ui: &mut egui::Ui) {
+        let idx = self.seq.index;
+        let (state, color) = if self.cache.get(idx).is_some() {
+            if self.cache.is_preview(idx) { ("preview", None) } else { ("loaded", None) }
+        } else if self.cache.is_pending(idx) {
+            ("loading", Some(egui::Color32::YELLOW))
+        } else if let Some(error) = self.cache.failure(idx) {
+            (error.kind.label(), Some(egui::Color32::RED))
+        } else {
+            ("not found / failed", Some(egui::Color32::RED))
+        };
+        let detail = self.cache.failure(idx).map_or(state, |e| e.message.as_str());
+        ui.strong(self.seq.file_name_for(idx))
+            .on_hover_text(format!("{}\n{}", self.seq.path_display(idx), detail));
+        if state != "loaded" {
+            match color {
+                Some(color) => ui.colored_label(color, state).on_hover_text(detail),
+                None => ui.weak(state),
+            };
+        }
+        ui.separator();
+        ui.label(match self.bounds.last {
+            Some(last) => format!("frame {} / {}", idx, last),
+            None => format!("frame {}", idx),
+        });
+        self.scrubber(ui);
+        ui.separator();
+        ui.label(match self.cache.display_size(idx) {
+            Some(size) => format!("{}×{}", size.x, size.y),
+            None => "-×-".to_string(),
+        });
+        ui.separator();
+        // In screen pixels: on a 200% display, a frame shown at its size in points is at 200%
+        let ppp = ui.ctx().pixels_per_point();
+        let one_to_one = if self.one_to_one.is_some() { " (1:1)" } else { "" };
+        ui.label(match self.display_zoom {
+            Some(zoom) => format!("{:.0}%{one_to_one}", zoom * ppp * 100.0),
+            None => "-%".to_string(),
+        })
+        .on_hover_text(format!("Displayed size relative to the frame's pixels, in physical screen pixels (display scale {:.0}%)", ppp * 100.0));
+        ui.separator();
+        let window = 2 * self.cache.radius() + 1;
+        let (full, previews, pending) = (self.cache.loaded_count(), self.cache.preview_count(), self.cache.pending_count());
+        let held = (full + previews).min(window);
+        ui.add(
+            egui::ProgressBar::new(held as f32 / window as f32)
+                .desired_width(70.0)
+                .text(format!("cache {}/{}", held, window)),
+        )
+        .on_hover_text(format!("{} full, {} previews, {} loading", full, previews, pending));
+        ui.separator();
+        ui.label(format!("step {}", self.step_size));
+        if self.seq.source.is_remote() {
+            ui.separator();
+            self.connection_indicator(ui);
+        }
+        if !self.status.is_empty() {
+            ui.separator();
+            ui.label(&self.status);
+        }
+    }
+
+    /// The remote worker's CAT of the frame on screen, if one is in progress and its full tier is still missing
+    fn current_download(&self) -> Option<Download> {
+        let idx = self.seq.index;
+        let download = self.remote_stats.as_ref()?.snapshot().downloads.into_iter().find(|d| d.idx == idx)?;
+        (self.cache.get(idx).is_none() || self.cache.is_preview(idx)).then_some(download)
+    }
+
+    /// Request frames that failed to load again: `idx` (with its compare frame) or all of them
+    fn retry_failed(&mut self, ctx: &egui::Context, idx: Option<i64>) {
+        let mut retried = match idx {
+            Some(idx) => usize::from(self.cache.retry(idx)),
+            None => self.cache.retry_all(),
+        };
+        if let Some(compare) = &mut self.compare {
+            retried += match idx {
+                Some(idx) => usize::from(compare.cache.retry(idx.saturating_add(compare.offset))),
+                None => compare.cache.retry_all(),
+            };
+        }
+        if retried == 0 {
+            self.status = "No failed frame to retry".to_string();
+            return;
+        }
+        info!("retrying {} failed frames", retried);
+        self.update_cache_and_status(ctx);
+    }
+
+    /// Ask the remote worker to reconnect and re-request frames that were in flight
+    fn reconnect(&mut self, ctx: &egui::Context) {
+        if let Some(tx) = &self.remote_tx {
+            info!("reconnect requested");
+            let _ = tx.send(RemoteWorkerRequest::Reconnect);
+            self.cache.forget_pending();
+            self.cache.retry_all();
+            self.update_cache_and_status(ctx);
+        }
+    }
+
+    /// Slider over the known frame range that jumps to the picked frame
+    fn scrubber(&mut self, ui: &mut egui::Ui) {
+        let (Some(first), Some(last)) = (self.bounds.first, self.bounds.last) else {
+            if self.bounds_rx.is_some() {
+                ui.weak("finding the ends…");
+            }
+            return;
+        };
+        if first == last {
+            return;
+        }
+        let mut target = self.seq.index;
+        ui.spacing_mut().slider_width = 160.0;
+        let mut response = ui
+            .add(egui::Slider::new(&mut target, first..=last).show_value(false))
+            .on_hover_text(format!("Frames {}..={} ({} in all)", first, last, self.bounds.frame_count().unwrap_or_default()));
+        if let Some((loop_first, loop_last)) = self.loop_range() {
+            // Highlight the loop region over the slider's rail (the handle's center
+            // travels between the rail's ends inset by its radius)
+            let rect = response.rect;
+            let rail = rect.x_range().shrink(rect.height() / 2.5);
+            let x = |idx: i64| egui::remap_clamp(idx as f32, first as f32..=last as f32, rail);
+            let span = egui::Rect::from_x_y_ranges(x(loop_first)..=x(loop_last).max(x(loop_first) + 2.0), rect.y_range());
+            ui.painter().rect_filled(span, 2.0, egui::Color32::from_rgba_unmultiplied(255, 200, 0, 60));
+            response = response.on_hover_text(format!("Loop {loop_first}..={loop_last}"));
+        }
+        if response.changed() && target != self.seq.index {
+            let ctx = ui.ctx().clone();
+            self.jump_to(&ctx, target);
+        }
+    }
+
+    /// Colored connection indicator with latency and a reconnect button (remote sources only)
+    fn connection_indicator(&mut self, ui: &mut egui::Ui) {
+        let Some(stats) = &self.remote_stats else {
+            return;
+        };
+        let snap = stats.snapshot();
+        let protocol = if matches!(self.seq.source, SequenceSource::Http { .. }) { "HTTP" } else { "SSH" };
+        let (color, text) = match &snap.state {
+            ConnectionState::Connecting => (egui::Color32::YELLOW, format!("{protocol} connecting…")),
+            ConnectionState::Reconnecting => (egui::Color32::YELLOW, format!("{protocol} reconnecting…")),
+            ConnectionState::Connected => (
+                egui::Color32::GREEN,
+                match snap.latency {
+                    Some(l) => format!("{protocol} connected to {} ({} ms)", snap.host, l.as_millis()),
+                    None => format!("{protocol} connected to {}", snap.host),
+                },
+            ),
+            ConnectionState::Failed(_) => (egui::Color32::RED, format!("{protocol} failed")),
+        };
+        let label = ui.colored_label(color, format!("● {}", text));
+        if let ConnectionState::Failed(err) = &snap.state {
+            label.on_hover_text(err);
         }
+        if ui.button("Reconnect").clicked() {
+            self.reconnect(ui.ctx());
+        }
+        // Keep the indicator fresh while nothing else triggers repaints
+        ui.ctx().request_repaint_after(Duration::from_secs(1));
     }
 
-    fn update_cache_and_status(&mut self, ctx: &egui::Context) {
-        let (loaded, evicted) = self.cache.update_for_index(self.seq.index, &self.seq, ctx);
+    /// Warning shown once the watchdog has had to replace a stuck or crashed background thread
+    fn restart_warning(&self, ui: &mut egui::Ui) {
+        let loader = self.cache.loader_restarts() + self.compare.as_ref().map_or(0, |c| c.cache.loader_restarts());
+        let worker = self.remote_stats.as_ref().map_or(0, |s| s.snapshot().restarts);
+        if loader == 0 && worker == 0 {
+            return;
+        }
+        let mut parts = Vec::new();
+        if loader > 0 {
+            parts.push(format!("loader ×{}", loader));
+        }
+        if worker > 0 {
+            parts.push(format!("SSH worker ×{}", worker));
+        }
+        ui.colored_label(egui::Color32::YELLOW, format!("⚠ restarted {}", parts.join(", ")))
+            .on_hover_text(format!(
+                "A background thread crashed or made no progress for {} s and was replaced; see the log",
+                self.cfg.stall_secs
+            ));
+    }
 
-        let path = self.seq.path_display(self.seq.index);
-        let idx = self.seq.index;
+    /// Side panel contents; returns what a click on a failed frame or bookmark asks for
+    fn diagnostics_panel(&self, ui: &mut egui::Ui) -> Option<DiagnosticsAction> {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::CollapsingHeader::new("Sequence")
+                .default_open(true)
+                .show(ui, |ui| {
+                    egui::Grid::new("diag_sequence").num_columns(2).show(ui, |ui| {
+                        ui.label("Pattern");
+                        ui.label(&self.pattern);
+                        ui.end_row();
+                        ui.label("Location");
+                        ui.label(self.seq.source.location());
+                        ui.end_row();
+                        ui.label("Index");
+                        ui.label(self.seq.index.to_string());
+                        ui.end_row();
+                        ui.label("Range");
+                        let end = |b: Option<i64>| b.map_or("?".to_string(), |i| i.to_string());
+                        ui.label(format!("{}..={}", end(self.bounds.first), end(self.bounds.last)));
+                        ui.end_row();
+                        ui.label("Step");
+                        ui.label(self.step_size.to_string());
+                        ui.end_row();
+                        ui.label("Skip gaps");
+                        ui.label(if self.skip_gaps {
+                            format!("on (up to {} steps)", self.cfg.max_gap)
+                        } else {
+                            "off".to_string()
+                        });
+                        ui.end_row();
+                        ui.label("Orientation");
+                        ui.label(self.orientation.describe());
+                        ui.end_row();
+                        ui.label("Channels");
+                        ui.label(self.channel_view.label());
+                        ui.end_row();
+                        ui.label("Zoom");
+                        ui.label(format!("{:.0}% of fit", self.viewport.zoom * 100.0));
+                        ui.end_row();
+                    });
+                });
 
-        if self.cache.get(idx).is_some() {
-            // Image is cached and ready
-            self.status = format!(
-                "{}  (pattern: {})  |  {} | +{} -{} | step: {}",
-                path,
-                self.pattern,
-                self.cache.cache_info(),
-                loaded,
-                evicted,
-                self.step_size
-            );
-        } else if self.cache.is_pending(idx) {
-            // Image is being loaded
-            self.status = format!(
-                "Loading {} | {} | step: {}",
-                path,
-                self.cache.cache_info(),
-                self.step_size
-            );
-        } else {
-            // Image not found or failed to load
-            self.status = format!(
-                "Not found / failed: {} | {} | +{} -{} | step: {}",
-                path,
-                self.cache.cache_info(),
-                loaded,
-                evicted,
-                self.step_size
-            );
-        }
+            egui::CollapsingHeader::new("Cache")
+                .default_open(true)
+                .show(ui, |ui| {
+                    egui::Grid::new("diag_cache").num_columns(2).show(ui, |ui| {
+                        ui.label("Radius");
+                        ui.label(self.cache.radius().to_string());
+                        ui.end_row();
+                        ui.label("Loaded");
+                        ui.label(self.cache.loaded_count().to_string());
+                        ui.end_row();
+                        ui.label("Previews");
+                        ui.label(self.cache.preview_count().to_string());
+                        ui.end_row();
+                        ui.label("Pending");
+                        ui.label(self.cache.pending_count().to_string());
+                        ui.end_row();
+                        ui.label("CPU pixels");
+                        ui.label(format!("{:.1} MB", self.cache.pixel_bytes() as f64 / 1_000_000.0));
+                        ui.end_row();
+                        ui.label("Last update");
+                        ui.label(format!("+{} -{}", self.last_loaded, self.last_evicted));
+                        ui.end_row();
+                        ui.label("Loader restarts");
+                        ui.label(self.cache.loader_restarts().to_string());
+                        ui.end_row();
+                    });
+                });
+
+            if let Some(compare) = &self.compare {
+                egui::CollapsingHeader::new("Compare")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        egui::Grid::new("diag_compare").num_columns(2).show(ui, |ui| {
+                            ui.label("Pattern");
+                            ui.label(&compare.pattern);
+                            ui.end_row();
+                            ui.label("Location");
+                            ui.label(compare.seq.source.location());
+                            ui.end_row();
+                            ui.label("Index");
+                            ui.label(format!("{} ({:+})", compare.seq.index, compare.offset));
+                            ui.end_row();
+                            ui.label("Loaded");
+                            ui.label(compare.cache.loaded_count().to_string());
+                            ui.end_row();
+                        });
+                    });
+            }
+
+            let server = match &self.seq.source {
+                SequenceSource::Remote { user_host, .. } => Some(("SSH", user_host)),
+                SequenceSource::Http { base_url } => Some(("HTTP", base_url)),
+                SequenceSource::Local(_) | SequenceSource::Video { .. } | SequenceSource::Animation { .. } => None,
+            };
+            if let Some((protocol, user_host)) = server {
+                egui::CollapsingHeader::new(protocol)
+                    .id_salt("diag_ssh")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        egui::Grid::new("diag_ssh").num_columns(2).show(ui, |ui| {
+                            ui.label("Host");
+                            ui.label(user_host);
+                            ui.end_row();
+                            match &self.remote_stats {
+                                Some(stats) => {
+                                    let s = stats.snapshot();
+                                    if !s.host.is_empty() && s.host != *user_host {
+                                        ui.label("Serving");
+                                        ui.label(format!("{} (replica)", s.host));
+                                        ui.end_row();
+                                    }
+                                    ui.label("Latency");
+                                    ui.label(match s.latency {
+                                        Some(l) => format!("{} ms", l.as_millis()),
+                                        None => "-".to_string(),
+                                    });
+                                    ui.end_row();
+                                    ui.label("Requests");
+                                    ui.label(s.requests.to_string());
+                                    ui.end_row();
+                                    ui.label("Received");
+                                    ui.label(format!("{:.1} MB", s.bytes as f64 / 1_000_000.0));
+                                    ui.end_row();
+                                    ui.label("Downloading");
+                                    ui.label(format!("{} frames", s.downloads.len()));
+                                    ui.end_row();
+                                    ui.label("Queued");
+                                    ui.label(s.queued.to_string());
+                                    ui.end_row();
+                                    ui.label("Errors");
+                                    ui.label(s.errors.to_string());
+                                    ui.end_row();
+                                    ui.label("Skipped");
+                                    ui.label(s.skipped.to_string());
+                                    ui.end_row();
+                                    ui.label("Worker restarts");
+                                    ui.label(s.restarts.to_string());
+                                    ui.end_row();
+                                }
+                                None => {
+                                    ui.label("State");
+                                    ui.label("not connected");
+                                    ui.end_row();
+                                }
+                            }
+                        });
+                    });
+            }
+
+            self.errors_section(ui).or(self.bookmarks_section(ui).map(DiagnosticsAction::Jump))
+        })
+        .inner
+    }
+
+    /// Frames in the cache window that failed to load and why; returns a click on one
+    fn errors_section(&self, ui: &mut egui::Ui) -> Option<DiagnosticsAction> {
+        let mut action = None;
+        let failures: Vec<_> = self.cache.failures().collect();
+        egui::CollapsingHeader::new(format!("Errors ({})", failures.len()))
+            .id_salt("diag_errors")
+            .default_open(true)
+            .show(ui, |ui| {
+                if failures.is_empty() {
+                    ui.label("No failed frames");
+                    return;
+                }
+                if ui.button("Retry all").clicked() {
+                    action = Some(DiagnosticsAction::Retry(None));
+                }
+                for (idx, error) in failures {
+                    ui.horizontal(|ui| {
+                        let text = format!("{}  {}", idx, error.kind.label());
+                        if ui.selectable_label(idx == self.seq.index, text).clicked() {
+                            action = Some(DiagnosticsAction::Jump(idx));
+                        }
+                        if ui.small_button("Retry").clicked() {
+                            action = Some(DiagnosticsAction::Retry(Some(idx)));
+                        }
+                    });
+                    ui.weak(&error.message);
+                }
+            });
+        action
     }
 
     fn try_step(&mut self, ctx: &egui::Context, delta: i64) {
-        let cur = self.seq.index as i64;
-        let step = self.step_size as i64;
-        let next = cur + delta * step;
-        if next < 0 {
+        self.step_by(ctx, delta, self.step_size);
+    }
+
+    /// Move `delta` steps of `step` frames (gap skipping continues with the same step)
+    fn step_by(&mut self, ctx: &egui::Context, delta: i64, step: u64) {
+        let cur = self.seq.index;
+        let next = cur.saturating_add(delta.saturating_mul(step as i64));
+        if next < self.seq.min_index() {
             return;
         }
-        let next_u = next as u64;
-        eprintln!("[Step] navigating from {} to {} (step={})", cur, next_u, step);
+        // Clamp at the known ends instead of stepping into missing frames
+        let next_u = self.bounds.clamp(next);
+        if next_u == self.seq.index {
+            return;
+        }
+        debug!("navigating from {} to {} (step={})", cur, next_u, step);
+        self.stop_blink();
+        self.gap_skipped = 0;
+        self.gap_search = None;
 
         // For local files, check existence first (fast, non-blocking)
-        if let SequenceSource::Local(dir) = &self.seq.source {
-            if !dir.join(self.seq.file_name_for(next_u)).exists() {
+        let mut next_u = next_u;
+        if self.local_frame_missing(next_u) {
+            let found = if self.skip_gaps { self.find_local_frame(next_u, delta, step) } else { None };
+            let Some((found, skipped)) = found else {
                 let p = self.seq.path_display(next_u);
-                self.status = format!("No file: {} | {}", p, self.cache.cache_info());
-                eprintln!("[Step] file not found: {}", p);
+                self.status = format!("No file: {}  |  frame {}", p, next_u);
+                debug!("file not found: {}", p);
                 return;
-            }
+            };
+            debug!("skipped {} missing frames to {}", skipped, found);
+            next_u = found;
+            self.gap_skipped = skipped;
         }
 
         // For remote: proceed optimistically (don't block UI with recv())
-        // The cache loader will attempt to fetch and show "Failed to load" if it doesn't exist
+        // The cache loader will attempt to fetch and show "Failed to load" if it doesn't exist;
+        // with gap skipping on, a missing frame continues the step in the same direction.
+        if self.skip_gaps && self.seq.source.is_remote() {
+            self.gap_search = Some(GapSearch {
+                direction: delta.signum(),
+                step,
+                skipped: 0,
+            });
+        }
         self.seq.index = next_u;
+        self.restart_playback_clock();
         self.update_cache_and_status(ctx);
     }
 
@@ -110,137 +3117,973 @@ impl ZapVisApp {
         if new_step == self.step_size {
             return;
         }
-        eprintln!("[Step] changing step size from {} to {}", self.step_size, new_step);
+        info!("changing step size from {} to {}", self.step_size, new_step);
         self.step_size = new_step;
         
         // Update cache step size and clear cache except current image
         self.cache.set_step_size(new_step);
         self.cache.clear_except_current(self.seq.index);
+        if let Some(compare) = &mut self.compare {
+            compare.cache.set_step_size(new_step);
+            compare.cache.clear_except_current(compare.seq.index);
+        }
         self.update_cache_and_status(ctx);
     }
 
-    fn toggle_fullscreen(&mut self, ctx: &egui::Context) {
-        if self.is_fullscreen {
-            // Restore to normal windowed mode
-            eprintln!("[Fullscreen] Restoring to normal windowed mode");
-            
-            // First, un-maximize the window
-            ctx.send_viewport_cmd(egui::ViewportCommand::Maximized(false));
-            
-            // Then restore previous size and position if available
-            if let Some(size) = self.saved_window_size {
-                ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
-            }
+    /// Maximize the window (F) or make it fullscreen (F11, `exclusive`), or go back to
+    /// the window's size and position when that mode is on already. Switching between
+    /// the two keeps the size and position saved when the window was left.
+    fn toggle_fullscreen(&mut self, ctx: &egui::Context, exclusive: bool) {
+        if self.is_fullscreen && self.exclusive_fullscreen == exclusive {
+            info!("leaving {}", if exclusive { "fullscreen" } else { "maximized mode" });
+            self.leave_fullscreen_mode(ctx);
+            // Restore the previous size and position; the position first, so that the
+            // size applies on the monitor the window came from
             if let Some(pos) = self.saved_window_pos {
                 ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(pos));
             }
-            
+            if let Some(size) = self.saved_window_size {
+                ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
+            }
             self.is_fullscreen = false;
+            self.exclusive_fullscreen = false;
+            return;
+        }
+        if self.is_fullscreen {
+            // Switching modes: the windowed size and position are saved already
+            self.leave_fullscreen_mode(ctx);
         } else {
-            // Enter fullscreen (maximized) mode
-            eprintln!("[Fullscreen] Entering fullscreen mode");
-            
-            // Save current window size and position before maximizing
             ctx.input(|i| {
                 if let Some(viewport) = i.raw.viewports.get(&i.raw.viewport_id) {
                     self.saved_window_size = viewport.inner_rect.map(|r| r.size());
                     self.saved_window_pos = viewport.outer_rect.map(|r| r.min);
                 }
             });
-            
-            // Maximize the window
-            ctx.send_viewport_cmd(egui::ViewportCommand::Maximized(true));
-            
-            self.is_fullscreen = true;
         }
+        info!("entering {}", if exclusive { "fullscreen" } else { "maximized mode" });
+        // Fullscreen goes to the monitor the window is on
+        ctx.send_viewport_cmd(match exclusive {
+            true => egui::ViewportCommand::Fullscreen(true),
+            false => egui::ViewportCommand::Maximized(true),
+        });
+        self.is_fullscreen = true;
+        self.exclusive_fullscreen = exclusive;
+    }
+
+    fn leave_fullscreen_mode(&self, ctx: &egui::Context) {
+        ctx.send_viewport_cmd(match self.exclusive_fullscreen {
+            true => egui::ViewportCommand::Fullscreen(false),
+            false => egui::ViewportCommand::Maximized(false),
+        });
     }
 }
 
 impl eframe::App for ZapVisApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let forwarded: Vec<OpenedInput> = self.forwarded.iter().flat_map(|rx| rx.try_iter()).collect();
+        for opened in forwarded {
+            self.open_forwarded(ctx, opened);
+        }
         // Process any decoded images from background threads
-        self.cache.tick(ctx);
+        let arrived = self.cache.tick(ctx);
+        self.update_bounds(ctx);
+        self.update_follow(ctx);
+        self.update_blink(ctx);
+        if let Some(compare) = &mut self.compare {
+            compare.cache.tick(ctx);
+            // Only the main sequence's gaps shape the bounds
+            compare.cache.take_missing();
+        }
+
+        if !self.is_fullscreen {
+            let (inner, outer) = ctx.input(|i| (i.viewport().inner_rect, i.viewport().outer_rect));
+            if let Some(rect) = inner {
+                self.window_size = Some(rect.size());
+            }
+            if let Some(rect) = outer {
+                self.window_pos = Some(rect.min);
+            }
+        }
 
         // Load initial cache once
-        if self.cache.is_empty() && self.status.is_empty() {
+        if !self.cache_started {
             self.update_cache_and_status(ctx);
+        } else if arrived > 0 {
+            // Refresh the notices (download progress) now that a tier arrived
+            self.refresh_status();
+        } else if self.current_download().is_some() {
+            self.refresh_status();
+            ctx.request_repaint_after(Duration::from_millis(100));
+        } else if self.cache.pending_count() > 0 {
+            // Settled loads wake the UI themselves; this keeps the watchdog checking on
+            // a loader that never reports back
+            ctx.request_repaint_after(PENDING_POLL);
         }
+        self.poll_fetch(ctx);
 
-        // Keyboard navigation
-        let input = ctx.input(|i| i.clone());
+        // Keyboard navigation (not while typing into a text field)
+        let mut input = ctx.input(|i| i.clone());
+        if ctx.wants_keyboard_input() {
+            input.events.clear();
+        }
         if input.key_pressed(egui::Key::ArrowRight) || input.key_pressed(egui::Key::D) {
             self.try_step(ctx, 1);
         }
         if input.key_pressed(egui::Key::ArrowLeft) || input.key_pressed(egui::Key::A) {
             self.try_step(ctx, -1);
         }
+        if input.key_pressed(egui::Key::Home) {
+            self.jump_to_end(ctx, false);
+        }
+        if input.key_pressed(egui::Key::End) {
+            self.jump_to_end(ctx, true);
+        }
+
+        // Step size selection (keys 0-9 for powers of 10); Alt+1..9 toggle single guides
+        if input.modifiers.alt {
+            let digits = [egui::Key::Num1, egui::Key::Num2, egui::Key::Num3, egui::Key::Num4, egui::Key::Num5, egui::Key::Num6, egui::Key::Num7, egui::Key::Num8, egui::Key::Num9];
+            if let Some(n) = digits.iter().position(|&key| input.key_pressed(key)) {
+                self.toggle_guide(n);
+            }
+        } else {
+            if input.key_pressed(egui::Key::Num0) {
+                self.set_step_size(1, ctx);
+            }
+            if input.key_pressed(egui::Key::Num1) {
+                self.set_step_size(10, ctx);
+            }
+            if input.key_pressed(egui::Key::Num2) {
+                self.set_step_size(100, ctx);
+            }
+            if input.key_pressed(egui::Key::Num3) {
+                self.set_step_size(1000, ctx);
+            }
+            if input.key_pressed(egui::Key::Num4) {
+                self.set_step_size(10000, ctx);
+            }
+            if input.key_pressed(egui::Key::Num5) {
+                self.set_step_size(100000, ctx);
+            }
+            if input.key_pressed(egui::Key::Num6) {
+                self.set_step_size(1000000, ctx);
+            }
+            if input.key_pressed(egui::Key::Num7) {
+                self.set_step_size(10000000, ctx);
+            }
+            if input.key_pressed(egui::Key::Num8) {
+                self.set_step_size(100000000, ctx);
+            }
+            if input.key_pressed(egui::Key::Num9) {
+                self.set_step_size(1000000000, ctx);
+            }
+        }
+
+        // Playback: Space plays/pauses at `playback_fps`
+        if input.key_pressed(egui::Key::Space) {
+            self.toggle_playback(ctx);
+        }
+        self.advance_playback(ctx);
+
+        // Maximized window (F key), OS fullscreen (F11)
+        if input.key_pressed(egui::Key::F) {
+            self.toggle_fullscreen(ctx, false);
+        }
+        if input.key_pressed(egui::Key::F11) {
+            self.toggle_fullscreen(ctx, true);
+        }
+
+        // Orientation: R rotates clockwise, Shift+R counter-clockwise, H/V flip
+        if input.key_pressed(egui::Key::R) && input.modifiers.command {
+            self.retry_failed(ctx, Some(self.seq.index));
+        } else if input.key_pressed(egui::Key::R) {
+            let clockwise = !input.modifiers.shift;
+            self.change_orientation(|o| o.rotate(clockwise));
+        }
+        if input.key_pressed(egui::Key::H) {
+            self.change_orientation(|o| o.toggle_flip_h());
+        }
+        if input.key_pressed(egui::Key::V) {
+            self.change_orientation(|o| o.toggle_flip_v());
+        }
 
-        // Step size selection (keys 0-9 for powers of 10)
-        if input.key_pressed(egui::Key::Num0) {
-            self.set_step_size(1, ctx);
+        // Playback filter (P key): all frames -> bookmarked -> bookmarked by each author
+        if input.key_pressed(egui::Key::P) {
+            self.playback_filter = self.playback_filter.next(&self.bookmarks);
+            info!("playback filter: {}", self.playback_filter.label());
+            self.restart_playback_clock();
         }
-        if input.key_pressed(egui::Key::Num1) {
-            self.set_step_size(10, ctx);
+
+        // Gap skipping (G key), gap interpolation (Shift+G)
+        if input.key_pressed(egui::Key::G) && input.modifiers.shift {
+            self.interpolate_gaps = !self.interpolate_gaps;
+            self.interpolated_tex = None;
+            info!("interpolate gaps: {}", self.interpolate_gaps);
+        } else if input.key_pressed(egui::Key::G) {
+            self.skip_gaps = !self.skip_gaps;
+            info!("skip gaps: {}", self.skip_gaps);
         }
-        if input.key_pressed(egui::Key::Num2) {
-            self.set_step_size(100, ctx);
+
+        // Prefetch pause (Q key): load only the frame shown
+        if input.key_pressed(egui::Key::Q) {
+            self.toggle_prefetch(ctx);
         }
-        if input.key_pressed(egui::Key::Num3) {
-            self.set_step_size(1000, ctx);
+
+        // Bookmarks: B toggles the current frame, N / Shift+N jump to the next / previous one
+        if input.key_pressed(egui::Key::B) {
+            self.toggle_bookmark();
         }
-        if input.key_pressed(egui::Key::Num4) {
-            self.set_step_size(10000, ctx);
+        if input.key_pressed(egui::Key::N) {
+            if let Some(idx) = self.bookmarks.next_index(self.seq.index, !input.modifiers.shift) {
+                self.jump_to(ctx, idx);
+            }
         }
-        if input.key_pressed(egui::Key::Num5) {
-            self.set_step_size(100000, ctx);
+
+        // Save the displayed frame (Ctrl+S)
+        if input.key_pressed(egui::Key::S) && input.modifiers.command {
+            self.save_frame_as();
         }
-        if input.key_pressed(egui::Key::Num6) {
-            self.set_step_size(1000000, ctx);
+
+        // Visible-region statistics overlay (S key), the paired sequence (Shift+S)
+        if input.key_pressed(egui::Key::S) && !input.modifiers.command && input.modifiers.shift {
+            self.toggle_pair(ctx);
         }
-        if input.key_pressed(egui::Key::Num7) {
-            self.set_step_size(10000000, ctx);
+        if input.key_pressed(egui::Key::S) && !input.modifiers.command && !input.modifiers.shift {
+            self.show_stats = !self.show_stats;
+            if !self.show_stats {
+                self.stats = None;
+            }
         }
-        if input.key_pressed(egui::Key::Num8) {
-            self.set_step_size(100000000, ctx);
+
+        // Clipboard: Ctrl+C copies the frame's path, Ctrl+Shift+C its pixels (the
+        // integration turns both into a copy event rather than a C press)
+        if input.events.iter().any(|e| matches!(e, egui::Event::Copy)) {
+            if input.modifiers.shift {
+                self.copy_image();
+            } else {
+                self.copy_path(ctx);
+            }
         }
-        if input.key_pressed(egui::Key::Num9) {
-            self.set_step_size(1000000000, ctx);
+
+        // Channel isolation: C cycles RGBA -> R -> G -> B -> A -> Luma, Shift+C backwards;
+        // Alt+C cycles the false-color maps
+        if input.key_pressed(egui::Key::C) && input.modifiers.alt {
+            let current = self.channel_view.false_color;
+            self.channel_view.false_color = Colormap::next(current.map(|fc| fc.map)).map(|map| FalseColor {
+                map,
+                ..current.unwrap_or(FalseColor::new(map))
+            });
+            info!("channel view: {}", self.channel_view.label());
+        } else if input.key_pressed(egui::Key::C) && !input.modifiers.command {
+            self.channel_view = if input.modifiers.shift {
+                self.channel_view.prev()
+            } else {
+                self.channel_view.next()
+            };
+            info!("channel view: {}", self.channel_view.label());
         }
 
-        // Fullscreen toggle (F key)
-        if input.key_pressed(egui::Key::F) {
-            self.toggle_fullscreen(ctx);
+        // Color management (U key): sRGB conversion or the raw values
+        if input.key_pressed(egui::Key::U) {
+            self.toggle_color_management(ctx);
+        }
+
+        // Tone mapping of HDR frames: [ / ] exposure down/up by half a stop, with Shift
+        // gamma; Y cycles clamp -> Reinhard -> ACES, Shift+Y resets
+        if input.key_pressed(egui::Key::OpenBracket) || input.key_pressed(egui::Key::CloseBracket) {
+            let sign = if input.key_pressed(egui::Key::CloseBracket) { 1.0 } else { -1.0 };
+            if input.modifiers.shift {
+                self.change_tone_map(ctx, |t| t.gamma += sign * 0.1);
+            } else {
+                self.change_tone_map(ctx, |t| t.exposure += sign * 0.5);
+            }
+        }
+        if input.key_pressed(egui::Key::Y) {
+            if input.modifiers.shift {
+                self.change_tone_map(ctx, |t| *t = ToneMap::default());
+            } else {
+                self.change_tone_map(ctx, |t| t.operator = t.operator.next());
+            }
+        }
+
+        // Stuck-pixel overlay (X key)
+        if input.key_pressed(egui::Key::X) {
+            self.show_defects = !self.show_defects;
+        }
+        if self.show_defects {
+            self.update_defects();
+        }
+
+        // Crop preview: K toggles, Shift+K clears the crop, Alt+K locks the view to it
+        if input.key_pressed(egui::Key::K) {
+            if input.modifiers.alt {
+                self.toggle_roi_lock();
+            } else if input.modifiers.shift {
+                self.set_crop(None);
+                if self.roi_lock {
+                    self.toggle_roi_lock();
+                }
+            } else {
+                self.show_crop = !self.show_crop;
+            }
+        }
+        self.poll_roi_export();
+
+        // Annotations: E toggles drawing, Shift+E saves the annotated frame, Backspace
+        // removes the newest annotation on this frame
+        if input.key_pressed(egui::Key::E) {
+            if input.modifiers.shift {
+                self.export_annotated();
+            } else {
+                self.annotating = !self.annotating;
+            }
+        }
+        if self.annotating && input.key_pressed(egui::Key::Backspace) {
+            self.remove_last_annotation();
+        }
+
+        // Follow a growing sequence: L toggles, Shift+L jumps to new frames as they appear
+        if input.key_pressed(egui::Key::L) {
+            self.toggle_follow(input.modifiers.shift);
+        }
+
+        // Blink comparison: T marks a frame, T on a second one starts flipping, then T flips;
+        // Shift+T flips automatically
+        if input.key_pressed(egui::Key::T) {
+            if input.modifiers.shift {
+                self.toggle_blink_auto();
+            } else {
+                self.blink_key(ctx);
+            }
+        }
+
+        // Framing guides (O key), onion skin (Shift+O)
+        if input.key_pressed(egui::Key::O) && !input.modifiers.command {
+            if input.modifiers.shift {
+                self.toggle_onion(ctx);
+            } else {
+                self.toggle_guides();
+            }
+        }
+
+        // Sequence switcher: Tab / Shift+Tab cycle through the sequences of the directory
+        if input.key_pressed(egui::Key::Tab) {
+            self.cycle_sequence(ctx, !input.modifiers.shift);
+        }
+
+        // Nearest-neighbor filtering (J key; N steps through bookmarks)
+        if input.key_pressed(egui::Key::J) {
+            self.toggle_nearest(ctx);
+        }
+
+        // Background under transparent pixels (W key)
+        if input.key_pressed(egui::Key::W) {
+            self.set_background(self.cfg.background.next());
+        }
+
+        // Fetch panel (M key), measure mode (Shift+M; Backspace drops the last point)
+        if input.key_pressed(egui::Key::M) {
+            if input.modifiers.shift {
+                self.measuring = !self.measuring;
+            } else {
+                self.toggle_fetch_panel();
+            }
+        }
+        if self.measuring && input.key_pressed(egui::Key::Backspace) {
+            self.measurer.undo();
+        }
+
+        // Loop region: Ctrl+I / Ctrl+O set the in / out point (I and O alone are the
+        // color picker and guides)
+        if input.modifiers.command && (input.key_pressed(egui::Key::I) || input.key_pressed(egui::Key::O)) {
+            self.set_loop_marker(ctx, input.key_pressed(egui::Key::O));
+        }
+
+        // Color picker (I key)
+        if input.key_pressed(egui::Key::I) && !input.modifiers.command {
+            self.picking = !self.picking;
+        }
+        if self.picking {
+            self.update_source_values(ctx);
+        }
+
+        // Diagnostics panel toggle (F3)
+        if input.key_pressed(egui::Key::F3) {
+            self.show_diagnostics = !self.show_diagnostics;
+        }
+
+        // Grade panel toggle (F4)
+        if input.key_pressed(egui::Key::F4) {
+            self.show_grade = !self.show_grade;
+        }
+
+        // Performance overlay toggle (F12)
+        if input.key_pressed(egui::Key::F12) {
+            self.show_perf = !self.show_perf;
         }
 
         egui::TopBottomPanel::top("top").show(ctx, |ui| {
-            ui.label(&self.status);
-            ui.label("Keys: Left/Right or A/D. 0-9 for step size. F for fullscreen. Esc closes the window.");
+            ui.horizontal(|ui| {
+                ui.toggle_value(&mut self.show_diagnostics, "Diagnostics");
+                self.background_picker(ui);
+                self.restart_warning(ui);
+                self.inferred_pattern_prompt(ui);
+                if let Some(i) = self.pattern_picker(ui) {
+                    self.switch_pattern(ctx, i);
+                }
+                if self.playback.as_ref().is_some_and(Playback::is_prerolling) {
+                    ui.strong("[buffering]");
+                }
+                if self.playback_filter != PlaybackFilter::All {
+                    ui.strong(format!("[play: {}]", self.playback_filter.label()));
+                }
+                if self.show_defects {
+                    ui.strong(match &self.defects {
+                        Some(DefectScan { pixels: Some(p), frames, .. }) => {
+                            let hot = p.iter().filter(|p| p.hot).count();
+                            format!("[stuck: {} hot, {} dead / {} frames]", hot, p.len() - hot, frames)
+                        }
+                        _ => "[stuck: scanning]".to_string(),
+                    });
+                }
+                if self.interpolate_gaps {
+                    ui.strong("[interpolating gaps]");
+                }
+                if !self.grade.grade.is_identity() {
+                    ui.strong("[graded]");
+                }
+                if self.follow.is_some() {
+                    ui.strong(if self.follow_advance { "[following: newest]" } else { "[following]" });
+                }
+                if let Some(blink) = &self.blink {
+                    let [a, b] = blink.frames();
+                    let auto = if blink.is_auto() { format!(" {} Hz", self.cfg.blink_hz) } else { String::new() };
+                    ui.strong(format!("[blink{}: {} of A={} / B={}]", auto, blink.label(), a, b));
+                    if ui.small_button("Stop blink").clicked() {
+                        self.stop_blink();
+                    }
+                } else if let Some(a) = self.blink_mark {
+                    ui.strong(format!("[blink: A={}, press T on the second frame]", a));
+                }
+                if self.show_guides {
+                    let mut toggled = None;
+                    ui.menu_button("Guides", |ui| {
+                        for (i, guide) in self.cfg.guides.iter().enumerate() {
+                            let mut shown = !self.hidden_guides.contains(guide);
+                            let label = if i < 9 { format!("{guide}  (Alt+{})", i + 1) } else { guide.to_string() };
+                            if ui.checkbox(&mut shown, label).changed() {
+                                toggled = Some(i);
+                            }
+                        }
+                    });
+                    if let Some(i) = toggled {
+                        self.toggle_guide(i);
+                    }
+                }
+                if self.show_crop {
+                    ui.strong(match self.crop {
+                        Some(c) => format!("[crop: {}x{} at {},{}]", c.width, c.height, c.x, c.y),
+                        None => "[crop: Shift+drag to draw]".to_string(),
+                    });
+                }
+                if self.roi_locked() {
+                    ui.strong("[ROI lock]");
+                    let running = self.roi_export.is_some();
+                    let export = ui.add_enabled(!running, egui::Button::new("Export ROI…").small());
+                    if export.on_hover_text("Write the loop region (else all frames) cropped to the ROI as PNGs").clicked() {
+                        self.start_roi_export(ctx);
+                    }
+                }
+                if !self.channel_view.is_all() {
+                    ui.strong(format!("[{}]", self.channel_view.label()));
+                }
+                if let Some(mut fc) = self.channel_view.false_color {
+                    for map in Colormap::ALL {
+                        ui.selectable_value(&mut fc.map, map, map.label());
+                    }
+                    ui.add(egui::DragValue::new(&mut fc.low).range(0..=fc.high.saturating_sub(1)).prefix("min "));
+                    ui.add(egui::DragValue::new(&mut fc.high).range(fc.low.saturating_add(1)..=255).prefix("max "));
+                    if ui.small_button("Auto").on_hover_text("Stretch over the values of this frame").clicked() {
+                        if let Some((low, high)) = self.channel_range() {
+                            (fc.low, fc.high) = (low, high.max(low.saturating_add(1)));
+                        }
+                    }
+                    self.channel_view.false_color = Some(fc);
+                }
+                if let Some(mut onion) = self.onion {
+                    ui.strong(match onion.pinned {
+                        Some(idx) => format!("[onion: frame {idx}]"),
+                        None => "[onion: previous step]".to_string(),
+                    });
+                    for mode in BlendMode::ALL {
+                        ui.selectable_value(&mut onion.mode, mode, mode.label());
+                    }
+                    ui.add(egui::Slider::new(&mut onion.opacity, 0.0..=1.0).text("opacity"));
+                    let pin = match onion.pinned {
+                        Some(_) => ui.small_button("Unpin").clicked().then_some(None),
+                        None => ui.small_button("Pin this frame").clicked().then_some(Some(self.seq.index)),
+                    };
+                    self.onion = Some(onion);
+                    if let Some(pinned) = pin {
+                        self.set_onion_pin(pinned);
+                        self.update_cache_and_status(ctx);
+                    }
+                }
+                if self.measuring {
+                    ui.strong("[measure]");
+                    let tool = self.measurer.tool;
+                    for t in MeasureTool::ALL {
+                        ui.selectable_value(&mut self.measurer.tool, t, t.label());
+                    }
+                    if self.measurer.tool != tool {
+                        self.measurer.cancel_pending();
+                    }
+                    if ui.add_enabled(!self.measurer.is_empty(), egui::Button::new("Clear").small()).clicked() {
+                        self.measurer.clear();
+                    }
+                }
+                if self.annotating {
+                    ui.strong("[annotate]");
+                    for tool in Tool::ALL {
+                        ui.selectable_value(&mut self.annotator.tool, tool, tool.label());
+                    }
+                    if self.annotator.tool == Tool::Text {
+                        ui.add(egui::TextEdit::singleline(&mut self.annotator.text).hint_text("label").desired_width(120.0));
+                    }
+                }
+            });
+            ui.label("Keys: Left/Right or A/D, Home/End. G skip gaps (Shift+G interpolate them). Space play/pause, P playback filter, Ctrl+I/Ctrl+O loop in/out point. 0-9 for step size. F maximizes, F11 fullscreen. R/Shift+R rotate, H/V flip. C channels (Alt+C false color). Ctrl+C copies the frame path, Ctrl+Shift+C the image, Ctrl+S saves it. Ctrl+R retries a frame that failed to load. M fetches a range to local disk, Shift+M measures (click 2 points for a distance, 3 for an angle). Wheel steps frames (Shift+wheel by step size), drag scrubs. Ctrl+wheel/+/- zoom, drag pans when zoomed, Z reset, Shift+Z 1:1 screen pixels. S stats (Shift+S paired sequence). O guides (Alt+1..9 single guides, Shift+O onion skin), K crop preview (Shift+drag draws, Shift+K clears, Alt+K locks the view to it). L follow new frames (Shift+L jump to newest). T blink compare (T on two frames, then T flips; Shift+T auto-flip). E annotate (drag draws, Backspace removes the last; Shift+E saves an annotated PNG). I color picker. X stuck pixels. B bookmark, N/Shift+N next/prev bookmark. F3 diagnostics, F4 grade (gain/offset/gamma, LUT), F12 performance overlay. Esc closes the window.");
+        });
+
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            ui.horizontal(|ui| self.status_bar(ui));
         });
 
+        if self.show_diagnostics {
+            let action = egui::SidePanel::right("diagnostics")
+                .resizable(true)
+                .default_width(240.0)
+                .show(ctx, |ui| self.diagnostics_panel(ui))
+                .inner;
+            match action {
+                Some(DiagnosticsAction::Jump(idx)) => self.jump_to(ctx, idx),
+                Some(DiagnosticsAction::Retry(idx)) => self.retry_failed(ctx, idx),
+                None => {}
+            }
+        }
+
+        if self.show_grade {
+            egui::SidePanel::right("grade")
+                .resizable(true)
+                .default_width(240.0)
+                .show(ctx, |ui| self.grade_panel(ui));
+        }
+
+        if self.show_fetch {
+            egui::SidePanel::left("fetch")
+                .resizable(true)
+                .default_width(260.0)
+                .show(ctx, |ui| self.fetch_panel(ui, ctx));
+        }
+
+        if self.picking {
+            egui::SidePanel::left("samples")
+                .resizable(true)
+                .default_width(260.0)
+                .show(ctx, |ui| self.samples_panel(ui));
+        }
+
+        let display_tex = self.display_texture(ctx);
+        let interpolated = self.interpolated_texture(ctx);
+        let compare_tex = self.compare_texture(ctx);
         egui::CentralPanel::default().show(ctx, |ui| {
-            if let Some(tex) = self.cache.get(self.seq.index) {
-                let avail = ui.available_size();
-
-                let tex_size = tex.size_vec2();
-                // In fullscreen mode, allow scaling up to fill the window
-                // In normal mode, cap at 1.0x to avoid upscaling
-                let scale = if self.is_fullscreen {
-                    (avail.x / tex_size.x).min(avail.y / tex_size.y)
+            let idx = self.seq.index;
+            let full = ui.available_rect_before_wrap();
+            let response = ui.allocate_rect(full, egui::Sense::click_and_drag());
+            // Split view: main sequence on the left, compared one on the right
+            let (panel, compare_panel) = match &self.compare {
+                Some(_) => {
+                    let half = (full.width() - SPLIT_GAP) / 2.0;
+                    let left = egui::Rect::from_min_size(full.min, egui::vec2(half, full.height()));
+                    let right = egui::Rect::from_min_size(left.right_top() + egui::vec2(SPLIT_GAP, 0.0), left.size());
+                    (left, Some(right))
+                }
+                None => (full, None),
+            };
+
+            // Zoom and pan are shared; zoom around the cursor in whichever half it is over
+            let hovered_half = compare_panel.filter(|p| response.hover_pos().is_some_and(|pos| p.contains(pos)));
+            let drawing_crop = self.show_crop && self.crop_input(ui, &response, panel);
+            let drawing = drawing_crop || (self.annotating && self.annotation_input(ui, &response, panel));
+            if !drawing {
+                if !self.roi_locked() {
+                    self.handle_viewport_input(ui, &response, hovered_half.unwrap_or(panel));
+                }
+                if let Some((n, step)) = self.mouse_navigation(ui, &response) {
+                    self.step_by(ui.ctx(), n, step);
+                }
+            }
+            if let Some(compare_panel) = compare_panel {
+                let compare_rect = self.compare.as_ref().and_then(|c| c.cache.display_size(c.seq.index)).map(|size| {
+                    let rect = self.viewport.image_rect(compare_panel, self.fit_size(size, compare_panel.size()));
+                    self.frame_mapping(rect, size)
+                });
+                let tiles = match (&mut self.compare, compare_rect) {
+                    (Some(c), Some(map)) => frame_tiles(ui.ctx(), &mut c.cache, c.seq.index, &map, compare_panel, self.channel_view),
+                    _ => Vec::new(),
+                };
+                self.paint_compare(ui, compare_panel, compare_tex, &tiles);
+                caption(&ui.painter_at(panel), panel, &self.seq.path_display(idx));
+            }
+            if self.show_perf {
+                self.perf_overlay(ui.ctx(), full);
+            }
+            let Some((tex_id, full_size)) = display_tex.zip(self.cache.display_size(idx)).or(interpolated) else {
+                let text = if self.cache.is_pending(idx) {
+                    "Loading…".to_string()
+                } else if self.cache.is_tiled(idx) {
+                    "Preparing overview…".to_string()
                 } else {
-                    (avail.x / tex_size.x).min(avail.y / tex_size.y).min(1.0)
+                    self.cache.failure(idx).map_or_else(|| "No image loaded.".to_string(), failure_text)
                 };
-                let size = tex_size * scale;
+                placeholder(ui, panel, &text);
+                self.display_zoom = None;
+                return;
+            };
+            self.keep_one_to_one(ui.ctx(), panel, full_size);
+            self.keep_roi_lock(panel, full_size);
+            let rect = self.viewport.image_rect(panel, self.fit_size(full_size, panel.size()));
+            // The status bar was laid out first; repaint once more when the zoom it shows is stale
+            let zoom = Some((rect.area() / (full_size.x * full_size.y)).sqrt());
+            if self.display_zoom != zoom {
+                self.display_zoom = zoom;
+                ui.ctx().request_repaint();
+            }
+            let map = self.frame_mapping(rect, full_size);
+            self.update_decode_region(ui.ctx(), &map, panel);
+            self.paint_background(&ui.painter_at(panel), rect);
+            paint_frame(&ui.painter_at(panel), &map, tex_id, self.cache.region(idx), &self.grade);
+            let tiles = frame_tiles(ui.ctx(), &mut self.cache, idx, &map, panel, self.channel_view);
+            paint_tiles(&ui.painter_at(panel), &map, &tiles, &self.grade);
+            if self.show_crop {
+                self.paint_crop(&ui.painter_at(panel), rect);
+            }
+            if self.nearest {
+                self.paint_pixel_grid(&ui.painter_at(panel), rect, full_size);
+            }
+            if self.show_guides {
+                paint_guides(&ui.painter_at(panel), rect, self.visible_guides());
+            }
+            if self.show_defects {
+                self.paint_defects(&ui.painter_at(panel), rect);
+            }
+            paint_annotations(&ui.painter_at(panel), &map, self.annotator.file.at(idx).chain(&self.annotator.drawing));
+            if self.measuring {
+                if response.hovered() {
+                    ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
+                }
+                if let Some(pos) = response.clicked().then(|| response.interact_pointer_pos()).flatten() {
+                    self.measurer.click(map.to_frame(pos));
+                }
+                let hover = response.hover_pos().map(|pos| map.to_frame(pos));
+                let scale = self.cfg.measure_scale_for(&self.seq.sequence_key(&self.pattern));
+                paint_measurements(&ui.painter_at(panel), &map, &self.measurer, hover, scale);
+            }
+            if let Some(fc) = self.channel_view.false_color {
+                paint_colorbar(&ui.painter_at(panel), panel, self.channel_view.mapped_channel().label(), fc);
+            }
+            if let Some(t) = self.interpolated_tex.as_ref().filter(|_| display_tex.is_none() && interpolated.is_some()) {
+                let text = format!("frame {idx} missing: cross-fade of {} and {}", t.before.0, t.after.0);
+                caption(&ui.painter_at(panel), panel, &text);
+            }
+            if self.picking {
+                if response.hovered() {
+                    ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
+                }
+                if let Some(pos) = response.clicked().then(|| response.interact_pointer_pos()).flatten() {
+                    self.pick_sample(pos, rect);
+                }
+            }
 
-                ui.add(egui::Image::new(tex).fit_to_exact_size(size));
-            } else {
-                ui.label("No image loaded.");
+            if self.show_stats {
+                self.update_stats(ui.ctx(), panel, rect);
+                self.stats_overlay(ui.ctx(), panel);
             }
         });
 
         // Allow ESC to quit (closes SSH connection and stops all pending image loads)
         if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
-            eprintln!("[UI] ESC pressed, closing application");
+            info!("Esc pressed, closing");
             ctx.send_viewport_cmd(egui::ViewportCommand::Close);
         }
     }
+
+    fn on_exit(&mut self, gl: Option<&eframe::glow::Context>) {
+        self.save_session();
+        if let Some(gl) = gl {
+            self.grade.destroy(gl);
+        }
+    }
+}
+
+/// Texture for frame `idx` of `cache` with `view` applied (CPU remap); `slot` keeps the
+/// remapped texture, tagged with the pixels and view it was built from
+fn channel_texture(
+    ctx: &egui::Context,
+    cache: &ImageCache,
+    idx: i64,
+    view: ChannelView,
+    options: egui::TextureOptions,
+    slot: &mut Option<(Arc<FramePixels>, ChannelView, egui::TextureHandle)>,
+    name: &str,
+) -> Option<egui::TextureId> {
+    let tex = cache.get(idx)?;
+    if view.is_all() {
+        return Some(tex.id());
+    }
+    let pixels = cache.texture_pixels(idx)?.clone();
+    let stale = match slot {
+        Some((src, v, _)) => !Arc::ptr_eq(src, &pixels) || *v != view,
+        None => true,
+    };
+    if stale {
+        let remapped = view.remap(&pixels.to_rgba())?;
+        let tex = rgba_to_texture(ctx, name, &remapped, options).ok()?;
+        *slot = Some((pixels, view, tex));
+    }
+    slot.as_ref().map(|(_, _, tex)| tex.id())
+}
+
+/// Centered note in place of a missing image
+/// Why a frame is not shown, for the placeholder in its place
+fn failure_text(error: &LoadError) -> String {
+    format!("Failed to load ({}):\n{}\n\nCtrl+R retries, F3 lists failed frames", error.kind.label(), error.message)
+}
+
+fn placeholder(ui: &egui::Ui, panel: egui::Rect, text: &str) {
+    let font = egui::FontId::proportional(14.0);
+    ui.painter_at(panel).text(panel.center(), egui::Align2::CENTER_CENTER, text, font, ui.visuals().text_color());
+}
+
+/// Path label in the top-left corner of a split view half
+/// Bars of the latest load times, oldest on the left: fetch at the bottom, decode on top
+fn timing_graph(ui: &mut egui::Ui, timings: &[LoadTiming]) {
+    const FETCH: egui::Color32 = egui::Color32::from_rgb(90, 160, 255);
+    const DECODE: egui::Color32 = egui::Color32::from_rgb(255, 170, 60);
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(240.0, 48.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, egui::Color32::from_black_alpha(120));
+    let longest = timings.iter().map(LoadTiming::total).max().unwrap_or_default().as_secs_f32();
+    if longest > 0.0 {
+        let bar = rect.width() / timings.len().max(60) as f32;
+        let height = |d: Duration| d.as_secs_f32() / longest * rect.height();
+        for (i, t) in timings.iter().enumerate() {
+            let x = rect.left() + i as f32 * bar;
+            let fetch_top = rect.bottom() - height(t.fetch.unwrap_or_default());
+            let decode_top = fetch_top - height(t.decode);
+            let column = |top: f32, bottom: f32| egui::Rect::from_x_y_ranges(x..=x + (bar - 1.0).max(1.0), top..=bottom);
+            painter.rect_filled(column(fetch_top, rect.bottom()), 0.0, FETCH);
+            painter.rect_filled(column(decode_top, fetch_top), 0.0, DECODE);
+        }
+    }
+    ui.horizontal(|ui| {
+        ui.colored_label(FETCH, "fetch");
+        ui.colored_label(DECODE, "decode");
+        ui.weak(format!("max {:.1} ms", longest * 1000.0));
+    });
+}
+
+fn caption(painter: &egui::Painter, panel: egui::Rect, text: &str) {
+    let galley = painter.layout_no_wrap(text.to_string(), egui::FontId::proportional(12.0), egui::Color32::WHITE);
+    let rect = egui::Rect::from_min_size(panel.min, galley.size()).expand(3.0).translate(egui::vec2(3.0, 3.0));
+    painter.rect_filled(rect, 2.0, egui::Color32::from_black_alpha(160));
+    painter.galley(rect.min + egui::vec2(3.0, 3.0), galley, egui::Color32::WHITE);
+}
+
+/// Legend of the false-color map in the panel's bottom-right corner: the colors from the
+/// low to the high end, labeled with their channel values
+fn paint_colorbar(painter: &egui::Painter, panel: egui::Rect, channel: &str, fc: FalseColor) {
+    let bar = egui::Rect::from_min_size(panel.right_bottom() - egui::vec2(276.0, 44.0), egui::vec2(256.0, 12.0));
+    let font = egui::FontId::proportional(11.0);
+    painter.rect_filled(bar.expand2(egui::vec2(8.0, 20.0)).translate(egui::vec2(0.0, 8.0)), 3.0, egui::Color32::from_black_alpha(160));
+    let mut mesh = egui::Mesh::default();
+    for step in 0..=64 {
+        let t = step as f32 / 64.0;
+        let [r, g, b] = fc.map.rgb(t);
+        let x = egui::lerp(bar.x_range(), t);
+        mesh.colored_vertex(egui::pos2(x, bar.top()), egui::Color32::from_rgb(r, g, b));
+        mesh.colored_vertex(egui::pos2(x, bar.bottom()), egui::Color32::from_rgb(r, g, b));
+        if step > 0 {
+            let i = 2 * step;
+            mesh.add_triangle(i - 2, i - 1, i);
+            mesh.add_triangle(i - 1, i + 1, i);
+        }
+    }
+    painter.add(egui::Shape::mesh(mesh));
+    let below = bar.bottom() + 3.0;
+    painter.text(egui::pos2(bar.left(), below), egui::Align2::LEFT_TOP, fc.low.to_string(), font.clone(), egui::Color32::WHITE);
+    painter.text(egui::pos2(bar.center().x, below), egui::Align2::CENTER_TOP, format!("{channel} ({})", fc.map.label()), font.clone(), egui::Color32::WHITE);
+    painter.text(egui::pos2(bar.right(), below), egui::Align2::RIGHT_TOP, fc.high.to_string(), font, egui::Color32::WHITE);
+}
+
+/// Outline the framing guides on the displayed image, each labeled in its top-left corner
+fn paint_guides<'a>(painter: &egui::Painter, image_rect: egui::Rect, guides: impl IntoIterator<Item = &'a Guide>) {
+    let stroke = egui::Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(255, 220, 0, 200));
+    let at = |u: f32, v: f32| image_rect.min + egui::vec2(u * image_rect.width(), v * image_rect.height());
+    for guide in guides {
+        match guide.shape(image_rect.width() / image_rect.height()) {
+            GuideShape::Rect([x0, y0, x1, y1]) => {
+                let rect = egui::Rect::from_min_max(at(x0, y0), at(x1, y1));
+                painter.rect_stroke(rect, 0.0, stroke);
+                let font = egui::FontId::proportional(11.0);
+                painter.text(rect.min + egui::vec2(3.0, 2.0), egui::Align2::LEFT_TOP, guide.to_string(), font, stroke.color);
+            }
+            GuideShape::Cross => {
+                let arm = 0.03 * image_rect.width().min(image_rect.height());
+                let c = image_rect.center();
+                painter.line_segment([c - egui::vec2(arm, 0.0), c + egui::vec2(arm, 0.0)], stroke);
+                painter.line_segment([c - egui::vec2(0.0, arm), c + egui::vec2(0.0, arm)], stroke);
+            }
+            GuideShape::Grid { columns, rows } => {
+                for i in 1..columns {
+                    let u = i as f32 / columns as f32;
+                    painter.line_segment([at(u, 0.0), at(u, 1.0)], stroke);
+                }
+                for i in 1..rows {
+                    let v = i as f32 / rows as f32;
+                    painter.line_segment([at(0.0, v), at(1.0, v)], stroke);
+                }
+            }
+        }
+    }
+}
+
+/// Sequences of the shown sequence's local directory that the configured patterns find,
+/// including the shown one (first, if its pattern is not configured)
+fn discover_siblings(cfg: &Config, pattern: &str, seq: &SequenceSpec) -> Vec<(String, SequenceSpec)> {
+    let current = (pattern.to_string(), seq.clone());
+    let SequenceSource::Local(dir) = &seq.source else {
+        return vec![current];
+    };
+    let patterns: Vec<String> = cfg.patterns_for(&seq.source).into_iter().filter(|p| !cfg.shards.contains_key(*p)).cloned().collect();
+    let mut found = match sibling_sequences(&patterns, dir, seq.index) {
+        Ok(found) => found,
+        Err(e) => {
+            warn!("could not list {}: {}", dir.display(), e);
+            Vec::new()
+        }
+    };
+    let key = seq.sequence_key(pattern);
+    match found.iter_mut().find(|(p, s)| s.sequence_key(p) == key) {
+        // The shown sequence keeps its own settings (shards) and frame
+        Some(entry) => *entry = current,
+        None => found.insert(0, current),
+    }
+    info!("{} sequences in {}", found.len(), dir.display());
+    found
+}
+
+/// Light and dark gray checks; drawn with repeating UVs, one texel per square
+fn checker_texture(ctx: &egui::Context) -> egui::TextureHandle {
+    let (light, dark) = ([204, 204, 204], [153, 153, 153]);
+    let rgb = [light, dark, dark, light].concat();
+    ctx.load_texture("zapvis_checker", egui::ColorImage::from_rgb([2, 2], &rgb), egui::TextureOptions::NEAREST_REPEAT)
+}
+
+/// Tiles to draw over the texture of frame `idx` of `cache` where `map` lays it out in
+/// `panel`; none unless the frame is tiled, nor with a channel view (which shows the overview)
+fn frame_tiles(
+    ctx: &egui::Context,
+    cache: &mut ImageCache,
+    idx: i64,
+    map: &FrameMapping,
+    panel: egui::Rect,
+    view: ChannelView,
+) -> Vec<([u32; 4], egui::TextureId)> {
+    if !cache.is_tiled(idx) || !view.is_all() {
+        return Vec::new();
+    }
+    let visible = visible_frame_bounds(map, panel);
+    let scale = (map.image_rect.area() / (map.size.x * map.size.y)).sqrt() * ctx.pixels_per_point();
+    cache.tiles(idx, visible, scale)
+}
+
+/// Frame pixels `[x0, y0, x1, y1]` that `map` shows in `panel`
+fn visible_frame_bounds(map: &FrameMapping, panel: egui::Rect) -> [f32; 4] {
+    let corners = [panel.left_top(), panel.right_top(), panel.right_bottom(), panel.left_bottom()].map(|p| map.to_frame(p));
+    let (xs, ys) = (corners.map(|c| c[0]), corners.map(|c| c[1]));
+    let min = |v: [f32; 4]| v.into_iter().fold(f32::INFINITY, f32::min);
+    let max = |v: [f32; 4]| v.into_iter().fold(0.0, f32::max);
+    [min(xs), min(ys), max(xs), max(ys)]
+}
+
+/// Region of a frame to decode for showing `visible` (frame pixels) of it: the visible
+/// part with half its size added on each side, or `None` for the whole frame when that
+/// is most of it or larger than `max_side`. `current` stays while it still covers the
+/// visible part and is not much larger than needed, so small pans reuse it.
+fn decode_region(visible: [f32; 4], size: egui::Vec2, current: Option<[u32; 4]>, max_side: u32) -> Option<[u32; 4]> {
+    let [x0, y0, x1, y1] = visible;
+    let (mx, my) = ((x1 - x0) / 2.0, (y1 - y0) / 2.0);
+    let want = [
+        (x0 - mx).max(0.0).floor() as u32,
+        (y0 - my).max(0.0).floor() as u32,
+        (x1 + mx).min(size.x).ceil() as u32,
+        (y1 + my).min(size.y).ceil() as u32,
+    ];
+    let area = |[x0, y0, x1, y1]: [u32; 4]| x1.saturating_sub(x0) as f32 * y1.saturating_sub(y0) as f32;
+    if area(want) > size.x * size.y * MAX_REGION_SHARE as f32 || want[2] - want[0] > max_side || want[3] - want[1] > max_side {
+        return None;
+    }
+    let covers = |r: [u32; 4]| r[0] as f32 <= x0.floor() && r[1] as f32 <= y0.floor() && r[2] as f32 >= x1.ceil().min(size.x) && r[3] as f32 >= y1.ceil().min(size.y);
+    match current {
+        Some(current) if covers(current) && area(current) <= 4.0 * area(want) => Some(current),
+        _ => Some(want),
+    }
+}
+
+/// Paint a frame's `texture` where `map` lays it out; a region decode covers only its
+/// part of the frame
+fn paint_frame(painter: &egui::Painter, map: &FrameMapping, texture: egui::TextureId, region: Option<FrameRegion>, grade: &GradeStage) {
+    match region {
+        Some(region) => paint_tiles(painter, map, &[(region.bounds, texture)], grade),
+        None => paint_oriented(painter, texture, map.image_rect, map.orientation, grade),
+    }
+}
+
+/// Paint `tiles` (frame pixel bounds and texture) where `map` puts their pixels
+fn paint_tiles(painter: &egui::Painter, map: &FrameMapping, tiles: &[([u32; 4], egui::TextureId)], grade: &GradeStage) {
+    for &([x0, y0, x1, y1], texture) in tiles {
+        let (x0, y0, x1, y1) = (x0 as f32, y0 as f32, x1 as f32, y1 as f32);
+        let corners = [[x0, y0], [x1, y0], [x1, y1], [x0, y1]];
+        let mut mesh = egui::Mesh::with_texture(texture);
+        for (corner, uv) in corners.into_iter().zip([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]) {
+            mesh.vertices.push(egui::epaint::Vertex {
+                pos: map.to_screen(corner),
+                uv: egui::pos2(uv[0], uv[1]),
+                color: egui::Color32::WHITE,
+            });
+        }
+        mesh.indices.extend([0, 1, 2, 0, 2, 3]);
+        grade.paint(painter, mesh);
+    }
+}
+
+/// Paint `texture` into `rect` with rotation/flips applied through the quad's UVs.
+fn paint_oriented(
+    painter: &egui::Painter,
+    texture: egui::TextureId,
+    rect: egui::Rect,
+    orientation: Orientation,
+    grade: &GradeStage,
+) {
+    let corners = [rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom()];
+    let mut mesh = egui::Mesh::with_texture(texture);
+    for (pos, uv) in corners.into_iter().zip(orientation.corner_uvs()) {
+        mesh.vertices.push(egui::epaint::Vertex {
+            pos,
+            uv: egui::pos2(uv[0], uv[1]),
+            color: egui::Color32::WHITE,
+        });
+    }
+    mesh.indices.extend([0, 1, 2, 0, 2, 3]);
+    grade.paint(painter, mesh);
 }
@@ -0,0 +1,114 @@
+//! Audio output for playback. Real output needs the `audio` feature; without it,
+//! opening a track reports that audio support was not compiled in.
+use tracing::{info, warn};
+use zapvis::config::AudioTrack;
+
+#[cfg(feature = "audio")]
+mod imp {
+    use anyhow::{anyhow, Context, Result};
+    use rodio::{Decoder, OutputStream, Sink, Source};
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::time::Duration;
+    use tracing::{debug, warn};
+    use zapvis::config::AudioTrack;
+
+    pub struct AudioPlayer {
+        track: AudioTrack,
+        // Output stream must outlive the sink
+        _stream: OutputStream,
+        sink: Sink,
+        /// Playback speed correcting a wrong header sample rate
+        speed: f32,
+    }
+
+    impl AudioPlayer {
+        pub fn open(track: &AudioTrack) -> Result<Self> {
+            let (stream, handle) = OutputStream::try_default().context("No audio output device")?;
+            let sink = Sink::try_new(&handle).map_err(|e| anyhow!("Failed to open audio sink: {e}"))?;
+            sink.pause();
+            let mut player = Self {
+                track: track.clone(),
+                _stream: stream,
+                sink,
+                speed: 1.0,
+            };
+            player.load()?;
+            Ok(player)
+        }
+
+        /// (Re)queue the decoder; needed initially and after the track played to its end.
+        fn load(&mut self) -> Result<()> {
+            let file = File::open(&self.track.path)
+                .with_context(|| format!("Failed to open audio {}", self.track.path.display()))?;
+            let decoder = Decoder::new(BufReader::new(file))
+                .with_context(|| format!("Failed to decode audio {}", self.track.path.display()))?;
+            self.speed = match self.track.sample_rate {
+                Some(rate) if rate > 0 => rate as f32 / decoder.sample_rate() as f32,
+                _ => 1.0,
+            };
+            self.sink.set_speed(self.speed);
+            self.sink.append(decoder);
+            Ok(())
+        }
+
+        /// Follow the frame clock: `media_time` is where the audio should be (seconds).
+        /// Sink positions and seeks are in played-back time, so the speed correction
+        /// needs no conversion here.
+        pub fn sync(&mut self, media_time: f64, playing: bool) {
+            if !playing || media_time < 0.0 {
+                self.sink.pause();
+                return;
+            }
+            if self.sink.empty() {
+                if let Err(e) = self.load() {
+                    warn!("{e:#}");
+                    return;
+                }
+            }
+            let position = self.sink.get_pos().as_secs_f64();
+            let drift = position - media_time;
+            if AudioTrack::needs_seek(position, media_time, self.sink.is_paused()) {
+                if let Err(e) = self.sink.try_seek(Duration::from_secs_f64(media_time)) {
+                    warn!("seek failed: {e}");
+                }
+                if !self.sink.is_paused() {
+                    debug!("corrected drift of {:.0} ms", drift * 1000.0);
+                }
+            }
+            self.sink.play();
+        }
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+mod imp {
+    use anyhow::{anyhow, Result};
+    use zapvis::config::AudioTrack;
+
+    pub struct AudioPlayer;
+
+    impl AudioPlayer {
+        pub fn open(_track: &AudioTrack) -> Result<Self> {
+            Err(anyhow!("zapvis was built without the `audio` feature"))
+        }
+
+        pub fn sync(&mut self, _media_time: f64, _playing: bool) {}
+    }
+}
+
+pub use imp::AudioPlayer;
+
+/// Open the audio for `track`, logging (not failing) when audio is unavailable.
+pub fn open_audio(track: &AudioTrack) -> Option<AudioPlayer> {
+    match AudioPlayer::open(track) {
+        Ok(p) => {
+            info!("playing {} (offset {:+.3}s)", track.path.display(), track.offset);
+            Some(p)
+        }
+        Err(e) => {
+            warn!("{e:#}");
+            None
+        }
+    }
+}
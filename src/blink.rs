@@ -0,0 +1,93 @@
+use std::time::{Duration, Instant};
+
+/// Blink comparison: two frames shown alternately, flipped by key or automatically
+/// at a fixed rate. Both stay pinned in the cache, so a flip never waits for a load.
+pub struct Blink {
+    frames: [i64; 2],
+    /// Index into `frames` of the frame on screen
+    showing: usize,
+    /// Automatic flip interval; `None` flips only by key
+    auto: Option<Duration>,
+    last_flip: Instant,
+}
+
+impl Blink {
+    /// Blink between `a` and `b`, starting on `b`.
+    pub fn new(a: i64, b: i64) -> Self {
+        Self {
+            frames: [a, b],
+            showing: 1,
+            auto: None,
+            last_flip: Instant::now(),
+        }
+    }
+
+    pub fn frames(&self) -> [i64; 2] {
+        self.frames
+    }
+
+    /// Frame on screen
+    pub fn current(&self) -> i64 {
+        self.frames[self.showing]
+    }
+
+    /// "A" or "B", for the frame on screen
+    pub fn label(&self) -> &'static str {
+        ["A", "B"][self.showing]
+    }
+
+    /// Show the other frame; returns it.
+    pub fn flip(&mut self) -> i64 {
+        self.showing = 1 - self.showing;
+        self.last_flip = Instant::now();
+        self.current()
+    }
+
+    /// Flip every `interval` on its own (`None` stops).
+    pub fn set_auto(&mut self, interval: Option<Duration>) {
+        self.auto = interval;
+        self.last_flip = Instant::now();
+    }
+
+    pub fn is_auto(&self) -> bool {
+        self.auto.is_some()
+    }
+
+    /// With automatic flipping on: flip if it is due. Returns whether it flipped and
+    /// how long until the next flip.
+    pub fn tick(&mut self) -> Option<(bool, Duration)> {
+        let interval = self.auto?;
+        let flipped = self.last_flip.elapsed() >= interval;
+        if flipped {
+            self.flip();
+        }
+        Some((flipped, interval.saturating_sub(self.last_flip.elapsed())))
+    }
+}
+
+/// Flip interval for a blink rate in flips per second (clamped to a sane range)
+pub fn blink_interval(hz: f64) -> Duration {
+    let hz = if hz.is_finite() { hz.clamp(0.1, 60.0) } else { 2.0 };
+    Duration::from_secs_f64(1.0 / hz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flips_between_the_two_frames() {
+        let mut blink = Blink::new(120, 450);
+        assert_eq!((blink.current(), blink.label()), (450, "B"));
+        assert_eq!(blink.flip(), 120);
+        assert_eq!(blink.label(), "A");
+        assert_eq!(blink.flip(), 450);
+        assert_eq!(blink.tick(), None, "no automatic flips unless enabled");
+
+        blink.set_auto(Some(Duration::ZERO));
+        assert_eq!(blink.tick(), Some((true, Duration::ZERO)));
+        assert_eq!(blink.current(), 120);
+        assert_eq!(blink_interval(4.0), Duration::from_millis(250));
+        assert_eq!(blink_interval(f64::NAN), Duration::from_millis(500));
+    }
+}
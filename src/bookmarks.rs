@@ -0,0 +1,211 @@
+//! Frame bookmarks stored in per-reviewer sidecar files.
+//!
+//! Every bookmark carries its author, so sidecars from several reviewers can be
+//! merged (union) without losing attribution, and merged files can be merged again.
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::ConfigError;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub index: i64,
+    pub author: String,
+    #[serde(default)]
+    pub note: String,
+}
+
+/// Contents of a bookmark sidecar (TOML).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BookmarkFile {
+    /// `SequenceSpec::sequence_key` the bookmarks were made on (informational)
+    #[serde(default)]
+    pub sequence: String,
+    #[serde(default)]
+    pub bookmarks: Vec<Bookmark>,
+}
+
+/// Merged bookmarks of one sequence: the local reviewer's own plus imported sidecars.
+#[derive(Debug, Clone, Default)]
+pub struct Bookmarks {
+    author: String,
+    entries: BTreeSet<Bookmark>,
+}
+
+impl Bookmarks {
+    pub fn new(author: impl Into<String>) -> Self {
+        Self {
+            author: author.into(),
+            entries: BTreeSet::new(),
+        }
+    }
+
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    /// Union with another sidecar; identical (index, author, note) entries are kept once.
+    pub fn merge(&mut self, file: BookmarkFile) {
+        self.entries.extend(file.bookmarks);
+    }
+
+    /// Add or remove the local author's bookmark on `index`. Returns true if it was added.
+    pub fn toggle(&mut self, index: i64) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|b| !(b.index == index && b.author == self.author));
+        if self.entries.len() != before {
+            return false;
+        }
+        self.entries.insert(Bookmark {
+            index,
+            author: self.author.clone(),
+            note: String::new(),
+        });
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// All bookmarks, ordered by index then author.
+    pub fn iter(&self) -> impl Iterator<Item = &Bookmark> {
+        self.entries.iter()
+    }
+
+    /// Bookmarks (from any author) on `index`.
+    pub fn at(&self, index: i64) -> impl Iterator<Item = &Bookmark> {
+        self.entries.iter().filter(move |b| b.index == index)
+    }
+
+    /// Nearest bookmarked index after (or before) `index`.
+    pub fn next_index(&self, index: i64, forward: bool) -> Option<i64> {
+        if forward {
+            self.entries.iter().map(|b| b.index).find(|&i| i > index)
+        } else {
+            self.entries.iter().rev().map(|b| b.index).find(|&i| i < index)
+        }
+    }
+
+    /// The local author's bookmarks as a sidecar to save or share.
+    pub fn own_file(&self, sequence: &str) -> BookmarkFile {
+        BookmarkFile {
+            sequence: sequence.to_string(),
+            bookmarks: self.entries.iter().filter(|b| b.author == self.author).cloned().collect(),
+        }
+    }
+}
+
+pub fn load_bookmark_file(path: &Path) -> Result<BookmarkFile, ConfigError> {
+    let txt = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    toml::from_str(&txt).map_err(|source| ConfigError::Parse {
+        name: format!("bookmarks {}", path.display()),
+        source,
+    })
+}
+
+pub fn save_bookmark_file(path: &Path, file: &BookmarkFile) -> Result<(), ConfigError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    let txt = toml::to_string_pretty(file).map_err(|source| ConfigError::Serialize {
+        name: "bookmarks".to_string(),
+        source,
+    })?;
+    fs::write(path, txt).map_err(|source| ConfigError::Write {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Default sidecar for the local reviewer's bookmarks on a sequence, under the config directory.
+pub fn bookmark_path(sequence_key: &str) -> Result<PathBuf, ConfigError> {
+    let proj = ProjectDirs::from("dev", "zapvis", "zapvis").ok_or(ConfigError::NoConfigDir)?;
+    let name: String = sequence_key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    Ok(proj.config_dir().join("bookmarks").join(format!("{name}.toml")))
+}
+
+/// Reviewer name for new bookmarks: the configured author, else the login name.
+pub fn default_author(configured: Option<&str>) -> String {
+    configured
+        .map(str::to_string)
+        .or_else(|| std::env::var("USER").ok())
+        .or_else(|| std::env::var("USERNAME").ok())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "anonymous".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mark(index: i64, author: &str) -> Bookmark {
+        Bookmark {
+            index,
+            author: author.to_string(),
+            note: String::new(),
+        }
+    }
+
+    #[test]
+    fn merge_is_a_union_with_attribution() {
+        let mut marks = Bookmarks::new("alice");
+        marks.toggle(10);
+        marks.merge(BookmarkFile {
+            sequence: String::new(),
+            bookmarks: vec![mark(10, "bob"), mark(30, "bob")],
+        });
+        // Merging the same sidecar twice changes nothing
+        marks.merge(BookmarkFile {
+            sequence: String::new(),
+            bookmarks: vec![mark(30, "bob")],
+        });
+        let authors: Vec<&str> = marks.at(10).map(|b| b.author.as_str()).collect();
+        assert_eq!(authors, ["alice", "bob"]);
+        assert_eq!(marks.len(), 3);
+        // Only the local author's bookmarks are written back
+        assert_eq!(marks.own_file("seq").bookmarks, vec![mark(10, "alice")]);
+    }
+
+    #[test]
+    fn toggle_and_navigate() {
+        let mut marks = Bookmarks::new("alice");
+        assert!(marks.toggle(5));
+        marks.merge(BookmarkFile {
+            sequence: String::new(),
+            bookmarks: vec![mark(20, "bob")],
+        });
+        assert_eq!(marks.next_index(5, true), Some(20));
+        assert_eq!(marks.next_index(20, false), Some(5));
+        assert_eq!(marks.next_index(20, true), None);
+        // Removing only touches the local author's entry
+        assert!(!marks.toggle(5));
+        assert!(marks.toggle(20));
+        assert_eq!(marks.at(20).count(), 2);
+    }
+
+    #[test]
+    fn sidecar_round_trip() {
+        let file = BookmarkFile {
+            sequence: "/data|frame_####.png".to_string(),
+            bookmarks: vec![mark(1, "alice"), mark(2, "bob")],
+        };
+        let txt = toml::to_string_pretty(&file).unwrap();
+        let back: BookmarkFile = toml::from_str(&txt).unwrap();
+        assert_eq!(back.bookmarks, file.bookmarks);
+        assert_eq!(back.sequence, file.sequence);
+    }
+}
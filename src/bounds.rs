@@ -0,0 +1,144 @@
+//! First/last existing frame of a sequence.
+//!
+//! Directories are never listed, so the ends are found by probing single indices:
+//! a galloping search outwards from a known frame, then a binary search for the
+//! edge. This assumes the frames between the ends are contiguous; bounds can also
+//! be narrowed later from frames that turn out to be missing.
+use crate::sequence::MAX_INDEX;
+
+/// Known ends of a sequence (`None` while unknown).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SequenceBounds {
+    pub first: Option<i64>,
+    pub last: Option<i64>,
+}
+
+impl SequenceBounds {
+    /// Clamp `idx` into the known range.
+    pub fn clamp(&self, idx: i64) -> i64 {
+        let idx = self.first.map_or(idx, |first| idx.max(first));
+        self.last.map_or(idx, |last| idx.min(last))
+    }
+
+    pub fn contains(&self, idx: i64) -> bool {
+        self.clamp(idx) == idx
+    }
+
+    /// Narrow the bounds from a frame found missing while `current` exists.
+    pub fn learn_missing(&mut self, missing: i64, current: i64) {
+        if missing > current {
+            let last = missing - 1;
+            self.last = Some(self.last.map_or(last, |l| l.min(last)));
+        } else if missing < current {
+            let first = missing + 1;
+            self.first = Some(self.first.map_or(first, |f| f.max(first)));
+        }
+    }
+
+    /// Number of frames between the ends, if both are known.
+    pub fn frame_count(&self) -> Option<u64> {
+        Some(self.last?.checked_sub(self.first?)? as u64 + 1)
+    }
+}
+
+/// Find both ends of the sequence around the existing frame `index`, looking no lower
+/// than `floor` (see `SequenceSpec::min_index`).
+pub fn discover_bounds<E>(index: i64, floor: i64, mut exists: impl FnMut(i64) -> Result<bool, E>) -> Result<SequenceBounds, E> {
+    let last = last_existing(index, &mut exists)?;
+    let first = first_existing(index, floor, &mut exists)?;
+    Ok(SequenceBounds {
+        first: Some(first),
+        last: Some(last),
+    })
+}
+
+/// Last frame of the run of existing frames starting at `from` (which must exist).
+pub fn last_existing<E>(from: i64, exists: &mut impl FnMut(i64) -> Result<bool, E>) -> Result<i64, E> {
+    // Gallop: lo exists, hi is the first probe found missing
+    let mut lo = from;
+    let mut step = 1i64;
+    let mut hi = loop {
+        let probe = lo.saturating_add(step).min(MAX_INDEX);
+        if probe == lo {
+            return Ok(lo);
+        }
+        if !exists(probe)? {
+            break probe;
+        }
+        lo = probe;
+        step = step.saturating_mul(2);
+    };
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if exists(mid)? {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    Ok(lo)
+}
+
+fn first_existing<E>(from: i64, floor: i64, exists: &mut impl FnMut(i64) -> Result<bool, E>) -> Result<i64, E> {
+    // Gallop downwards: hi exists, lo is the first probe found missing
+    let mut hi = from;
+    let mut step = 1i64;
+    let mut lo = loop {
+        if hi <= floor {
+            return Ok(hi);
+        }
+        let probe = hi.saturating_sub(step).max(floor);
+        if !exists(probe)? {
+            break probe;
+        }
+        hi = probe;
+        step = step.saturating_mul(2);
+    };
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if exists(mid)? {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    Ok(hi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discovers_both_ends() {
+        let mut probes = 0;
+        let bounds = discover_bounds(1234, 0, |i| {
+            probes += 1;
+            Ok::<_, ()>((17..=5000).contains(&i))
+        })
+        .unwrap();
+        assert_eq!(bounds.first, Some(17));
+        assert_eq!(bounds.last, Some(5000));
+        assert_eq!(bounds.frame_count(), Some(4984));
+        assert!(probes < 60, "{probes} probes");
+
+        let single = discover_bounds(0, 0, |i| Ok::<_, ()>(i == 0)).unwrap();
+        assert_eq!((single.first, single.last), (Some(0), Some(0)));
+
+        // Signed sequences continue below zero
+        let signed = discover_bounds(3, -MAX_INDEX, |i| Ok::<_, ()>((-5..=5).contains(&i))).unwrap();
+        assert_eq!((signed.first, signed.last), (Some(-5), Some(5)));
+    }
+
+    #[test]
+    fn learned_bounds_clamp_navigation() {
+        let mut bounds = SequenceBounds::default();
+        assert_eq!(bounds.clamp(99), 99);
+        bounds.learn_missing(101, 50);
+        bounds.learn_missing(120, 50); // a farther miss does not widen the range
+        bounds.learn_missing(9, 50);
+        assert_eq!(bounds.clamp(1000), 100);
+        assert_eq!(bounds.clamp(0), 10);
+        assert!(bounds.contains(42));
+    }
+}
@@ -0,0 +1,116 @@
+use image::{Rgba, RgbaImage};
+
+use crate::colormap::FalseColor;
+
+/// Which part of the image is displayed: everything, a single channel, or luminance.
+/// Single channels and luminance are shown as opaque grayscale.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Channel {
+    #[default]
+    All,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    Luminance,
+}
+
+const ORDER: [Channel; 6] = [
+    Channel::All,
+    Channel::Red,
+    Channel::Green,
+    Channel::Blue,
+    Channel::Alpha,
+    Channel::Luminance,
+];
+
+impl Channel {
+    pub fn label(self) -> &'static str {
+        match self {
+            Channel::All => "RGBA",
+            Channel::Red => "R",
+            Channel::Green => "G",
+            Channel::Blue => "B",
+            Channel::Alpha => "A",
+            Channel::Luminance => "Luma",
+        }
+    }
+
+    /// The channel's value of a pixel; `None` for `All`
+    pub fn pick(self) -> Option<fn(&Rgba<u8>) -> u8> {
+        Some(match self {
+            Channel::All => return None,
+            Channel::Red => |p| p[0],
+            Channel::Green => |p| p[1],
+            Channel::Blue => |p| p[2],
+            Channel::Alpha => |p| p[3],
+            // Rec. 709 luma
+            Channel::Luminance => |p| (0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32).round() as u8,
+        })
+    }
+}
+
+/// The displayed channel, in grayscale or in false color (Alt+C)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChannelView {
+    pub channel: Channel,
+    /// Colormap the channel is shown with; with `All`, luminance is mapped
+    pub false_color: Option<FalseColor>,
+}
+
+impl ChannelView {
+    pub fn next(self) -> Self {
+        let i = ORDER.iter().position(|&c| c == self.channel).unwrap_or(0);
+        Self { channel: ORDER[(i + 1) % ORDER.len()], ..self }
+    }
+
+    pub fn prev(self) -> Self {
+        let i = ORDER.iter().position(|&c| c == self.channel).unwrap_or(0);
+        Self { channel: ORDER[(i + ORDER.len() - 1) % ORDER.len()], ..self }
+    }
+
+    /// View named by its label, ignoring case (`"luma"`, `"r"`, `"rgba"`, ...)
+    pub fn from_name(name: &str) -> Option<Self> {
+        let channel = ORDER.into_iter().find(|c| c.label().eq_ignore_ascii_case(name.trim()))?;
+        Some(Self { channel, false_color: None })
+    }
+
+    /// True when the frame is shown as it is
+    pub fn is_all(self) -> bool {
+        self == Self::default()
+    }
+
+    /// Channel mapped to colors: the chosen one, or luminance when all are shown
+    pub fn mapped_channel(self) -> Channel {
+        match (self.channel, self.false_color) {
+            (Channel::All, Some(_)) => Channel::Luminance,
+            (channel, _) => channel,
+        }
+    }
+
+    pub fn label(self) -> String {
+        match self.false_color {
+            Some(fc) => format!("{} {} {}..{}", self.mapped_channel().label(), fc.map.label(), fc.low, fc.high),
+            None => self.channel.label().to_string(),
+        }
+    }
+
+    /// CPU remap of `src` into the displayed grayscale or false-color image. Returns None
+    /// for `All` without false color.
+    pub fn remap(self, src: &RgbaImage) -> Option<RgbaImage> {
+        let pick = self.mapped_channel().pick()?;
+        let table = self.false_color.map(FalseColor::table);
+        let mut out = RgbaImage::new(src.width(), src.height());
+        for (o, p) in out.pixels_mut().zip(src.pixels()) {
+            let v = pick(p);
+            *o = match &table {
+                Some(table) => {
+                    let [r, g, b] = table[v as usize];
+                    Rgba([r, g, b, 255])
+                }
+                None => Rgba([v, v, v, 255]),
+            };
+        }
+        Some(out)
+    }
+}
@@ -0,0 +1,272 @@
+//! `zapvis check`: scan a sequence for missing, empty and undecodable frames and for
+//! resolution changes, without opening the viewer.
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::fs;
+use tracing::info;
+
+use crate::cli::{CheckArgs, FrameRange};
+use crate::commands::print_json;
+use crate::input::open_input;
+use zapvis::config::load_config;
+use zapvis::frame::{load_frame_from_bytes, load_frame_from_bytes_with_command, DecodeOptions};
+use zapvis::sequence::{match_index, SequenceSource, SequenceSpec};
+
+/// What became of one frame of the scanned range
+pub enum FrameCheck {
+    Missing,
+    Empty,
+    Undecodable(String),
+    Decoded { width: u32, height: u32 },
+}
+
+/// A frame whose resolution differs from the decodable frame before it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResolutionChange {
+    pub index: i64,
+    pub from: (u32, u32),
+    pub to: (u32, u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Undecodable {
+    pub index: i64,
+    pub error: String,
+}
+
+/// Findings of `zapvis check`, printed as a table or (with `--json`) as JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HealthReport {
+    pub sequence: String,
+    pub pattern: String,
+    pub first: i64,
+    pub last: i64,
+    /// Frames in `first..=last` that exist and decode
+    pub ok: u64,
+    pub missing: Vec<i64>,
+    pub zero_byte: Vec<i64>,
+    pub undecodable: Vec<Undecodable>,
+    /// Resolution of the first decodable frame
+    pub resolution: Option<(u32, u32)>,
+    pub resolution_changes: Vec<ResolutionChange>,
+}
+
+impl HealthReport {
+    pub fn new(sequence: String, pattern: String, range: FrameRange) -> Self {
+        Self {
+            sequence,
+            pattern,
+            first: range.first,
+            last: range.last,
+            ok: 0,
+            missing: Vec::new(),
+            zero_byte: Vec::new(),
+            undecodable: Vec::new(),
+            resolution: None,
+            resolution_changes: Vec::new(),
+        }
+    }
+
+    /// Add frame `index`; frames are expected in order
+    pub fn record(&mut self, index: i64, check: FrameCheck) {
+        match check {
+            FrameCheck::Missing => self.missing.push(index),
+            FrameCheck::Empty => self.zero_byte.push(index),
+            FrameCheck::Undecodable(error) => self.undecodable.push(Undecodable { index, error }),
+            FrameCheck::Decoded { width, height } => {
+                self.ok += 1;
+                let size = (width, height);
+                let previous = self.resolution_changes.last().map(|c| c.to).or(self.resolution);
+                match previous {
+                    None => self.resolution = Some(size),
+                    Some(from) if from != size => self.resolution_changes.push(ResolutionChange { index, from, to: size }),
+                    Some(_) => {}
+                }
+            }
+        }
+    }
+
+    pub fn problems(&self) -> usize {
+        self.missing.len() + self.zero_byte.len() + self.undecodable.len() + self.resolution_changes.len()
+    }
+
+    /// Summary table for the terminal
+    pub fn table(&self) -> String {
+        let count = self.last.abs_diff(self.first) + 1;
+        let mut lines = vec![
+            format!("Sequence:           {}", self.sequence),
+            format!("Pattern:            {}", self.pattern),
+            format!("Frames:             {}..={} ({} expected, {} ok)", self.first, self.last, count, self.ok),
+        ];
+        if let Some((w, h)) = self.resolution {
+            lines.push(format!("Resolution:         {w}x{h}"));
+        }
+        for (label, indices) in [("Missing:           ", &self.missing), ("Zero-byte:         ", &self.zero_byte)] {
+            lines.push(format!("{label} {:<5} {}", indices.len(), index_runs(indices)).trim_end().to_string());
+        }
+        lines.push(format!("Undecodable:        {}", self.undecodable.len()));
+        for u in &self.undecodable {
+            lines.push(format!("  {}: {}", u.index, u.error));
+        }
+        lines.push(format!("Resolution changes: {}", self.resolution_changes.len()));
+        for c in &self.resolution_changes {
+            lines.push(format!("  {}: {}x{} -> {}x{}", c.index, c.from.0, c.from.1, c.to.0, c.to.1));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Sorted indices as runs, e.g. `3..=5, 9`
+fn index_runs(indices: &[i64]) -> String {
+    let mut runs: Vec<(i64, i64)> = Vec::new();
+    for &idx in indices {
+        match runs.last_mut() {
+            Some((_, last)) if *last + 1 == idx => *last = idx,
+            _ => runs.push((idx, idx)),
+        }
+    }
+    let runs: Vec<String> = runs
+        .into_iter()
+        .map(|(a, b)| if a == b { a.to_string() } else { format!("{a}..={b}") })
+        .collect();
+    runs.join(", ")
+}
+
+/// First and last frame in a local, unsharded directory listing, which (unlike probing
+/// from the opened frame) also reaches frames behind gaps
+fn listed_extent(seq: &SequenceSpec, pattern: &str) -> Option<FrameRange> {
+    let SequenceSource::Local(dir) = &seq.source else {
+        return None;
+    };
+    if seq.relative_path_for(seq.index) != seq.file_name_for(seq.index) {
+        return None;
+    }
+    let indices: Vec<i64> = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| match_index(pattern, entry.file_name().to_str()?).ok().flatten())
+        .collect();
+    Some(FrameRange {
+        first: *indices.iter().min()?,
+        last: *indices.iter().max()?,
+    })
+}
+
+/// Check a frame's file content. The resolution is the one stored: no EXIF orientation,
+/// downscaling, color conversion or tone mapping, whatever the config says.
+fn check_bytes(bytes: &[u8], source: &str, loader_command: Option<&str>) -> FrameCheck {
+    if bytes.is_empty() {
+        return FrameCheck::Empty;
+    }
+    let decoded = match loader_command {
+        Some(command) => load_frame_from_bytes_with_command(command, bytes, source, DecodeOptions::raw()),
+        None => load_frame_from_bytes(bytes, source, DecodeOptions::raw()),
+    };
+    match decoded {
+        Ok(pixels) => FrameCheck::Decoded {
+            width: pixels.width(),
+            height: pixels.height(),
+        },
+        Err(e) => FrameCheck::Undecodable(e.to_string()),
+    }
+}
+
+pub fn run_check(args: CheckArgs) -> Result<()> {
+    let mut cfg = load_config().unwrap_or_default();
+    let opened = open_input(&args.input, args.pattern.clone(), &mut cfg)?;
+    let (pattern, seq) = &opened.candidates[0];
+    let range = match args.range {
+        Some(range) => range,
+        None => match listed_extent(seq, pattern) {
+            Some(range) => range,
+            None => {
+                let bounds = seq.find_bounds(opened.remote_tx.as_ref()).context("Failed to find the sequence's first and last frame")?;
+                let (first, last) = bounds.first.zip(bounds.last).ok_or_else(|| anyhow!("The sequence has no frames"))?;
+                FrameRange { first, last }
+            }
+        },
+    };
+    info!("checking frames {}..={} of {} with pattern {:?}", range.first, range.last, seq.source.location(), pattern);
+    let loader_command = cfg.loader_command_for(pattern);
+    opened.remote_range.set(range.first, range.last);
+    let mut report = HealthReport::new(seq.source.location(), pattern.clone(), range);
+    for (n, idx) in range.frames().enumerate() {
+        let source = seq.path_display(idx);
+        let check = match seq.fetch_bytes(idx, opened.remote_tx.as_ref()) {
+            Err(e) if e.is_not_found() => FrameCheck::Missing,
+            Err(e) => return Err(e).context(format!("Failed to fetch frame {idx}")),
+            Ok(bytes) => check_bytes(&bytes, &source, loader_command),
+        };
+        report.record(idx, check);
+        if !args.json {
+            info!("checked {}/{} {}", n + 1, range.len(), source);
+        }
+    }
+
+    if args.json {
+        print_json(&report)?;
+    } else {
+        println!("{}", report.table());
+    }
+    match report.problems() {
+        0 => Ok(()),
+        n => Err(anyhow!("{n} problems found")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_collects_problems_and_resolution_changes() {
+        let mut report = HealthReport::new("/data".to_string(), "f_####.png".to_string(), FrameRange { first: 1, last: 8 });
+        let decoded = |width, height| FrameCheck::Decoded { width, height };
+        report.record(1, decoded(64, 48));
+        report.record(2, FrameCheck::Missing);
+        report.record(3, FrameCheck::Missing);
+        report.record(4, FrameCheck::Empty);
+        report.record(5, decoded(64, 48));
+        report.record(6, decoded(32, 24));
+        report.record(7, FrameCheck::Undecodable("truncated".to_string()));
+        report.record(8, decoded(32, 24));
+        assert_eq!(report.ok, 4);
+        assert_eq!(report.resolution, Some((64, 48)));
+        assert_eq!(report.resolution_changes, [ResolutionChange { index: 6, from: (64, 48), to: (32, 24) }]);
+        assert_eq!(report.problems(), 5);
+        assert_eq!(index_runs(&[2, 3, 5, 9, 10, 11]), "2..=3, 5, 9..=11");
+        assert!(report.table().contains("Missing:            2     2..=3"));
+
+        let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["missing"], serde_json::json!([2, 3]));
+        assert_eq!(json["undecodable"][0]["index"], 7);
+        assert_eq!(json["resolution_changes"][0]["to"], serde_json::json!([32, 24]));
+    }
+
+    /// A 4x2 JPEG whose EXIF Orientation tag asks for a quarter turn (6)
+    fn rotated_jpeg() -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        image::RgbImage::new(4, 2).write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg).unwrap();
+        // APP1 segment right after SOI: "Exif\0\0", a little-endian TIFF header and one
+        // IFD entry, Orientation (0x0112) as SHORT = 6
+        let mut app1 = vec![0xFF, 0xE1, 0x00, 0x22];
+        app1.extend_from_slice(b"Exif\0\0II*\0\x08\0\0\0\x01\0");
+        app1.extend_from_slice(&[0x12, 0x01, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00]);
+        app1.extend_from_slice(&[0; 4]);
+        jpeg.splice(2..2, app1);
+        jpeg
+    }
+
+    #[test]
+    fn exif_rotation_is_no_resolution_change() {
+        let rotated = rotated_jpeg();
+        let shown = load_frame_from_bytes(&rotated, "f_0002.jpg", DecodeOptions { honor_exif_orientation: true, ..DecodeOptions::raw() }).unwrap();
+        assert_eq!(shown.dimensions(), (2, 4), "the fixture's tag is read");
+
+        let mut report = HealthReport::new("/data".to_string(), "f_####.jpg".to_string(), FrameRange { first: 1, last: 2 });
+        report.record(1, FrameCheck::Decoded { width: 4, height: 2 });
+        report.record(2, check_bytes(&rotated, "f_0002.jpg", None));
+        assert_eq!(report.resolution_changes, []);
+        assert!(matches!(check_bytes(&[], "f_0003.jpg", None), FrameCheck::Empty));
+    }
+}
@@ -1,19 +1,358 @@
-use clap::Parser;
+use clap::{ArgAction, Args as ClapArgs, Parser, Subcommand, ValueEnum};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
 
 /// zapvis: sequence-only image viewer.
 /// Opens a file, matches it against configured patterns with # as digit placeholders,
 /// then navigates by changing the numeric id and stat()'ing the constructed filename.
+///
+/// `zapvis <file>` is short for `zapvis view <file>`.
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[command(author, version, about, args_conflicts_with_subcommands = true)]
 pub struct Args {
-    /// Image file to open (recommended). Folder mode is intentionally not supported.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub view: ViewArgs,
+
+    /// Log more: -v info, -vv debug (with timings of SSH requests and decodes), -vvv trace.
+    /// ZAPVIS_LOG takes precedence, e.g. ZAPVIS_LOG="info,zapvis::remote_worker=debug".
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Old spelling of `zapvis config show`, kept for existing scripts
+    #[arg(short = 'c', long = "config", hide = true, conflicts_with = "input")]
+    pub show_config: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Open a sequence in the viewer
+    View(ViewArgs),
+    /// Inspect or edit the config file
+    Config(ConfigArgs),
+    /// Manage the configured filename patterns
+    Patterns(PatternsArgs),
+    /// Write a range of frames to a local directory without opening the viewer
+    Export(ExportArgs),
+    /// Encode a range of frames to a video file with ffmpeg
+    Encode(EncodeArgs),
+    /// Copy a range of frames as they are (no decoding) to a local directory
+    Fetch(FetchArgs),
+    /// Report missing, empty and undecodable frames and resolution changes
+    Check(CheckArgs),
+}
+
+#[derive(ClapArgs, Debug, Default)]
+pub struct ViewArgs {
+    /// Image file (or `.zapseq` manifest) to open; without one, the recently opened
+    /// sequences are offered. Folder mode is intentionally not supported. `-` reads the
+    /// first frame shown from stdin (see `--dir`).
     pub input: Option<String>,
 
+    /// Second sequence to show side by side, stepping in lockstep with the first
+    pub compare: Option<String>,
+
     /// Optional pattern override, e.g. "########_#.png"
     #[arg(long)]
     pub pattern: Option<String>,
 
-    /// Show config file path and content, then exit
-    #[arg(short, long)]
-    pub config: bool,
+    /// With `-` as the input: directory (local or `user@host:/path`) of the sequence the
+    /// frame piped in on stdin belongs to; needs `--pattern`
+    #[arg(long, value_name = "DIR", requires = "pattern")]
+    pub dir: Option<String>,
+
+    /// With `-` as the input: frame the piped one is shown as; its file need not exist
+    /// [default: the first frame in a local `--dir`, else 0]
+    #[arg(long, allow_hyphen_values = true, requires = "dir")]
+    pub index: Option<i64>,
+
+    /// Audio file to play in sync with the sequence; remembered for this sequence
+    #[arg(long, value_name = "FILE")]
+    pub audio: Option<PathBuf>,
+
+    /// Audio time in seconds at frame index 0 (negative if the audio starts later)
+    #[arg(long, value_name = "SECONDS", allow_hyphen_values = true, requires = "audio")]
+    pub audio_offset: Option<f64>,
+
+    /// Actual recording sample rate of the audio, if the file header is off
+    #[arg(long, value_name = "HZ", requires = "audio")]
+    pub audio_rate: Option<u32>,
+
+    /// Bookmark sidecar from another reviewer to merge into the view (repeatable)
+    #[arg(long = "bookmarks", value_name = "FILE")]
+    pub bookmarks: Vec<PathBuf>,
+
+    /// Open a window of its own instead of handing the input to the running viewer
+    #[arg(long)]
+    pub new_window: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct ExportArgs {
+    /// Any frame of the sequence (or a `.zapseq` manifest), local or `user@host:/path`
+    pub input: String,
+
+    /// Frames to export: `A..B` (B excluded) or `A..=B` [default: the loop region marked
+    /// in the viewer with Ctrl+I / Ctrl+O]
+    #[arg(long, value_parser = parse_frame_range, allow_hyphen_values = true)]
+    pub range: Option<FrameRange>,
+
+    /// Output directory (created if missing); `-` writes the frames to stdout one after
+    /// another, in `--format`
+    #[arg(long, value_name = "DIR")]
+    pub out: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = ExportFormat::Png)]
+    pub format: ExportFormat,
+
+    /// Resize factor applied to every frame, e.g. 0.5
+    #[arg(long, default_value_t = 1.0)]
+    pub scale: f32,
+
+    /// Burn the sequence's annotations (drawn with E in the viewer) into the frames
+    #[arg(long)]
+    pub annotations: bool,
+
+    /// Cut every frame to the sequence's crop (drawn with Shift+drag in the viewer's
+    /// crop preview), before `--scale`
+    #[arg(long)]
+    pub crop: bool,
+
+    /// Print a summary of the written and missing frames as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Optional pattern override, e.g. "########_#.png"
+    #[arg(long)]
+    pub pattern: Option<String>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct CheckArgs {
+    /// Any frame of the sequence (or a `.zapseq` manifest), local or `user@host:/path`
+    pub input: String,
+
+    /// Frames to check: `A..B` (B excluded) or `A..=B` [default: every frame found in
+    /// the directory, or around the input for remote and sharded sequences]
+    #[arg(long, value_parser = parse_frame_range, allow_hyphen_values = true)]
+    pub range: Option<FrameRange>,
+
+    /// Print the report as JSON instead of a table
+    #[arg(long)]
+    pub json: bool,
+
+    /// Optional pattern override, e.g. "########_#.png"
+    #[arg(long)]
+    pub pattern: Option<String>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct EncodeArgs {
+    /// Any frame of the sequence (or a `.zapseq` manifest), local or `user@host:/path`
+    pub input: String,
+
+    /// Frames to encode: `A..B` (B excluded) or `A..=B` [default: the loop region marked
+    /// in the viewer with Ctrl+I / Ctrl+O]
+    #[arg(long, value_parser = parse_frame_range, allow_hyphen_values = true)]
+    pub range: Option<FrameRange>,
+
+    /// Frame rate of the video [default: the manifest's fps, else the directory's `.zapvis.toml`, else `playback_fps`]
+    #[arg(long)]
+    pub fps: Option<f64>,
+
+    /// Output video file; ffmpeg picks the container and codec from its extension
+    #[arg(long, value_name = "FILE")]
+    pub out: PathBuf,
+
+    /// ffmpeg executable
+    #[arg(long, value_name = "PATH", default_value = "ffmpeg")]
+    pub ffmpeg: PathBuf,
+
+    /// Burn the sequence's annotations (drawn with E in the viewer) into the frames they
+    /// were made on
+    #[arg(long)]
+    pub annotations: bool,
+
+    /// Print a summary of the written and missing frames as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Optional pattern override, e.g. "########_#.png"
+    #[arg(long)]
+    pub pattern: Option<String>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct FetchArgs {
+    /// Any frame of the sequence (or a `.zapseq` manifest), local or `user@host:/path`
+    pub input: String,
+
+    /// Frames to fetch: `A..B` (B excluded) or `A..=B`
+    #[arg(long, value_parser = parse_frame_range, allow_hyphen_values = true)]
+    pub range: FrameRange,
+
+    /// Local directory for the frames (created if missing); frames already there are kept
+    #[arg(long, value_name = "DIR")]
+    pub dest: PathBuf,
+
+    /// Print a summary of the written and missing frames as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Optional pattern override, e.g. "########_#.png"
+    #[arg(long)]
+    pub pattern: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Png,
+    Jpg,
+}
+
+/// Frames `first..=last`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameRange {
+    pub first: i64,
+    pub last: i64,
+}
+
+impl FrameRange {
+    pub fn frames(&self) -> RangeInclusive<i64> {
+        self.first..=self.last
+    }
+
+    pub fn len(&self) -> u64 {
+        self.last.abs_diff(self.first) + 1
+    }
+}
+
+/// Parse `A..B` (exclusive end, like Rust ranges) or `A..=B`.
+pub fn parse_frame_range(s: &str) -> Result<FrameRange, String> {
+    let (first, last, inclusive) = match s.split_once("..=") {
+        Some((a, b)) => (a, b, true),
+        None => {
+            let (a, b) = s.split_once("..").ok_or_else(|| format!("expected A..B or A..=B, got {s:?}"))?;
+            (a, b, false)
+        }
+    };
+    let parse = |v: &str| v.trim().parse::<i64>().map_err(|e| format!("bad frame number {v:?}: {e}"));
+    let (first, end) = (parse(first)?, parse(last)?);
+    let last = if inclusive { Some(end) } else { end.checked_sub(1) };
+    match last {
+        Some(last) if last >= first => Ok(FrameRange { first, last }),
+        _ => Err(format!("empty frame range {s:?}")),
+    }
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommand,
+
+    /// Print the result as JSON, for scripts
+    #[arg(long, global = true)]
+    pub json: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the config file path and content
+    Show,
+    /// Open the config file in $VISUAL / $EDITOR
+    Edit,
+    /// Print the config file path
+    Path,
+    /// Check the patterns and SSH settings for mistakes, with their line and column;
+    /// exits with 1 if there are errors
+    Validate,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct PatternsArgs {
+    #[command(subcommand)]
+    pub command: PatternsCommand,
+
+    /// Print the result as JSON, for scripts
+    #[arg(long, global = true)]
+    pub json: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PatternsCommand {
+    /// List the configured patterns in the order they are tried
+    List,
+    /// Add a pattern (checked for validity first)
+    Add { pattern: String },
+    /// Remove a pattern
+    Remove { pattern: String },
+    /// Check whether a pattern matches a filename and which frame index it yields
+    Test { pattern: String, filename: String },
+}
+
+impl Args {
+    /// The subcommand to run; a bare invocation means `view`.
+    pub fn into_command(self) -> Command {
+        if self.show_config {
+            return Command::Config(ConfigArgs {
+                command: ConfigCommand::Show,
+                json: false,
+            });
+        }
+        self.command.unwrap_or(Command::View(self.view))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_ranges_follow_rust_syntax() {
+        assert_eq!(parse_frame_range("100..200"), Ok(FrameRange { first: 100, last: 199 }));
+        assert_eq!(parse_frame_range("100..=200").unwrap().len(), 101);
+        assert!(parse_frame_range("5..5").is_err());
+        assert!(parse_frame_range("0..0").is_err());
+        assert!(parse_frame_range("7").is_err());
+        assert_eq!(parse_frame_range("-3..=2"), Ok(FrameRange { first: -3, last: 2 }));
+    }
+
+    #[test]
+    fn verbosity_counts_before_the_input_and_after_a_subcommand() {
+        assert_eq!(Args::parse_from(["zapvis", "-vv", "f_0001.png"]).verbose, 2);
+        let args = Args::parse_from(["zapvis", "patterns", "list", "-v"]);
+        assert_eq!(args.verbose, 1);
+        assert!(matches!(args.into_command(), Command::Patterns(_)));
+    }
+
+    #[test]
+    fn old_config_flag_shows_the_config() {
+        for flag in ["-c", "--config"] {
+            let command = Args::parse_from(["zapvis", flag]).into_command();
+            assert!(matches!(command, Command::Config(ConfigArgs { command: ConfigCommand::Show, json: false })), "{flag}");
+        }
+        assert!(Args::try_parse_from(["zapvis", "--config", "patterns", "list"]).is_err());
+    }
+
+    #[test]
+    fn stdin_input_takes_its_sequence_from_dir_and_pattern() {
+        let args = Args::parse_from(["zapvis", "view", "-", "--dir", "renders", "--pattern", "f_####.png", "--index", "-2"]);
+        match args.into_command() {
+            Command::View(view) => assert_eq!((view.input.as_deref(), view.index), (Some("-"), Some(-2))),
+            other => panic!("unexpected command {other:?}"),
+        }
+        assert!(Args::try_parse_from(["zapvis", "-", "--dir", "renders"]).is_err(), "--dir needs --pattern");
+    }
+
+    #[test]
+    fn json_is_accepted_before_and_after_the_nested_subcommand() {
+        for argv in [["zapvis", "config", "--json", "validate"], ["zapvis", "config", "validate", "--json"]] {
+            match Args::parse_from(argv).into_command() {
+                Command::Config(args) => assert!(args.json && matches!(args.command, ConfigCommand::Validate)),
+                other => panic!("unexpected command {other:?}"),
+            }
+        }
+    }
 }
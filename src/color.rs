@@ -0,0 +1,209 @@
+//! Color management at decode time. Frames with an embedded ICC profile (AdobeRGB,
+//! Display P3, ...) are converted to sRGB, and linear float frames (EXR, Radiance HDR)
+//! are encoded with the sRGB transfer curve, so both look on an sRGB display the way
+//! they were meant to. Everything else is taken to be sRGB already.
+//!
+//! Float frames go through a [`ToneMap`] on the way: exposure, an operator compressing
+//! highlights and a gamma adjustment, chosen per sequence in the viewer.
+use image::{DynamicImage, RgbaImage};
+use moxcms::{ColorProfile, DataColorSpace, Layout, Transform8BitExecutor, TransformOptions};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::sync::Arc;
+use tracing::warn;
+
+thread_local! {
+    /// The transform built for the last profile, reused while a sequence repeats it
+    static LAST_TRANSFORM: RefCell<Option<(Vec<u8>, Arc<Transform8BitExecutor>)>> = const { RefCell::new(None) };
+}
+
+/// How linear float (HDR) values are brought into the displayable range
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToneMap {
+    /// Stops of exposure applied before the operator (each doubles the values)
+    pub exposure: f32,
+    pub operator: ToneMapOperator,
+    /// Gamma applied after the sRGB curve; 1 leaves it as it is
+    pub gamma: f32,
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            operator: ToneMapOperator::default(),
+            gamma: 1.0,
+        }
+    }
+}
+
+impl ToneMap {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Linear `v` to an sRGB-encoded display value in 0..=1
+    fn map(&self, v: f32) -> f32 {
+        let v = self.operator.apply(v * self.exposure.exp2()).clamp(0.0, 1.0);
+        let srgb = if v <= 0.003_130_8 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 };
+        if self.gamma == 1.0 || self.gamma <= 0.0 {
+            srgb
+        } else {
+            srgb.powf(1.0 / self.gamma)
+        }
+    }
+
+    /// Short description for the status bar, e.g. `+1.5 EV ACES γ1.2`
+    pub fn label(&self) -> String {
+        let mut label = format!("{:+.1} EV {}", self.exposure, self.operator.label());
+        if self.gamma != 1.0 {
+            label.push_str(&format!(" γ{:.1}", self.gamma));
+        }
+        label
+    }
+}
+
+/// Curve compressing linear values above 1 into the displayable range
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMapOperator {
+    /// Values above 1 are cut off
+    #[default]
+    Clamp,
+    /// `v / (1 + v)`: highlights roll off smoothly, midtones get darker
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve: contrasty, with a soft shoulder
+    Aces,
+}
+
+impl ToneMapOperator {
+    pub fn next(self) -> Self {
+        match self {
+            ToneMapOperator::Clamp => ToneMapOperator::Reinhard,
+            ToneMapOperator::Reinhard => ToneMapOperator::Aces,
+            ToneMapOperator::Aces => ToneMapOperator::Clamp,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ToneMapOperator::Clamp => "clamp",
+            ToneMapOperator::Reinhard => "Reinhard",
+            ToneMapOperator::Aces => "ACES",
+        }
+    }
+
+    fn apply(self, v: f32) -> f32 {
+        let v = v.max(0.0);
+        match self {
+            ToneMapOperator::Clamp => v,
+            ToneMapOperator::Reinhard => v / (1.0 + v),
+            ToneMapOperator::Aces => (v * (2.51 * v + 0.03)) / (v * (2.43 * v + 0.59) + 0.14),
+        }
+    }
+}
+
+/// Whether [`to_display_rgba`] changes the pixels of `img` with profile `icc`
+pub fn needs_conversion(img: &DynamicImage, icc: Option<&[u8]>) -> bool {
+    is_linear_float(img) || icc.is_some()
+}
+
+/// RGBA pixels of `img` for an sRGB display, converted from `icc` if it has a usable
+/// RGB profile, or tone mapped with `tone` if it is linear float data. Profiles that
+/// cannot be read leave the values as they are.
+pub fn to_display_rgba(img: &DynamicImage, icc: Option<&[u8]>, tone: ToneMap) -> RgbaImage {
+    if is_linear_float(img) {
+        return linear_to_srgb8(img, tone);
+    }
+    let mut rgba = img.to_rgba8();
+    if let Some(icc) = icc {
+        match transform_for(icc) {
+            Ok(Some(transform)) => {
+                let src = rgba.as_raw().clone();
+                if let Err(e) = transform.transform(&src, &mut rgba) {
+                    warn!("color conversion failed, showing raw values: {}", e);
+                    return img.to_rgba8();
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("ignoring unreadable ICC profile: {}", e),
+        }
+    }
+    rgba
+}
+
+/// Float pixels are scene-linear by convention of the formats `image` reads them from
+fn is_linear_float(img: &DynamicImage) -> bool {
+    matches!(img, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_))
+}
+
+fn linear_to_srgb8(img: &DynamicImage, tone: ToneMap) -> RgbaImage {
+    let linear = img.to_rgba32f();
+    let encode = |v: f32| (tone.map(v) * 255.0).round() as u8;
+    RgbaImage::from_fn(linear.width(), linear.height(), |x, y| {
+        let [r, g, b, a] = linear.get_pixel(x, y).0;
+        image::Rgba([encode(r), encode(g), encode(b), (a.clamp(0.0, 1.0) * 255.0).round() as u8])
+    })
+}
+
+/// Transform from `icc` to sRGB, `None` for profiles of other color spaces (gray, CMYK)
+fn transform_for(icc: &[u8]) -> Result<Option<Arc<Transform8BitExecutor>>, moxcms::CmsError> {
+    if let Some(transform) = LAST_TRANSFORM.with_borrow(|last| last.as_ref().filter(|(p, _)| p == icc).map(|(_, t)| t.clone())) {
+        return Ok(Some(transform));
+    }
+    let profile = ColorProfile::new_from_slice(icc)?;
+    if profile.color_space != DataColorSpace::Rgb {
+        return Ok(None);
+    }
+    let transform = profile.create_transform_8bit(Layout::Rgba, &ColorProfile::new_srgb(), Layout::Rgba, TransformOptions::default())?;
+    LAST_TRANSFORM.set(Some((icc.to_vec(), transform.clone())));
+    Ok(Some(transform))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb32FImage, RgbImage};
+
+    #[test]
+    fn linear_floats_get_the_srgb_curve() {
+        let img = DynamicImage::ImageRgb32F(Rgb32FImage::from_pixel(1, 1, image::Rgb([0.0, 0.2159, 1.5])));
+        assert!(needs_conversion(&img, None));
+        // Linear 0.2159 is sRGB 0.502 (128); out-of-range values clip
+        assert_eq!(to_display_rgba(&img, None, ToneMap::default()).get_pixel(0, 0).0, [0, 128, 255, 255]);
+    }
+
+    #[test]
+    fn tone_maps_bring_highlights_into_range() {
+        let tone = |exposure, operator, gamma| ToneMap {
+            exposure,
+            operator,
+            gamma,
+        };
+        // One stop down makes linear 0.4318 the sRGB midpoint
+        assert!((tone(-1.0, ToneMapOperator::Clamp, 1.0).map(0.4318) - 0.502).abs() < 0.001);
+        // Reinhard maps 1 to 0.5 linear; ACES keeps highlights below 1 a little longer
+        assert!((tone(0.0, ToneMapOperator::Reinhard, 1.0).map(1.0) - tone(0.0, ToneMapOperator::Clamp, 1.0).map(0.5)).abs() < 1e-6);
+        assert!(tone(0.0, ToneMapOperator::Aces, 1.0).map(4.0) < 1.0);
+        assert!(tone(0.0, ToneMapOperator::Aces, 1.0).map(100.0) > 0.99);
+        // Gamma above 1 lifts the midtones
+        assert!(tone(0.0, ToneMapOperator::Clamp, 2.0).map(0.2) > tone(0.0, ToneMapOperator::Clamp, 1.0).map(0.2));
+        assert_eq!(tone(0.5, ToneMapOperator::Aces, 1.2).label(), "+0.5 EV ACES γ1.2");
+        assert!(ToneMap::default().is_identity());
+    }
+
+    #[test]
+    fn profiles_convert_to_srgb() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, image::Rgb([200, 30, 30])));
+        assert!(!needs_conversion(&img, None));
+        let srgb = ToneMap::default();
+        assert_eq!(to_display_rgba(&img, None, srgb).get_pixel(0, 0).0, [200, 30, 30, 255]);
+        // Display P3 red is more saturated than sRGB can show: the red channel clips up
+        let p3 = ColorProfile::new_display_p3().encode().unwrap();
+        let [r, g, b, a] = to_display_rgba(&img, Some(&p3), srgb).get_pixel(0, 0).0;
+        assert!(r > 200 && g < 30 && b < 40 && a == 255, "{:?}", [r, g, b, a]);
+        // Garbage profiles are ignored
+        assert_eq!(to_display_rgba(&img, Some(b"not a profile"), srgb).get_pixel(0, 0).0, [200, 30, 30, 255]);
+    }
+}
@@ -0,0 +1,129 @@
+//! False color for single-channel data (depth, temperature, masks): the shown channel's
+//! values, between a low and a high end, looked up in a perceptual colormap.
+
+/// Colormaps to pick from. Viridis and magma are perceptually uniform; turbo and jet
+/// are rainbow maps that show small differences at the cost of false edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+    Turbo,
+    Jet,
+}
+
+/// Polynomial fits of matplotlib's viridis and magma (Matt Zucker), per channel from
+/// the constant term up
+const VIRIDIS: [[f32; 3]; 7] = [
+    [0.277_727_33, 0.005_407_344_5, 0.334_099_8],
+    [0.105_093_04, 1.404_613_5, 1.384_590_2],
+    [-0.330_861_83, 0.214_847_56, 0.095_095_16],
+    [-4.634_230_6, -5.799_101, -19.332_441],
+    [6.228_27, 14.179_933, 56.690_55],
+    [4.776_385, -13.745_145, -65.353_03],
+    [-5.435_456, 4.645_852_6, 26.312_435],
+];
+const MAGMA: [[f32; 3]; 7] = [
+    [-0.002_136_485, -0.000_749_655_05, -0.005_386_128],
+    [0.251_660_54, 0.677_523_2, 2.494_026_6],
+    [8.353_717, -3.577_719_5, 0.314_467_9],
+    [-27.668_733, 14.264_731, -13.649_213],
+    [52.176_14, -27.943_606, 12.944_169],
+    [-50.768_524, 29.046_583, 4.234_153],
+    [18.655_705, -11.489_773, -5.601_961_5],
+];
+/// Polynomial fit of turbo (Google), per channel from the constant term up
+const TURBO: [[f32; 3]; 6] = [
+    [0.135_721_38, 0.091_402_61, 0.106_673_3],
+    [4.615_392_6, 2.194_188_4, 12.641_946],
+    [-42.660_324, 4.842_966_6, -60.582_05],
+    [132.131_08, -14.185_033, 110.362_77],
+    [-152.942_4, 4.277_298_5, -89.903_11],
+    [59.286_38, 2.829_566, 27.348_25],
+];
+
+fn polynomial(coefficients: &[[f32; 3]], t: f32) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for c in coefficients.iter().rev() {
+        for i in 0..3 {
+            out[i] = out[i] * t + c[i];
+        }
+    }
+    out
+}
+
+impl Colormap {
+    pub const ALL: [Colormap; 4] = [Colormap::Viridis, Colormap::Magma, Colormap::Turbo, Colormap::Jet];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Colormap::Viridis => "viridis",
+            Colormap::Magma => "magma",
+            Colormap::Turbo => "turbo",
+            Colormap::Jet => "jet",
+        }
+    }
+
+    /// The next map, `None` after the last one (Alt+C cycles off → viridis → ... → off)
+    pub fn next(current: Option<Colormap>) -> Option<Colormap> {
+        match current {
+            None => Some(Self::ALL[0]),
+            Some(map) => Self::ALL.iter().skip_while(|&&m| m != map).nth(1).copied(),
+        }
+    }
+
+    /// Color at `t` in 0..=1
+    pub fn rgb(self, t: f32) -> [u8; 3] {
+        let t = t.clamp(0.0, 1.0);
+        let rgb = match self {
+            Colormap::Viridis => polynomial(&VIRIDIS, t),
+            Colormap::Magma => polynomial(&MAGMA, t),
+            Colormap::Turbo => polynomial(&TURBO, t),
+            Colormap::Jet => [1.5 - (4.0 * t - 3.0).abs(), 1.5 - (4.0 * t - 2.0).abs(), 1.5 - (4.0 * t - 1.0).abs()],
+        };
+        rgb.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+}
+
+/// A colormap over the channel values `low..=high`; values outside get the end colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FalseColor {
+    pub map: Colormap,
+    pub low: u8,
+    pub high: u8,
+}
+
+impl FalseColor {
+    pub fn new(map: Colormap) -> Self {
+        Self { map, low: 0, high: 255 }
+    }
+
+    /// Color of each 8-bit channel value
+    pub fn table(self) -> [[u8; 3]; 256] {
+        let span = (self.high as f32 - self.low as f32).max(1.0);
+        std::array::from_fn(|v| self.map.rgb((v as f32 - self.low as f32) / span))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colormaps_match_their_reference_ends() {
+        let close = |a: [u8; 3], b: [u8; 3]| a.iter().zip(b).all(|(&x, y)| x.abs_diff(y) <= 5);
+        assert!(close(Colormap::Viridis.rgb(0.0), [68, 1, 84]));
+        assert!(close(Colormap::Viridis.rgb(1.0), [253, 231, 37]));
+        assert!(close(Colormap::Magma.rgb(0.0), [0, 0, 4]));
+        assert!(close(Colormap::Magma.rgb(1.0), [252, 253, 191]));
+        assert_eq!(Colormap::Jet.rgb(0.5), [128, 255, 128]);
+        assert_eq!(Colormap::Jet.rgb(0.0), [0, 0, 128]);
+
+        // The range stretches low..=high over the whole map
+        let table = FalseColor { map: Colormap::Jet, low: 100, high: 200 }.table();
+        assert_eq!((table[50], table[100], table[255]), (Colormap::Jet.rgb(0.0), Colormap::Jet.rgb(0.0), Colormap::Jet.rgb(1.0)));
+        assert_eq!(table[150], Colormap::Jet.rgb(0.5));
+
+        assert_eq!(Colormap::next(None), Some(Colormap::Viridis));
+        assert_eq!(Colormap::next(Some(Colormap::Jet)), None);
+    }
+}
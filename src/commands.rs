@@ -0,0 +1,195 @@
+//! Non-GUI subcommands: `zapvis config ...` and `zapvis patterns ...`.
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use serde_json::json;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+
+use crate::cli::{ConfigArgs, ConfigCommand, PatternsArgs, PatternsCommand};
+use zapvis::config::{config_path, load_config, maybe_add_pattern, remove_pattern, save_config, PatternScope};
+use zapvis::lint::{lint_config, Severity};
+use zapvis::sequence::{compile_pattern, match_index, SequenceSource, SequenceSpec};
+
+/// Print `value` for `--json`: pretty-printed, alone on stdout. A closed pipe is an
+/// error rather than a panic, as output often goes to another tool.
+pub fn print_json(value: &impl Serialize) -> Result<()> {
+    let mut out = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut out, value)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+pub fn run_config(args: ConfigArgs) -> Result<()> {
+    let path = config_path()?;
+    match args.command {
+        ConfigCommand::Path if args.json => print_json(&json!({ "path": path }))?,
+        ConfigCommand::Path => println!("{}", path.display()),
+        ConfigCommand::Show if args.json => {
+            // The parsed config, defaults filled in
+            print_json(&json!({ "path": path, "exists": path.exists(), "config": load_config()? }))?;
+        }
+        ConfigCommand::Show => {
+            println!("Config path: {}", path.display());
+            if path.exists() {
+                let content = fs::read_to_string(&path).context("Failed to read config file")?;
+                println!("\nConfig content:\n{}", content);
+            } else {
+                println!("Config file does not exist.");
+            }
+        }
+        ConfigCommand::Edit => {
+            if !path.exists() {
+                // Start from the defaults so every option is visible
+                save_config(&load_config()?)?;
+            }
+            let editor = std::env::var("VISUAL")
+                .or_else(|_| std::env::var("EDITOR"))
+                .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+            let status = process::Command::new(&editor)
+                .arg(&path)
+                .status()
+                .with_context(|| format!("Failed to run editor {editor:?}"))?;
+            if !status.success() {
+                return Err(anyhow!("Editor {editor:?} exited with {status}"));
+            }
+            // Catch mistakes right away instead of at the next launch
+            load_config().context("Edited config is invalid")?;
+            if args.json {
+                print_json(&json!({ "path": path }))?;
+            }
+        }
+        ConfigCommand::Validate => {
+            let findings = match path.exists() {
+                true => lint_config(&fs::read_to_string(&path).context("Failed to read config file")?),
+                false => Vec::new(),
+            };
+            let errors = findings.iter().filter(|f| f.severity == Severity::Error).count();
+            if args.json {
+                print_json(&json!({
+                    "path": path,
+                    "exists": path.exists(),
+                    "errors": errors,
+                    "warnings": findings.len() - errors,
+                    "findings": findings,
+                }))?;
+                if errors > 0 {
+                    process::exit(1);
+                }
+                return Ok(());
+            }
+            if !path.exists() {
+                println!("Config file does not exist; the defaults are used.");
+                return Ok(());
+            }
+            for finding in &findings {
+                println!("{}:{}", path.display(), finding);
+            }
+            match findings.len() {
+                0 => println!("{}: no problems found", path.display()),
+                n => println!("{} error(s), {} warning(s)", errors, n - errors),
+            }
+            if errors > 0 {
+                process::exit(1);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `[pattern_scopes]` entry as listed next to its pattern
+fn describe_scope(scope: &PatternScope) -> String {
+    let mut parts = Vec::new();
+    if scope.priority != 0 {
+        parts.push(format!("priority {}", scope.priority));
+    }
+    if !scope.dirs.is_empty() {
+        parts.push(format!("in {}", scope.dirs.join(", ")));
+    }
+    if !scope.hosts.is_empty() {
+        parts.push(format!("on {}", scope.hosts.join(", ")));
+    }
+    format!("({})", parts.join("; "))
+}
+
+pub fn run_patterns(args: PatternsArgs) -> Result<()> {
+    let json = args.json;
+    match args.command {
+        PatternsCommand::List => {
+            let cfg = load_config()?;
+            let mut patterns: Vec<&String> = cfg.patterns.iter().collect();
+            patterns.sort_by_key(|p| std::cmp::Reverse(cfg.pattern_priority(p)));
+            if json {
+                let listed: Vec<_> = patterns
+                    .into_iter()
+                    .map(|p| {
+                        let scope = cfg.pattern_scopes.get(p).cloned().unwrap_or_default();
+                        json!({ "pattern": p, "priority": scope.priority, "dirs": scope.dirs, "hosts": scope.hosts })
+                    })
+                    .collect();
+                return print_json(&listed);
+            }
+            if cfg.patterns.is_empty() {
+                println!("No patterns configured.");
+            }
+            for (i, p) in patterns.into_iter().enumerate() {
+                match cfg.pattern_scopes.get(p) {
+                    Some(scope) => println!("{:>3}) {}  {}", i + 1, p, describe_scope(scope)),
+                    None => println!("{:>3}) {}", i + 1, p),
+                }
+            }
+        }
+        PatternsCommand::Add { pattern } => {
+            compile_pattern(&pattern)?;
+            let mut cfg = load_config()?;
+            let added = !cfg.patterns.contains(&pattern);
+            if added {
+                maybe_add_pattern(&mut cfg, pattern.clone());
+                save_config(&cfg)?;
+            }
+            match (json, added) {
+                (true, _) => print_json(&json!({ "pattern": pattern, "added": added }))?,
+                (false, true) => println!("Added: {pattern}"),
+                (false, false) => println!("Already configured: {pattern}"),
+            }
+        }
+        PatternsCommand::Remove { pattern } => {
+            let mut cfg = load_config()?;
+            if !remove_pattern(&mut cfg, &pattern) {
+                return Err(anyhow!("Pattern not configured: {pattern}"));
+            }
+            save_config(&cfg)?;
+            match json {
+                true => print_json(&json!({ "pattern": pattern, "removed": true }))?,
+                false => println!("Removed: {pattern}"),
+            }
+        }
+        PatternsCommand::Test { pattern, filename } => {
+            let idx = match_index(&pattern, &filename)?;
+            if json {
+                let (mut previous, mut next) = (None, None);
+                if let Some(idx) = idx {
+                    let seq = SequenceSpec::from_pattern(&pattern, SequenceSource::Local(".".into()), idx)?;
+                    previous = (idx > 0).then(|| seq.relative_path_for(idx - 1));
+                    next = Some(seq.relative_path_for(idx + 1));
+                }
+                print_json(&json!({ "pattern": pattern, "filename": filename, "index": idx, "previous": previous, "next": next }))?;
+                if idx.is_none() {
+                    process::exit(1);
+                }
+                return Ok(());
+            }
+            let Some(idx) = idx else {
+                println!("No match: {pattern:?} does not match {filename:?}");
+                process::exit(1);
+            };
+            let seq = SequenceSpec::from_pattern(&pattern, SequenceSource::Local(".".into()), idx)?;
+            println!("Match: frame index {idx}");
+            if idx > 0 {
+                println!("  previous: {}", seq.relative_path_for(idx - 1));
+            }
+            println!("  next:     {}", seq.relative_path_for(idx + 1));
+        }
+    }
+    Ok(())
+}
@@ -1,39 +1,633 @@
-use anyhow::{anyhow, Context, Result};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::PathBuf;
+use std::time::Duration;
 
-use crate::sequence::compile_pattern;
+use crate::color::ToneMap;
+use crate::error::{ConfigError, PatternError};
+use crate::guides::Guide;
+use crate::orientation::Orientation;
+use crate::persistent_ssh::Compression;
+use crate::sequence::{compile_pattern, expand_local_path, SequenceSource, ShardRule};
+use crate::throttle::Throttle;
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub patterns: Vec<String>,
+    /// Longest edge (in pixels) of the preview JPEG frames get, decoded at reduced
+    /// scale ahead of the full-resolution frame; with previews, only the frames on
+    /// screen or next to it are decoded in full. 0 disables previews.
+    #[serde(default = "default_preview_max_dim")]
+    pub preview_max_dim: u32,
+    /// Rotate/flip decoded frames according to their EXIF Orientation tag.
+    #[serde(default = "default_true")]
+    pub honor_exif_orientation: bool,
+    /// Convert frames with an embedded ICC profile, and linear float (EXR/HDR) frames,
+    /// to sRGB for display (toggle with U to see the raw values).
+    #[serde(default = "default_true")]
+    pub color_management: bool,
+    /// Longest edge (in pixels) kept after decoding; larger frames are downscaled
+    /// with a high-quality filter to save memory and upload time. 0 means no cap.
+    /// JPEGs are decoded at a reduced scale; other formats are decoded whole and then
+    /// downscaled, so for them the cap limits only the cached size.
+    #[serde(default)]
+    pub max_decode_dim: u32,
+    /// When zoomed into a small part of a large strip or tile TIFF, decode only the
+    /// visible region plus a margin instead of the whole frame for every step.
+    #[serde(default = "default_true")]
+    pub roi_decoding: bool,
+    /// Start with gap skipping on: stepping onto a missing frame moves on to the
+    /// nearest existing one in the same direction (toggle with G).
+    #[serde(default)]
+    pub skip_gaps: bool,
+    /// Start with gap interpolation on: a missing frame is shown as a cross-fade of the
+    /// nearest loaded frames around it, and playback runs through it (toggle with Shift+G).
+    #[serde(default)]
+    pub interpolate_gaps: bool,
+    /// Load the frames around the current one ahead of time. Off loads only the frame
+    /// shown, for laptops on battery or metered connections (toggle with Q).
+    #[serde(default = "default_true")]
+    pub prefetch: bool,
+    /// Frames kept on each side of the current one, in steps; where the radius starts
+    /// when it adapts.
+    #[serde(default = "default_cache_radius")]
+    pub cache_radius: usize,
+    /// Widen the radius for frames that load fast and narrow it for slow or large ones,
+    /// up to `max_cache_radius`.
+    #[serde(default = "default_true")]
+    pub adaptive_cache_radius: bool,
+    #[serde(default = "default_max_cache_radius")]
+    pub max_cache_radius: usize,
+    /// Decoded pixels the adaptive radius keeps at most, in MB. 0 means half the memory
+    /// available.
+    #[serde(default)]
+    pub cache_memory_mb: u64,
+    /// Steps the current frame moves away from the center of the cached window before
+    /// the window follows it. 1 recenters on every step; larger values evict and
+    /// re-request less while going back and forth.
+    #[serde(default = "default_reload_threshold")]
+    pub reload_threshold: u64,
+    /// Farthest a gap skip searches, in steps.
+    #[serde(default = "default_max_gap")]
+    pub max_gap: u64,
+    /// Frames per second for playback (Space).
+    #[serde(default = "default_playback_fps")]
+    pub playback_fps: f64,
+    /// Delay (ms) before playback starts, spent buffering frames ahead. 0 starts immediately.
+    #[serde(default = "default_playback_preroll_ms")]
+    pub playback_preroll_ms: u64,
+    /// Name recorded on bookmarks you create; defaults to the login name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Reopen a sequence at the frame, zoom, step size and window it was closed with.
+    #[serde(default = "default_true")]
+    pub remember_sessions: bool,
+    /// Hand the input of a later `zapvis <file>` to the running viewer instead of opening
+    /// another window (Unix; `--new-window` opts out once).
+    #[serde(default = "default_true")]
+    pub single_instance: bool,
+    /// Keepalive latency (ms) above which the worker moves to a faster replica. 0 disables.
+    #[serde(default)]
+    pub replica_slow_ms: u64,
+    /// Seconds the frame loader or SSH worker may spend on one request before it is
+    /// considered stuck and restarted. 0 disables the watchdog.
+    #[serde(default = "default_stall_secs")]
+    pub stall_secs: u64,
+    /// How often follow mode (L) checks for new frames, in ms. Local directories are
+    /// also watched for file events, so this is mostly the remote polling interval.
+    #[serde(default = "default_follow_poll_ms")]
+    pub follow_poll_ms: u64,
+    /// Flips per second of the automatic blink comparison (Shift+T).
+    #[serde(default = "default_blink_hz")]
+    pub blink_hz: f64,
+    /// Compress remote transfers: `"off"`, `"gzip"`, `"zstd"` or `"auto"` (zstd where the
+    /// host has it). Helps raw formats like TIFF/PPM; PNG and JPEG are never recompressed.
+    #[serde(default)]
+    pub remote_compression: Compression,
+    /// SSH sessions fetching remote frames in parallel, besides the one answering
+    /// existence checks.
+    #[serde(default = "default_remote_channels")]
+    pub remote_channels: usize,
+    /// Cap on the bandwidth of remote prefetching, in MB/s; the frame on screen is not
+    /// held back. 0 means no cap.
+    #[serde(default)]
+    pub remote_bandwidth_limit: f64,
+    /// Most MB of remote frames being transferred at once before further prefetches wait.
+    /// 0 means no limit.
+    #[serde(default)]
+    pub remote_max_in_flight_mb: f64,
+    /// What is drawn under the image, showing through transparent pixels (cycle with W).
+    #[serde(default)]
+    pub background: Background,
+    /// Color of the `custom` background, as `[r, g, b]`.
+    #[serde(default = "default_background_color")]
+    pub background_color: [u8; 3],
+    /// Framing guides drawn by the guides overlay (O): `"W:H"` aspect ratios, `"center"`,
+    /// `"action_safe"`, `"title_safe"`, `"safe:N"` margins, `"thirds"` and `"grid:CxR"`.
+    #[serde(default = "Guide::defaults")]
+    pub guides: Vec<Guide>,
+    /// Sequences with the guides overlay turned on, by `SequenceSpec::sequence_key`.
+    #[serde(default)]
+    pub guides_shown: BTreeSet<String>,
+    /// Guides of `guides` turned off (Alt+1..9) for a sequence, by `SequenceSpec::sequence_key`.
+    #[serde(default)]
+    pub hidden_guides: BTreeMap<String, Vec<Guide>>,
+    /// Sequences shown with nearest-neighbor filtering (J), by `SequenceSpec::sequence_key`.
+    #[serde(default)]
+    pub nearest_filtering: BTreeSet<String>,
+    /// Per-host opt-in command prefix for remote file access, e.g.
+    /// `"user@host" = "sudo -n -u capture"`. Keys are `user@host` or a bare host name.
+    #[serde(default)]
+    pub remote_command_prefix: BTreeMap<String, String>,
+    /// Replica hosts holding the same dataset as a primary, tried in order when the
+    /// primary fails or is slow. Keys are `user@host` or a bare host name; replicas
+    /// without a user inherit the primary's.
+    #[serde(default)]
+    pub replicas: BTreeMap<String, Vec<String>>,
+    /// Display orientation per sequence, keyed by `SequenceSpec::sequence_key`.
+    #[serde(default)]
+    pub orientations: BTreeMap<String, Orientation>,
+    /// Exposure, tone-map operator and gamma for HDR sequences, keyed by
+    /// `SequenceSpec::sequence_key`.
+    #[serde(default)]
+    pub tone_maps: BTreeMap<String, ToneMap>,
+    /// Audio track played along with a sequence, keyed by `SequenceSpec::sequence_key`.
+    #[serde(default)]
+    pub audio_tracks: BTreeMap<String, AudioTrack>,
+    /// Subdirectory layout per pattern for sequences split over numbered folders.
+    #[serde(default)]
+    pub shards: BTreeMap<String, ShardRule>,
+    /// Priority and where a pattern applies (directory globs, hosts), per pattern.
+    #[serde(default)]
+    pub pattern_scopes: BTreeMap<String, PatternScope>,
+    /// Partner pattern shown beside a pattern's frames, at the same index in the same
+    /// directory, e.g. `"left_####.png" = "right_####.png"` for stereo pairs.
+    #[serde(default)]
+    pub paired_patterns: BTreeMap<String, String>,
+    /// External converter per pattern for formats `image` cannot read, e.g.
+    /// `"sim_####.dat" = "dat2png {path}"`; its stdout (PNG, PPM, ...) is decoded instead.
+    #[serde(default)]
+    pub loader_commands: BTreeMap<String, String>,
+    /// Crop previewed over a sequence (K), keyed by `SequenceSpec::sequence_key`.
+    #[serde(default)]
+    pub crops: BTreeMap<String, CropRect>,
+    /// Real-world size of a frame pixel for measurements (Shift+M), keyed by
+    /// `SequenceSpec::sequence_key`.
+    #[serde(default)]
+    pub measure_scales: BTreeMap<String, MeasureScale>,
 }
 
-pub fn load_config() -> Result<Config> {
+/// Rank of a pattern and the frames it is limited to, in `[pattern_scopes]`. Without
+/// one a pattern has priority 0 and applies everywhere.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PatternScope {
+    /// Patterns are tried highest priority first; equal ones keep their config order.
+    #[serde(default)]
+    pub priority: i32,
+    /// Globs the frames' directory must match, e.g. `"~/renders/**"` (`*` within a
+    /// folder name, `**` any number of folders); empty for any directory. Remote
+    /// directories are matched as absolute paths on their host.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dirs: Vec<String>,
+    /// Hosts of the frames, as `user@host` or a bare host name (SSH) or the URL's host
+    /// (HTTP, without the port); `"local"` stands for this machine's files. Empty for any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+}
+
+impl PatternScope {
+    /// True if frames from `source` are in the scope
+    pub fn contains(&self, source: &SequenceSource) -> bool {
+        let (host, dir) = match source {
+            SequenceSource::Local(dir) => (None, dir.display().to_string()),
+            SequenceSource::Video { path, .. } | SequenceSource::Animation { path, .. } => {
+                (None, path.parent().unwrap_or(path).display().to_string())
+            }
+            SequenceSource::Remote { user_host, dir } => (Some(user_host.as_str()), dir.clone()),
+            SequenceSource::Http { base_url } => {
+                let rest = base_url.split_once("://").map_or(base_url.as_str(), |(_, rest)| rest);
+                let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+                (Some(host.split(':').next().unwrap_or(host)), format!("/{path}"))
+            }
+        };
+        let host_ok = self.hosts.is_empty()
+            || self.hosts.iter().any(|h| match host {
+                None => h == "local",
+                Some(user_host) => h == user_host || h == user_host.rsplit('@').next().unwrap_or(user_host),
+            });
+        let dir_ok = self.dirs.is_empty()
+            || self.dirs.iter().any(|glob| {
+                // `~` is this machine's home; remote globs are given as absolute paths
+                let glob = if host.is_none() { expand_local_path(glob).display().to_string() } else { glob.clone() };
+                glob_matches(&glob, &dir)
+            });
+        host_ok && dir_ok
+    }
+}
+
+/// Whether `path` matches `glob`, folder by folder: `**` stands for any number of
+/// folders (none included), `*` and `?` for characters within one folder name
+fn glob_matches(glob: &str, path: &str) -> bool {
+    let split = |s: &str| -> Vec<String> { s.split(['/', '\\']).filter(|p| !p.is_empty()).map(str::to_string).collect() };
+    let (glob, path) = (split(glob), split(path));
+    fn segments(glob: &[String], path: &[String]) -> bool {
+        match glob.split_first() {
+            None => path.is_empty(),
+            Some((first, rest)) if first == "**" => (0..=path.len()).any(|skip| segments(rest, &path[skip..])),
+            Some((first, rest)) => path.split_first().is_some_and(|(name, path_rest)| name_matches(first, name) && segments(rest, path_rest)),
+        }
+    }
+    fn name_matches(glob: &str, name: &str) -> bool {
+        let (glob, name): (Vec<char>, Vec<char>) = (glob.chars().collect(), name.chars().collect());
+        fn chars(glob: &[char], name: &[char]) -> bool {
+            match glob.split_first() {
+                None => name.is_empty(),
+                Some(('*', rest)) => (0..=name.len()).any(|skip| chars(rest, &name[skip..])),
+                Some(('?', rest)) => !name.is_empty() && chars(rest, &name[1..]),
+                Some((c, rest)) => name.first() == Some(c) && chars(rest, &name[1..]),
+            }
+        }
+        chars(&glob, &name)
+    }
+    segments(&glob, &path)
+}
+
+/// Backdrop under the image, where transparent pixels show it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Background {
+    /// Gray checks, the usual way of marking transparency
+    #[default]
+    Checkerboard,
+    Black,
+    White,
+    /// `background_color` from the config
+    Custom,
+}
+
+impl Background {
+    pub const ALL: [Background; 4] = [Background::Checkerboard, Background::Black, Background::White, Background::Custom];
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|&b| b == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Background::Checkerboard => "checkerboard",
+            Background::Black => "black",
+            Background::White => "white",
+            Background::Custom => "custom",
+        }
+    }
+}
+
+/// Crop rectangle in pixels of the decoded frame (after EXIF orientation and
+/// `max_decode_dim`, before the view's rotation and flips).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Length of one frame pixel in real-world units, e.g. 0.05 `mm` for a calibrated camera.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeasureScale {
+    pub per_pixel: f64,
+    pub unit: String,
+}
+
+impl MeasureScale {
+    /// `pixels` in the scale's unit, e.g. "12.35 mm"
+    pub fn format(&self, pixels: f64) -> String {
+        format!("{:.2} {}", pixels * self.per_pixel, self.unit)
+    }
+}
+
+/// Audio recorded alongside a sequence.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioTrack {
+    pub path: PathBuf,
+    /// Audio time (seconds) at frame index 0; negative if the audio starts later.
+    #[serde(default)]
+    pub offset: f64,
+    /// Actual recording sample rate, when it differs from the file header (clock drift of the rig).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<u32>,
+}
+
+impl AudioTrack {
+    /// Drift (seconds) between audio and frame clock tolerated before the audio is re-seeked
+    pub const DRIFT_TOLERANCE: f64 = 0.08;
+
+    /// Audio time (seconds) at a fractional frame position.
+    pub fn time_at(&self, frame: f64, fps: f64) -> f64 {
+        self.offset + frame / fps
+    }
+
+    /// True if audio at `audio_pos` must be seeked to `media_time` (both in seconds)
+    /// before playing on: when resuming from a pause, as the frame clock may have moved
+    /// meanwhile, or once the two drifted apart by more than [`Self::DRIFT_TOLERANCE`].
+    pub fn needs_seek(audio_pos: f64, media_time: f64, resuming: bool) -> bool {
+        resuming || (audio_pos - media_time).abs() > Self::DRIFT_TOLERANCE
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            patterns: Vec::new(),
+            preview_max_dim: default_preview_max_dim(),
+            honor_exif_orientation: true,
+            color_management: true,
+            max_decode_dim: 0,
+            roi_decoding: true,
+            skip_gaps: false,
+            interpolate_gaps: false,
+            prefetch: true,
+            cache_radius: default_cache_radius(),
+            adaptive_cache_radius: true,
+            max_cache_radius: default_max_cache_radius(),
+            cache_memory_mb: 0,
+            reload_threshold: default_reload_threshold(),
+            max_gap: default_max_gap(),
+            playback_fps: default_playback_fps(),
+            playback_preroll_ms: default_playback_preroll_ms(),
+            author: None,
+            remember_sessions: true,
+            single_instance: true,
+            replica_slow_ms: 0,
+            stall_secs: default_stall_secs(),
+            follow_poll_ms: default_follow_poll_ms(),
+            blink_hz: default_blink_hz(),
+            remote_compression: Compression::Off,
+            remote_channels: default_remote_channels(),
+            remote_bandwidth_limit: 0.0,
+            remote_max_in_flight_mb: 0.0,
+            background: Background::default(),
+            background_color: default_background_color(),
+            guides: Guide::defaults(),
+            guides_shown: BTreeSet::new(),
+            hidden_guides: BTreeMap::new(),
+            nearest_filtering: BTreeSet::new(),
+            remote_command_prefix: BTreeMap::new(),
+            replicas: BTreeMap::new(),
+            orientations: BTreeMap::new(),
+            tone_maps: BTreeMap::new(),
+            audio_tracks: BTreeMap::new(),
+            shards: BTreeMap::new(),
+            pattern_scopes: BTreeMap::new(),
+            paired_patterns: BTreeMap::new(),
+            loader_commands: BTreeMap::new(),
+            crops: BTreeMap::new(),
+            measure_scales: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Watchdog limit from `stall_secs`, `None` when disabled.
+    pub fn stall_timeout(&self) -> Option<Duration> {
+        (self.stall_secs > 0).then(|| Duration::from_secs(self.stall_secs))
+    }
+
+    /// Command prefix configured for `user_host`, matching `user@host` first, then the bare host.
+    pub fn command_prefix_for(&self, user_host: &str) -> Option<&str> {
+        let host = user_host.rsplit('@').next().unwrap_or(user_host);
+        self.remote_command_prefix
+            .get(user_host)
+            .or_else(|| self.remote_command_prefix.get(host))
+            .map(|s| s.as_str())
+    }
+
+    /// Replicas configured for `user_host` as `user@host` strings (primary not included).
+    pub fn replicas_for(&self, user_host: &str) -> Vec<String> {
+        let (user, host) = match user_host.rsplit_once('@') {
+            Some((user, host)) => (Some(user), host),
+            None => (None, user_host),
+        };
+        self.replicas
+            .get(user_host)
+            .or_else(|| self.replicas.get(host))
+            .map(|list| {
+                list.iter()
+                    .map(|r| match user {
+                        Some(user) if !r.contains('@') => format!("{user}@{r}"),
+                        _ => r.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn audio_track_for(&self, sequence_key: &str) -> Option<&AudioTrack> {
+        self.audio_tracks.get(sequence_key)
+    }
+
+    pub fn crop_for(&self, sequence_key: &str) -> Option<CropRect> {
+        self.crops.get(sequence_key).copied()
+    }
+
+    /// Remember the crop for a sequence (`None` removes it).
+    pub fn set_crop(&mut self, sequence_key: &str, crop: Option<CropRect>) {
+        match crop {
+            Some(crop) => self.crops.insert(sequence_key.to_string(), crop),
+            None => self.crops.remove(sequence_key),
+        };
+    }
+
+    pub fn measure_scale_for(&self, sequence_key: &str) -> Option<&MeasureScale> {
+        self.measure_scales.get(sequence_key)
+    }
+
+    pub fn guides_shown_for(&self, sequence_key: &str) -> bool {
+        self.guides_shown.contains(sequence_key)
+    }
+
+    pub fn set_guides_shown(&mut self, sequence_key: &str, shown: bool) {
+        if shown {
+            self.guides_shown.insert(sequence_key.to_string());
+        } else {
+            self.guides_shown.remove(sequence_key);
+        }
+    }
+
+    pub fn hidden_guides_for(&self, sequence_key: &str) -> Vec<Guide> {
+        self.hidden_guides.get(sequence_key).cloned().unwrap_or_default()
+    }
+
+    /// Remember which guides are off for a sequence (none removes the entry).
+    pub fn set_hidden_guides(&mut self, sequence_key: &str, hidden: Vec<Guide>) {
+        if hidden.is_empty() {
+            self.hidden_guides.remove(sequence_key);
+        } else {
+            self.hidden_guides.insert(sequence_key.to_string(), hidden);
+        }
+    }
+
+    pub fn nearest_filtering_for(&self, sequence_key: &str) -> bool {
+        self.nearest_filtering.contains(sequence_key)
+    }
+
+    pub fn set_nearest_filtering(&mut self, sequence_key: &str, nearest: bool) {
+        if nearest {
+            self.nearest_filtering.insert(sequence_key.to_string());
+        } else {
+            self.nearest_filtering.remove(sequence_key);
+        }
+    }
+
+    /// Transfer limits for remote workers from `remote_bandwidth_limit` and
+    /// `remote_max_in_flight_mb`.
+    pub fn transfer_throttle(&self) -> Throttle {
+        let bytes = |mb: f64| if mb.is_finite() && mb > 0.0 { (mb * 1_000_000.0) as u64 } else { 0 };
+        Throttle::new(bytes(self.remote_bandwidth_limit), bytes(self.remote_max_in_flight_mb))
+    }
+
+    /// Pixel memory for the adaptive cache radius from `cache_memory_mb`, `None` when unset.
+    pub fn cache_memory_budget(&self) -> Option<u64> {
+        (self.cache_memory_mb > 0).then(|| self.cache_memory_mb * 1_000_000)
+    }
+
+    /// The configured patterns that may match frames from `source`, in the order they
+    /// are tried: by `[pattern_scopes]` priority, then config order.
+    pub fn patterns_for(&self, source: &SequenceSource) -> Vec<&String> {
+        let mut patterns: Vec<&String> = self
+            .patterns
+            .iter()
+            .filter(|p| self.pattern_scopes.get(*p).is_none_or(|scope| scope.contains(source)))
+            .collect();
+        patterns.sort_by_key(|p| std::cmp::Reverse(self.pattern_priority(p)));
+        patterns
+    }
+
+    /// Priority of `pattern` from `[pattern_scopes]` (0 without an entry)
+    pub fn pattern_priority(&self, pattern: &str) -> i32 {
+        self.pattern_scopes.get(pattern).map_or(0, |scope| scope.priority)
+    }
+
+    /// Partner pattern shown beside `pattern`'s frames, if one is configured
+    pub fn pair_for(&self, pattern: &str) -> Option<&str> {
+        self.paired_patterns.get(pattern).map(String::as_str).filter(|p| !p.trim().is_empty())
+    }
+
+    /// Converter command configured for frames of `pattern`, if any
+    pub fn loader_command_for(&self, pattern: &str) -> Option<&str> {
+        self.loader_commands.get(pattern).map(String::as_str).filter(|c| !c.trim().is_empty())
+    }
+
+    pub fn orientation_for(&self, sequence_key: &str) -> Orientation {
+        self.orientations.get(sequence_key).copied().unwrap_or_default()
+    }
+
+    /// Remember the orientation for a sequence (identity removes the entry).
+    pub fn set_orientation(&mut self, sequence_key: &str, orientation: Orientation) {
+        if orientation.is_identity() {
+            self.orientations.remove(sequence_key);
+        } else {
+            self.orientations.insert(sequence_key.to_string(), orientation);
+        }
+    }
+
+    pub fn tone_map_for(&self, sequence_key: &str) -> ToneMap {
+        self.tone_maps.get(sequence_key).copied().unwrap_or_default()
+    }
+
+    /// Remember the tone mapping for a sequence (the default removes the entry).
+    pub fn set_tone_map(&mut self, sequence_key: &str, tone: ToneMap) {
+        if tone.is_identity() {
+            self.tone_maps.remove(sequence_key);
+        } else {
+            self.tone_maps.insert(sequence_key.to_string(), tone);
+        }
+    }
+}
+
+fn default_preview_max_dim() -> u32 {
+    1024
+}
+
+fn default_cache_radius() -> usize {
+    10
+}
+
+fn default_max_cache_radius() -> usize {
+    40
+}
+
+fn default_reload_threshold() -> u64 {
+    1
+}
+
+fn default_max_gap() -> u64 {
+    100
+}
+
+fn default_playback_fps() -> f64 {
+    25.0
+}
+
+fn default_playback_preroll_ms() -> u64 {
+    500
+}
+
+fn default_stall_secs() -> u64 {
+    60
+}
+
+fn default_follow_poll_ms() -> u64 {
+    2000
+}
+
+fn default_blink_hz() -> f64 {
+    2.0
+}
+
+fn default_remote_channels() -> usize {
+    3
+}
+
+fn default_background_color() -> [u8; 3] {
+    [64, 64, 64]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+pub fn load_config() -> Result<Config, ConfigError> {
     let path = config_path()?;
     if !path.exists() {
         return Ok(Config::default());
     }
-    let txt = fs::read_to_string(&path).context("Failed to read config")?;
-    let cfg: Config = toml::from_str(&txt).context("Failed to parse config TOML")?;
+    let txt = fs::read_to_string(&path).map_err(|source| ConfigError::Read { path, source })?;
+    let cfg: Config = toml::from_str(&txt).map_err(|source| ConfigError::Parse {
+        name: "config".to_string(),
+        source,
+    })?;
     Ok(cfg)
 }
 
-pub fn save_config(cfg: &Config) -> Result<()> {
+pub fn save_config(cfg: &Config) -> Result<(), ConfigError> {
     let path = config_path()?;
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent).ok();
     }
-    let txt = toml::to_string_pretty(cfg).context("Failed to serialize config TOML")?;
-    fs::write(&path, txt).context("Failed to write config")?;
+    let txt = toml::to_string_pretty(cfg).map_err(|source| ConfigError::Serialize {
+        name: "config".to_string(),
+        source,
+    })?;
+    fs::write(&path, txt).map_err(|source| ConfigError::Write { path, source })?;
     Ok(())
 }
 
-pub fn config_path() -> Result<PathBuf> {
-    let proj = ProjectDirs::from("dev", "zapvis", "zapvis")
-        .ok_or_else(|| anyhow!("Could not determine config directory"))?;
+pub fn config_path() -> Result<PathBuf, ConfigError> {
+    let proj = ProjectDirs::from("dev", "zapvis", "zapvis").ok_or(ConfigError::NoConfigDir)?;
     Ok(proj.config_dir().join("config.toml"))
 }
 
@@ -43,7 +637,79 @@ pub fn maybe_add_pattern(cfg: &mut Config, pat: String) {
     }
 }
 
-pub fn pattern_matches_file(pat: &str, file_name: &str) -> Result<bool> {
+/// Remove `pat` from the config. Returns false if it was not configured.
+pub fn remove_pattern(cfg: &mut Config, pat: &str) -> bool {
+    let before = cfg.patterns.len();
+    cfg.patterns.retain(|p| p != pat);
+    cfg.patterns.len() != before
+}
+
+pub fn pattern_matches_file(pat: &str, file_name: &str) -> Result<bool, PatternError> {
     let (re, _, _, _) = compile_pattern(pat)?;
     Ok(re.is_match(file_name))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audio_follows_the_frame_clock() {
+        let track = AudioTrack {
+            path: PathBuf::from("mic.wav"),
+            offset: 1.25,
+            sample_rate: None,
+        };
+        assert_eq!(track.time_at(0.0, 25.0), 1.25);
+        assert_eq!(track.time_at(50.0, 25.0), 3.25);
+        assert_eq!(track.time_at(12.5, 25.0), 1.75, "fractional positions between frames");
+        let late = AudioTrack { offset: -2.0, ..track };
+        assert!(late.time_at(25.0, 25.0) < 0.0, "silent until the audio starts");
+
+        assert!(!AudioTrack::needs_seek(3.3, 3.25, false), "small drift plays on");
+        assert!(AudioTrack::needs_seek(3.4, 3.25, false));
+        assert!(AudioTrack::needs_seek(1.0, 3.25, false), "the frame clock jumped");
+        assert!(AudioTrack::needs_seek(3.25, 3.25, true), "always resynced after a pause");
+    }
+
+    #[test]
+    fn replicas_inherit_the_primary_user() {
+        let mut cfg = Config::default();
+        cfg.replicas.insert(
+            "node-01".to_string(),
+            vec!["node-02".to_string(), "svc@node-03".to_string()],
+        );
+        assert_eq!(cfg.replicas_for("me@node-01"), ["me@node-02", "svc@node-03"]);
+        assert!(cfg.replicas_for("me@node-09").is_empty());
+    }
+
+    #[test]
+    fn scoped_patterns_apply_to_their_directories_and_hosts_by_priority() {
+        let mut cfg = Config {
+            patterns: ["a_#.png", "b_#.png", "c_#.png"].map(String::from).to_vec(),
+            ..Config::default()
+        };
+        let scope = |priority, dirs: &[&str], hosts: &[&str]| PatternScope {
+            priority,
+            dirs: dirs.iter().map(|d| d.to_string()).collect(),
+            hosts: hosts.iter().map(|h| h.to_string()).collect(),
+        };
+        cfg.pattern_scopes.insert("b_#.png".to_string(), scope(0, &["/data/renders/**"], &[]));
+        cfg.pattern_scopes.insert("c_#.png".to_string(), scope(5, &["/shots/*/exr"], &["render01"]));
+        let local = |dir: &str| SequenceSource::Local(dir.into());
+        let remote = |dir: &str| SequenceSource::Remote {
+            user_host: "me@render01".to_string(),
+            dir: dir.to_string(),
+        };
+        assert_eq!(cfg.patterns_for(&local("/data/renders")), ["a_#.png", "b_#.png"]);
+        assert_eq!(cfg.patterns_for(&local("/data/renders/x/y")), ["a_#.png", "b_#.png"]);
+        assert_eq!(cfg.patterns_for(&local("/data/other")), ["a_#.png"]);
+        assert_eq!(cfg.patterns_for(&local("/shots/s1/exr")), ["a_#.png"]);
+        assert_eq!(cfg.patterns_for(&remote("/shots/s1/exr")), ["c_#.png", "a_#.png"]);
+        assert_eq!(cfg.patterns_for(&remote("/shots/s1/s2/exr")), ["a_#.png"]);
+        let http = SequenceSource::Http {
+            base_url: "https://render01:8080/shots/s1/exr".to_string(),
+        };
+        assert_eq!(cfg.patterns_for(&http), ["c_#.png", "a_#.png"]);
+    }
+}
@@ -0,0 +1,158 @@
+//! Stuck-pixel detection over the cached frames.
+//!
+//! A pixel is flagged when it holds the same extreme value in every frame while its
+//! neighbors do not: stuck bright (hot) or stuck dark (dead). The neighbor check keeps
+//! blown-out skies and black letterboxes from being reported wholesale.
+use std::sync::Arc;
+
+use zapvis::frame::FramePixels;
+
+/// Channel value at or above which a stuck pixel counts as hot
+const HOT_LEVEL: u8 = 240;
+/// Channel value at or below which a stuck pixel counts as dead
+const DEAD_LEVEL: u8 = 16;
+/// Largest change across frames still considered constant
+const TOLERANCE: u8 = 8;
+/// How far a stuck pixel must stand out from its neighbors
+const CONTRAST: u16 = 48;
+/// Fewer frames than this say nothing about a pixel being constant
+pub const MIN_FRAMES: usize = 3;
+/// Results are capped; more than this means the thresholds do not fit the footage
+const MAX_REPORTED: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StuckPixel {
+    pub x: u32,
+    pub y: u32,
+    pub hot: bool,
+}
+
+/// Per-pixel, per-channel minimum and maximum across frames
+struct Extremes {
+    width: usize,
+    height: usize,
+    channels: usize,
+    min: Vec<u8>,
+    max: Vec<u8>,
+}
+
+impl Extremes {
+    fn of(frames: &[Arc<FramePixels>]) -> Option<Self> {
+        let first = frames.first()?;
+        let (w, h) = first.dimensions();
+        let raws: Vec<(&[u8], usize)> = frames
+            .iter()
+            .filter(|f| f.dimensions() == (w, h))
+            .map(|f| match f.as_ref() {
+                FramePixels::Rgba(img) => (img.as_raw().as_slice(), 4),
+                FramePixels::Luma(img) => (img.as_raw().as_slice(), 1),
+            })
+            .collect();
+        // Mixed RGBA/grayscale frames are not comparable
+        let channels = raws[0].1;
+        let raws: Vec<&[u8]> = raws.into_iter().filter(|r| r.1 == channels).map(|r| r.0).collect();
+        if raws.len() < MIN_FRAMES {
+            return None;
+        }
+        let mut min = raws[0].to_vec();
+        let mut max = raws[0].to_vec();
+        for raw in &raws[1..] {
+            for (i, &v) in raw.iter().enumerate() {
+                min[i] = min[i].min(v);
+                max[i] = max[i].max(v);
+            }
+        }
+        Some(Self {
+            width: w as usize,
+            height: h as usize,
+            channels,
+            min,
+            max,
+        })
+    }
+
+    /// Average of `values` over the 4-neighborhood of (x, y) in channel `c`
+    fn neighbor_mean(&self, values: &[u8], x: usize, y: usize, c: usize) -> u16 {
+        let mut sum = 0u32;
+        let mut n = 0u32;
+        let neighbors = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        for (nx, ny) in neighbors {
+            if nx < self.width && ny < self.height {
+                sum += values[(ny * self.width + nx) * self.channels + c] as u32;
+                n += 1;
+            }
+        }
+        (sum / n.max(1)) as u16
+    }
+}
+
+/// Hot and dead pixels across `frames` (all of the first frame's size), or `None` with
+/// fewer than [`MIN_FRAMES`] comparable frames.
+pub fn detect_stuck_pixels(frames: &[Arc<FramePixels>]) -> Option<Vec<StuckPixel>> {
+    let ex = Extremes::of(frames)?;
+    // Color channels only; alpha is not sensor data
+    let color_channels = ex.channels.min(3);
+    let mut found = Vec::new();
+    for y in 0..ex.height {
+        for x in 0..ex.width {
+            let base = (y * ex.width + x) * ex.channels;
+            let stuck = |c: usize| ex.max[base + c] - ex.min[base + c] <= TOLERANCE;
+            let hot = (0..color_channels).any(|c| {
+                stuck(c)
+                    && ex.min[base + c] >= HOT_LEVEL
+                    && ex.min[base + c] as u16 >= ex.neighbor_mean(&ex.min, x, y, c) + CONTRAST
+            });
+            let dead = !hot
+                && (0..color_channels).all(|c| ex.max[base + c] <= DEAD_LEVEL)
+                && (0..color_channels)
+                    .any(|c| ex.neighbor_mean(&ex.max, x, y, c) >= ex.max[base + c] as u16 + CONTRAST);
+            if hot || dead {
+                found.push(StuckPixel {
+                    x: x as u32,
+                    y: y as u32,
+                    hot,
+                });
+                if found.len() >= MAX_REPORTED {
+                    return Some(found);
+                }
+            }
+        }
+    }
+    Some(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GrayImage;
+
+    fn frame(seed: u8, hot: (u32, u32), dead: (u32, u32)) -> Arc<FramePixels> {
+        let mut img = GrayImage::from_fn(8, 8, |x, y| image::Luma([100 + ((x + y) as u8 * 7 + seed) % 60]));
+        img.put_pixel(hot.0, hot.1, image::Luma([255]));
+        img.put_pixel(dead.0, dead.1, image::Luma([0]));
+        Arc::new(FramePixels::Luma(img))
+    }
+
+    #[test]
+    fn flags_pixels_stuck_at_an_extreme() {
+        let frames: Vec<_> = (0..4).map(|i| frame(i * 13, (2, 3), (6, 6))).collect();
+        let found = detect_stuck_pixels(&frames).unwrap();
+        assert_eq!(
+            found,
+            [StuckPixel { x: 2, y: 3, hot: true }, StuckPixel { x: 6, y: 6, hot: false }]
+        );
+        assert!(detect_stuck_pixels(&frames[..2]).is_none());
+    }
+
+    #[test]
+    fn uniform_bright_areas_are_not_hot() {
+        let white = Arc::new(FramePixels::Luma(GrayImage::from_pixel(4, 4, image::Luma([255]))));
+        let frames = vec![white.clone(), white.clone(), white];
+        assert_eq!(detect_stuck_pixels(&frames), Some(Vec::new()));
+    }
+}
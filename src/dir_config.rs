@@ -0,0 +1,93 @@
+//! Per-directory `.zapvis.toml`: display settings the dataset owner keeps next to the frames.
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::Sender;
+
+use crate::error::ConfigError;
+use crate::orientation::Orientation;
+use crate::remote_worker::RemoteWorkerRequest;
+use crate::sequence::{fetch_frame_bytes, SequenceSource, SequenceSpec};
+
+/// File name of the per-directory settings, looked up in the sequence's source directory
+pub const DIR_CONFIG_FILE: &str = ".zapvis.toml";
+
+/// How the frames in a directory should be viewed, for everyone who opens them.
+///
+/// Its settings are preferred over the global config (its pattern is tried before the
+/// configured ones), but a viewer's own saved settings for the sequence (e.g. an
+/// orientation or exposure they chose) still win; a manifest's `fps` wins over the
+/// directory's.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DirConfig {
+    /// Sequence pattern of the frames (e.g. `"shot_####.exr"`), so nobody has to add it
+    /// to their own config
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Rotation and flips, as in the `[orientations]` table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<Orientation>,
+    /// Playback frames per second
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fps: Option<f64>,
+    /// Exposure in stops for linear float frames, as set with `[` and `]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exposure: Option<f32>,
+    /// Channel view to open with: `"rgba"`, `"r"`, `"g"`, `"b"`, `"a"` or `"luma"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channels: Option<String>,
+}
+
+impl DirConfig {
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        toml::from_str(text).map_err(|source| ConfigError::Parse {
+            name: DIR_CONFIG_FILE.to_string(),
+            source,
+        })
+    }
+}
+
+/// Read the `.zapvis.toml` of `seq`'s directory, locally or over the remote worker's CAT.
+/// `Ok(None)` if the directory has none.
+pub fn load_dir_config(seq: &SequenceSpec, request_tx: Option<&Sender<RemoteWorkerRequest>>) -> Result<Option<DirConfig>, ConfigError> {
+    // The sequence's own index passes the remote worker's range check
+    load_dir_config_at(&seq.source, seq.index, request_tx)
+}
+
+/// [`load_dir_config`] for the directory of `source`, before a sequence is picked (to
+/// learn its pattern). `idx` must pass the remote worker's range check; any does
+/// before the first frame is requested.
+pub fn load_dir_config_at(source: &SequenceSource, idx: i64, request_tx: Option<&Sender<RemoteWorkerRequest>>) -> Result<Option<DirConfig>, ConfigError> {
+    let bytes = match fetch_frame_bytes(&source.neighbor_source(), DIR_CONFIG_FILE, idx, request_tx) {
+        Ok(bytes) => bytes,
+        Err(e) if e.is_not_found() => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let text = String::from_utf8(bytes).map_err(|_| ConfigError::Invalid(format!("{} is not UTF-8", DIR_CONFIG_FILE)))?;
+    DirConfig::parse(&text).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_directory_settings_if_present() {
+        let dir = std::env::temp_dir().join(format!("zapvis_dir_config_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let seq = SequenceSpec::from_pattern("f_#.png", SequenceSource::Local(dir.clone()), 1).unwrap();
+        assert_eq!(load_dir_config(&seq, None).unwrap(), None);
+
+        let text = "pattern = \"f_#.png\"\nfps = 12.0\nexposure = -1.5\nchannels = \"luma\"\n\n[orientation]\nrotation = 1\n";
+        std::fs::write(dir.join(DIR_CONFIG_FILE), text).unwrap();
+        let cfg = load_dir_config(&seq, None).unwrap().unwrap();
+        assert_eq!(load_dir_config_at(&seq.source, 0, None).unwrap().as_ref(), Some(&cfg));
+        assert_eq!(cfg.pattern.as_deref(), Some("f_#.png"));
+        assert_eq!(cfg.exposure, Some(-1.5));
+        assert_eq!(cfg.fps, Some(12.0));
+        assert_eq!(cfg.channels.as_deref(), Some("luma"));
+        assert_eq!(cfg.orientation.map(|o| o.rotation), Some(1));
+
+        std::fs::write(dir.join(DIR_CONFIG_FILE), "fps = \"fast\"").unwrap();
+        assert!(load_dir_config(&seq, None).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
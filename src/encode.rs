@@ -0,0 +1,139 @@
+//! `zapvis encode`: stream a decoded frame range into an ffmpeg child process.
+use anyhow::{anyhow, Context, Result};
+use image::imageops::FilterType;
+use image::RgbaImage;
+use serde_json::json;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use tracing::{info, warn};
+
+use crate::cli::EncodeArgs;
+use crate::commands::print_json;
+use crate::export::{annotated, for_each_frame, load_annotations, range_or_loop, view_scale};
+use crate::input::open_input;
+use zapvis::config::load_config;
+
+pub fn run_encode(args: EncodeArgs) -> Result<()> {
+    let mut cfg = load_config().unwrap_or_default();
+    let opened = open_input(&args.input, args.pattern.clone(), &mut cfg)?;
+    let range = range_or_loop(args.range, &opened)?;
+    let fps = args
+        .fps
+        .or_else(|| opened.manifest.as_ref().and_then(|m| m.fps))
+        .or_else(|| opened.dir_config.as_ref().and_then(|d| d.fps))
+        .or_else(|| opened.candidates[0].1.source.native_fps())
+        .unwrap_or(cfg.playback_fps);
+    if !(fps > 0.0 && fps.is_finite()) {
+        return Err(anyhow!("--fps must be a positive number, got {}", fps));
+    }
+    let annotations = load_annotations(&opened, args.annotations)?;
+
+    // ffmpeg is started with the first frame, whose size fixes the video size
+    let mut encoder: Option<Ffmpeg> = None;
+    let mut last: Option<RgbaImage> = None;
+    let mut written = 0u64;
+    let mut missing = Vec::new();
+    let result = for_each_frame(&opened, range, &cfg, "Encode", |n, idx, _, pixels| {
+        match pixels {
+            Some(pixels) => {
+                let scale = view_scale(pixels.dimensions(), cfg.max_decode_dim);
+                let mut rgba = annotated(pixels, &annotations, idx, scale).into_rgba8();
+                if let Some(enc) = encoder.as_ref().filter(|e| rgba.dimensions() != (e.width, e.height)) {
+                    rgba = image::imageops::resize(&rgba, enc.width, enc.height, FilterType::CatmullRom);
+                }
+                last = Some(rgba);
+            }
+            // Repeat the previous frame so the clip keeps its timing
+            None => {
+                warn!("frame {} missing", idx);
+                missing.push(idx);
+            }
+        }
+        let Some(frame) = &last else {
+            return Ok(());
+        };
+        let enc = match &mut encoder {
+            Some(enc) => enc,
+            None => encoder.insert(Ffmpeg::spawn(&args.ffmpeg, frame.width(), frame.height(), fps, &args.out)?),
+        };
+        enc.write(frame)?;
+        written += 1;
+        info!("encoded {}/{} frame {}", n + 1, range.len(), idx);
+        Ok(())
+    });
+
+    let Some(encoder) = encoder else {
+        result?;
+        return Err(anyhow!("No frames in {}..={} could be encoded", range.first, range.last));
+    };
+    // Let ffmpeg finish the file either way; its exit status explains a broken pipe
+    let finished = encoder.finish();
+    result?;
+    finished?;
+    if args.json {
+        let (pattern, seq) = &opened.candidates[0];
+        return print_json(&json!({
+            "sequence": seq.source.location(),
+            "pattern": pattern,
+            "first": range.first,
+            "last": range.last,
+            "out": args.out,
+            "fps": fps,
+            "written": written,
+            "missing": missing,
+        }));
+    }
+    println!(
+        "Encoded {} frames at {} fps to {} ({} missing)",
+        written,
+        fps,
+        args.out.display(),
+        missing.len()
+    );
+    Ok(())
+}
+
+/// An ffmpeg process reading raw RGBA frames from stdin
+struct Ffmpeg {
+    child: Child,
+    stdin: ChildStdin,
+    width: u32,
+    height: u32,
+}
+
+impl Ffmpeg {
+    fn spawn(ffmpeg: &Path, width: u32, height: u32, fps: f64, out: &Path) -> Result<Self> {
+        let mut child = Command::new(ffmpeg)
+            .args(["-hide_banner", "-loglevel", "error", "-y"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba", "-s", &format!("{width}x{height}")])
+            .args(["-framerate", &fps.to_string(), "-i", "-"])
+            // yuv420p, which most players expect, needs even dimensions
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-pix_fmt", "yuv420p"])
+            .arg(out)
+            .stdin(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {} (is ffmpeg installed?)", ffmpeg.display()))?;
+        let stdin = child.stdin.take().context("ffmpeg stdin unavailable")?;
+        Ok(Self {
+            child,
+            stdin,
+            width,
+            height,
+        })
+    }
+
+    fn write(&mut self, frame: &RgbaImage) -> Result<()> {
+        self.stdin.write_all(frame.as_raw()).context("ffmpeg stopped accepting frames")
+    }
+
+    fn finish(self) -> Result<()> {
+        let Self { mut child, stdin, .. } = self;
+        drop(stdin); // end of input
+        let status = child.wait().context("Failed to wait for ffmpeg")?;
+        if !status.success() {
+            return Err(anyhow!("ffmpeg exited with {status}"));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+//! Typed errors of the library, so callers can tell an authentication failure from a
+//! missing frame or a broken file without matching on messages. The binary wraps them
+//! in `anyhow` at its boundary.
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Failures of the persistent SSH session and the remote worker (and of the HTTP worker,
+/// which answers the same requests)
+#[derive(Debug, Error)]
+pub enum SshError {
+    #[error("failed to start ssh to {host}: {source}")]
+    Spawn {
+        host: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The server rejected the key (ssh runs in batch mode, so there is no other way in)
+    #[error("SSH authentication to {host} failed: {message}")]
+    AuthFailed { host: String, message: String },
+    /// ssh exited or closed the pipe: unreachable host, dropped connection, ...
+    #[error("SSH session to {host} closed{}", if .message.is_empty() { String::new() } else { format!(": {}", .message) })]
+    Closed { host: String, message: String },
+    #[error("SSH pipe error: {0}")]
+    Io(#[from] io::Error),
+    #[error("unexpected response from the remote loop: {0}")]
+    Protocol(String),
+    /// The payload does not match the remote md5sum (after one retry)
+    #[error("checksum mismatch for {path}: remote md5 {expected}, received {actual}")]
+    Checksum {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("failed to decompress the {codec} transfer: {source}")]
+    Decompress {
+        codec: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The server answered with an error status or could not be reached
+    #[error("HTTP request for {url} failed: {message}")]
+    Http { url: String, message: String },
+    /// The remote file does not exist
+    #[error("not found on the remote host: {0}")]
+    NotFound(String),
+    #[error("remote command prefix {0:?} contains forbidden characters or is empty")]
+    CommandPrefix(String),
+    #[error("SSH not connected (use reconnect)")]
+    NotConnected,
+    #[error("no remote hosts configured")]
+    NoHosts,
+    /// The remote worker thread is gone
+    #[error("remote worker hung up")]
+    WorkerGone,
+    /// Dropped because the frame left the cached window before its turn
+    #[error("cancelled: out of range")]
+    Cancelled,
+}
+
+/// Problems with a sequence pattern or with matching one to a file
+#[derive(Debug, Error)]
+pub enum PatternError {
+    #[error("pattern must contain at least one # run, got {0:?}")]
+    NoHashRun(String),
+    #[error("multiple # blocks must be separated by '_', got {separator:?} in {pattern:?}")]
+    BadSeparator { pattern: String, separator: String },
+    #[error("shard directory must contain exactly one # run, got {0:?}")]
+    BadShardDir(String),
+    #[error("shard rule for {0:?} needs frames_per_dir > 0")]
+    NoFramesPerDir(String),
+    #[error("folder part of {0:?} must be a single folder with a # run, optionally followed by :N frames per folder")]
+    BadDirPattern(String),
+    #[error("radix modifier after the last # run must be %x, %X or %N with N from 2 to 36, got {0:?}")]
+    BadRadix(String),
+    #[error("frame number in {0:?} is too large")]
+    BadIndex(String),
+    #[error("no patterns configured")]
+    NoPatterns,
+    #[error("no configured pattern matched with neighbor evidence")]
+    NoMatch,
+    #[error("no digit run in {0:?} to infer a pattern from")]
+    NothingToInfer(String),
+    #[error("no inferred pattern has a neighboring frame")]
+    NoNeighbors,
+    #[error("non-UTF-8 file name not supported")]
+    NonUtf8Name,
+    #[error("failed to compile regex from pattern: {0}")]
+    Regex(#[from] regex::Error),
+}
+
+/// Failures fetching or decoding a frame
+#[derive(Debug, Error)]
+pub enum CacheError {
+    /// The frame file does not exist (as opposed to transfer or decode failures)
+    #[error("frame not found: {0}")]
+    NotFound(String),
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("failed to decode {path}: {source}")]
+    Decode {
+        path: String,
+        #[source]
+        source: image::ImageError,
+    },
+    /// The configured `loader_commands` converter failed or could not be started
+    #[error("loader command for {path} failed: {message}")]
+    LoaderCommand { path: String, message: String },
+    /// ffprobe or ffmpeg failed on a video opened as a sequence
+    #[error("video {path}: {message}")]
+    Video { path: String, message: String },
+    #[error("SSH connection not available")]
+    NoConnection,
+    /// The frame left the cache window before it was loaded
+    #[error("load cancelled")]
+    Cancelled,
+    #[error(transparent)]
+    Ssh(SshError),
+}
+
+impl CacheError {
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, CacheError::NotFound(_))
+    }
+
+    pub fn kind(&self) -> FailureKind {
+        match self {
+            CacheError::NotFound(_) => FailureKind::NotFound,
+            CacheError::Read { .. } => FailureKind::Read,
+            CacheError::Decode { .. } | CacheError::LoaderCommand { .. } | CacheError::Video { .. } => FailureKind::Decode,
+            CacheError::NoConnection | CacheError::Cancelled | CacheError::Ssh(_) => FailureKind::Transfer,
+        }
+    }
+}
+
+/// Broad class of a [`CacheError`], for telling failed frames apart at a glance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    NotFound,
+    /// Reading a local file failed
+    Read,
+    /// The file was read but is not a decodable image
+    Decode,
+    /// The SSH connection or transfer failed
+    Transfer,
+}
+
+impl FailureKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            FailureKind::NotFound => "not found",
+            FailureKind::Read => "read error",
+            FailureKind::Decode => "decode error",
+            FailureKind::Transfer => "SSH error",
+        }
+    }
+}
+
+impl From<SshError> for CacheError {
+    fn from(e: SshError) -> Self {
+        match e {
+            SshError::NotFound(path) => CacheError::NotFound(path),
+            SshError::Cancelled => CacheError::Cancelled,
+            e => CacheError::Ssh(e),
+        }
+    }
+}
+
+/// Failures reading or writing the config, manifests, bookmarks and `.zapvis.toml` files
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("could not determine the config directory")]
+    NoConfigDir,
+    #[error("failed to read {}: {source}", .path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("failed to write {}: {source}", .path.display())]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("failed to parse {name}: {source}")]
+    Parse {
+        name: String,
+        #[source]
+        source: toml::de::Error,
+    },
+    #[error("failed to serialize {name}: {source}")]
+    Serialize {
+        name: String,
+        #[source]
+        source: toml::ser::Error,
+    },
+    /// Well-formed but unusable contents
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Pattern(#[from] PatternError),
+    #[error(transparent)]
+    Fetch(#[from] CacheError),
+}
+
+/// Failures loading a `.cube` 3D LUT
+#[derive(Debug, Error)]
+pub enum LutError {
+    #[error("failed to read {}: {source}", .path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+    /// A 1D LUT, or a 3D one without its size or with the wrong number of entries
+    #[error("{0}")]
+    Unsupported(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_remote_files_become_missing_frames() {
+        assert!(CacheError::from(SshError::NotFound("/data/f_1.png".to_string())).is_not_found());
+        let dropped = CacheError::from(SshError::Closed {
+            host: "me@host".to_string(),
+            message: String::new(),
+        });
+        assert!(!dropped.is_not_found());
+        assert_eq!(dropped.to_string(), "SSH session to me@host closed");
+    }
+}
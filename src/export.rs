@@ -0,0 +1,358 @@
+//! `zapvis export`: fetch, decode and write a frame range without starting the GUI.
+use anyhow::{anyhow, Context, Result};
+use image::imageops::FilterType;
+use image::ImageFormat;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use tracing::{info, warn};
+
+use crate::cli::{ExportArgs, ExportFormat, FrameRange};
+use crate::commands::print_json;
+use crate::input::{open_input, OpenedInput};
+use zapvis::annotations::{annotation_path, burn_in, load_annotation_file, AnnotationFile};
+use zapvis::config::{load_config, Config, CropRect};
+use zapvis::error::CacheError;
+use zapvis::frame::{capped_size, load_frame_from_bytes, load_frame_from_bytes_with_command, DecodeOptions, FramePixels};
+use zapvis::remote_worker::RemoteWorkerRequest;
+use zapvis::sequence::SequenceSpec;
+use zapvis::session::{load_session_file, session_path};
+
+#[derive(Debug, Serialize)]
+pub struct ExportedFrame {
+    pub index: i64,
+    pub path: PathBuf,
+}
+
+/// Frame that stopped the export, with the reason
+#[derive(Debug, Serialize)]
+pub struct FrameError {
+    pub index: i64,
+    pub error: String,
+}
+
+/// Outcome of `zapvis export --json`
+#[derive(Debug, Serialize)]
+pub struct ExportSummary {
+    pub sequence: String,
+    pub pattern: String,
+    pub first: i64,
+    pub last: i64,
+    pub written: Vec<ExportedFrame>,
+    pub missing: Vec<i64>,
+    pub failed: Option<FrameError>,
+}
+
+pub fn run_export(args: ExportArgs) -> Result<()> {
+    if !(args.scale > 0.0 && args.scale.is_finite()) {
+        return Err(anyhow!("--scale must be a positive number, got {}", args.scale));
+    }
+    // `--out -` streams the encoded frames to stdout, e.g. into ffmpeg's image2pipe
+    let to_stdout = args.out == Path::new("-");
+    if to_stdout && args.json {
+        return Err(anyhow!("--json and `--out -` both write to stdout; use one of them"));
+    }
+    let mut cfg = load_config().unwrap_or_default();
+    let opened = open_input(&args.input, args.pattern.clone(), &mut cfg)?;
+    let range = range_or_loop(args.range, &opened)?;
+
+    let annotations = load_annotations(&opened, args.annotations)?;
+    let crop = if args.crop {
+        let (pattern, seq) = &opened.candidates[0];
+        let crop = cfg.crop_for(&seq.sequence_key(pattern));
+        Some(crop.ok_or_else(|| anyhow!("--crop given but no crop (Shift+drag with K in the viewer) is saved for this sequence"))?)
+    } else {
+        None
+    };
+
+    if !to_stdout {
+        fs::create_dir_all(&args.out).with_context(|| format!("Failed to create {}", args.out.display()))?;
+    }
+    let (pattern, seq) = &opened.candidates[0];
+    let mut summary = ExportSummary {
+        sequence: seq.source.location(),
+        pattern: pattern.clone(),
+        first: range.first,
+        last: range.last,
+        written: Vec::new(),
+        missing: Vec::new(),
+        failed: None,
+    };
+    // The frame being worked on, which is the failed one if the export stops
+    let mut next = range.first;
+    let result = for_each_frame(&opened, range, &cfg, "Export", |n, idx, seq, pixels| {
+        let Some(pixels) = pixels else {
+            warn!("frame {} missing, skipped", idx);
+            summary.missing.push(idx);
+            next = idx + 1;
+            return Ok(());
+        };
+        let scale = view_scale(pixels.dimensions(), cfg.max_decode_dim);
+        let img = annotated(pixels, &annotations, idx, scale);
+        let img = match crop {
+            Some(crop) => crop_frame(img, scale_crop(crop, scale)),
+            None => img,
+        };
+        let out = match to_stdout {
+            true => {
+                write_frame(img, args.scale, args.format, &mut io::stdout().lock())?;
+                args.out.clone()
+            }
+            false => {
+                let out = args.out.join(output_name(&seq.file_name_for(idx), args.format));
+                save_frame(img, args.scale, args.format, &out)?;
+                out
+            }
+        };
+        info!("exported {}/{} {}", n + 1, range.len(), out.display());
+        summary.written.push(ExportedFrame { index: idx, path: out });
+        next = idx + 1;
+        Ok(())
+    });
+
+    if args.json {
+        if let Err(e) = &result {
+            summary.failed = Some(FrameError { index: next, error: format!("{e:#}") });
+        }
+        print_json(&summary)?;
+    }
+    result?;
+    let written = summary.written.len();
+    match (args.json, to_stdout) {
+        (true, _) => {}
+        (false, true) => eprintln!("Exported {} frames to stdout ({} missing)", written, summary.missing.len()),
+        (false, false) => println!("Exported {} frames to {} ({} missing)", written, args.out.display(), summary.missing.len()),
+    }
+    if written == 0 {
+        return Err(anyhow!("No frames in {}..={} could be exported", range.first, range.last));
+    }
+    Ok(())
+}
+
+/// `range` if given, else the loop region saved with the viewer's last session on the
+/// input's first sequence
+pub fn range_or_loop(range: Option<FrameRange>, opened: &OpenedInput) -> Result<FrameRange> {
+    if let Some(range) = range {
+        return Ok(range);
+    }
+    let (pattern, seq) = &opened.candidates[0];
+    let session = load_session_file(&session_path()?)?.sessions.remove(&seq.sequence_key(pattern));
+    match session.map(|s| (s.loop_in, s.loop_out)) {
+        Some((Some(first), Some(last))) if first <= last => {
+            info!("using the loop region {}..={} marked in the viewer", first, last);
+            Ok(FrameRange { first, last })
+        }
+        _ => Err(anyhow!("No --range given and no loop region (both Ctrl+I and Ctrl+O in the viewer) marked for this sequence")),
+    }
+}
+
+/// Fetch and decode `range` of the input's first sequence in order, passing each frame
+/// (`None` if it does not exist) to `sink` together with its position in the range.
+/// Frames are decoded at full size, with the pattern's loader command when `cfg` has one.
+pub fn for_each_frame(
+    opened: &OpenedInput,
+    range: FrameRange,
+    cfg: &Config,
+    tag: &str,
+    mut sink: impl FnMut(u64, i64, &SequenceSpec, Option<FramePixels>) -> Result<()>,
+) -> Result<()> {
+    let (pattern, seq) = &opened.candidates[0];
+    info!("{}: {} with pattern {:?}", tag, seq.source.location(), pattern);
+    // Oriented and color managed like in the viewer, but not limited to `max_decode_dim`
+    let decode_opts = DecodeOptions {
+        honor_exif_orientation: cfg.honor_exif_orientation,
+        color_manage: cfg.color_management,
+        tone: cfg.tone_map_for(&seq.sequence_key(pattern)),
+        ..DecodeOptions::raw()
+    };
+    let loader_command = cfg.loader_command_for(pattern);
+    opened.remote_range.set(range.first, range.last);
+    for (n, idx) in range.frames().enumerate() {
+        let pixels = decode_frame(seq, idx, opened.remote_tx.as_ref(), loader_command, decode_opts)?;
+        sink(n as u64, idx, seq, pixels)?;
+    }
+    Ok(())
+}
+
+/// Fetch and decode frame `idx` of `seq`; `None` if it does not exist
+pub fn decode_frame(
+    seq: &SequenceSpec,
+    idx: i64,
+    request_tx: Option<&Sender<RemoteWorkerRequest>>,
+    loader_command: Option<&str>,
+    decode_opts: DecodeOptions,
+) -> Result<Option<FramePixels>> {
+    let bytes = match seq.fetch_bytes(idx, request_tx) {
+        Ok(bytes) => bytes,
+        Err(e) if e.is_not_found() => return Ok(None),
+        Err(e) => return Err(e).context(format!("Failed to fetch frame {idx}")),
+    };
+    let pixels = match loader_command {
+        Some(command) => load_frame_from_bytes_with_command(command, &bytes, &seq.path_display(idx), decode_opts),
+        None => load_frame_from_bytes(&bytes, &seq.path_display(idx), decode_opts),
+    };
+    pixels.map(Some).map_err(|e: CacheError| e.into())
+}
+
+/// The annotation sidecar of the input's first sequence if `wanted`, else no annotations
+pub fn load_annotations(opened: &OpenedInput, wanted: bool) -> Result<AnnotationFile> {
+    if !wanted {
+        return Ok(AnnotationFile::default());
+    }
+    let (pattern, seq) = &opened.candidates[0];
+    Ok(load_annotation_file(&annotation_path(seq, pattern)?)?)
+}
+
+/// Size of a full-size `size` frame relative to the frame the viewer decodes under the
+/// resolution cap `max_decode_dim`, in whose pixels crops and annotations are stored
+pub fn view_scale(size: (u32, u32), max_decode_dim: u32) -> f32 {
+    size.0 as f32 / capped_size(size, max_decode_dim).0 as f32
+}
+
+/// Frame `idx` with the annotations made on it burned in, resized by `scale` (see
+/// [`view_scale`])
+pub fn annotated(pixels: FramePixels, annotations: &AnnotationFile, idx: i64, scale: f32) -> image::DynamicImage {
+    match annotations.at(idx).next() {
+        Some(_) => {
+            let mut img = pixels.to_rgba().into_owned();
+            let shapes: Vec<_> = annotations.at(idx).map(|shape| shape.scaled(scale)).collect();
+            burn_in(&mut img, &shapes);
+            image::DynamicImage::ImageRgba8(img)
+        }
+        None => pixels.into_dynamic(),
+    }
+}
+
+/// `crop` resized by `scale` (see [`view_scale`])
+fn scale_crop(crop: CropRect, scale: f32) -> CropRect {
+    let s = |v: u32| (v as f32 * scale).round() as u32;
+    CropRect {
+        x: s(crop.x),
+        y: s(crop.y),
+        width: s(crop.width).max(1),
+        height: s(crop.height).max(1),
+    }
+}
+
+/// `img` cut to `crop`, clipped to the image
+pub fn crop_frame(img: image::DynamicImage, crop: CropRect) -> image::DynamicImage {
+    img.crop_imm(crop.x, crop.y, crop.width, crop.height)
+}
+
+/// Write `frames` of `seq` cropped to `crop` as PNGs named after the frames into `out`
+/// (the viewer's ROI export). Returns the frames written and those missing.
+pub fn export_cropped(
+    seq: &SequenceSpec,
+    frames: std::ops::RangeInclusive<i64>,
+    crop: CropRect,
+    out: &Path,
+    request_tx: Option<&Sender<RemoteWorkerRequest>>,
+    loader_command: Option<&str>,
+    decode_opts: DecodeOptions,
+) -> Result<(u64, u64)> {
+    fs::create_dir_all(out).with_context(|| format!("Failed to create {}", out.display()))?;
+    let (mut written, mut missing) = (0, 0);
+    for idx in frames {
+        let Some(pixels) = decode_frame(seq, idx, request_tx, loader_command, decode_opts)? else {
+            missing += 1;
+            continue;
+        };
+        let img = crop_frame(pixels.into_dynamic(), crop);
+        save_frame(img, 1.0, ExportFormat::Png, &out.join(output_name(&seq.file_name_for(idx), ExportFormat::Png)))?;
+        written += 1;
+    }
+    info!("exported {} frames cropped to {:?} to {} ({} missing)", written, crop, out.display(), missing);
+    Ok((written, missing))
+}
+
+/// Source filename with the extension of the export format
+fn output_name(file_name: &str, format: ExportFormat) -> String {
+    let stem = Path::new(file_name)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(file_name);
+    let ext = match format {
+        ExportFormat::Png => "png",
+        ExportFormat::Jpg => "jpg",
+    };
+    format!("{stem}.{ext}")
+}
+
+/// `img` resized by `scale`, in the image format of `format`
+fn prepare_frame(img: image::DynamicImage, scale: f32, format: ExportFormat) -> (image::DynamicImage, ImageFormat) {
+    let img = if scale == 1.0 {
+        img
+    } else {
+        let w = ((img.width() as f32 * scale).round() as u32).max(1);
+        let h = ((img.height() as f32 * scale).round() as u32).max(1);
+        img.resize_exact(w, h, FilterType::CatmullRom)
+    };
+    match format {
+        ExportFormat::Png => (img, ImageFormat::Png),
+        // JPEG has no alpha channel
+        ExportFormat::Jpg => (image::DynamicImage::ImageRgb8(img.to_rgb8()), ImageFormat::Jpeg),
+    }
+}
+
+fn save_frame(img: image::DynamicImage, scale: f32, format: ExportFormat, out: &Path) -> Result<()> {
+    let (img, format) = prepare_frame(img, scale, format);
+    img.save_with_format(out, format).with_context(|| format!("Failed to write {}", out.display()))
+}
+
+/// Encode a frame as [`save_frame`] does, into `out` (stdout for `--out -`)
+fn write_frame(img: image::DynamicImage, scale: f32, format: ExportFormat, out: &mut impl Write) -> Result<()> {
+    let (img, format) = prepare_frame(img, scale, format);
+    // The encoders need to seek, a pipe cannot
+    let mut encoded = Cursor::new(Vec::new());
+    img.write_to(&mut encoded, format).context("Failed to encode the frame")?;
+    out.write_all(encoded.get_ref())
+        .and_then(|_| out.flush())
+        .context("Failed to write the frame")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use zapvis::annotations::{Shape, ANNOTATION_COLOR};
+
+    #[test]
+    fn annotations_are_burned_into_their_frame_only() {
+        let mut annotations = AnnotationFile::default();
+        annotations.add(3, Shape::Rect { min: [2.0, 2.0], max: [20.0, 12.0] });
+        let frame = || FramePixels::Rgba(RgbaImage::from_pixel(32, 32, Rgba([0, 0, 0, 255])));
+
+        let marked = annotated(frame(), &annotations, 3, 1.0).into_rgba8();
+        assert_eq!(marked.get_pixel(2, 6).0, ANNOTATION_COLOR);
+        let plain = annotated(frame(), &annotations, 4, 1.0).into_rgba8();
+        assert_eq!(plain.get_pixel(2, 6).0, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn frames_export_at_full_size_past_max_decode_dim() {
+        let dir = std::env::temp_dir().join(format!("zapvis_export_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        RgbaImage::from_pixel(200, 100, Rgba([0, 0, 0, 255])).save(dir.join("f_0001.png")).unwrap();
+        let mut cfg = Config {
+            patterns: vec!["f_####.png".to_string()],
+            max_decode_dim: 64,
+            ..Config::default()
+        };
+        let opened = open_input(dir.join("f_0001.png").to_str().unwrap(), None, &mut cfg).unwrap();
+
+        let mut sizes = Vec::new();
+        for_each_frame(&opened, FrameRange { first: 1, last: 1 }, &cfg, "Export", |_, _, _, pixels| {
+            sizes.push(pixels.unwrap().dimensions());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(sizes, [(200, 100)]);
+        // Crops and annotations drawn on the 64x32 frame in the viewer grow with it
+        assert_eq!(view_scale((200, 100), 64), 3.125);
+        let crop = CropRect { x: 8, y: 8, width: 16, height: 8 };
+        assert_eq!(scale_crop(crop, 3.125), CropRect { x: 25, y: 25, width: 50, height: 25 });
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,67 @@
+//! `zapvis fetch`: mirror a frame range of a (remote) sequence to a local directory.
+use anyhow::{anyhow, Context, Result};
+use serde_json::json;
+use std::fs;
+use std::time::Duration;
+use tracing::info;
+
+use crate::cli::FetchArgs;
+use crate::commands::print_json;
+use crate::input::open_input;
+use zapvis::config::load_config;
+use zapvis::mirror::{mirror_range, MirrorProgress};
+
+/// How often the progress line is refreshed
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+pub fn run_fetch(args: FetchArgs) -> Result<()> {
+    let mut cfg = load_config().unwrap_or_default();
+    let opened = open_input(&args.input, args.pattern.clone(), &mut cfg)?;
+    let (pattern, seq) = &opened.candidates[0];
+    fs::create_dir_all(&args.dest).with_context(|| format!("Failed to create {}", args.dest.display()))?;
+    info!("fetching {} with pattern {:?} to {}", seq.source.location(), pattern, args.dest.display());
+
+    opened.remote_range.set(args.range.first, args.range.last);
+    let progress = MirrorProgress::new();
+    let total = args.range.len();
+    let done = std::thread::scope(|scope| {
+        let mirror = scope.spawn(|| {
+            mirror_range(seq, args.range.frames(), &args.dest, opened.remote_tx.as_ref(), cfg.remote_channels, &progress)
+        });
+        let mut shown = u64::MAX;
+        while !mirror.is_finished() {
+            let snap = progress.snapshot();
+            if snap.done != shown {
+                info!("fetched {}/{} ({:.1} MB)", snap.done, total, snap.bytes as f64 / 1_000_000.0);
+                shown = snap.done;
+            }
+            std::thread::sleep(PROGRESS_INTERVAL);
+        }
+        mirror.join().map_err(|_| anyhow!("Fetch thread panicked"))
+    })?;
+
+    if args.json {
+        print_json(&json!({
+            "sequence": seq.source.location(),
+            "pattern": pattern,
+            "first": args.range.first,
+            "last": args.range.last,
+            "dest": args.dest,
+            "result": done,
+        }))?;
+    } else {
+        println!(
+            "Fetched {} frames to {} ({} already there, {} missing, {} failed, {:.1} MB)",
+            done.written,
+            args.dest.display(),
+            done.present,
+            done.missing,
+            done.failed,
+            done.bytes as f64 / 1_000_000.0
+        );
+    }
+    if let Some(e) = done.last_error {
+        return Err(anyhow!("{} frames could not be fetched; last error: {}", done.failed, e));
+    }
+    Ok(())
+}
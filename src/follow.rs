@@ -0,0 +1,144 @@
+//! Following a sequence that is still being written: noticing frames appended after the last one.
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::bounds::last_existing;
+use crate::remote_worker::RemoteWorkerRequest;
+use crate::sequence::{SequenceSource, SequenceSpec};
+
+/// Wait after a file event before probing, so a frame being written has time to land
+const SETTLE: Duration = Duration::from_millis(250);
+
+/// Background watch for new frames past the last known one.
+///
+/// Local directories are watched for file events (with `poll` as a fallback for
+/// filesystems that do not deliver them, such as NFS); remote sequences are polled
+/// with EXISTS requests through the remote worker every `poll`. Either way the new
+/// end is found by probing forwards from the last frame, so frames must be appended
+/// in order.
+pub struct Follower {
+    rx: Receiver<i64>,
+    stop: Arc<AtomicBool>,
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl Follower {
+    /// Start following `seq`, whose last frame is currently `last`.
+    pub fn start(
+        seq: SequenceSpec,
+        last: i64,
+        request_tx: Option<Sender<RemoteWorkerRequest>>,
+        poll: Duration,
+    ) -> notify::Result<Self> {
+        let (events_tx, events_rx) = channel();
+        let watcher = match &seq.source {
+            SequenceSource::Local(dir) => {
+                let mut watcher = notify::recommended_watcher(events_tx)?;
+                // New shards appear as subdirectories
+                let mode = if seq.shard.is_some() {
+                    RecursiveMode::Recursive
+                } else {
+                    RecursiveMode::NonRecursive
+                };
+                watcher.watch(dir, mode)?;
+                Some(watcher)
+            }
+            SequenceSource::Remote { .. } | SequenceSource::Http { .. } | SequenceSource::Video { .. } | SequenceSource::Animation { .. } => {
+                drop(events_tx);
+                None
+            }
+        };
+
+        let (tx, rx) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            let mut last = last;
+            let mut exists = |idx| seq.exists_with_ssh(idx, request_tx.clone());
+            while !stopped.load(Ordering::Relaxed) {
+                if watcher_event(&events_rx, poll) {
+                    thread::sleep(SETTLE);
+                    while events_rx.try_recv().is_ok() {}
+                }
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                match last_existing(last, &mut exists) {
+                    Ok(found) if found > last => {
+                        info!("new frames {}..={}", last + 1, found);
+                        last = found;
+                        if tx.send(last).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("probe failed: {e:#}"),
+                }
+            }
+            info!("watcher stopped");
+        });
+
+        Ok(Self {
+            rx,
+            stop,
+            _watcher: watcher,
+        })
+    }
+
+    /// Newest last frame found since the previous call, if the sequence grew.
+    pub fn latest(&self) -> Option<i64> {
+        self.rx.try_iter().last()
+    }
+}
+
+/// Wait up to `poll` for a file event; true if one arrived. Without a watcher this just sleeps.
+fn watcher_event(events_rx: &Receiver<notify::Result<notify::Event>>, poll: Duration) -> bool {
+    match events_rx.recv_timeout(poll) {
+        Ok(_) => true,
+        Err(RecvTimeoutError::Timeout) => false,
+        Err(RecvTimeoutError::Disconnected) => {
+            thread::sleep(poll);
+            false
+        }
+    }
+}
+
+impl Drop for Follower {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn reports_frames_appended_to_a_local_sequence() {
+        let dir = std::env::temp_dir().join(format!("zapvis_follow_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for i in 0..3 {
+            std::fs::write(dir.join(format!("f_{i}.png")), b"").unwrap();
+        }
+        let seq = SequenceSpec::from_pattern("f_#.png", SequenceSource::Local(dir.clone()), 0).unwrap();
+        let follower = Follower::start(seq, 2, None, Duration::from_millis(50)).unwrap();
+        for i in 3..6 {
+            std::fs::write(dir.join(format!("f_{i}.png")), b"").unwrap();
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut last = None;
+        while last != Some(5) && Instant::now() < deadline {
+            last = follower.latest().or(last);
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(last, Some(5));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,667 @@
+//! Frame decoding shared by the viewer, the headless subcommands and library users.
+use image::error::{DecodingError, ImageFormatHint};
+use image::imageops::FilterType;
+use image::{
+    ColorType, DynamicImage, GrayImage, ImageBuffer, ImageDecoder, ImageError, ImageFormat, ImageReader, Rgba, Rgba32FImage, RgbImage, RgbaImage,
+};
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::debug_span;
+use crate::color::{needs_conversion, to_display_rgba, ToneMap};
+use crate::config::Config;
+use crate::error::CacheError;
+use crate::orientation::Orientation;
+
+/// Decoded frame pixels as kept in the cache.
+/// Grayscale sources stay single-channel (a quarter of the RGBA memory) and are
+/// expanded to RGBA only when uploading the texture or when a view needs RGBA.
+pub enum FramePixels {
+    Rgba(RgbaImage),
+    Luma(GrayImage),
+}
+
+impl FramePixels {
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            FramePixels::Rgba(img) => img.dimensions(),
+            FramePixels::Luma(img) => img.dimensions(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.dimensions().0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.dimensions().1
+    }
+
+    /// RGBA view of the pixels (borrowed for RGBA frames, expanded copy for grayscale)
+    pub fn to_rgba(&self) -> Cow<'_, RgbaImage> {
+        match self {
+            FramePixels::Rgba(img) => Cow::Borrowed(img),
+            FramePixels::Luma(img) => Cow::Owned(DynamicImage::ImageLuma8(img.clone()).to_rgba8()),
+        }
+    }
+
+    /// RGBA value of one pixel (grayscale expanded), or `None` outside the frame
+    pub fn pixel_rgba(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+        Some(match self {
+            FramePixels::Rgba(img) => img.get_pixel(x, y).0,
+            FramePixels::Luma(img) => {
+                let l = img.get_pixel(x, y).0[0];
+                [l, l, l, 255]
+            }
+        })
+    }
+
+    pub fn into_dynamic(self) -> DynamicImage {
+        match self {
+            FramePixels::Rgba(img) => DynamicImage::ImageRgba8(img),
+            FramePixels::Luma(img) => DynamicImage::ImageLuma8(img),
+        }
+    }
+
+    /// CPU memory held by the pixel buffer
+    pub fn byte_size(&self) -> usize {
+        match self {
+            FramePixels::Rgba(img) => img.as_raw().len(),
+            FramePixels::Luma(img) => img.as_raw().len(),
+        }
+    }
+}
+
+/// Values of a 16-bit or float frame as the file holds them, before they are cut to the
+/// 8 bits of the display texture; kept for the current frame only, for the color picker
+pub enum SourceValues {
+    Integer { image: ImageBuffer<Rgba<u16>, Vec<u16>>, channels: u8 },
+    Float { image: Rgba32FImage, channels: u8 },
+}
+
+/// Channel values of one pixel from [`SourceValues`] (gray, gray + alpha, RGB or RGBA)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SourceValue {
+    pub values: [f32; 4],
+    pub channels: u8,
+}
+
+impl fmt::Display for SourceValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, v) in self.values[..self.channels as usize].iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{v}")?;
+        }
+        Ok(())
+    }
+}
+
+impl SourceValues {
+    /// Decode `bytes` keeping the file's values, EXIF orientation applied as for the
+    /// cached frame. `None` for 8-bit frames, whose displayed values are the file's.
+    pub fn decode(bytes: &[u8], source: &str, honor_exif_orientation: bool) -> Result<Option<Self>, CacheError> {
+        let _span = debug_span!("source_values", path = source).entered();
+        let img = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .map_err(ImageError::from)
+            .and_then(|reader| reader.decode())
+            .map_err(|source_err| CacheError::Decode {
+                path: source.to_string(),
+                source: source_err,
+            })?;
+        let orientation = if honor_exif_orientation {
+            exif_orientation(&mut Cursor::new(bytes))
+        } else {
+            Orientation::default()
+        };
+        let channels = img.color().channel_count();
+        Ok(match img.color() {
+            ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => Some(SourceValues::Integer {
+                image: orientation.apply_to_image(&img.to_rgba16()),
+                channels,
+            }),
+            ColorType::Rgb32F | ColorType::Rgba32F => Some(SourceValues::Float {
+                image: orientation.apply_to_image(&img.to_rgba32f()),
+                channels,
+            }),
+            _ => None,
+        })
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            SourceValues::Integer { image, .. } => image.dimensions(),
+            SourceValues::Float { image, .. } => image.dimensions(),
+        }
+    }
+
+    /// Values of the pixel at `x`, `y`, or `None` outside the frame
+    pub fn value(&self, x: u32, y: u32) -> Option<SourceValue> {
+        let (w, h) = self.dimensions();
+        if x >= w || y >= h {
+            return None;
+        }
+        let (rgba, channels) = match self {
+            SourceValues::Integer { image, channels } => (image.get_pixel(x, y).0.map(f32::from), *channels),
+            SourceValues::Float { image, channels } => (image.get_pixel(x, y).0, *channels),
+        };
+        // Gray sources were expanded to RGBA: their channels are gray and alpha
+        let values = if channels <= 2 { [rgba[0], rgba[3], 0.0, 0.0] } else { rgba };
+        Some(SourceValue { values, channels })
+    }
+}
+
+/// Options applied by the loader thread while decoding
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecodeOptions {
+    /// Rotate/flip according to the EXIF Orientation tag
+    pub honor_exif_orientation: bool,
+    /// Longest edge allowed after decoding; larger frames are downscaled (0 = no cap)
+    pub max_decode_dim: u32,
+    /// Convert to sRGB from embedded ICC profiles and linear float data (see
+    /// [`crate::color`]); off shows the raw values
+    pub color_manage: bool,
+    /// Exposure, operator and gamma for linear float frames (with `color_manage`)
+    pub tone: ToneMap,
+}
+
+impl DecodeOptions {
+    /// The frames as stored: no orientation, downscaling, color conversion or tone mapping
+    pub fn raw() -> Self {
+        Self {
+            honor_exif_orientation: false,
+            max_decode_dim: 0,
+            color_manage: false,
+            tone: ToneMap::default(),
+        }
+    }
+
+    pub fn from_config(cfg: &Config) -> Self {
+        Self {
+            honor_exif_orientation: cfg.honor_exif_orientation,
+            max_decode_dim: cfg.max_decode_dim,
+            color_manage: cfg.color_management,
+            tone: ToneMap::default(),
+        }
+    }
+}
+
+fn open_image(path: &Path) -> Result<ImageReader<BufReader<File>>, CacheError> {
+    ImageReader::open(path).map_err(|source| match source.kind() {
+        std::io::ErrorKind::NotFound => CacheError::NotFound(path.display().to_string()),
+        _ => CacheError::Read {
+            path: path.display().to_string(),
+            source,
+        },
+    })
+}
+
+/// Load and decode an image file (can be done in background thread)
+pub fn load_frame(path: &Path, opts: DecodeOptions) -> Result<FramePixels, CacheError> {
+    let _span = debug_span!("decode", path = %path.display()).entered();
+    let reader = ImageReader::open(path).map_err(|source| match source.kind() {
+        std::io::ErrorKind::NotFound => CacheError::NotFound(path.display().to_string()),
+        _ => CacheError::Read {
+            path: path.display().to_string(),
+            source,
+        },
+    })?;
+    let (img, icc) = decode_with_icc(reader, opts).map_err(|source| CacheError::Decode {
+        path: path.display().to_string(),
+        source,
+    })?;
+    let orientation = if opts.honor_exif_orientation {
+        File::open(path)
+            .map(|f| exif_orientation(&mut BufReader::new(f)))
+            .unwrap_or_default()
+    } else {
+        Orientation::default()
+    };
+    Ok(finish_decode(img, orientation, icc.as_deref(), opts))
+}
+
+/// Reduced-resolution copy of a frame, decoded without decoding the whole
+pub struct FramePreview {
+    pub pixels: FramePixels,
+    /// Size of the frame once decoded in full with the same options
+    pub full_size: (u32, u32),
+}
+
+/// Preview of a JPEG with its longest edge at most `max_dim`, decoded at 1/2, 1/4 or 1/8
+/// scale instead of whole. `None` for other formats, which have no decode cheaper than
+/// the whole frame, and for frames that already fit.
+pub fn load_frame_preview(path: &Path, max_dim: u32, opts: DecodeOptions) -> Result<Option<FramePreview>, CacheError> {
+    let _span = debug_span!("decode_preview", path = %path.display()).entered();
+    let reader = open_image(path)?;
+    if reader.format() != Some(ImageFormat::Jpeg) {
+        return Ok(None);
+    }
+    let failed = |source| CacheError::Decode {
+        path: path.display().to_string(),
+        source,
+    };
+    let mut inner = reader.into_inner();
+    let (w, h) = ImageReader::with_format(&mut inner, ImageFormat::Jpeg).into_dimensions().map_err(failed)?;
+    if max_dim == 0 || w.max(h) <= max_dim {
+        return Ok(None);
+    }
+    inner.rewind().map_err(|e| failed(e.into()))?;
+    let Some((img, icc)) = decode_jpeg_scaled(&mut inner, max_dim, opts.color_manage).map_err(failed)? else {
+        return Ok(None);
+    };
+    let orientation = if opts.honor_exif_orientation {
+        inner.rewind().map(|()| exif_orientation(&mut inner)).unwrap_or_default()
+    } else {
+        Orientation::default()
+    };
+    let (fw, fh) = capped_size((w, h), opts.max_decode_dim);
+    let full_size = if orientation.swaps_axes() { (fh, fw) } else { (fw, fh) };
+    let preview_opts = DecodeOptions {
+        max_decode_dim: max_dim,
+        ..opts
+    };
+    Ok(Some(FramePreview {
+        pixels: finish_decode(img, orientation, icc.as_deref(), preview_opts),
+        full_size,
+    }))
+}
+
+pub fn load_frame_from_bytes(bytes: &[u8], source: &str, opts: DecodeOptions) -> Result<FramePixels, CacheError> {
+    let _span = debug_span!("decode", path = source, bytes = bytes.len()).entered();
+    let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format().map_err(|e| CacheError::Decode {
+        path: source.to_string(),
+        source: e.into(),
+    })?;
+    let (img, icc) = decode_with_icc(reader, opts).map_err(|e| CacheError::Decode {
+        path: source.to_string(),
+        source: e,
+    })?;
+    let orientation = if opts.honor_exif_orientation {
+        exif_orientation(&mut Cursor::new(bytes))
+    } else {
+        Orientation::default()
+    };
+    Ok(finish_decode(img, orientation, icc.as_deref(), opts))
+}
+
+/// A decoded image with the ICC profile embedded next to it
+type DecodedWithIcc = (DynamicImage, Option<Vec<u8>>);
+
+/// Decode `reader`'s image along with its ICC profile, if color management wants it.
+/// JPEGs larger than `max_decode_dim` are decoded at a reduced scale.
+fn decode_with_icc<R: BufRead + Seek>(mut reader: ImageReader<R>, opts: DecodeOptions) -> Result<DecodedWithIcc, ImageError> {
+    if opts.max_decode_dim > 0 && reader.format() == Some(ImageFormat::Jpeg) {
+        let mut inner = reader.into_inner();
+        let start = inner.stream_position()?;
+        if let Some(decoded) = decode_jpeg_scaled(&mut inner, opts.max_decode_dim, opts.color_manage)? {
+            return Ok(decoded);
+        }
+        inner.seek(SeekFrom::Start(start))?;
+        reader = ImageReader::with_format(inner, ImageFormat::Jpeg);
+    }
+    let mut decoder = reader.into_decoder()?;
+    let icc = if opts.color_manage { decoder.icc_profile().ok().flatten() } else { None };
+    Ok((DynamicImage::from_decoder(decoder)?, icc))
+}
+
+/// Decode a JPEG at the smallest DCT scale (1, 1/2, 1/4 or 1/8) that keeps its longest
+/// edge at least `max_dim`, so a capped frame is never held at full size; `finish_decode`
+/// scales the rest of the way. `None`, with `reader` consumed only up to the frame
+/// header, for the pixel formats left to `image` (CMYK, 16-bit).
+pub(crate) fn decode_jpeg_scaled<R: Read>(reader: R, max_dim: u32, want_icc: bool) -> Result<Option<DecodedWithIcc>, ImageError> {
+    let failed = |e: jpeg_decoder::Error| ImageError::Decoding(DecodingError::new(ImageFormatHint::Exact(ImageFormat::Jpeg), e));
+    let mut decoder = jpeg_decoder::Decoder::new(reader);
+    decoder.read_info().map_err(failed)?;
+    let Some(info) = decoder.info() else {
+        return Ok(None);
+    };
+    if !matches!(info.pixel_format, jpeg_decoder::PixelFormat::L8 | jpeg_decoder::PixelFormat::RGB24) {
+        return Ok(None);
+    }
+    let longest = u32::from(info.width.max(info.height));
+    let (width, height) = if longest > max_dim {
+        let side = |full: u16| (u32::from(full) * max_dim).div_ceil(longest).max(1) as u16;
+        decoder.scale(side(info.width), side(info.height)).map_err(failed)?
+    } else {
+        (info.width, info.height)
+    };
+    let pixels = decoder.decode().map_err(failed)?;
+    let (width, height) = (u32::from(width), u32::from(height));
+    let img = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => GrayImage::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+        _ => RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+    };
+    let icc = if want_icc { decoder.icc_profile() } else { None };
+    Ok(img.map(|img| (img, icc)))
+}
+
+/// ICC profile in the header of an encoded image, if any
+fn embedded_icc(bytes: &[u8]) -> Option<Vec<u8>> {
+    let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format().ok()?;
+    reader.into_decoder().ok()?.icc_profile().ok().flatten()
+}
+
+/// Decode the frame at `path` with an external converter instead of `image`: `command`
+/// is split like a shell command line (quotes group words, no pipes or expansions),
+/// `{path}` in it is replaced by the frame path (appended as the last argument when
+/// missing) and whatever the command writes to stdout (PNG, PPM, ...) is decoded.
+pub fn load_frame_with_command(command: &str, path: &Path, opts: DecodeOptions) -> Result<FramePixels, CacheError> {
+    let _span = debug_span!("loader_command", path = %path.display()).entered();
+    if !path.exists() {
+        return Err(CacheError::NotFound(path.display().to_string()));
+    }
+    let failed = |message: String| CacheError::LoaderCommand {
+        path: path.display().to_string(),
+        message,
+    };
+    let path_arg = path.to_string_lossy();
+    let mut args = split_command_line(command);
+    if !args.iter().any(|arg| arg.contains("{path}")) {
+        args.push("{path}".to_string());
+    }
+    let args: Vec<String> = args.iter().map(|arg| arg.replace("{path}", &path_arg)).collect();
+    let Some((program, args)) = args.split_first() else {
+        return Err(failed("the command is empty".to_string()));
+    };
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| failed(format!("could not run {program:?}: {e}")))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let last_line = stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+        return Err(failed(format!("{program:?} exited with {}: {last_line}", output.status)));
+    }
+    load_frame_from_bytes(&output.stdout, &path.display().to_string(), opts)
+}
+
+/// [`load_frame_with_command`] for a frame fetched into memory, e.g. from a remote host.
+/// The bytes go to a temporary file named like `source` so converters that look at the
+/// extension still work.
+pub fn load_frame_from_bytes_with_command(command: &str, bytes: &[u8], source: &str, opts: DecodeOptions) -> Result<FramePixels, CacheError> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let name = source.rsplit(['/', '\\']).next().unwrap_or("frame");
+    let tmp = std::env::temp_dir().join(format!("zapvis-{}-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed), name));
+    fs::write(&tmp, bytes).map_err(|source| CacheError::Read {
+        path: tmp.display().to_string(),
+        source,
+    })?;
+    let result = load_frame_with_command(command, &tmp, opts).map_err(|e| match e {
+        CacheError::LoaderCommand { message, .. } => CacheError::LoaderCommand {
+            path: source.to_string(),
+            message,
+        },
+        e => e,
+    });
+    let _ = fs::remove_file(&tmp);
+    result
+}
+
+/// Split a command line into words: whitespace separates them and single or double
+/// quotes group them. Backslashes are kept as they are, for Windows paths.
+fn split_command_line(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Decode the beginning of a progressive JPEG or interlaced PNG that is still arriving:
+/// the whole image at the detail of the scans or passes received so far.
+///
+/// Returns `None` for other formats, which would only show their top rows, and when
+/// too little has arrived to show anything.
+pub fn load_partial_frame(bytes: &[u8], opts: DecodeOptions) -> Option<FramePixels> {
+    let img = if is_progressive_jpeg(bytes) {
+        // The JPEG decoder fills data past the end with neutral coefficients
+        image::load_from_memory(bytes).ok()?
+    } else {
+        decode_partial_interlaced_png(bytes)?
+    };
+    let orientation = if opts.honor_exif_orientation {
+        exif_orientation(&mut Cursor::new(bytes))
+    } else {
+        Orientation::default()
+    };
+    // The profile sits in the header, so the preview gets the colors of the full frame
+    let icc = if opts.color_manage { embedded_icc(bytes) } else { None };
+    Some(finish_decode(img, orientation, icc.as_deref(), opts))
+}
+
+/// True for a JPEG whose frame header is progressive and whose first scan has started.
+fn is_progressive_jpeg(bytes: &[u8]) -> bool {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return false;
+    }
+    let mut progressive = false;
+    let mut pos = 2;
+    // Walk the marker segments up to the first start of scan
+    while let [0xFF, marker, hi, lo, ..] = bytes[pos.min(bytes.len())..] {
+        match marker {
+            0xC2 | 0xC6 | 0xCA | 0xCE => progressive = true,
+            0xC0 | 0xC1 | 0xC3 | 0xC5 | 0xC7 | 0xC9 | 0xCB | 0xCD | 0xCF => return false,
+            0xDA => return progressive,
+            _ => {}
+        }
+        pos += 2 + u16::from_be_bytes([hi, lo]) as usize;
+    }
+    false
+}
+
+/// Adam7 passes decoded so far, each pixel repeated over the block it stands for.
+fn decode_partial_interlaced_png(bytes: &[u8]) -> Option<DynamicImage> {
+    let mut decoder = png::Decoder::new(Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    if !reader.info().interlaced {
+        return None;
+    }
+    let (width, height) = (reader.info().width, reader.info().height);
+    let (color, _) = reader.output_color_type();
+    let stride = width as usize * color.samples();
+    let mut buf = vec![0u8; stride * height as usize];
+    let mut rows = 0;
+    // Stops at the end of the received data
+    while let Ok(Some(row)) = reader.next_interlaced_row() {
+        if let png::InterlaceInfo::Adam7(info) = row.interlace() {
+            png::splat_interlaced_row(&mut buf, stride, row.data(), info, color.samples() as u8 * 8);
+            rows += 1;
+        }
+    }
+    if rows == 0 {
+        return None;
+    }
+    match color {
+        png::ColorType::Grayscale => GrayImage::from_raw(width, height, buf).map(DynamicImage::ImageLuma8),
+        png::ColorType::GrayscaleAlpha => image::GrayAlphaImage::from_raw(width, height, buf).map(DynamicImage::ImageLumaA8),
+        png::ColorType::Rgb => image::RgbImage::from_raw(width, height, buf).map(DynamicImage::ImageRgb8),
+        png::ColorType::Rgba => RgbaImage::from_raw(width, height, buf).map(DynamicImage::ImageRgba8),
+        png::ColorType::Indexed => None,
+    }
+}
+
+/// Size a `size` image comes out of [`finish_decode`] with under the resolution cap `cap`
+pub fn capped_size((w, h): (u32, u32), cap: u32) -> (u32, u32) {
+    if cap == 0 || w.max(h) <= cap {
+        return (w, h);
+    }
+    let ratio = cap as f64 / w.max(h) as f64;
+    (((w as f64 * ratio).round() as u32).max(1), ((h as f64 * ratio).round() as u32).max(1))
+}
+
+/// Shared post-decode steps: resolution cap, color management with the embedded `icc`
+/// profile, pixel conversion, EXIF orientation.
+///
+/// The cap resizes what was decoded. Only JPEGs arrive here already reduced (see
+/// [`decode_jpeg_scaled`]); other formats are decoded whole first, so for them the cap
+/// limits what is cached and uploaded, not the memory the decode itself takes.
+pub(crate) fn finish_decode(img: DynamicImage, orientation: Orientation, icc: Option<&[u8]>, opts: DecodeOptions) -> FramePixels {
+    let cap = opts.max_decode_dim;
+    // Downscale before converting to RGBA so the full-size RGBA copy is never allocated.
+    let img = if cap > 0 && img.width().max(img.height()) > cap {
+        img.resize(cap, cap, FilterType::CatmullRom)
+    } else {
+        img
+    };
+    // Grayscale without alpha stays single-channel
+    if matches!(img.color(), ColorType::L8 | ColorType::L16) {
+        let luma = img.to_luma8();
+        return FramePixels::Luma(if orientation.is_identity() {
+            luma
+        } else {
+            orientation.apply_to_image(&luma)
+        });
+    }
+    let rgba = if opts.color_manage && needs_conversion(&img, icc) {
+        to_display_rgba(&img, icc, opts.tone)
+    } else {
+        img.to_rgba8()
+    };
+    FramePixels::Rgba(if orientation.is_identity() {
+        rgba
+    } else {
+        orientation.apply_to_image(&rgba)
+    })
+}
+
+/// Read the EXIF Orientation tag; missing or unreadable EXIF means identity.
+fn exif_orientation<R: BufRead + Seek>(reader: &mut R) -> Orientation {
+    exif::Reader::new()
+        .read_from_container(reader)
+        .ok()
+        .and_then(|data| {
+            data.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .map(Orientation::from_exif)
+        .unwrap_or_default()
+}
+
+/// Build a downscaled copy whose longest edge is at most `max_dim` pixels.
+/// Returns None if the image already fits (or `max_dim` is 0), so callers can skip the preview tier.
+pub fn downscale_to_max_dim(frame: &FramePixels, max_dim: u32) -> Option<FramePixels> {
+    let (w, h) = frame.dimensions();
+    if max_dim == 0 || (w <= max_dim && h <= max_dim) {
+        return None;
+    }
+    let scale = max_dim as f64 / w.max(h) as f64;
+    let pw = ((w as f64 * scale).round() as u32).max(1);
+    let ph = ((h as f64 * scale).round() as u32).max(1);
+    Some(match frame {
+        FramePixels::Rgba(img) => FramePixels::Rgba(image::imageops::thumbnail(img, pw, ph)),
+        FramePixels::Luma(img) => FramePixels::Luma(image::imageops::thumbnail(img, pw, ph)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_progressive_jpeg_headers() {
+        // SOI, an APP0 segment, then the frame header
+        let header = |sof: u8| {
+            let mut b = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, sof, 0x00, 0x02];
+            b.extend([0xFF, 0xDA, 0x00, 0x02]);
+            b
+        };
+        assert!(is_progressive_jpeg(&header(0xC2)));
+        assert!(!is_progressive_jpeg(&header(0xC0)));
+        // Nothing to show before the first scan starts
+        assert!(!is_progressive_jpeg(&header(0xC2)[..12]));
+        assert!(!is_progressive_jpeg(b"\x89PNG"));
+    }
+
+    #[test]
+    fn source_values_keep_16_bit_and_float_values() {
+        let encode = |img: DynamicImage, format| {
+            let mut bytes = Cursor::new(Vec::new());
+            img.write_to(&mut bytes, format).unwrap();
+            bytes.into_inner()
+        };
+        let gray = image::ImageBuffer::from_raw(2, 1, vec![12u16, 54321]).map(DynamicImage::ImageLuma16).unwrap();
+        let values = SourceValues::decode(&encode(gray, image::ImageFormat::Png), "gray.png", false).unwrap().unwrap();
+        assert_eq!(values.value(1, 0).unwrap().to_string(), "54321");
+        assert!(values.value(2, 0).is_none());
+
+        let float = Rgba32FImage::from_pixel(1, 1, Rgba([0.00342, 2.5, 0.0, 1.0]));
+        let values = SourceValues::decode(&encode(float.into(), image::ImageFormat::OpenExr), "f.exr", false).unwrap().unwrap();
+        assert_eq!(values.value(0, 0).unwrap().to_string(), "0.00342 2.5 0 1");
+
+        // 8-bit frames show their own values already
+        let rgb = DynamicImage::ImageRgba8(RgbaImage::new(1, 1));
+        assert!(SourceValues::decode(&encode(rgb, image::ImageFormat::Png), "x.png", false).unwrap().is_none());
+    }
+
+    #[test]
+    fn capped_frames_fit_max_decode_dim() {
+        let encode = |format| {
+            let img = DynamicImage::ImageRgb8(RgbImage::from_fn(200, 100, |x, y| image::Rgb([x as u8, y as u8, 128])));
+            let mut bytes = Cursor::new(Vec::new());
+            img.write_to(&mut bytes, format).unwrap();
+            bytes.into_inner()
+        };
+        let opts = DecodeOptions { max_decode_dim: 64, ..DecodeOptions::raw() };
+        for format in [ImageFormat::Jpeg, ImageFormat::Png] {
+            let pixels = load_frame_from_bytes(&encode(format), "frame", opts).unwrap();
+            assert_eq!(pixels.dimensions(), (64, 32), "{format:?}");
+        }
+        let uncapped = load_frame_from_bytes(&encode(ImageFormat::Jpeg), "frame.jpg", DecodeOptions::raw()).unwrap();
+        assert_eq!(uncapped.dimensions(), (200, 100));
+
+        // The JPEG itself comes out of the decoder at half size, not whole
+        let (scaled, _) = decode_jpeg_scaled(Cursor::new(encode(ImageFormat::Jpeg)), 64, false).unwrap().unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (100, 50));
+        let (whole, _) = decode_jpeg_scaled(Cursor::new(encode(ImageFormat::Jpeg)), 400, false).unwrap().unwrap();
+        assert_eq!((whole.width(), whole.height()), (200, 100));
+    }
+
+    #[test]
+    fn command_lines_split_on_whitespace_outside_quotes() {
+        assert_eq!(split_command_line("dat2png --gain 2 {path}"), ["dat2png", "--gain", "2", "{path}"]);
+        assert_eq!(split_command_line(r#"  "C:\Program Files\conv.exe" '{path}' -o "" "#), [r"C:\Program Files\conv.exe", "{path}", "-o", ""]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn loader_command_output_is_decoded() {
+        let dir = std::env::temp_dir().join(format!("zapvis-loader-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let frame = dir.join("frame_0001.dat");
+        // A 2x1 PGM behind a fake header the converter strips
+        fs::write(&frame, b"DAT!P5 2 1 255\n\x10\x20").unwrap();
+        let opts = DecodeOptions::raw();
+        let pixels = load_frame_with_command("tail -c +5 {path}", &frame, opts).unwrap();
+        assert_eq!(pixels.dimensions(), (2, 1));
+        let bytes = fs::read(&frame).unwrap();
+        let pixels = load_frame_from_bytes_with_command("tail -c +5", &bytes, "host:/x/frame_0001.dat", opts).unwrap();
+        assert_eq!(pixels.pixel_rgba(1, 0), Some([0x20, 0x20, 0x20, 255]));
+        let failed = load_frame_with_command("false {path}", &frame, opts);
+        assert!(matches!(failed, Err(CacheError::LoaderCommand { .. })));
+        assert!(matches!(load_frame_with_command("cat", &dir.join("missing.dat"), opts), Err(CacheError::NotFound(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
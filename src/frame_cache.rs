@@ -0,0 +1,1453 @@
+//! Decoded-frame cache around the current index, filled by a background loader thread.
+use image::ImageFormat;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, debug_span, warn};
+
+use crate::animation::load_animation_frame;
+use crate::bounds::SequenceBounds;
+use crate::error::{CacheError, FailureKind};
+use crate::frame::{
+    downscale_to_max_dim, load_frame, load_frame_from_bytes, load_frame_from_bytes_with_command, load_frame_preview, load_frame_with_command,
+    load_partial_frame, DecodeOptions, FramePixels, FramePreview,
+};
+use crate::radius::{adaptive_radius, available_memory, LoadCost};
+use crate::region::{load_frame_region, load_frame_region_from_bytes, FrameRegion};
+use crate::remote_worker::{PartialCat, RemoteRange, RemoteWorkerRequest};
+use crate::sequence::{fetch_frame_bytes, fetch_frame_bytes_with_partials, SequenceSource, SequenceSpec};
+use crate::watchdog::Heartbeat;
+
+/// Called by the loader threads after each result they send, e.g. to wake a UI that only
+/// polls the cache when it repaints
+pub type Wake = Arc<dyn Fn() + Send + Sync>;
+
+// Load request for the background loader threads
+#[derive(Clone)]
+struct LoadRequest {
+    idx: i64,
+    /// Frame path relative to the source directory
+    rel_path: String,
+    seq_source: SequenceSource,
+    request_tx: Option<Sender<RemoteWorkerRequest>>,
+    /// External converter run instead of decoding the file directly
+    loader_command: Option<Arc<str>>,
+    /// Part of the frame to decode, where the format allows (see [`FrameCache::set_decode_region`])
+    region: Option<[u32; 4]>,
+    /// Decode only the reduced preview (see [`load_frame_preview`]); the full decode is
+    /// a request of its own
+    preview: bool,
+    /// Set when the frame is no longer wanted; the loader checks it before the fetch
+    /// and again before decoding
+    cancelled: Arc<AtomicBool>,
+}
+
+impl LoadRequest {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Resolution tier of a decoded image coming back from the loader thread
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tier {
+    /// Reduced-scale decode (longest edge <= preview_max_dim) of a local JPEG, for fast
+    /// scrubbing, or a partial decode of a remote frame still downloading (then maybe full size).
+    /// Carries the full-resolution size so the preview is displayed at the final size.
+    Preview { full_size: (u32, u32) },
+    /// Full-resolution decode, with how long it took and, for a region decode, where
+    /// in the frame its pixels lie
+    Full { timing: LoadTiming, region: Option<FrameRegion> },
+}
+
+/// Time a full-resolution load spent fetching and decoding its frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoadTiming {
+    pub idx: i64,
+    /// Transfer over SSH; `None` for local frames, which are read while decoding
+    pub fetch: Option<Duration>,
+    pub decode: Duration,
+}
+
+impl LoadTiming {
+    pub fn total(&self) -> Duration {
+        self.fetch.unwrap_or_default() + self.decode
+    }
+}
+
+/// Full loads whose timings are kept for [`FrameCache::timings`]
+const TIMING_HISTORY: usize = 120;
+
+/// Shortest time between two adaptations of the radius
+const ADAPT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Bounds of the radius while it adapts (see [`FrameCache::set_adaptive_radius`])
+struct Adaptive {
+    max: usize,
+    /// Bytes of decoded pixels to keep at most; `None` for half of what is available
+    memory_budget: Option<u64>,
+    last: Option<Instant>,
+}
+
+/// Decoded pixels together with the full-resolution size (also for previews)
+struct CachedFrame {
+    pixels: Arc<FramePixels>,
+    full_size: (u32, u32),
+    /// Where the pixels lie in the frame, when only a region was decoded
+    region: Option<FrameRegion>,
+}
+
+// Decoded image sent back from the loader thread
+struct LoadResult {
+    idx: i64,
+    tier: Tier,
+    pixels: FramePixels,
+}
+
+// Load that produced no image
+struct LoadFailure {
+    idx: i64,
+    error: LoadError,
+}
+
+/// Why the load of a frame failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadError {
+    pub kind: FailureKind,
+    /// The error as the loader reported it
+    pub message: String,
+}
+
+/// Queue and result channel of the loader threads, and their heartbeats
+struct Loader {
+    queue: Arc<LoadQueue>,
+    result_rx: Receiver<Result<LoadResult, LoadFailure>>,
+    heartbeats: Vec<Heartbeat>,
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        // The threads exit once they finish what they are loading, if they ever return
+        self.queue.close();
+    }
+}
+
+/// Load requests waiting for a loader thread, taken best rank first rather than in
+/// the order they were sent, so the frame on screen never waits behind prefetches.
+#[derive(Default)]
+struct LoadQueue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    /// Waiting requests by index and whether they are for the preview, with their rank
+    /// (lower loads sooner)
+    waiting: HashMap<(i64, bool), (usize, LoadRequest)>,
+    closed: bool,
+}
+
+impl LoadQueue {
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn push(&self, rank: usize, req: LoadRequest) {
+        self.lock().waiting.insert((req.idx, req.preview), (rank, req));
+        self.ready.notify_one();
+    }
+
+    fn remove(&self, idx: i64, preview: bool) {
+        self.lock().waiting.remove(&(idx, preview));
+    }
+
+    /// Re-rank the waiting requests after the index moved (see [`load_rank`]).
+    fn rerank(&self, ranks: &HashMap<i64, usize>) {
+        for (rank, req) in self.lock().waiting.values_mut() {
+            *rank = load_rank(ranks, req);
+        }
+    }
+
+    /// Block until a request is waiting and take the best-ranked one; `None` once closed.
+    fn pop(&self) -> Option<LoadRequest> {
+        let mut state = self.lock();
+        loop {
+            if state.closed {
+                return None;
+            }
+            let best = state.waiting.iter().map(|(&key, (rank, _))| (*rank, key)).min();
+            if let Some((_, key)) = best {
+                return state.waiting.remove(&key).map(|(_, req)| req);
+            }
+            state = self.ready.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+/// Queue rank of `req` given the load order of the wanted frames: previews in that order,
+/// then the full decodes in that order, then requests for frames no longer wanted.
+fn load_rank(ranks: &HashMap<i64, usize>, req: &LoadRequest) -> usize {
+    match ranks.get(&req.idx) {
+        Some(&rank) if req.preview => rank,
+        Some(&rank) => ranks.len() + rank,
+        None => usize::MAX,
+    }
+}
+
+/// Navigation direction inferred from the last moves of the current index
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Heading {
+    Forward,
+    Backward,
+}
+
+/// Moves in one direction after which the prefetch window leans that way
+const MOVES_BEFORE_BIAS: u32 = 2;
+
+/// Share of the prefetch window placed ahead while navigating in one direction
+const AHEAD_SHARE: f64 = 0.8;
+
+/// Bidirectional cache of decoded frames with configurable radius.
+/// Keeps frames for indices in range [current - radius * step, current + radius * step].
+/// After a few steps in one direction, the same budget of 2 * radius frames is split
+/// 80/20 in favor of that direction, until the direction changes or the index jumps.
+/// Uses a background loader thread (or several, see [`FrameCache::set_loader_threads`])
+/// with a queue for image decoding.
+///
+/// Two tiers are kept: a preview decoded at reduced scale, and the full-resolution
+/// frame that replaces it once ready. Local JPEGs get a preview across the whole
+/// window, ahead of any full decode, and are decoded in full only once on screen or
+/// next to it; frames with no cheaper decode than the whole are decoded in full
+/// wherever they are. Frames come back only through
+/// [`FrameCache::poll`] (or [`FrameCache::wait_for`]), so the owner decides when the
+/// cache changes, e.g. once per UI frame.
+///
+/// With [`FrameCache::set_stall_timeout`], `poll` also replaces the loader threads when
+/// one panicked or got stuck on one frame, re-requesting everything that was in flight.
+pub struct FrameCache {
+    cache: BTreeMap<i64, CachedFrame>,
+    previews: BTreeMap<i64, CachedFrame>,
+    cache_radius: usize,
+    adaptive: Option<Adaptive>,
+    step_size: u64,
+    /// Steps the index moves away from the window's center before the window follows
+    reload_threshold: u64,
+    /// Index and step size the window was last centered on
+    center: Option<(i64, u64)>,
+    /// Consecutive frames ahead of the current one to load first (playback buffering)
+    lookahead: u64,
+    /// Load the frames around the current one too, not only the current (and pinned) ones
+    prefetch: bool,
+    /// Index of the previous update, direction of the moves since and how many in a row
+    last_index: Option<i64>,
+    heading: Option<Heading>,
+    streak: u32,
+    /// Requests sent to the loader and not settled yet, kept for resending after a restart
+    pending_loads: HashMap<i64, LoadRequest>,
+    /// Preview requests likewise (see `LoadRequest::preview`)
+    pending_previews: HashMap<i64, LoadRequest>,
+    /// Load order of the wanted frames around the current index (see `update_for_index`)
+    ranks: HashMap<i64, usize>,
+    loader: Loader,
+    loader_threads: usize,
+    wake: Option<Wake>,
+    preview_max_dim: u32,
+    decode_opts: DecodeOptions,
+    stall_timeout: Option<Duration>,
+    loader_restarts: u64,
+    /// Frames reported missing since the last `take_missing`
+    missing: BTreeSet<i64>,
+    /// Frames whose load failed, not requested again until retried or out of the window
+    failures: BTreeMap<i64, LoadError>,
+    /// Known sequence ends; nothing outside is prefetched
+    bounds: SequenceBounds,
+    /// Frames loaded and kept wherever the current index is
+    pinned: BTreeSet<i64>,
+    /// Indices kept around the current one, as of the last `update_for_index`
+    window: Option<RangeInclusive<i64>>,
+    /// Timings of the latest full loads, oldest first
+    timings: VecDeque<LoadTiming>,
+    seq_source: SequenceSource,
+    request_tx: Option<Sender<RemoteWorkerRequest>>,
+    remote_range: Option<RemoteRange>,
+    loader_command: Option<Arc<str>>,
+    /// Part of the frames to decode instead of all of it (see `set_decode_region`)
+    region: Option<[u32; 4]>,
+}
+
+impl FrameCache {
+    /// Start the loader thread for frames of `seq_source`. Remote sources need the worker's
+    /// `request_tx`; its `remote_range` is kept in step with the cached window.
+    /// `preview_max_dim` 0 disables the preview tier.
+    pub fn new(
+        cache_radius: usize,
+        seq_source: SequenceSource,
+        request_tx: Option<Sender<RemoteWorkerRequest>>,
+        remote_range: Option<RemoteRange>,
+        preview_max_dim: u32,
+        decode_opts: DecodeOptions,
+    ) -> Self {
+        Self {
+            cache: BTreeMap::new(),
+            previews: BTreeMap::new(),
+            cache_radius,
+            adaptive: None,
+            step_size: 1,
+            reload_threshold: 1,
+            center: None,
+            lookahead: 0,
+            prefetch: true,
+            last_index: None,
+            heading: None,
+            streak: 0,
+            pending_loads: HashMap::new(),
+            pending_previews: HashMap::new(),
+            ranks: HashMap::new(),
+            loader: spawn_loader(preview_max_dim, decode_opts, 1, None),
+            loader_threads: 1,
+            wake: None,
+            preview_max_dim,
+            decode_opts,
+            stall_timeout: None,
+            loader_restarts: 0,
+            missing: BTreeSet::new(),
+            failures: BTreeMap::new(),
+            bounds: SequenceBounds::default(),
+            pinned: BTreeSet::new(),
+            window: None,
+            timings: VecDeque::new(),
+            seq_source,
+            request_tx,
+            remote_range,
+            loader_command: None,
+            region: None,
+        }
+    }
+
+    /// Decoded pixels for `idx` if cached (full resolution, else the preview)
+    pub fn pixels(&self, idx: i64) -> Option<&Arc<FramePixels>> {
+        self.frame(idx).map(|f| &f.pixels)
+    }
+
+    /// Cache `pixels` as the full-resolution frame for `idx` instead of loading it (a
+    /// frame piped in on stdin). It stays until evicted or the cache is cleared.
+    pub fn insert(&mut self, idx: i64, pixels: FramePixels) {
+        self.cancel_load(idx);
+        self.failures.remove(&idx);
+        self.previews.remove(&idx);
+        let full_size = pixels.dimensions();
+        self.cache.insert(idx, CachedFrame { pixels: Arc::new(pixels), full_size, region: None });
+    }
+
+    /// Full-resolution size of the frame for `idx`, even while only its preview is cached
+    pub fn full_size(&self, idx: i64) -> Option<(u32, u32)> {
+        self.frame(idx).map(|f| f.full_size)
+    }
+
+    /// Where the cached pixels of `idx` lie in the frame, if only a region was decoded
+    pub fn region(&self, idx: i64) -> Option<FrameRegion> {
+        self.cache.get(&idx).and_then(|f| f.region)
+    }
+
+    /// True if the full-resolution pixels of `idx` are cached and cover the wanted region
+    /// (all of the frame without one)
+    fn covers(&self, idx: i64) -> bool {
+        self.cache.get(&idx).is_some_and(|f| match (f.region, self.region) {
+            (None, _) => true,
+            (Some(decoded), Some(want)) => decoded.contains(want),
+            (Some(_), None) => false,
+        })
+    }
+
+    fn frame(&self, idx: i64) -> Option<&CachedFrame> {
+        self.cache.get(&idx).or_else(|| self.previews.get(&idx))
+    }
+
+    /// Every cached frame with its best available tier, in index order
+    pub fn frames(&self) -> impl Iterator<Item = (i64, &Arc<FramePixels>)> {
+        let previews = self.previews.iter().filter(|(idx, _)| !self.cache.contains_key(idx));
+        self.cache.iter().chain(previews).map(|(&idx, f)| (idx, &f.pixels))
+    }
+
+    /// True if only the downscaled preview is available for this index
+    pub fn is_preview(&self, idx: i64) -> bool {
+        !self.cache.contains_key(&idx) && self.previews.contains_key(&idx)
+    }
+
+    /// Clear cache except for the current index (and pinned frames) and set new step size
+    pub fn clear_except_current(&mut self, current_idx: i64) {
+        // Keep only the current index
+        let pinned = &self.pinned;
+        self.cache.retain(|idx, _| *idx == current_idx || pinned.contains(idx));
+        self.previews.retain(|idx, _| *idx == current_idx || pinned.contains(idx));
+        // Cancel the other pending loads
+        let pinned = &self.pinned;
+        let dropped: Vec<i64> = self.pending_indices().filter(|&idx| idx != current_idx && !pinned.contains(&idx)).collect();
+        for idx in dropped {
+            self.cancel_load(idx);
+        }
+        debug!("cleared except idx={}", current_idx);
+    }
+
+    /// Forget in-flight loads so the next update re-requests them (e.g. after a reconnect)
+    pub fn forget_pending(&mut self) {
+        let pending: Vec<i64> = self.pending_indices().collect();
+        for idx in pending {
+            self.cancel_load(idx);
+        }
+    }
+
+    /// Drop the pending loads of `idx`, preview and full. A queued request is removed;
+    /// one already being loaded stops before its fetch or decode, whichever comes next.
+    fn cancel_load(&mut self, idx: i64) {
+        if let Some(req) = self.pending_loads.remove(&idx) {
+            req.cancelled.store(true, Ordering::Relaxed);
+            self.loader.queue.remove(idx, false);
+        }
+        self.cancel_preview(idx);
+    }
+
+    fn cancel_preview(&mut self, idx: i64) {
+        if let Some(req) = self.pending_previews.remove(&idx) {
+            req.cancelled.store(true, Ordering::Relaxed);
+            self.loader.queue.remove(idx, true);
+        }
+    }
+
+    /// Indices with a preview or full load pending (some twice)
+    fn pending_indices(&self) -> impl Iterator<Item = i64> + '_ {
+        self.pending_loads.keys().chain(self.pending_previews.keys()).copied()
+    }
+
+    /// Let the radius follow what loading costs, from the one given to `new` up to `max`
+    /// or down to [`crate::radius::MIN_ADAPTIVE_RADIUS`]: wider for frames that load
+    /// fast, narrower for slow ones, and never more than `memory_budget` bytes of pixels
+    /// (`None`: half the memory available). Reconsidered as load timings come in.
+    pub fn set_adaptive_radius(&mut self, max: usize, memory_budget: Option<u64>) {
+        self.adaptive = Some(Adaptive {
+            max,
+            memory_budget,
+            last: None,
+        });
+    }
+
+    /// Size the radius for the latest load timings and memory, at most every [`ADAPT_INTERVAL`]
+    fn adapt_radius(&mut self) {
+        let Some(adaptive) = &self.adaptive else {
+            return;
+        };
+        if self.timings.is_empty() || adaptive.last.is_some_and(|t| t.elapsed() < ADAPT_INTERVAL) {
+            return;
+        }
+        let Some(frame_bytes) = self.cache.values().map(|f| f.pixels.byte_size() as u64).sum::<u64>().checked_div(self.cache.len() as u64) else {
+            return;
+        };
+        let cost = LoadCost {
+            mean_load: self.timings.iter().map(LoadTiming::total).sum::<Duration>() / self.timings.len() as u32,
+            frame_bytes,
+            threads: self.loader_threads,
+        };
+        // What the cache holds now is available to it too
+        let budget = match adaptive.memory_budget {
+            Some(budget) => budget,
+            None => available_memory().map_or(u64::MAX, |free| (free + self.pixel_bytes() as u64) / 2),
+        };
+        let radius = adaptive_radius(cost, budget, adaptive.max);
+        if radius != self.cache_radius {
+            debug!("cache radius {} -> {}", self.cache_radius, radius);
+            self.cache_radius = radius;
+        }
+        if let Some(adaptive) = &mut self.adaptive {
+            adaptive.last = Some(Instant::now());
+        }
+    }
+
+    /// Replace the loader thread when it dies or spends longer than `timeout` on one
+    /// frame (`None` turns the watchdog off). Checked on every `poll`.
+    pub fn set_stall_timeout(&mut self, timeout: Option<Duration>) {
+        self.stall_timeout = timeout;
+    }
+
+    /// Call `wake` whenever a load settles, so the owner can poll without waiting for its
+    /// next frame. Restarts the loader, resending the pending loads.
+    pub fn set_wake(&mut self, wake: Wake) {
+        self.wake = Some(wake);
+        self.restart_loader();
+    }
+
+    /// Load up to `threads` frames at once, e.g. one per SSH transfer channel of a remote
+    /// source. Restarts the loader, resending the pending loads.
+    pub fn set_loader_threads(&mut self, threads: usize) {
+        self.loader_threads = threads.max(1);
+        self.restart_loader();
+    }
+
+    /// How often the loader thread has been replaced by the watchdog
+    pub fn loader_restarts(&self) -> u64 {
+        self.loader_restarts
+    }
+
+    /// Start a fresh loader if one of its threads died or is stuck, resending the pending loads
+    fn supervise_loader(&mut self) {
+        let Some(limit) = self.stall_timeout else {
+            return;
+        };
+        let Some(stall) = self.loader.heartbeats.iter().find_map(|h| h.check(limit)) else {
+            return;
+        };
+        warn!(
+            "loader thread {}, restarting with {} pending loads",
+            stall,
+            self.pending_count()
+        );
+        self.loader_restarts += 1;
+        self.restart_loader();
+    }
+
+    fn restart_loader(&mut self) {
+        // The old threads exit on their own once their channels are gone, if they ever return
+        self.loader = spawn_loader(self.preview_max_dim, self.decode_opts, self.loader_threads, self.wake.clone());
+        for req in self.pending_loads.values().chain(self.pending_previews.values()) {
+            self.loader.queue.push(load_rank(&self.ranks, req), req.clone());
+        }
+    }
+
+    /// Options the frames are decoded with
+    pub fn decode_options(&self) -> DecodeOptions {
+        self.decode_opts
+    }
+
+    /// Decode with `opts` from now on, dropping every frame decoded with the old ones
+    pub fn set_decode_options(&mut self, opts: DecodeOptions) {
+        self.decode_opts = opts;
+        self.cache.clear();
+        self.previews.clear();
+        self.failures.clear();
+        self.forget_pending();
+        self.restart_loader();
+    }
+
+    /// Decode frames with an external converter (see [`load_frame_with_command`]) instead
+    /// of reading them as images; `None` goes back to the built-in decoders. Applies to
+    /// loads requested from now on.
+    pub fn set_loader_command(&mut self, command: Option<&str>) {
+        self.loader_command = command.map(Arc::from);
+    }
+
+    /// Decode only `[x0, y0, x1, y1]` of the frames from now on, in frame pixels, where
+    /// the format allows it (strip or tile TIFFs, see [`crate::region`]); `None` decodes
+    /// them whole again. Frames decoded for a region that does not cover the new one
+    /// stay until their reload replaces them, so the view does not go blank meanwhile.
+    pub fn set_decode_region(&mut self, region: Option<[u32; 4]>) {
+        if self.region == region {
+            return;
+        }
+        self.region = region;
+        let stale: Vec<i64> = self.pending_loads.iter().filter(|(_, req)| req.region != region).map(|(&idx, _)| idx).collect();
+        for idx in stale {
+            self.cancel_load(idx);
+        }
+    }
+
+    /// Region the frames are decoded for, if any
+    pub fn decode_region(&self) -> Option<[u32; 4]> {
+        self.region
+    }
+
+    /// Keep `frames` loaded wherever the cache is centered, e.g. the two frames of a blink
+    /// comparison. They are requested by the next `update_for_index`; an empty slice
+    /// releases them to normal eviction.
+    pub fn set_pinned(&mut self, frames: &[i64]) {
+        self.pinned = frames.iter().copied().collect();
+        if let Some(r) = &self.remote_range {
+            r.set_pinned(frames);
+        }
+    }
+
+    pub fn is_pinned(&self, idx: i64) -> bool {
+        self.pinned.contains(&idx)
+    }
+
+    pub fn set_bounds(&mut self, bounds: SequenceBounds) {
+        self.bounds = bounds;
+    }
+
+    /// Set the step size for cache filling
+    pub fn set_step_size(&mut self, step: u64) {
+        self.step_size = step;
+    }
+
+    /// Keep the window where it is until the index is `steps` step sizes away from its
+    /// center (1, the least, moves it along with every step). Moves smaller than a step,
+    /// e.g. single frames while stepping by 1000, then keep the frames on the step grid.
+    pub fn set_reload_threshold(&mut self, steps: u64) {
+        self.reload_threshold = steps.max(1);
+    }
+
+    /// Load the next `frames` consecutive frames before anything else (0 turns it off)
+    pub fn set_lookahead(&mut self, frames: u64) {
+        self.lookahead = frames;
+    }
+
+    /// Turn loading the frames around the current one on or off, e.g. on a metered
+    /// connection. While off, only the current and pinned frames are loaded; frames
+    /// already cached stay until they leave the window.
+    pub fn set_prefetch(&mut self, prefetch: bool) {
+        self.prefetch = prefetch;
+    }
+
+    pub fn prefetch(&self) -> bool {
+        self.prefetch
+    }
+
+    /// Direction the prefetch window currently leans to, if any
+    pub fn heading(&self) -> Option<Heading> {
+        self.heading.filter(|_| self.streak >= MOVES_BEFORE_BIAS)
+    }
+
+    /// Note a move of the current index. A move further than the window reaches is a
+    /// jump rather than navigation and resets the direction.
+    fn track_heading(&mut self, new_index: i64) {
+        let Some(last) = self.last_index.replace(new_index) else {
+            return;
+        };
+        let heading = match new_index.cmp(&last) {
+            std::cmp::Ordering::Greater => Heading::Forward,
+            std::cmp::Ordering::Less => Heading::Backward,
+            std::cmp::Ordering::Equal => return,
+        };
+        if new_index.abs_diff(last) > self.cache_radius as u64 * self.step_size {
+            self.heading = None;
+            self.streak = 0;
+        } else if self.heading == Some(heading) {
+            self.streak = self.streak.saturating_add(1);
+        } else {
+            self.heading = Some(heading);
+            self.streak = 1;
+        }
+    }
+
+    /// Frames to keep behind and ahead of the current index, in steps
+    fn window_sides(&self) -> (u64, u64) {
+        let radius = self.cache_radius as u64;
+        let lead = ((2 * radius) as f64 * AHEAD_SHARE).round() as u64;
+        match self.heading() {
+            None => (radius, radius),
+            Some(Heading::Forward) => (2 * radius - lead, lead),
+            Some(Heading::Backward) => (lead, 2 * radius - lead),
+        }
+    }
+
+    /// Center of the window for `new_index`: the last one while the index stays within
+    /// the reload threshold of it at the same step size, else the index itself
+    fn window_center(&mut self, new_index: i64) -> i64 {
+        let step = self.step_size;
+        let reach = self.reload_threshold.saturating_mul(step);
+        let in_window = self.window.as_ref().is_some_and(|w| w.contains(&new_index));
+        let center = match self.center {
+            Some((center, s)) if s == step && in_window && center.abs_diff(new_index) < reach => center,
+            _ => new_index,
+        };
+        self.center = Some((center, step));
+        center
+    }
+
+    /// True once the lookahead frames after `idx` are loaded (at any tier) or past the known end.
+    pub fn lookahead_ready(&self, idx: i64) -> bool {
+        (1..=self.lookahead).all(|offset| {
+            let i = idx.saturating_add_unsigned(offset);
+            self.cache.contains_key(&i) || self.previews.contains_key(&i) || !self.bounds.contains(i)
+        })
+    }
+
+    /// Take in every frame the loader has finished (non-blocking).
+    /// Returns the number of loads that settled (a preview or full frame, or a failure).
+    pub fn poll(&mut self) -> usize {
+        self.supervise_loader();
+        let mut settled = 0;
+        while let Ok(result) = self.loader.result_rx.try_recv() {
+            if self.accept(result).is_some() {
+                settled += 1;
+            }
+        }
+        settled
+    }
+
+    /// Block until every load of `idx` has settled (the full-resolution frame, or the
+    /// preview of a frame too far out to be decoded in full), it fails to load, or
+    /// `timeout` passes, and return its best tier. `idx` must have been requested by
+    /// `update_for_index`.
+    pub fn wait_for(&mut self, idx: i64, timeout: Duration) -> Option<&Arc<FramePixels>> {
+        let deadline = Instant::now() + timeout;
+        while !self.covers(idx) && self.is_pending(idx) {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.loader.result_rx.recv_timeout(left) {
+                Ok(result) => {
+                    self.accept(result);
+                }
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        self.pixels(idx)
+    }
+
+    /// Store one loader result; returns the index it settled, if it was still wanted
+    fn accept(&mut self, result: Result<LoadResult, LoadFailure>) -> Option<i64> {
+        let LoadResult { idx, tier, pixels } = match result {
+            Ok(loaded) => loaded,
+            Err(LoadFailure { idx, error }) => {
+                if !self.pending_loads.contains_key(&idx) && !self.pending_previews.contains_key(&idx) {
+                    return None;
+                }
+                // The other tier of the frame would fail the same way
+                self.cancel_load(idx);
+                if error.kind == FailureKind::NotFound {
+                    self.missing.insert(idx);
+                }
+                self.failures.insert(idx, error);
+                return Some(idx);
+            }
+        };
+        // Only insert if this idx is still pending (i.e., not evicted out-of-range), and a
+        // region decode only if it covers what the pending request wants. A preview request
+        // comes back in full when the frame has no reduced decode.
+        let want = match self.pending_loads.get(&idx) {
+            Some(req) => req.region,
+            None => self.pending_previews.get(&idx)?.region,
+        };
+        if let Tier::Full { region: Some(decoded), .. } = tier {
+            if !want.is_some_and(|want| decoded.contains(want)) {
+                return None;
+            }
+        }
+        self.failures.remove(&idx);
+        let (w, h) = pixels.dimensions();
+        let pixels = Arc::new(pixels);
+        match tier {
+            Tier::Preview { full_size } => {
+                debug!("preview idx={} ({}x{})", idx, w, h);
+                self.pending_previews.remove(&idx);
+                self.previews.insert(idx, CachedFrame { pixels, full_size, region: None });
+            }
+            Tier::Full { timing, region } => {
+                debug!("loaded idx={} ({}x{})", idx, w, h);
+                if self.timings.len() == TIMING_HISTORY {
+                    self.timings.pop_front();
+                }
+                self.timings.push_back(timing);
+                self.cancel_load(idx);
+                let full_size = region.map_or((w, h), |r| r.full_size);
+                self.cache.insert(idx, CachedFrame { pixels, full_size, region });
+                self.previews.remove(&idx);
+            }
+        }
+        Some(idx)
+    }
+
+    /// Update cache centered on new_index, preloading neighbors and evicting out-of-range entries.
+    /// Returns how many loads were launched and how many frames were evicted.
+    pub fn update_for_index(&mut self, new_index: i64, seq: &SequenceSpec) -> (usize, usize) {
+        // First, take in any frames the loader has finished
+        self.poll();
+        self.adapt_radius();
+        self.track_heading(new_index);
+
+        let (behind, ahead) = self.window_sides();
+        let step = self.step_size;
+        let center = self.window_center(new_index);
+
+        // Calculate min/max indices based on step size
+        let min_idx = center.saturating_sub_unsigned(behind * step).max(seq.min_index());
+        let max_idx = center
+            .saturating_add_unsigned(ahead * step)
+            .max(new_index.saturating_add_unsigned(self.lookahead));
+
+        self.window = Some(min_idx..=max_idx);
+        // Update remote range for SSH worker to check
+        if let Some(r) = &self.remote_range {
+            r.set(min_idx, max_idx);
+            r.set_current(new_index);
+        }
+
+        // Evict entries outside the desired range
+        let wanted = |idx: &i64| (min_idx..=max_idx).contains(idx) || self.pinned.contains(idx);
+        let to_evict: Vec<i64> = self.cache.keys().filter(|idx| !wanted(idx)).copied().collect();
+
+        let evicted_count = to_evict.len();
+        if evicted_count > 0 {
+            debug!("evicted {} entries", evicted_count);
+        }
+        for idx in to_evict {
+            self.cache.remove(&idx);
+        }
+        self.previews.retain(|idx, _| wanted(idx));
+        // Failed frames are tried again once they come back into the window
+        self.failures.retain(|idx, _| wanted(idx));
+
+        // Cancel pending loads outside range (without prefetching, all but the current frame's)
+        let loading = |idx: &i64| wanted(idx) && (self.prefetch || *idx == new_index || self.pinned.contains(idx));
+        let dropped: Vec<i64> = self.pending_indices().filter(|idx| !loading(idx)).collect();
+        if !dropped.is_empty() {
+            debug!("cancelled {} loads", dropped.len());
+        }
+        for idx in dropped {
+            self.cancel_load(idx);
+        }
+
+        // Generate indices to load using centered order
+        // Start with current index for immediate visual feedback on startup
+        // Order: current, c-s, c+s, c-2s, c+2s, ... around the window center c (the side
+        // moved toward first, and only the longer side once the shorter one is exhausted)
+        let mut indices_to_check = Vec::new();
+        // new_index is always in range: the window recenters once it falls outside
+        if new_index >= min_idx && new_index <= max_idx {
+            indices_to_check.push(new_index);
+        }
+        indices_to_check.extend(self.pinned.iter().copied());
+        // While playing, the frames about to be shown come first
+        let (lookahead, behind, ahead) = if self.prefetch { (self.lookahead, behind, ahead) } else { (0, 0, 0) };
+        for offset in 1..=lookahead {
+            indices_to_check.push(new_index.saturating_add_unsigned(offset));
+        }
+        let forward_first = self.heading() == Some(Heading::Forward);
+        for offset in 1..=behind.max(ahead) {
+            // Backward index (c - offset*step)
+            let back_idx = center.checked_sub_unsigned(offset * step).filter(|&i| offset <= behind && i >= min_idx);
+            // Forward index (c + offset*step)
+            let forward_idx = Some(center.saturating_add_unsigned(offset * step)).filter(|&i| offset <= ahead && i <= max_idx);
+            if forward_first {
+                indices_to_check.extend(forward_idx.into_iter().chain(back_idx));
+            } else {
+                indices_to_check.extend(back_idx.into_iter().chain(forward_idx));
+            }
+        }
+
+        // Loads still queued from earlier positions move to their place in the new order
+        self.ranks.clear();
+        for (rank, &idx) in indices_to_check.iter().enumerate() {
+            self.ranks.entry(idx).or_insert(rank);
+        }
+        self.loader.queue.rerank(&self.ranks);
+
+        // Launch background loads for missing entries. Previews are queued first, so a
+        // loader thread never takes a full decode while a preview is still to come.
+        let mut launched_count = 0;
+        let mut full_loads = Vec::new();
+        for idx in indices_to_check {
+            if !self.covers(idx) && !self.failures.contains_key(&idx) && self.bounds.contains(idx) {
+                // For local files: check existence directly. For remote: always try to load
+                let should_load = match &self.seq_source {
+                    SequenceSource::Local(dir) => dir.join(seq.relative_path_for(idx)).exists(),
+                    SequenceSource::Video { info, .. } => info.contains(idx),
+                    SequenceSource::Animation { info, .. } => info.contains(idx),
+                    SequenceSource::Remote { .. } | SequenceSource::Http { .. } => true,
+                };
+
+                if should_load {
+                    let rel_path = seq.relative_path_for(idx);
+                    let reduced = self.has_reduced_preview(&rel_path);
+                    let req = LoadRequest {
+                        idx,
+                        rel_path,
+                        seq_source: self.seq_source.clone(),
+                        request_tx: self.request_tx.clone(),
+                        loader_command: self.loader_command.clone(),
+                        region: self.region,
+                        preview: false,
+                        cancelled: Arc::new(AtomicBool::new(false)),
+                    };
+                    if reduced && !self.previews.contains_key(&idx) && !self.pending_previews.contains_key(&idx) {
+                        let req = LoadRequest {
+                            preview: true,
+                            cancelled: Arc::new(AtomicBool::new(false)),
+                            ..req.clone()
+                        };
+                        self.loader.queue.push(load_rank(&self.ranks, &req), req.clone());
+                        self.pending_previews.insert(idx, req);
+                        launched_count += 1;
+                    }
+                    // Frames decoded in full even where a reduced preview would do: on
+                    // screen or next to it, pinned, and those about to be played
+                    let near = idx.abs_diff(new_index) <= step
+                        || self.pinned.contains(&idx)
+                        || (new_index..=new_index.saturating_add_unsigned(self.lookahead)).contains(&idx);
+                    if (!reduced || near) && !self.pending_loads.contains_key(&idx) {
+                        full_loads.push(req);
+                    }
+                }
+            }
+        }
+        for req in full_loads {
+            self.loader.queue.push(load_rank(&self.ranks, &req), req.clone());
+            self.pending_loads.insert(req.idx, req);
+            launched_count += 1;
+        }
+
+        (launched_count, evicted_count)
+    }
+
+    /// True if frames at `rel_path` get a preview decoded at reduced scale ahead of the
+    /// full decode: local JPEGs read directly and decoded whole
+    fn has_reduced_preview(&self, rel_path: &str) -> bool {
+        self.preview_max_dim > 0
+            && self.loader_command.is_none()
+            && self.region.is_none()
+            && matches!(self.seq_source, SequenceSource::Local(_))
+            && ImageFormat::from_path(rel_path).is_ok_and(|format| format == ImageFormat::Jpeg)
+    }
+
+    /// Frames found missing since the last call (for learning the sequence bounds)
+    pub fn take_missing(&mut self) -> BTreeSet<i64> {
+        std::mem::take(&mut self.missing)
+    }
+
+    pub fn loaded_count(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn preview_count(&self) -> usize {
+        self.previews.len()
+    }
+
+    /// Frames with a load pending, of either tier
+    pub fn pending_count(&self) -> usize {
+        let previews_only = self.pending_previews.keys().filter(|idx| !self.pending_loads.contains_key(idx));
+        self.pending_loads.len() + previews_only.count()
+    }
+
+    /// Full-resolution pixels of every loaded frame (previews and region decodes excluded)
+    pub fn full_frames(&self) -> impl Iterator<Item = &Arc<FramePixels>> {
+        self.cache.values().filter(|f| f.region.is_none()).map(|f| &f.pixels)
+    }
+
+    /// Nearest frames before and after `idx` loaded whole at full resolution
+    pub fn loaded_around(&self, idx: i64) -> (Option<i64>, Option<i64>) {
+        let whole = |(&i, f): (&i64, &CachedFrame)| f.region.is_none().then_some(i);
+        (self.cache.range(..idx).rev().find_map(whole), self.cache.range(idx.saturating_add(1)..).find_map(whole))
+    }
+
+    /// CPU memory held by retained pixel buffers (full and preview tiers)
+    pub fn pixel_bytes(&self) -> usize {
+        self.cache
+            .values()
+            .chain(self.previews.values())
+            .map(|f| f.pixels.byte_size())
+            .sum()
+    }
+
+    /// Indices kept around the current one (pinned frames aside), once an index was set
+    pub fn window(&self) -> Option<RangeInclusive<i64>> {
+        self.window.clone()
+    }
+
+    /// Fetch and decode times of the latest full-resolution loads, oldest first
+    pub fn timings(&self) -> impl Iterator<Item = &LoadTiming> {
+        self.timings.iter()
+    }
+
+    /// Frames kept on each side, in steps; follows the load costs once adaptive
+    pub fn radius(&self) -> usize {
+        self.cache_radius
+    }
+
+    pub fn is_radius_adaptive(&self) -> bool {
+        self.adaptive.is_some()
+    }
+
+    pub fn is_pending(&self, idx: i64) -> bool {
+        self.pending_loads.contains_key(&idx) || self.pending_previews.contains_key(&idx)
+    }
+
+    /// Why the last load of `idx` failed, if it did
+    pub fn failure(&self, idx: i64) -> Option<&LoadError> {
+        self.failures.get(&idx)
+    }
+
+    /// Failed frames in the window, in index order
+    pub fn failures(&self) -> impl Iterator<Item = (i64, &LoadError)> {
+        self.failures.iter().map(|(&idx, e)| (idx, e))
+    }
+
+    /// Forget the failure of `idx` so the next update requests it again.
+    /// Returns false if it had not failed.
+    pub fn retry(&mut self, idx: i64) -> bool {
+        self.failures.remove(&idx).is_some()
+    }
+
+    /// Forget every failure (e.g. after a reconnect); returns how many frames failed
+    pub fn retry_all(&mut self) -> usize {
+        std::mem::take(&mut self.failures).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty() && self.previews.is_empty()
+    }
+}
+
+/// Start `threads` background loader threads sharing one queue
+fn spawn_loader(preview_max_dim: u32, decode_opts: DecodeOptions, threads: usize, wake: Option<Wake>) -> Loader {
+    let queue = Arc::new(LoadQueue::default());
+    let (result_tx, result_rx) = channel::<Result<LoadResult, LoadFailure>>();
+    let mut heartbeats = Vec::new();
+
+    for _ in 0..threads {
+        let heartbeat = Heartbeat::default();
+        let beat = heartbeat.clone();
+        heartbeats.push(heartbeat);
+        let (queue, result_tx, wake) = (queue.clone(), result_tx.clone(), wake.clone());
+        thread::spawn(move || {
+            let _alive = beat.alive();
+            let send = |result: Result<LoadResult, LoadFailure>| {
+                let _ = result_tx.send(result);
+                if let Some(wake) = &wake {
+                    wake();
+                }
+            };
+            while let Some(req) = queue.pop() {
+                beat.busy();
+                let span = debug_span!("load", idx = req.idx);
+                let _entered = span.enter();
+                let started = Instant::now();
+                // A preview request decodes only the preview, unless the frame has no reduced
+                // decode after all; then it loads whole below (as do its errors)
+                let preview = match &req.seq_source {
+                    SequenceSource::Local(dir) if req.preview && !req.is_cancelled() => {
+                        load_frame_preview(&dir.join(&req.rel_path), preview_max_dim, decode_opts).ok().flatten()
+                    }
+                    _ => None,
+                };
+                if let Some(FramePreview { pixels, full_size }) = preview {
+                    send(Ok(LoadResult {
+                        idx: req.idx,
+                        tier: Tier::Preview { full_size },
+                        pixels,
+                    }));
+                    beat.idle();
+                    continue;
+                }
+                let mut fetch = None;
+                // Wrap in closure that returns Result to use ?
+                let decoded: Result<(FramePixels, Option<FrameRegion>), CacheError> = (|| {
+                    if req.is_cancelled() {
+                        return Err(CacheError::Cancelled);
+                    }
+                    let whole = |pixels| Ok((pixels, None));
+                    match &req.seq_source {
+                        SequenceSource::Local(dir) => {
+                            let path = dir.join(&req.rel_path);
+                            match (&req.loader_command, req.region) {
+                                (Some(command), _) => load_frame_with_command(command, &path, decode_opts).and_then(whole),
+                                (None, Some(want)) => match load_frame_region(&path, want, decode_opts)? {
+                                    Some((pixels, region)) => Ok((pixels, Some(region))),
+                                    None => load_frame(&path, decode_opts).and_then(whole),
+                                },
+                                (None, None) => load_frame(&path, decode_opts).and_then(whole),
+                            }
+                        }
+                        SequenceSource::Animation { path, info } => load_animation_frame(path, *info, req.idx, decode_opts).and_then(whole),
+                        SequenceSource::Video { .. } => {
+                            let bytes = fetch_frame_bytes(&req.seq_source, &req.rel_path, req.idx, None)?;
+                            fetch = Some(started.elapsed());
+                            if req.is_cancelled() {
+                                return Err(CacheError::Cancelled);
+                            }
+                            load_frame_from_bytes(&bytes, &req.seq_source.path_display(&req.rel_path), decode_opts).and_then(whole)
+                        }
+                        SequenceSource::Remote { .. } | SequenceSource::Http { .. } => {
+                            if req.request_tx.is_none() {
+                                return Err(CacheError::NoConnection);
+                            }
+                            // Show progressive/interlaced frames while the rest downloads
+                            let mut on_partial = |partial: PartialCat| {
+                                if req.is_cancelled() {
+                                    return;
+                                }
+                                let Some(pixels) = load_partial_frame(&partial.bytes, decode_opts) else {
+                                    return;
+                                };
+                                let full_size = pixels.dimensions();
+                                let pixels = downscale_to_max_dim(&pixels, preview_max_dim).unwrap_or(pixels);
+                                debug!("partial idx={} ({}/{} bytes)", req.idx, partial.bytes.len(), partial.total);
+                                send(Ok(LoadResult {
+                                    idx: req.idx,
+                                    tier: Tier::Preview { full_size },
+                                    pixels,
+                                }));
+                            };
+                            let bytes = fetch_frame_bytes_with_partials(
+                                &req.seq_source,
+                                &req.rel_path,
+                                req.idx,
+                                req.request_tx.as_ref(),
+                                Some(&mut on_partial),
+                            )?;
+                            fetch = Some(started.elapsed());
+                            if req.is_cancelled() {
+                                return Err(CacheError::Cancelled);
+                            }
+                            let source = req.seq_source.path_display(&req.rel_path);
+                            match (&req.loader_command, req.region) {
+                                (Some(command), _) => load_frame_from_bytes_with_command(command, &bytes, &source, decode_opts).and_then(whole),
+                                (None, Some(want)) => match load_frame_region_from_bytes(&bytes, &source, want, decode_opts)? {
+                                    Some((pixels, region)) => Ok((pixels, Some(region))),
+                                    None => load_frame_from_bytes(&bytes, &source, decode_opts).and_then(whole),
+                                },
+                                (None, None) => load_frame_from_bytes(&bytes, &source, decode_opts).and_then(whole),
+                            }
+                        }
+                    }
+                })();
+                let elapsed = started.elapsed();
+
+                match decoded {
+                    Ok((pixels, region)) => {
+                        let timing = LoadTiming {
+                            idx: req.idx,
+                            fetch,
+                            decode: elapsed.saturating_sub(fetch.unwrap_or_default()),
+                        };
+                        send(Ok(LoadResult {
+                            idx: req.idx,
+                            tier: Tier::Full { timing, region },
+                            pixels,
+                        }));
+                    }
+                    Err(CacheError::Cancelled) => {
+                        debug!("dropped idx={} (no longer wanted)", req.idx);
+                    }
+                    Err(e) => {
+                        warn!("idx={} failed: {}", req.idx, e);
+                        send(Err(LoadFailure {
+                            idx: req.idx,
+                            error: LoadError {
+                                kind: e.kind(),
+                                message: e.to_string(),
+                            },
+                        }));
+                    }
+                }
+                beat.idle();
+            }
+            beat.finish();
+        });
+    }
+
+    Loader {
+        queue,
+        result_rx,
+        heartbeats,
+    }
+}
+
+impl Drop for FrameCache {
+    fn drop(&mut self) {
+        // Clear pending loads and close loader queue
+        let pending_count = self.pending_count();
+        if pending_count > 0 {
+            debug!("cancelling {} pending loads", pending_count);
+        }
+        self.forget_pending();
+        debug!("loader exiting");
+        // Dropping the loader closes its queue, so the loader threads exit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GrayImage;
+
+    /// Frames `f_0.png`, `f_1.png`, ... of `size`, frame i shaded `i * shade_step`, in a
+    /// fresh temp directory named after `name`
+    fn write_gray_seq(name: &str, count: u8, size: (u32, u32), shade_step: u8) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("zapvis_frame_cache_{name}_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for i in 0..count {
+            GrayImage::from_pixel(size.0, size.1, image::Luma([i * shade_step])).save(dir.join(format!("f_{i}.png"))).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn loads_local_frames() {
+        let dir = write_gray_seq("load", 3, (64, 32), 50);
+        let seq = SequenceSpec::from_pattern("f_#.png", SequenceSource::Local(dir.clone()), 1).unwrap();
+        let opts = DecodeOptions::raw();
+        let mut cache = FrameCache::new(1, seq.source.clone(), None, None, 16, opts);
+        let wakes = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = wakes.clone();
+        cache.set_wake(Arc::new(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }));
+        assert_eq!(cache.update_for_index(1, &seq), (3, 0));
+        // The pending loads move over to the new threads
+        cache.set_loader_threads(3);
+
+        let frame = cache.wait_for(1, Duration::from_secs(10)).expect("frame 1 loads");
+        assert_eq!(frame.dimensions(), (64, 32));
+        assert_eq!(frame.pixel_rgba(0, 0), Some([50, 50, 50, 255]));
+        assert_eq!(cache.full_size(1), Some((64, 32)));
+        assert!(cache.wait_for(2, Duration::from_secs(10)).is_some());
+        assert!(cache.wait_for(0, Duration::from_secs(10)).is_some());
+        assert!(!cache.is_preview(0), "PNGs have no reduced decode to preview");
+        // The wake follows each result it announces (a load the old thread had started
+        // may settle twice)
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while wakes.load(Ordering::Relaxed) < 3 && Instant::now() < deadline {
+            thread::yield_now();
+        }
+        assert!(wakes.load(Ordering::Relaxed) >= 3, "every settled load wakes the owner");
+        assert_eq!(cache.window(), Some(0..=2));
+        let timed: BTreeSet<i64> = cache.timings().map(|t| t.idx).collect();
+        assert!(timed.is_superset(&BTreeSet::from([1, 2])));
+        assert!(cache.timings().all(|t| t.fetch.is_none()), "local frames are read while decoding");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn decodes_only_the_region_of_tiffs() {
+        let dir = std::env::temp_dir().join(format!("zapvis_frame_cache_region_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        GrayImage::from_fn(200, 100, |x, y| image::Luma([(x + y) as u8])).save(dir.join("f_0.tif")).unwrap();
+        let seq = SequenceSpec::from_pattern("f_#.tif", SequenceSource::Local(dir.clone()), 0).unwrap();
+        let opts = DecodeOptions::raw();
+        let mut cache = FrameCache::new(0, seq.source.clone(), None, None, 16, opts);
+        cache.set_decode_region(Some([20, 10, 60, 40]));
+        cache.update_for_index(0, &seq);
+        let frame = cache.wait_for(0, Duration::from_secs(10)).expect("region loads");
+        assert_eq!(frame.dimensions(), (40, 30));
+        assert_eq!(frame.pixel_rgba(0, 0), Some([30, 30, 30, 255]));
+        assert_eq!(cache.full_size(0), Some((200, 100)));
+        assert_eq!(cache.region(0).map(|r| r.bounds), Some([20, 10, 60, 40]));
+        assert_eq!(cache.full_frames().count(), 0);
+
+        // A region inside the decoded one needs no reload; one reaching out of it does,
+        // and the old region stays on screen meanwhile
+        cache.set_decode_region(Some([30, 20, 50, 30]));
+        assert_eq!(cache.update_for_index(0, &seq), (0, 0));
+        cache.set_decode_region(None);
+        assert_eq!(cache.update_for_index(0, &seq), (1, 0));
+        assert!(cache.pixels(0).is_some());
+        let frame = cache.wait_for(0, Duration::from_secs(10)).expect("whole frame loads");
+        assert_eq!(frame.dimensions(), (200, 100));
+        assert_eq!(cache.region(0), None);
+    }
+
+    #[test]
+    fn jpeg_previews_arrive_before_full_decodes() {
+        let dir = std::env::temp_dir().join(format!("zapvis_frame_cache_jpeg_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for i in 0..5 {
+            image::RgbImage::from_pixel(256, 128, image::Rgb([i * 40, 0, 0])).save(dir.join(format!("f_{i}.jpg"))).unwrap();
+        }
+        let seq = SequenceSpec::from_pattern("f_#.jpg", SequenceSource::Local(dir.clone()), 1).unwrap();
+        let mut cache = FrameCache::new(2, seq.source.clone(), None, None, 32, DecodeOptions::raw());
+        // Previews for the window 0..=4, full decodes only for 1..=3 around the current frame
+        assert_eq!(cache.update_for_index(2, &seq), (8, 0));
+
+        let mut settled = Vec::new();
+        while cache.pending_count() > 0 {
+            let result = cache.loader.result_rx.recv_timeout(Duration::from_secs(10)).expect("loads settle");
+            if let Ok(loaded) = &result {
+                settled.push((loaded.idx, matches!(loaded.tier, Tier::Preview { .. })));
+            }
+            let previewed = cache.accept(result).and_then(|idx| cache.is_preview(idx).then_some(idx));
+            if let Some(idx) = previewed {
+                assert_eq!(cache.pixels(idx).unwrap().dimensions(), (32, 16));
+                assert_eq!(cache.full_size(idx), Some((256, 128)));
+            }
+        }
+        let previews: Vec<i64> = settled.iter().filter(|(_, preview)| *preview).map(|(idx, _)| *idx).collect();
+        assert_eq!(previews, [2, 1, 3, 0, 4]);
+        assert!(settled[..5].iter().all(|(_, preview)| *preview), "{settled:?}");
+        assert_eq!(cache.loaded_count(), 3);
+        assert!(cache.is_preview(0) && cache.is_preview(4));
+
+        // Next to the current frame now, so 4 is decoded in full
+        cache.update_for_index(3, &seq);
+        assert_eq!(cache.wait_for(4, Duration::from_secs(10)).map(|p| p.dimensions()), Some((256, 128)));
+
+        // A frame that only gets a preview is done once the preview is in
+        let mut cache = FrameCache::new(2, seq.source.clone(), None, None, 32, DecodeOptions::raw());
+        cache.update_for_index(2, &seq);
+        assert_eq!(cache.wait_for(0, Duration::from_secs(10)).map(|p| p.dimensions()), Some((32, 16)));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn queued_loads_follow_the_current_index() {
+        let request = |idx| LoadRequest {
+            idx,
+            rel_path: format!("f_{idx}.png"),
+            seq_source: SequenceSource::Local(std::env::temp_dir()),
+            request_tx: None,
+            loader_command: None,
+            region: None,
+            preview: false,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        let queue = LoadQueue::default();
+        for (rank, idx) in [10, 9, 11, 8, 12].into_iter().enumerate() {
+            queue.push(rank, request(idx));
+        }
+        assert_eq!(queue.pop().map(|r| r.idx), Some(10));
+
+        // Jumped to 12: it goes first, 8 is no longer wanted and goes last, and every
+        // preview goes ahead of the full decodes
+        let ranks = HashMap::from([(12, 0), (11, 1), (13, 2), (9, 3)]);
+        queue.rerank(&ranks);
+        queue.push(load_rank(&ranks, &request(13)), request(13));
+        let preview = LoadRequest { preview: true, ..request(9) };
+        queue.push(load_rank(&ranks, &preview), preview);
+        let order: Vec<(i64, bool)> = (0..5).filter_map(|_| queue.pop()).map(|r| (r.idx, r.preview)).collect();
+        assert_eq!(order, [(9, true), (12, false), (11, false), (13, false), (9, false)]);
+        assert_eq!(queue.pop().map(|r| r.idx), Some(8));
+        queue.close();
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn window_leans_toward_the_navigation_direction() {
+        let dir = std::env::temp_dir().join(format!("zapvis_frame_cache_heading_{}", std::process::id()));
+        let seq = SequenceSpec::from_pattern("f_#.png", SequenceSource::Local(dir), 100).unwrap();
+        let opts = DecodeOptions::raw();
+        let mut cache = FrameCache::new(10, seq.source.clone(), None, None, 0, opts);
+        cache.update_for_index(100, &seq);
+        cache.update_for_index(101, &seq);
+        assert_eq!(cache.window_sides(), (10, 10), "one step is not a direction yet");
+        cache.update_for_index(102, &seq);
+        assert_eq!(cache.heading(), Some(Heading::Forward));
+        assert_eq!(cache.window_sides(), (4, 16));
+
+        cache.update_for_index(101, &seq);
+        assert_eq!(cache.window_sides(), (10, 10), "symmetric again after turning around");
+        cache.update_for_index(100, &seq);
+        assert_eq!(cache.window_sides(), (16, 4));
+        cache.update_for_index(5000, &seq);
+        assert_eq!(cache.heading(), None, "a jump is not navigation");
+    }
+
+    #[test]
+    fn window_follows_the_index_by_whole_steps() {
+        let dir = std::env::temp_dir().join(format!("zapvis_frame_cache_step_{}", std::process::id()));
+        let seq = SequenceSpec::from_pattern("f_#.png", SequenceSource::Local(dir), 0).unwrap();
+        let opts = DecodeOptions::raw();
+        let mut cache = FrameCache::new(2, seq.source.clone(), None, None, 0, opts);
+        cache.update_for_index(5000, &seq);
+        assert_eq!(cache.window(), Some(4998..=5002));
+        cache.update_for_index(5001, &seq);
+        assert_eq!(cache.window(), Some(4999..=5003), "a whole step at step size 1");
+
+        // Single frames while stepping by 1000 keep the window on its step grid (the
+        // moves turn around, so the window does not lean either way)
+        cache.set_step_size(1000);
+        cache.update_for_index(5000, &seq);
+        assert_eq!(cache.window(), Some(3000..=7000));
+        cache.update_for_index(5999, &seq);
+        cache.update_for_index(5001, &seq);
+        assert_eq!(cache.window(), Some(3000..=7000));
+        cache.update_for_index(6000, &seq);
+        assert_eq!(cache.window(), Some(4000..=8000));
+
+        // A larger threshold lets the index wander further before the window follows
+        cache.set_reload_threshold(2);
+        cache.update_for_index(4500, &seq);
+        assert_eq!(cache.window(), Some(4000..=8000));
+        cache.update_for_index(8000, &seq);
+        assert_eq!(cache.window(), Some(6000..=10000));
+        // Nor does it outlast a change of step size
+        cache.set_step_size(10);
+        cache.update_for_index(7999, &seq);
+        assert_eq!(cache.window(), Some(7979..=8019));
+    }
+
+    #[test]
+    fn pinned_frames_survive_moving_away() {
+        let dir = write_gray_seq("pin", 12, (8, 8), 20);
+        let seq = SequenceSpec::from_pattern("f_#.png", SequenceSource::Local(dir.clone()), 10).unwrap();
+        let opts = DecodeOptions::raw();
+        let mut cache = FrameCache::new(1, seq.source.clone(), None, None, 0, opts);
+        cache.set_pinned(&[2]);
+        cache.update_for_index(10, &seq);
+        assert!(cache.wait_for(2, Duration::from_secs(10)).is_some(), "pinned frame loads outside the window");
+        assert!(cache.wait_for(10, Duration::from_secs(10)).is_some());
+
+        cache.update_for_index(5, &seq);
+        assert!(cache.pixels(2).is_some());
+        assert!(cache.pixels(10).is_none());
+        assert!(!cache.is_pending(11) && !cache.is_pending(9), "loads that left the window are cancelled");
+        assert!(cache.loader.queue.lock().waiting.keys().all(|&(idx, _)| (4..=6).contains(&idx)));
+        cache.set_pinned(&[]);
+        cache.update_for_index(10, &seq);
+        assert!(cache.pixels(2).is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn only_the_current_frame_loads_without_prefetching() {
+        let dir = write_gray_seq("prefetch", 6, (8, 8), 40);
+        let seq = SequenceSpec::from_pattern("f_#.png", SequenceSource::Local(dir.clone()), 2).unwrap();
+        let opts = DecodeOptions::raw();
+        let mut cache = FrameCache::new(2, seq.source.clone(), None, None, 0, opts);
+        cache.set_prefetch(false);
+        assert_eq!(cache.update_for_index(2, &seq), (1, 0));
+        assert!(cache.wait_for(2, Duration::from_secs(10)).is_some());
+        assert_eq!(cache.update_for_index(3, &seq), (1, 0));
+        assert!(cache.pixels(2).is_some(), "cached frames stay while in the window");
+
+        assert!(cache.wait_for(3, Duration::from_secs(10)).is_some());
+
+        cache.set_prefetch(true);
+        assert_eq!(cache.update_for_index(3, &seq), (3, 0), "the rest of the window follows");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn inserted_frames_replace_their_load() {
+        let dir = write_gray_seq("insert", 3, (8, 8), 40);
+        let seq = SequenceSpec::from_pattern("f_#.png", SequenceSource::Local(dir.clone()), 1).unwrap();
+        let opts = DecodeOptions::raw();
+        let mut cache = FrameCache::new(1, seq.source.clone(), None, None, 0, opts);
+        assert_eq!(cache.update_for_index(1, &seq), (3, 0));
+        cache.insert(1, FramePixels::Luma(GrayImage::from_pixel(4, 2, image::Luma([255]))));
+        assert!(!cache.is_pending(1), "the file's load is cancelled");
+        assert!(cache.wait_for(2, Duration::from_secs(10)).is_some());
+        cache.poll();
+        assert_eq!(cache.pixels(1).unwrap().dimensions(), (4, 2));
+        assert_eq!(cache.update_for_index(1, &seq), (0, 0), "not loaded from disk while cached");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn failed_frames_wait_for_a_retry() {
+        let dir = std::env::temp_dir().join(format!("zapvis_frame_cache_fail_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("f_1.png"), b"not a png").unwrap();
+        let seq = SequenceSpec::from_pattern("f_#.png", SequenceSource::Local(dir.clone()), 1).unwrap();
+        let opts = DecodeOptions::raw();
+        let mut cache = FrameCache::new(0, seq.source.clone(), None, None, 0, opts);
+        assert_eq!(cache.update_for_index(1, &seq), (1, 0));
+        assert!(cache.wait_for(1, Duration::from_secs(10)).is_none());
+        let error = cache.failure(1).expect("the failure is kept").clone();
+        assert_eq!(error.kind, FailureKind::Decode);
+        assert!(error.message.contains("f_1.png"), "{}", error.message);
+        assert_eq!(cache.update_for_index(1, &seq), (0, 0), "not requested again by itself");
+
+        assert!(cache.retry(1));
+        assert!(!cache.retry(1));
+        assert_eq!(cache.update_for_index(1, &seq), (1, 0));
+        assert!(cache.wait_for(1, Duration::from_secs(10)).is_none());
+        assert_eq!(cache.failures().count(), 1);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,294 @@
+//! Grading on the GPU: gain, offset and gamma, then an optional 3D LUT (`.cube`), applied
+//! by a shader while the frame is drawn, so that a color pipeline can be previewed and
+//! tuned live without decoding the frames again. It is a paint callback on the viewer's
+//! OpenGL (glow) renderer; frames are drawn as usual while the grade is neutral.
+use eframe::egui;
+use eframe::egui_glow;
+use eframe::glow::{self, HasContext};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use tracing::{info, warn};
+use zapvis::lut::Lut3d;
+
+/// Applied to the frame's values as displayed (sRGB-encoded), in this order
+#[derive(Clone)]
+pub struct Grade {
+    pub gain: f32,
+    pub offset: f32,
+    pub gamma: f32,
+    /// The LUT and the file it was loaded from
+    pub lut: Option<(PathBuf, Arc<Lut3d>)>,
+}
+
+impl Default for Grade {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            offset: 0.0,
+            gamma: 1.0,
+            lut: None,
+        }
+    }
+}
+
+impl Grade {
+    pub fn is_identity(&self) -> bool {
+        self.gain == 1.0 && self.offset == 0.0 && self.gamma == 1.0 && self.lut.is_none()
+    }
+}
+
+/// The grade and the GL objects drawing it; the renderer is shared with the callbacks
+#[derive(Default)]
+pub struct GradeStage {
+    pub grade: Grade,
+    renderer: Arc<Mutex<Renderer>>,
+}
+
+impl GradeStage {
+    /// Paint `mesh` (a frame or tile quad) with the grade applied
+    pub fn paint(&self, painter: &egui::Painter, mesh: egui::Mesh) {
+        if self.grade.is_identity() || self.unavailable().is_some() {
+            painter.add(egui::Shape::mesh(mesh));
+            return;
+        }
+        let (grade, renderer) = (self.grade.clone(), self.renderer.clone());
+        let rect = mesh.calc_bounds();
+        let callback = egui_glow::CallbackFn::new(move |info, painter| {
+            renderer.lock().unwrap_or_else(PoisonError::into_inner).paint(painter, &info, &mesh, &grade);
+        });
+        painter.add(egui::PaintCallback {
+            rect,
+            callback: Arc::new(callback),
+        });
+    }
+
+    /// Why grading cannot be shown (an OpenGL too old for 3D textures, a shader the
+    /// driver rejects); frames are then drawn ungraded
+    pub fn unavailable(&self) -> Option<String> {
+        self.renderer.lock().unwrap_or_else(PoisonError::into_inner).error.clone()
+    }
+
+    /// Free the GL objects; the context is about to go away
+    pub fn destroy(&self, gl: &glow::Context) {
+        if let Some(objects) = self.renderer.lock().unwrap_or_else(PoisonError::into_inner).objects.take() {
+            // SAFETY: the objects were made on this context and are used nowhere else
+            unsafe { objects.destroy(gl) };
+        }
+    }
+}
+
+#[derive(Default)]
+struct Renderer {
+    objects: Option<GlObjects>,
+    error: Option<String>,
+    /// The LUT in the 3D texture, by identity
+    uploaded: Option<Arc<Lut3d>>,
+}
+
+impl Renderer {
+    fn paint(&mut self, painter: &egui_glow::Painter, info: &egui::PaintCallbackInfo, mesh: &egui::Mesh, grade: &Grade) {
+        if self.error.is_some() {
+            return;
+        }
+        let gl = painter.gl();
+        if self.objects.is_none() {
+            // SAFETY: called on the renderer's thread with its context current
+            match unsafe { GlObjects::new(gl) } {
+                Ok(objects) => self.objects = Some(objects),
+                Err(e) => {
+                    warn!("grading unavailable: {e}");
+                    self.error = Some(e);
+                    return;
+                }
+            }
+        }
+        let (Some(objects), Some(texture)) = (&self.objects, painter.texture(mesh.texture_id)) else {
+            return;
+        };
+        let lut = grade.lut.as_ref().map(|(_, lut)| lut);
+        if let Some(lut) = lut.filter(|lut| self.uploaded.as_ref().is_none_or(|up| !Arc::ptr_eq(up, lut))) {
+            // SAFETY: as above
+            unsafe { objects.upload_lut(gl, lut) };
+            info!("uploaded LUT {:?} ({}³)", lut.title, lut.size);
+            self.uploaded = Some(lut.clone());
+        }
+        // Triangles in normalized device coordinates of the whole window
+        let [w, h] = info.screen_size_px.map(|v| v as f32);
+        let ppp = info.pixels_per_point;
+        let vertices: Vec<f32> = mesh
+            .indices
+            .iter()
+            .flat_map(|&i| {
+                let v = mesh.vertices[i as usize];
+                [v.pos.x * ppp / w * 2.0 - 1.0, 1.0 - v.pos.y * ppp / h * 2.0, v.uv.x, v.uv.y]
+            })
+            .collect();
+        // SAFETY: as above
+        unsafe { objects.draw(gl, info, texture, &vertices, grade, lut.map(|l| &**l)) };
+    }
+}
+
+const VERTEX_SHADER: &str = r#"
+in vec2 a_pos;
+in vec2 a_uv;
+out vec2 v_uv;
+void main() {
+    v_uv = a_uv;
+    gl_Position = vec4(a_pos, 0.0, 1.0);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+#ifdef GL_ES
+precision highp float;
+precision highp sampler3D;
+#endif
+uniform sampler2D u_frame;
+uniform sampler3D u_lut;
+uniform float u_gain;
+uniform float u_offset;
+uniform float u_gamma;
+uniform int u_use_lut;
+uniform vec3 u_lut_min;
+uniform vec3 u_lut_max;
+uniform float u_lut_size;
+in vec2 v_uv;
+out vec4 f_color;
+
+vec3 srgb_from_linear(vec3 c) {
+    vec3 lower = c * 12.92;
+    vec3 higher = 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055;
+    return mix(higher, lower, vec3(lessThan(c, vec3(0.0031308))));
+}
+
+void main() {
+    // egui's textures are premultiplied sRGBA, which the sampler decodes to linear
+    vec4 texel = texture(u_frame, v_uv);
+    vec3 c = srgb_from_linear(texel.rgb);
+    c = texel.a > 0.0 ? c / texel.a : vec3(0.0);
+    c = max(c * u_gain + u_offset, 0.0);
+    c = pow(c, vec3(1.0 / u_gamma));
+    if (u_use_lut != 0) {
+        // Sample at the texel centers, so that the ends of the domain hit the first and last entries
+        vec3 t = clamp((c - u_lut_min) / (u_lut_max - u_lut_min), 0.0, 1.0);
+        c = texture(u_lut, t * ((u_lut_size - 1.0) / u_lut_size) + 0.5 / u_lut_size).rgb;
+    }
+    // The framebuffer takes sRGB-encoded, premultiplied values
+    f_color = vec4(clamp(c, 0.0, 1.0) * texel.a, texel.a);
+}
+"#;
+
+struct GlObjects {
+    program: glow::Program,
+    vertex_array: glow::VertexArray,
+    buffer: glow::Buffer,
+    lut: glow::Texture,
+}
+
+impl GlObjects {
+    unsafe fn new(gl: &glow::Context) -> Result<Self, String> {
+        let version = egui_glow::ShaderVersion::get(gl);
+        if !version.is_new_shader_interface() {
+            return Err(format!("needs OpenGL 3 or OpenGL ES 3, the driver offers {version:?}"));
+        }
+        let program = gl.create_program()?;
+        let mut shaders = Vec::new();
+        for (kind, source) in [(glow::VERTEX_SHADER, VERTEX_SHADER), (glow::FRAGMENT_SHADER, FRAGMENT_SHADER)] {
+            let shader = gl.create_shader(kind)?;
+            gl.shader_source(shader, &format!("{}\n{}", version.version_declaration(), source));
+            gl.compile_shader(shader);
+            if !gl.get_shader_compile_status(shader) {
+                return Err(format!("shader does not compile: {}", gl.get_shader_info_log(shader)));
+            }
+            gl.attach_shader(program, shader);
+            shaders.push(shader);
+        }
+        gl.link_program(program);
+        if !gl.get_program_link_status(program) {
+            return Err(format!("shaders do not link: {}", gl.get_program_info_log(program)));
+        }
+        for shader in shaders {
+            gl.detach_shader(program, shader);
+            gl.delete_shader(shader);
+        }
+
+        let vertex_array = gl.create_vertex_array()?;
+        let buffer = gl.create_buffer()?;
+        gl.bind_vertex_array(Some(vertex_array));
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer));
+        let stride = 4 * std::mem::size_of::<f32>() as i32;
+        for (name, offset) in [("a_pos", 0), ("a_uv", 2 * std::mem::size_of::<f32>() as i32)] {
+            let location = gl.get_attrib_location(program, name).ok_or(format!("no attribute {name}"))?;
+            gl.enable_vertex_attrib_array(location);
+            gl.vertex_attrib_pointer_f32(location, 2, glow::FLOAT, false, stride, offset);
+        }
+        gl.bind_vertex_array(None);
+
+        let lut = gl.create_texture()?;
+        gl.bind_texture(glow::TEXTURE_3D, Some(lut));
+        for (param, value) in [
+            (glow::TEXTURE_MIN_FILTER, glow::LINEAR),
+            (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+            (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+            (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            (glow::TEXTURE_WRAP_R, glow::CLAMP_TO_EDGE),
+        ] {
+            gl.tex_parameter_i32(glow::TEXTURE_3D, param, value as i32);
+        }
+        gl.bind_texture(glow::TEXTURE_3D, None);
+        Ok(Self {
+            program,
+            vertex_array,
+            buffer,
+            lut,
+        })
+    }
+
+    unsafe fn upload_lut(&self, gl: &glow::Context, lut: &Lut3d) {
+        let bytes: Vec<u8> = lut.table.iter().flatten().flat_map(|v| v.to_ne_bytes()).collect();
+        let size = lut.size as i32;
+        gl.bind_texture(glow::TEXTURE_3D, Some(self.lut));
+        gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+        gl.tex_image_3d(glow::TEXTURE_3D, 0, glow::RGB16F as i32, size, size, size, 0, glow::RGB, glow::FLOAT, Some(&bytes));
+        gl.bind_texture(glow::TEXTURE_3D, None);
+    }
+
+    unsafe fn draw(&self, gl: &glow::Context, info: &egui::PaintCallbackInfo, frame: glow::Texture, vertices: &[f32], grade: &Grade, lut: Option<&Lut3d>) {
+        let bytes: Vec<u8> = vertices.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        gl.viewport(0, 0, info.screen_size_px[0] as i32, info.screen_size_px[1] as i32);
+        gl.use_program(Some(self.program));
+        let uniform = |name: &str| gl.get_uniform_location(self.program, name);
+        gl.uniform_1_i32(uniform("u_frame").as_ref(), 0);
+        gl.uniform_1_i32(uniform("u_lut").as_ref(), 1);
+        gl.uniform_1_f32(uniform("u_gain").as_ref(), grade.gain);
+        gl.uniform_1_f32(uniform("u_offset").as_ref(), grade.offset);
+        gl.uniform_1_f32(uniform("u_gamma").as_ref(), grade.gamma.max(0.01));
+        gl.uniform_1_i32(uniform("u_use_lut").as_ref(), lut.is_some() as i32);
+        if let Some(lut) = lut {
+            let [r0, g0, b0] = lut.domain_min;
+            let [r1, g1, b1] = lut.domain_max;
+            gl.uniform_3_f32(uniform("u_lut_min").as_ref(), r0, g0, b0);
+            gl.uniform_3_f32(uniform("u_lut_max").as_ref(), r1, g1, b1);
+            gl.uniform_1_f32(uniform("u_lut_size").as_ref(), lut.size as f32);
+        }
+        gl.active_texture(glow::TEXTURE1);
+        gl.bind_texture(glow::TEXTURE_3D, Some(self.lut));
+        gl.active_texture(glow::TEXTURE0);
+        gl.bind_texture(glow::TEXTURE_2D, Some(frame));
+        gl.bind_vertex_array(Some(self.vertex_array));
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.buffer));
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, &bytes, glow::STREAM_DRAW);
+        gl.draw_arrays(glow::TRIANGLES, 0, (vertices.len() / 4) as i32);
+        gl.bind_vertex_array(None);
+        gl.active_texture(glow::TEXTURE1);
+        gl.bind_texture(glow::TEXTURE_3D, None);
+        gl.active_texture(glow::TEXTURE0);
+    }
+
+    unsafe fn destroy(self, gl: &glow::Context) {
+        gl.delete_program(self.program);
+        gl.delete_vertex_array(self.vertex_array);
+        gl.delete_buffer(self.buffer);
+        gl.delete_texture(self.lut);
+    }
+}
@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::ConfigError;
+
+/// Framing guide drawn over the image.
+///
+/// Written in the config as strings: `"16:9"` (any `W:H` aspect ratio), `"center"`,
+/// `"action_safe"`, `"title_safe"`, `"safe:7.5"` (margin in percent), `"thirds"` or
+/// `"grid:4x3"` (columns x rows).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Guide {
+    /// Largest centered area of this width:height ratio
+    Aspect { width: f32, height: f32 },
+    /// Cross marking the image center
+    Center,
+    /// Area inside a 5% margin on every side
+    ActionSafe,
+    /// Area inside a 10% margin on every side
+    TitleSafe,
+    /// Area inside a margin of this many percent on every side
+    Safe { margin: f32 },
+    /// Rule-of-thirds lines
+    Thirds,
+    /// Lines splitting the image into equal cells
+    Grid { columns: u32, rows: u32 },
+}
+
+/// What to draw for a guide, in image coordinates normalized to 0..1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GuideShape {
+    /// `[x0, y0, x1, y1]`
+    Rect([f32; 4]),
+    Cross,
+    /// Inner lines of a grid of equal cells
+    Grid { columns: u32, rows: u32 },
+}
+
+impl Guide {
+    /// The guides shown when none are configured.
+    pub fn defaults() -> Vec<Guide> {
+        vec![
+            Guide::Aspect { width: 16.0, height: 9.0 },
+            Guide::Aspect { width: 4.0, height: 3.0 },
+            Guide::ActionSafe,
+            Guide::TitleSafe,
+            Guide::Center,
+        ]
+    }
+
+    /// Shape of the guide on an image of `aspect` (displayed width / height).
+    pub fn shape(&self, aspect: f32) -> GuideShape {
+        match *self {
+            Guide::Aspect { width, height } => {
+                let ratio = width / height;
+                if ratio < aspect {
+                    // Narrower than the image: full height, pillarboxed
+                    let margin = (1.0 - ratio / aspect) / 2.0;
+                    GuideShape::Rect([margin, 0.0, 1.0 - margin, 1.0])
+                } else {
+                    let margin = (1.0 - aspect / ratio) / 2.0;
+                    GuideShape::Rect([0.0, margin, 1.0, 1.0 - margin])
+                }
+            }
+            Guide::Center => GuideShape::Cross,
+            Guide::ActionSafe => GuideShape::Rect([0.05, 0.05, 0.95, 0.95]),
+            Guide::TitleSafe => GuideShape::Rect([0.1, 0.1, 0.9, 0.9]),
+            Guide::Safe { margin } => {
+                let m = margin / 100.0;
+                GuideShape::Rect([m, m, 1.0 - m, 1.0 - m])
+            }
+            Guide::Thirds => GuideShape::Grid { columns: 3, rows: 3 },
+            Guide::Grid { columns, rows } => GuideShape::Grid { columns, rows },
+        }
+    }
+}
+
+impl fmt::Display for Guide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Guide::Aspect { width, height } => write!(f, "{width}:{height}"),
+            Guide::Center => f.write_str("center"),
+            Guide::ActionSafe => f.write_str("action_safe"),
+            Guide::TitleSafe => f.write_str("title_safe"),
+            Guide::Safe { margin } => write!(f, "safe:{margin}"),
+            Guide::Thirds => f.write_str("thirds"),
+            Guide::Grid { columns, rows } => write!(f, "grid:{columns}x{rows}"),
+        }
+    }
+}
+
+impl FromStr for Guide {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, ConfigError> {
+        match s {
+            "center" => return Ok(Guide::Center),
+            "action_safe" => return Ok(Guide::ActionSafe),
+            "title_safe" => return Ok(Guide::TitleSafe),
+            "thirds" => return Ok(Guide::Thirds),
+            _ => {}
+        }
+        if let Some(margin) = s.strip_prefix("safe:") {
+            return match margin.trim().parse::<f32>() {
+                Ok(margin) if (0.0..50.0).contains(&margin) => Ok(Guide::Safe { margin }),
+                _ => Err(ConfigError::Invalid(format!("Bad safe area {s:?} (expected a margin in percent below 50)"))),
+            };
+        }
+        if let Some(cells) = s.strip_prefix("grid:") {
+            let parsed = cells.split_once('x').and_then(|(c, r)| Some((c.trim().parse::<u32>().ok()?, r.trim().parse::<u32>().ok()?)));
+            return match parsed {
+                Some((columns, rows)) if columns > 0 && rows > 0 && columns.max(rows) <= 100 => Ok(Guide::Grid { columns, rows }),
+                _ => Err(ConfigError::Invalid(format!("Bad grid {s:?} (expected grid:COLUMNSxROWS, up to 100 each)"))),
+            };
+        }
+        let (w, h) = s.split_once(':').ok_or_else(|| {
+            ConfigError::Invalid(format!("Unknown guide {s:?} (expected W:H, center, action_safe, title_safe, safe:N, thirds or grid:CxR)"))
+        })?;
+        let parse = |v: &str| v.trim().parse::<f32>().ok().filter(|v| *v > 0.0 && v.is_finite());
+        match (parse(w), parse(h)) {
+            (Some(width), Some(height)) => Ok(Guide::Aspect { width, height }),
+            _ => Err(ConfigError::Invalid(format!("Bad aspect ratio guide {s:?}"))),
+        }
+    }
+}
+
+impl TryFrom<String> for Guide {
+    type Error = ConfigError;
+
+    fn try_from(s: String) -> Result<Self, ConfigError> {
+        s.parse()
+    }
+}
+
+impl From<Guide> for String {
+    fn from(guide: Guide) -> Self {
+        guide.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guides_round_trip_through_strings() {
+        for s in ["16:9", "2.39:1", "center", "action_safe", "title_safe", "safe:7.5", "thirds", "grid:4x3"] {
+            assert_eq!(s.parse::<Guide>().unwrap().to_string(), s);
+        }
+        assert!("16x9".parse::<Guide>().is_err());
+        assert!("0:1".parse::<Guide>().is_err());
+        assert!("safe:50".parse::<Guide>().is_err());
+        assert!("grid:0x2".parse::<Guide>().is_err());
+        assert_eq!("safe:20".parse::<Guide>().unwrap().shape(1.0), GuideShape::Rect([0.2, 0.2, 0.8, 0.8]));
+        assert_eq!(Guide::Thirds.shape(1.0), GuideShape::Grid { columns: 3, rows: 3 });
+    }
+
+    #[test]
+    fn aspect_guides_letterbox_or_pillarbox() {
+        let wide = Guide::Aspect { width: 2.0, height: 1.0 };
+        assert_eq!(wide.shape(1.0), GuideShape::Rect([0.0, 0.25, 1.0, 0.75]));
+        let square = Guide::Aspect { width: 1.0, height: 1.0 };
+        assert_eq!(square.shape(2.0), GuideShape::Rect([0.25, 0.0, 0.75, 1.0]));
+    }
+}
@@ -0,0 +1,298 @@
+//! Worker serving [`RemoteWorkerRequest`]s for sequences on an HTTP(S) server, so the
+//! cache, bounds search and follow mode treat them like SSH sequences. Paths in the
+//! requests are full URLs (see [`SequenceSource::worker_path`]).
+use std::io::{ErrorKind, Read};
+use std::sync::{
+    Arc, Mutex, PoisonError,
+    atomic::Ordering,
+    mpsc::{channel, Receiver, Sender},
+};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, debug_span, info, warn};
+
+use crate::bounds::{discover_bounds, SequenceBounds};
+use crate::error::SshError;
+use crate::remote_worker::{ConnectionState, Download, PartialCat, RemoteRange, RemoteStats, RemoteWorkerRequest, PARTIAL_INTERVAL};
+use crate::sequence::{SequenceSource, SequenceSpec};
+use crate::throttle::{Throttle, Transfer};
+
+type Result<T> = std::result::Result<T, SshError>;
+
+/// Bodies are read in chunks of this size, reporting progress after each
+const CHUNK: usize = 64 * 1024;
+
+/// Times a dropped download is resumed with a range request before giving up
+const MAX_RESUMES: usize = 3;
+
+/// Spawn the HTTP worker threads for the server at `base_url` and return the request
+/// sender. Like [`spawn_remote_worker`](crate::remote_worker::spawn_remote_worker),
+/// existence checks get a thread of their own (HEAD requests) while `transfer_channels`
+/// threads download frames (GET) in parallel, sharing one connection pool.
+///
+/// Downloads stream in, with [`PartialCat`]s for progressive display; a download that
+/// breaks off is resumed with a range request where the server supports them. Downloads
+/// of frames other than the one on screen are held to the limits of `throttle`.
+pub fn spawn_http_worker(
+    base_url: &str,
+    range: RemoteRange,
+    stats: RemoteStats,
+    transfer_channels: usize,
+    throttle: Throttle,
+) -> Sender<RemoteWorkerRequest> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(5))
+        .timeout_read(Duration::from_secs(30))
+        .user_agent(concat!("zapvis/", env!("CARGO_PKG_VERSION")))
+        .build();
+    stats.set_host(base_url);
+    stats.set_state(ConnectionState::Connected);
+
+    let (tx, rx) = channel::<RemoteWorkerRequest>();
+    let (control_tx, control_rx) = channel::<RemoteWorkerRequest>();
+    let (transfer_tx, transfer_rx) = channel::<RemoteWorkerRequest>();
+    let dispatch_stats = stats.clone();
+    thread::spawn(move || {
+        while let Ok(req) = rx.recv() {
+            let routed = match req {
+                // There is no session to drop; the pool reconnects by itself
+                RemoteWorkerRequest::Reconnect => continue,
+                RemoteWorkerRequest::Cat { .. } => transfer_tx.send(req),
+                _ => control_tx.send(req),
+            };
+            dispatch_stats.queued.fetch_add(1, Ordering::Relaxed);
+            if routed.is_err() {
+                break;
+            }
+        }
+    });
+
+    let control_rx = Arc::new(Mutex::new(control_rx));
+    let transfer_rx = Arc::new(Mutex::new(transfer_rx));
+    for slot in 0..=transfer_channels.max(1) {
+        let rx = if slot == 0 { control_rx.clone() } else { transfer_rx.clone() };
+        let (agent, range, stats, throttle) = (agent.clone(), range.clone(), stats.clone(), throttle.clone());
+        thread::spawn(move || run_worker(&agent, &rx, &range, &stats, &throttle));
+    }
+    tx
+}
+
+fn run_worker(agent: &ureq::Agent, rx: &Mutex<Receiver<RemoteWorkerRequest>>, range: &RemoteRange, stats: &RemoteStats, throttle: &Throttle) {
+    loop {
+        let next = rx.lock().unwrap_or_else(PoisonError::into_inner).recv();
+        let Ok(req) = next else {
+            break;
+        };
+        stats.queued.fetch_sub(1, Ordering::Relaxed);
+        match req {
+            RemoteWorkerRequest::Reconnect => {}
+            RemoteWorkerRequest::Exists { path, response_tx } => {
+                let _span = debug_span!("head", url = %path).entered();
+                let _ = response_tx.send(exists(agent, stats, &path));
+            }
+            RemoteWorkerRequest::Resolve { dir, response_tx } => {
+                let _ = response_tx.send(Err(SshError::Protocol(format!("{dir:?} is not a URL"))));
+            }
+            RemoteWorkerRequest::FindBounds { seq, response_tx } => {
+                let _span = debug_span!("find_bounds", index = seq.index).entered();
+                let result = find_bounds(agent, stats, &seq);
+                if let Err(e) = &result {
+                    warn!("bounds of {} not found: {}", seq.source.location(), e);
+                }
+                let _ = response_tx.send(result);
+            }
+            RemoteWorkerRequest::Cat { idx, path, response_tx, partial_tx } => {
+                let transfer = throttle.start(range.is_current(idx), || range.contains(idx));
+                let Some(mut transfer) = transfer.filter(|_| range.contains(idx)) else {
+                    debug!("get SKIP idx={} (out of range)", idx);
+                    stats.skipped.fetch_add(1, Ordering::Relaxed);
+                    let _ = response_tx.send(Err(SshError::Cancelled));
+                    continue;
+                };
+                let _span = debug_span!("get", idx, url = %path).entered();
+                let result = get(agent, stats, idx, &path, partial_tx.as_ref(), &mut transfer);
+                stats.set_download(idx, None);
+                match &result {
+                    Ok(bytes) => {
+                        debug!("received {} bytes", bytes.len());
+                        stats.bytes.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+                    }
+                    Err(SshError::NotFound(_)) => debug!("not found"),
+                    Err(e) => warn!("get {} failed: {}", path, e),
+                }
+                let _ = response_tx.send(result);
+            }
+        }
+    }
+    info!("HTTP worker exiting");
+}
+
+/// HEAD `url`: 2xx exists, 404/410 does not. Servers that refuse HEAD get a one-byte GET.
+fn exists(agent: &ureq::Agent, stats: &RemoteStats, url: &str) -> Result<bool> {
+    let started = Instant::now();
+    let response = match call(stats, url, agent.head(url)) {
+        Err(SshError::Http { .. }) => call(stats, url, agent.get(url).set("Range", "bytes=0-0")),
+        response => response,
+    };
+    match response {
+        Ok(_) => {
+            stats.set_latency(started.elapsed());
+            Ok(true)
+        }
+        Err(SshError::NotFound(_)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn find_bounds(agent: &ureq::Agent, stats: &RemoteStats, seq: &SequenceSpec) -> Result<SequenceBounds> {
+    if !matches!(seq.source, SequenceSource::Http { .. }) {
+        return Err(SshError::Protocol("bounds requested for a sequence not on an HTTP server".to_string()));
+    }
+    discover_bounds(seq.index, seq.min_index(), |idx| match seq.source.worker_path(&seq.relative_path_for(idx)) {
+        Some(url) => exists(agent, stats, &url),
+        None => Ok(false),
+    })
+}
+
+/// GET `url`, reporting progress and partial bodies, resuming a broken download with
+/// `Range` requests when the server answers them with 206.
+fn get(
+    agent: &ureq::Agent,
+    stats: &RemoteStats,
+    idx: i64,
+    url: &str,
+    partial_tx: Option<&Sender<PartialCat>>,
+    transfer: &mut Transfer,
+) -> Result<Vec<u8>> {
+    let response = call(stats, url, agent.get(url))?;
+    let total = response.header("Content-Length").and_then(|n| n.parse::<usize>().ok());
+    let resumable = total.is_some() && response.header("Accept-Ranges") == Some("bytes");
+    let mut body = Vec::with_capacity(total.unwrap_or(CHUNK));
+    let mut reader = response.into_reader();
+    let mut last_partial = Instant::now();
+    let mut resumes = 0;
+    let mut chunk = vec![0u8; CHUNK];
+    loop {
+        let n = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) if resumable && resumes < MAX_RESUMES => {
+                resumes += 1;
+                warn!("download broke off after {} bytes ({}), resuming", body.len(), e);
+                let range = format!("bytes={}-", body.len());
+                let response = call(stats, url, agent.get(url).set("Range", &range))?;
+                if response.status() != 206 {
+                    return Err(http_error(url, "server ignored the range request"));
+                }
+                reader = response.into_reader();
+                continue;
+            }
+            Err(e) => return Err(http_error(url, &e.to_string())),
+        };
+        body.extend_from_slice(&chunk[..n]);
+        transfer.progress(body.len(), total.unwrap_or(body.len()));
+        let Some(total) = total else {
+            continue;
+        };
+        stats.set_download(idx, Some(Download {
+            idx,
+            received: body.len(),
+            total,
+        }));
+        if let Some(tx) = partial_tx {
+            if body.len() < total && last_partial.elapsed() >= PARTIAL_INTERVAL {
+                last_partial = Instant::now();
+                let _ = tx.send(PartialCat {
+                    bytes: body.clone(),
+                    total,
+                });
+            }
+        }
+    }
+    if total.is_some_and(|total| body.len() != total) {
+        return Err(http_error(url, &format!("got {} of {} bytes", body.len(), total.unwrap_or(0))));
+    }
+    Ok(body)
+}
+
+/// Send `request`, counting it, and map error statuses: 404/410 to [`SshError::NotFound`],
+/// others and transport failures to [`SshError::Http`].
+fn call(stats: &RemoteStats, url: &str, request: ureq::Request) -> Result<ureq::Response> {
+    stats.requests.fetch_add(1, Ordering::Relaxed);
+    match request.call() {
+        Ok(response) => {
+            stats.set_state(ConnectionState::Connected);
+            Ok(response)
+        }
+        Err(ureq::Error::Status(404 | 410, _)) => {
+            stats.set_state(ConnectionState::Connected);
+            Err(SshError::NotFound(url.to_string()))
+        }
+        Err(ureq::Error::Status(status, response)) => {
+            stats.errors.fetch_add(1, Ordering::Relaxed);
+            Err(http_error(url, &format!("{} {}", status, response.status_text())))
+        }
+        Err(ureq::Error::Transport(e)) => {
+            stats.errors.fetch_add(1, Ordering::Relaxed);
+            stats.set_state(ConnectionState::Failed(e.to_string()));
+            Err(http_error(url, &e.to_string()))
+        }
+    }
+}
+
+fn http_error(url: &str, message: &str) -> SshError {
+    SshError::Http {
+        url: url.to_string(),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Serves `frame_0001.png` .. `frame_0003.png` (their names as contents) over plain HTTP
+    fn serve_frames() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming().map_while(|s| s.ok()) {
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                reader.read_line(&mut request_line).unwrap();
+                // Skip the headers
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                    line.clear();
+                }
+                let mut parts = request_line.split_whitespace();
+                let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+                let name = path.trim_start_matches("/frames/");
+                let found = ["frame_0001.png", "frame_0002.png", "frame_0003.png"].contains(&name);
+                let (status, body) = if found { ("200 OK", name) } else { ("404 Not Found", "") };
+                let mut stream = stream;
+                let _ = write!(stream, "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+                if method == "GET" {
+                    let _ = stream.write_all(body.as_bytes());
+                }
+            }
+        });
+        format!("http://{addr}/frames")
+    }
+
+    #[test]
+    fn frames_are_fetched_and_probed_over_http() {
+        let base_url = serve_frames();
+        let tx = spawn_http_worker(&base_url, RemoteRange::new(), RemoteStats::new(), 1, Throttle::default());
+        let seq = SequenceSpec::from_pattern("frame_####.png", SequenceSource::Http { base_url }, 2).unwrap();
+        assert_eq!(seq.fetch_bytes(1, Some(&tx)).unwrap(), b"frame_0001.png");
+        assert!(seq.fetch_bytes(7, Some(&tx)).unwrap_err().is_not_found());
+        assert!(seq.exists_with_ssh(3, Some(tx.clone())).unwrap());
+        assert!(!seq.exists_with_ssh(4, Some(tx.clone())).unwrap());
+        let bounds = seq.find_bounds(Some(&tx)).unwrap();
+        assert_eq!((bounds.first, bounds.last), (Some(1), Some(3)));
+    }
+}
@@ -1,251 +1,248 @@
-use anyhow::{anyhow, Context, Result};
-use egui::TextureHandle;
-use image::RgbaImage;
-use std::collections::{BTreeMap, HashSet};
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::thread;
-
-use crate::image_util::{load_image_rgba, load_image_rgba_from_bytes, rgba_to_texture};
-use crate::remote_worker::{RemoteRange, RemoteWorkerRequest};
-use crate::sequence::{SequenceSource, SequenceSpec};
-
-// Load request for the single background loader thread
-#[derive(Clone)]
-struct LoadRequest {
-    idx: u64,
-    file_name: String,
-    seq_source: SequenceSource,
-    request_tx: Option<Sender<RemoteWorkerRequest>>,
-}
-
-/// Bidirectional image cache with configurable radius.
-/// Maintains textures for indices in range [current - radius, current + radius].
-/// Uses a single background loader thread with a queue for image decoding.
+use egui::{TextureHandle, TextureOptions};
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+use crate::image_util::{frame_to_color_image, frame_to_texture};
+use crate::tiles::TiledFrames;
+use zapvis::frame::{DecodeOptions, FramePixels};
+use zapvis::frame_cache::FrameCache;
+use zapvis::remote_worker::{RemoteRange, RemoteWorkerRequest};
+use zapvis::sequence::{SequenceSource, SequenceSpec};
+
+/// Textures for the frames of a [`FrameCache`].
+///
+/// Each cached frame (preview or full resolution) gets a texture, tagged with the
+/// pixels it was uploaded from so it is replaced when the full tier arrives and
+/// dropped when the frame is evicted. Everything else is the frame cache's and is
+/// reached through `Deref`.
+///
+/// The loader threads request a repaint whenever a load settles, so frames show up as
+/// soon as they are decoded rather than with the next input event.
+///
+/// Textures of dropped frames go to a [`TexturePool`] and are overwritten with the next
+/// frames of the same size, so steady scrubbing does not allocate GPU memory.
+///
+/// Frames too large for one texture are [tiled](crate::tiles): their texture is a
+/// downscaled overview (the preview's stays until it is rendered), and [`Self::tiles`]
+/// adds the detail the zoom asks for.
 pub struct ImageCache {
-    cache: BTreeMap<u64, TextureHandle>,
-    cache_radius: usize,
-    step_size: u64,
-    pending_loads: HashSet<u64>,
-    load_request_tx: Sender<LoadRequest>,
-    result_rx: Receiver<(u64, RgbaImage)>,
-    seq_source: SequenceSource,
-    request_tx: Option<Sender<RemoteWorkerRequest>>,
-    remote_range: Option<RemoteRange>,
+    frames: FrameCache,
+    textures: BTreeMap<i64, (Arc<FramePixels>, TextureHandle)>,
+    pool: TexturePool,
+    tiled: TiledFrames,
+    /// Filtering of the uploaded textures: linear, or nearest for a pixel-exact view
+    texture_options: TextureOptions,
 }
 
 impl ImageCache {
     pub fn new(
+        ctx: &egui::Context,
         cache_radius: usize,
         seq_source: SequenceSource,
         request_tx: Option<Sender<RemoteWorkerRequest>>,
         remote_range: Option<RemoteRange>,
+        preview_max_dim: u32,
+        decode_opts: DecodeOptions,
     ) -> Self {
-        let (load_request_tx, load_request_rx) = channel::<LoadRequest>();
-        let (result_tx, result_rx) = channel::<(u64, RgbaImage)>();
-
-        // Spawn single loader thread that processes requests from queue
-        thread::spawn(move || {
-            while let Ok(req) = load_request_rx.recv() {
-                // Wrap in closure that returns Result to use ?
-                let rgba: Result<RgbaImage> = (|| {
-                    match &req.seq_source {
-                        SequenceSource::Local(dir) => {
-                            load_image_rgba(&dir.join(&req.file_name))
-                        }
-                        SequenceSource::Remote { user_host, dir } => {
-                            let remote_path = crate::sequence::build_remote_path(dir, &req.file_name);
-                            if let Some(tx) = &req.request_tx {
-                                let (response_tx, response_rx) = channel();
-                                eprintln!("[SSH] cat: {} (idx={})", remote_path, req.idx);
-                                tx.send(RemoteWorkerRequest::Cat {
-                                    idx: req.idx,
-                                    path: remote_path.clone(),
-                                    response_tx,
-                                }).context("Failed to send CAT request")?;
-                                let bytes = response_rx.recv().context("remote worker hung up")??;
-                                eprintln!("[SSH] cat received {} bytes (idx={})", bytes.len(), req.idx);
-                                load_image_rgba_from_bytes(&bytes, &format!("{}:{}", user_host, remote_path))
-                            } else {
-                                Err(anyhow!("SSH connection not available for background loading"))
-                            }
-                        }
-                    }
-                })();
-
-                if let Ok(rgba) = rgba {
-                    let _ = result_tx.send((req.idx, rgba));
-                }
-            }
-        });
-
+        let mut frames = FrameCache::new(cache_radius, seq_source, request_tx, remote_range, preview_max_dim, decode_opts);
+        let wake_ctx = ctx.clone();
+        frames.set_wake(Arc::new(move || wake_ctx.request_repaint()));
         Self {
-            cache: BTreeMap::new(),
-            cache_radius,
-            step_size: 1,
-            pending_loads: HashSet::new(),
-            load_request_tx,
-            result_rx,
-            seq_source,
-            request_tx,
-            remote_range,
+            frames,
+            textures: BTreeMap::new(),
+            pool: TexturePool::default(),
+            tiled: TiledFrames::new(ctx),
+            texture_options: TextureOptions::LINEAR,
+        }
+    }
+
+    /// Upload the cached frames again with `options` if they were uploaded with others
+    pub fn set_texture_options(&mut self, ctx: &egui::Context, options: TextureOptions) {
+        if self.texture_options != options {
+            self.texture_options = options;
+            self.textures.clear();
+            self.pool.clear();
+            self.tiled.clear_tiles();
+            self.sync_textures(ctx);
         }
     }
 
-    /// Get texture for specific index if cached
-    pub fn get(&self, idx: u64) -> Option<&TextureHandle> {
-        self.cache.get(&idx)
+    /// Get texture for specific index if cached (full resolution, else the preview)
+    pub fn get(&self, idx: i64) -> Option<&TextureHandle> {
+        self.textures.get(&idx).map(|(_, tex)| tex)
     }
 
-    /// Clear cache except for the current index and set new step size
-    pub fn clear_except_current(&mut self, current_idx: u64) {
-        // Keep only the current index
-        self.cache.retain(|&idx, _| idx == current_idx);
-        // Clear pending loads
-        self.pending_loads.clear();
-        eprintln!("[Cache] cleared except idx={}", current_idx);
+    /// Pixels the texture of `idx` shows: the overview of a tiled frame, else the frame's
+    pub fn texture_pixels(&self, idx: i64) -> Option<&Arc<FramePixels>> {
+        match self.tiled.is_tiled(idx) {
+            true => self.tiled.overview(idx),
+            false => self.frames.pixels(idx),
+        }
     }
 
-    /// Set the step size for cache filling
-    pub fn set_step_size(&mut self, step: u64) {
-        self.step_size = step;
+    pub fn is_tiled(&self, idx: i64) -> bool {
+        self.tiled.is_tiled(idx)
     }
 
-    /// Process any decoded images from background loader thread (convert to textures)
-    fn process_decoded_images(&mut self, ctx: &egui::Context) -> usize {
-        let mut converted = 0;
-        // Process all available decoded images (non-blocking)
-        while let Ok((idx, rgba_image)) = self.result_rx.try_recv() {
-            // Only insert if this idx is still pending (i.e., not evicted out-of-range)
-            if self.pending_loads.remove(&idx) {
-                let (w, h) = (rgba_image.width(), rgba_image.height());
-                if let Ok(tex) = rgba_to_texture(ctx, idx, rgba_image) {
-                    eprintln!("[Cache] loaded idx={} ({}x{})", idx, w, h);
-                    self.cache.insert(idx, tex);
-                    converted += 1;
+    /// Tiles of the tiled frame `idx` to draw over its texture, where `visible` (frame
+    /// pixels, `[x0, y0, x1, y1]`) is shown at `scale` screen pixels per frame pixel.
+    /// Empty while the overview is detailed enough; see [`TiledFrames::tiles`].
+    pub fn tiles(&mut self, idx: i64, visible: [f32; 4], scale: f32) -> Vec<([u32; 4], egui::TextureId)> {
+        self.tiled.tiles(idx, visible, scale)
+    }
+
+    /// Size at which the image for `idx` should be laid out (full resolution, even for previews)
+    pub fn display_size(&self, idx: i64) -> Option<egui::Vec2> {
+        self.frames.full_size(idx).map(|(w, h)| egui::vec2(w as f32, h as f32))
+    }
+
+    /// GPU memory taken by the textures (spare ones included), assuming 4 bytes per pixel
+    pub fn texture_bytes(&self) -> usize {
+        let bytes = |tex: &TextureHandle| tex.size().iter().product::<usize>() * 4;
+        let spare: usize = self.pool.spare.iter().map(bytes).sum();
+        self.textures.values().map(|(_, tex)| bytes(tex)).sum::<usize>() + spare + self.tiled.texture_bytes()
+    }
+
+    /// Upload textures for newly cached frames and drop those of evicted or replaced ones
+    fn sync_textures(&mut self, ctx: &egui::Context) {
+        let Self { frames, textures, pool, tiled, .. } = self;
+        tiled.sync(ctx, frames.frames().filter(|&(idx, _)| !frames.is_preview(idx)));
+        textures.retain(|&idx, (pixels, tex)| {
+            // A tiled frame keeps showing its preview until the overview is ready
+            let current = frames.pixels(idx).is_some_and(|p| Arc::ptr_eq(p, pixels))
+                || (tiled.is_tiled(idx) && tiled.overview(idx).is_none());
+            if !current {
+                pool.put(tex.clone());
+            }
+            current
+        });
+        for (idx, source, overview) in tiled.overviews() {
+            if let Entry::Vacant(slot) = textures.entry(idx) {
+                if let Ok(texture) = pool.upload(ctx, &format!("zapvis_overview_{idx}"), overview, self.texture_options) {
+                    slot.insert((source.clone(), texture));
                 }
             }
         }
-        converted
+        for (idx, pixels) in frames.frames() {
+            if textures.contains_key(&idx) || tiled.is_tiled(idx) {
+                continue;
+            }
+            let name = if frames.is_preview(idx) {
+                format!("zapvis_preview_{idx}")
+            } else {
+                format!("zapvis_image_{idx}")
+            };
+            if let Ok(texture) = pool.upload(ctx, &name, pixels, self.texture_options) {
+                textures.insert(idx, (pixels.clone(), texture));
+            }
+        }
+        pool.trim();
     }
 
     /// Update cache centered on new_index, preloading neighbors and evicting out-of-range entries
-    pub fn update_for_index(
-        &mut self,
-        new_index: u64,
-        seq: &SequenceSpec,
-        ctx: &egui::Context,
-    ) -> (usize, usize) {
-        // First, process any decoded images waiting to become textures
-        self.process_decoded_images(ctx);
-
-        let radius = self.cache_radius as u64;
-        let step = self.step_size;
-        
-        // Calculate min/max indices based on step size
-        let min_idx = new_index.saturating_sub(radius * step);
-        let max_idx = new_index.saturating_add(radius * step);
-
-        // Update remote range for SSH worker to check
-        if let Some(r) = &self.remote_range {
-            r.set(min_idx, max_idx);
-        }
-
-        // Evict entries outside the desired range
-        let to_evict: Vec<u64> = self
-            .cache
-            .keys()
-            .filter(|&&idx| idx < min_idx || idx > max_idx)
-            .copied()
-            .collect();
+    pub fn update_for_index(&mut self, new_index: i64, seq: &SequenceSpec, ctx: &egui::Context) -> (usize, usize) {
+        let counts = self.frames.update_for_index(new_index, seq);
+        self.tiled.set_current(new_index);
+        self.sync_textures(ctx);
+        counts
+    }
 
-        let evicted_count = to_evict.len();
-        if evicted_count > 0 {
-            eprintln!("[Cache] evicted {} entries", evicted_count);
-        }
-        for idx in to_evict {
-            self.cache.remove(&idx);
+    /// Process any newly decoded images on each frame.
+    /// Returns the number of loads that settled (a preview or full texture, or a failure).
+    pub fn tick(&mut self, ctx: &egui::Context) -> usize {
+        let settled = self.frames.poll();
+        let overviews = self.tiled.poll(ctx, self.texture_options);
+        if settled > 0 || !overviews.is_empty() {
+            self.sync_textures(ctx);
         }
+        settled
+    }
+}
 
-        // Cancel pending loads outside range
-        self.pending_loads.retain(|&idx| idx >= min_idx && idx <= max_idx);
+/// Most textures of dropped frames kept for reuse
+const SPARE_TEXTURES: usize = 8;
 
-        // Generate indices to load using symmetric centered order
-        // Start with current index for immediate visual feedback on startup
-        // Order: current, i-s, i+s, i-2s, i+2s, i-3s, i+3s, ...
-        let mut indices_to_check = Vec::new();
-        // new_index is always in range by construction (it's the center), but check for clarity
-        if new_index >= min_idx && new_index <= max_idx {
-            indices_to_check.push(new_index);
-        }
-        for offset in 1..=radius {
-            // Add backward index (i - offset*step)
-            if let Some(back_idx) = new_index.checked_sub(offset * step) {
-                if back_idx >= min_idx {
-                    indices_to_check.push(back_idx);
-                }
-            }
-            // Add forward index (i + offset*step)
-            let forward_idx = new_index.saturating_add(offset * step);
-            if forward_idx <= max_idx {
-                indices_to_check.push(forward_idx);
-            }
-        }
+/// Textures no frame uses any more, waiting to be overwritten with a frame of their size
+#[derive(Default)]
+struct TexturePool {
+    /// Most recently dropped last
+    spare: Vec<TextureHandle>,
+}
 
-        // Launch background loads for missing entries
-        let mut launched_count = 0;
-        for idx in indices_to_check {
-            if !self.cache.contains_key(&idx) && !self.pending_loads.contains(&idx) {
-                // For local files: check existence directly. For remote: always try to load
-                let should_load = match &self.seq_source {
-                    SequenceSource::Local(dir) => dir.join(seq.file_name_for(idx)).exists(),
-                    SequenceSource::Remote { .. } => true,
-                };
-
-                if should_load {
-                    self.pending_loads.insert(idx);
-                    let file_name = seq.file_name_for(idx);
-                    let req = LoadRequest {
-                        idx,
-                        file_name,
-                        seq_source: self.seq_source.clone(),
-                        request_tx: self.request_tx.clone(),
-                    };
-                    let _ = self.load_request_tx.send(req);
-                    launched_count += 1;
-                }
+impl TexturePool {
+    fn put(&mut self, texture: TextureHandle) {
+        self.spare.push(texture);
+    }
+
+    /// Texture showing `pixels`: a spare one of the same size overwritten in place, or a
+    /// new one named `name`
+    fn upload(&mut self, ctx: &egui::Context, name: &str, pixels: &FramePixels, options: TextureOptions) -> anyhow::Result<TextureHandle> {
+        let (w, h) = pixels.dimensions();
+        let size = [w as usize, h as usize];
+        match self.spare.iter().rposition(|tex| tex.size() == size) {
+            Some(i) => {
+                let mut texture = self.spare.remove(i);
+                // A partial update writes into the existing texture instead of reallocating it
+                texture.set_partial([0, 0], frame_to_color_image(pixels), options);
+                Ok(texture)
             }
+            None => frame_to_texture(ctx, name, pixels, options),
         }
-
-        (launched_count, evicted_count)
     }
 
-    /// Process any newly decoded images on each frame
-    pub fn tick(&mut self, ctx: &egui::Context) {
-        self.process_decoded_images(ctx);
+    /// Free the spare textures beyond [`SPARE_TEXTURES`], oldest first
+    fn trim(&mut self) {
+        let excess = self.spare.len().saturating_sub(SPARE_TEXTURES);
+        self.spare.drain(..excess);
     }
 
-    pub fn cache_info(&self) -> String {
-        format!("Cache: {} loaded, {} pending", self.cache.len(), self.pending_loads.len())
+    fn clear(&mut self) {
+        self.spare.clear();
     }
+}
+
+impl Deref for ImageCache {
+    type Target = FrameCache;
 
-    pub fn is_pending(&self, idx: u64) -> bool {
-        self.pending_loads.contains(&idx)
+    fn deref(&self) -> &FrameCache {
+        &self.frames
     }
+}
 
-    pub fn is_empty(&self) -> bool {
-        self.cache.is_empty()
+impl DerefMut for ImageCache {
+    fn deref_mut(&mut self) -> &mut FrameCache {
+        &mut self.frames
     }
 }
 
-impl Drop for ImageCache {
-    fn drop(&mut self) {
-        // Clear pending loads and close loader channel
-        let pending_count = self.pending_loads.len();
-        if pending_count > 0 {
-            eprintln!("[Loader] cancelling {} pending loads", pending_count);
-        }
-        self.pending_loads.clear();
-        eprintln!("[Loader] exiting");
-        // Dropping load_request_tx will cause loader thread to exit
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GrayImage;
+
+    #[test]
+    fn spare_textures_of_the_same_size_are_reused() {
+        let ctx = egui::Context::default();
+        let frame = |w, h| FramePixels::Luma(GrayImage::new(w, h));
+        let mut pool = TexturePool::default();
+        let first = pool.upload(&ctx, "a", &frame(4, 2), TextureOptions::LINEAR).unwrap();
+        let id = first.id();
+        pool.put(first);
+        assert_ne!(pool.upload(&ctx, "b", &frame(2, 4), TextureOptions::LINEAR).unwrap().id(), id);
+        assert_eq!(pool.upload(&ctx, "c", &frame(4, 2), TextureOptions::LINEAR).unwrap().id(), id);
+        assert!(pool.spare.is_empty());
+
+
+        // Only the most recently dropped are kept
+        let textures: Vec<TextureHandle> = (1..=SPARE_TEXTURES as u32 + 3)
+            .map(|w| frame_to_texture(&ctx, "d", &frame(w, 1), TextureOptions::LINEAR).unwrap())
+            .collect();
+        textures.into_iter().for_each(|tex| pool.put(tex));
+        pool.trim();
+        assert_eq!(pool.spare.len(), SPARE_TEXTURES);
+        assert_eq!(pool.spare[0].size(), [4, 1]);
     }
 }
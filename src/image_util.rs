@@ -1,29 +1,30 @@
-use anyhow::{Context, Result};
-use egui::{ColorImage, TextureHandle};
+use anyhow::Result;
+use egui::{ColorImage, TextureHandle, TextureOptions};
 use image::RgbaImage;
-use std::path::Path;
+use zapvis::frame::FramePixels;
 
-/// Load and decode image to RGBA (can be done in background thread)
-pub fn load_image_rgba(path: &Path) -> Result<RgbaImage> {
-    let img = image::open(path)
-        .with_context(|| format!("image::open failed for {}", path.display()))?;
-    Ok(img.to_rgba8())
+/// Upload decoded frame pixels as a texture; grayscale is expanded to RGBA here.
+pub fn frame_to_texture(ctx: &egui::Context, name: &str, frame: &FramePixels, options: TextureOptions) -> Result<TextureHandle> {
+    Ok(ctx.load_texture(name, frame_to_color_image(frame), options))
 }
 
-pub fn load_image_rgba_from_bytes(bytes: &[u8], source: &str) -> Result<RgbaImage> {
-    let img = image::load_from_memory(bytes)
-        .with_context(|| format!("image::load_from_memory failed for {}", source))?;
-    Ok(img.to_rgba8())
+/// Decoded frame pixels as an egui image, for uploading into a new or existing texture
+pub fn frame_to_color_image(frame: &FramePixels) -> ColorImage {
+    match frame {
+        FramePixels::Rgba(img) => {
+            let (w, h) = img.dimensions();
+            ColorImage::from_rgba_unmultiplied([w as usize, h as usize], img.as_raw())
+        }
+        FramePixels::Luma(img) => {
+            let (w, h) = img.dimensions();
+            ColorImage::from_gray([w as usize, h as usize], img.as_raw())
+        }
+    }
 }
 
 /// Convert RgbaImage to egui TextureHandle (must be done on main thread with Context)
-pub fn rgba_to_texture(ctx: &egui::Context, idx: u64, rgba: RgbaImage) -> Result<TextureHandle> {
+pub fn rgba_to_texture(ctx: &egui::Context, name: &str, rgba: &RgbaImage, options: TextureOptions) -> Result<TextureHandle> {
     let (w, h) = rgba.dimensions();
-    let pixels = rgba.into_raw();
-    let color_image = ColorImage::from_rgba_unmultiplied([w as usize, h as usize], &pixels);
-    Ok(ctx.load_texture(
-        format!("zapvis_image_{idx}"),
-        color_image,
-        egui::TextureOptions::LINEAR,
-    ))
+    let color_image = ColorImage::from_rgba_unmultiplied([w as usize, h as usize], rgba.as_raw());
+    Ok(ctx.load_texture(name, color_image, options))
 }
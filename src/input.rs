@@ -0,0 +1,415 @@
+//! Resolving the command-line input (frame file or manifest) to a sequence, shared by
+//! the viewer and the headless subcommands.
+use anyhow::{anyhow, Context, Result};
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, IsTerminal, Read};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use tracing::{info, warn};
+use zapvis::config::{maybe_add_pattern, pattern_matches_file, save_config, Config, PatternScope};
+use zapvis::dir_config::{load_dir_config, load_dir_config_at, DirConfig, DIR_CONFIG_FILE};
+use zapvis::error::{ConfigError, PatternError};
+use zapvis::http_worker::spawn_http_worker;
+use zapvis::manifest::{is_manifest_path, load_manifest, Manifest};
+use zapvis::recent::{record_recent, recent_path, RecentEntry};
+use zapvis::remote_worker::{spawn_remote_worker, RemoteHost, RemoteRange, RemoteStats, RemoteWorkerRequest};
+use zapvis::sequence::{
+    expand_local_path, file_name_from_path, file_name_from_str_path, infer_sequence, is_http_url, match_index,
+    matching_sequences, parse_http_input, parse_remote_input, InputSpec, SequenceSource, SequenceSpec,
+};
+use zapvis::animation::{probe_animation, ANIMATION_PATTERN};
+use zapvis::video::{is_video_path, probe_video, VIDEO_PATTERN};
+use zapvis::PersistentSsh;
+
+/// The sequences an input resolves to, plus the remote worker serving them
+pub struct OpenedInput {
+    pub manifest: Option<Manifest>,
+    /// Matching (pattern, sequence) pairs; the first one is used
+    pub candidates: Vec<(String, SequenceSpec)>,
+    /// The pattern was inferred from the filename rather than configured
+    pub inferred: bool,
+    /// Display settings from the `.zapvis.toml` next to the frames, if any
+    pub dir_config: Option<DirConfig>,
+    pub remote_tx: Option<Sender<RemoteWorkerRequest>>,
+    pub remote_range: RemoteRange,
+    pub remote_stats: RemoteStats,
+}
+
+/// Neither a configured nor an inferred pattern fits the input. The viewer catches it
+/// to let the user build a pattern; the other commands quit with it.
+#[derive(Debug)]
+pub struct NoPatternMatched {
+    pub input: InputSpec,
+    /// Worker for existence checks of remote frames
+    pub request_tx: Option<Sender<RemoteWorkerRequest>>,
+}
+
+impl fmt::Display for NoPatternMatched {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No sequence pattern matched. Quitting.")
+    }
+}
+
+impl std::error::Error for NoPatternMatched {}
+
+/// Resolve `input` to its sequences, spawning the remote worker for remote inputs.
+/// A `pattern` override that matches the input is saved to the config.
+pub fn open_input(input: &str, pattern: Option<String>, cfg: &mut Config) -> Result<OpenedInput> {
+    // A manifest names the sequence directly; any other input is a frame to match patterns against
+    let manifest = if is_manifest_path(input) {
+        Some(load_manifest(std::path::Path::new(input))?)
+    } else {
+        None
+    };
+    if manifest.is_none() {
+        let path = expand_local_path(input);
+        if is_video_path(&path) && path.is_file() {
+            return open_video(&path);
+        }
+    }
+    let manifest_seq = manifest.as_ref().map(Manifest::sequence).transpose()?;
+    let input_spec = match &manifest_seq {
+        Some(seq) => InputSpec {
+            file_name: seq.file_name_for(seq.index),
+            source: seq.source.clone(),
+        },
+        None => input_spec_for(input)?,
+    };
+    open_sequence(manifest, manifest_seq, input_spec, pattern, cfg)
+}
+
+/// Resolve the frame `input_spec` names like [`open_input`] does a frame file; the frame
+/// need not exist (one piped in on stdin stands in for it).
+pub fn open_input_spec(input_spec: InputSpec, pattern: Option<String>, cfg: &mut Config) -> Result<OpenedInput> {
+    open_sequence(None, None, input_spec, pattern, cfg)
+}
+
+fn open_sequence(
+    manifest: Option<Manifest>,
+    mut manifest_seq: Option<SequenceSpec>,
+    mut input_spec: InputSpec,
+    pattern: Option<String>,
+    cfg: &mut Config,
+) -> Result<OpenedInput> {
+    // If user provided --pattern, try it first and store it if it works.
+    if let Some(pat) = pattern.filter(|_| manifest.is_none()) {
+        if pattern_matches_file(&pat, &input_spec.file_name)? {
+            maybe_add_pattern(cfg, pat);
+            save_config(cfg).ok(); // ignore save errors (still can run)
+        } else {
+            return Err(anyhow!(
+                "Provided --pattern did not match the input filename. Nothing saved."
+            ));
+        }
+    }
+
+    // Spawn the remote worker early if remote; it establishes (and re-establishes) the SSH session
+    let remote_range = RemoteRange::new();
+    let remote_stats = RemoteStats::new();
+    let remote_worker_tx = spawn_source_worker(cfg, &input_spec.source, remote_range.clone(), remote_stats.clone());
+    // `user@host:renders/...` and `user@host:~/renders/...` are made absolute by the host
+    input_spec.source = input_spec.source.resolve_remote_dir(remote_worker_tx.as_ref()).context("Failed to resolve the remote directory")?;
+    if let Some(seq) = &mut manifest_seq {
+        seq.source = input_spec.source.clone();
+    }
+
+    // Settings the dataset owner keeps with the frames, read before matching since they
+    // may name the pattern (a manifest names its own)
+    let early_dir_config = match manifest {
+        Some(_) => None,
+        None => dir_config_or_default(load_dir_config_at(&input_spec.source, 0, remote_worker_tx.as_ref())),
+    };
+    // The directory's pattern is tried before the configured ones, without saving it
+    let lookup = match early_dir_config.as_ref().and_then(|d| d.pattern.as_ref()) {
+        Some(pat) if pattern_matches_file(pat, &input_spec.file_name).unwrap_or(false) => {
+            info!("trying the pattern {:?} of {} first", pat, DIR_CONFIG_FILE);
+            let mut lookup = cfg.clone();
+            lookup.patterns.retain(|p| p != pat);
+            lookup.patterns.insert(0, pat.clone());
+            let first = PatternScope {
+                priority: i32::MAX,
+                ..PatternScope::default()
+            };
+            lookup.pattern_scopes.insert(pat.clone(), first);
+            Cow::Owned(lookup)
+        }
+        Some(pat) => {
+            warn!("the pattern {:?} of {} does not match {:?}", pat, DIR_CONFIG_FILE, input_spec.file_name);
+            Cow::Borrowed(&*cfg)
+        }
+        None => Cow::Borrowed(&*cfg),
+    };
+
+    // Determine which pattern to use; fall back to inferring one from the filename.
+    let mut inferred = false;
+    let picked = match (&manifest, manifest_seq) {
+        (Some(m), Some(mut seq)) => {
+            if seq.shard.is_none() {
+                seq.shard = cfg.shards.get(&m.pattern).cloned();
+            }
+            Ok(vec![(m.pattern.clone(), seq)])
+        }
+        _ => matching_sequences(&lookup, &input_spec, remote_worker_tx.clone())
+        .and_then(|found| {
+            if found.is_empty() {
+                Err(PatternError::NoMatch)
+            } else {
+                Ok(found)
+            }
+        })
+        .or_else(|e| {
+            warn!("{e}");
+            info!("trying to infer a pattern from {:?}", input_spec.file_name);
+            let v = infer_sequence(&input_spec, remote_worker_tx.clone())?;
+            info!("inferred pattern {:?} (not saved; use the top bar to keep it)", v.0);
+            inferred = true;
+            anyhow::Ok(vec![v])
+        }),
+    };
+    let candidates = match picked {
+        Ok(v) => v,
+        Err(e) => {
+            // A lone animated GIF/PNG or multi-page TIFF is a sequence of its own frames
+            if let SequenceSource::Local(dir) = &input_spec.source {
+                let path = dir.join(&input_spec.file_name);
+                if let Some(info) = probe_animation(&path).context("Failed to read the input")? {
+                    return open_single_file(SequenceSource::Animation { path, info }, ANIMATION_PATTERN);
+                }
+            }
+            // Neither a configured nor an inferred pattern fits: quit.
+            warn!("{e}");
+            eprintln!("\nKnown patterns in config:");
+            for (i, p) in cfg.patterns.iter().enumerate() {
+                eprintln!("  {}) {}", i + 1, p);
+            }
+            eprintln!("\nTip: run with --pattern \"########_#.png\" to add/try a new one.");
+            return Err(NoPatternMatched {
+                input: input_spec,
+                request_tx: remote_worker_tx,
+            }
+            .into());
+        }
+    };
+
+    // A pattern with a folder part puts the frames in another directory than the input's
+    let seq = &candidates[0].1;
+    let dir_config = match manifest.is_none() && seq.source.location() == input_spec.source.location() {
+        true => early_dir_config,
+        false => dir_config_or_default(load_dir_config(seq, remote_worker_tx.as_ref())),
+    };
+    if dir_config.is_some() {
+        info!("using the {} in {}", DIR_CONFIG_FILE, seq.source.location());
+    }
+
+    Ok(OpenedInput {
+        manifest,
+        candidates,
+        inferred,
+        dir_config,
+        remote_tx: remote_worker_tx,
+        remote_range,
+        remote_stats,
+    })
+}
+
+/// A frame piped in on stdin (`zapvis view -`) and the frame of the sequence in `dir`
+/// it stands in for
+pub struct PipedFrame {
+    pub bytes: Vec<u8>,
+    pub spec: InputSpec,
+}
+
+/// Read the frame piped in on stdin and place it in the sequence `pattern` names in `dir`
+pub fn read_piped_frame(dir: &str, pattern: &str, index: Option<i64>) -> Result<PipedFrame> {
+    if io::stdin().is_terminal() {
+        return Err(anyhow!("Input `-` reads a frame from stdin, but nothing is piped in"));
+    }
+    let mut bytes = Vec::new();
+    io::stdin().read_to_end(&mut bytes).context("Failed to read the frame from stdin")?;
+    if bytes.is_empty() {
+        return Err(anyhow!("Nothing was piped in on stdin"));
+    }
+    let spec = piped_frame_spec(dir, pattern, index)?;
+    Ok(PipedFrame { bytes, spec })
+}
+
+/// Where frame `index` of the sequence `pattern` names in `dir` is, whether or not it
+/// exists. Without `index`, the first frame listed in a local `dir`, else frame 0.
+pub fn piped_frame_spec(dir: &str, pattern: &str, index: Option<i64>) -> Result<InputSpec> {
+    let local_dir = (!is_http_url(dir) && parse_remote_input(dir).is_none()).then(|| expand_local_path(dir));
+    let index = match (index, &local_dir) {
+        (Some(index), _) => index,
+        (None, Some(local_dir)) => std::fs::read_dir(local_dir)
+            .with_context(|| format!("Failed to list {}", local_dir.display()))?
+            .flatten()
+            .filter_map(|entry| match_index(pattern, entry.file_name().to_str()?).ok().flatten())
+            .min()
+            .unwrap_or(0),
+        (None, None) => 0,
+    };
+    let relative = SequenceSpec::from_pattern(pattern, SequenceSource::Local(".".into()), index)?.relative_path_for(index);
+    let path = match local_dir {
+        Some(local_dir) => local_dir.join(relative).to_string_lossy().into_owned(),
+        None => format!("{}/{}", dir.trim_end_matches('/'), relative),
+    };
+    frame_spec_for(&path)
+}
+
+/// Put the sequence first in the recent list, under an input that reopens it from any
+/// working directory
+pub fn remember_recent(input: &str, is_manifest: bool, pattern: &str, seq: &SequenceSpec) {
+    let input = match &seq.source {
+        _ if is_manifest => std::fs::canonicalize(input).map_or_else(|_| input.to_string(), |path| path.display().to_string()),
+        SequenceSource::Local(dir) => {
+            let dir = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.clone());
+            dir.join(seq.relative_path_for(seq.index)).display().to_string()
+        }
+        SequenceSource::Video { path, .. } | SequenceSource::Animation { path, .. } => path.display().to_string(),
+        SequenceSource::Remote { .. } | SequenceSource::Http { .. } => seq.path_display(seq.index),
+    };
+    let entry = RecentEntry {
+        input,
+        pattern: pattern.to_string(),
+        sequence_key: seq.sequence_key(pattern),
+    };
+    if let Err(e) = recent_path().and_then(|path| record_recent(&path, entry)) {
+        warn!("could not record the recent sequence: {e}");
+    }
+}
+
+/// A video file as a sequence of its frames, starting at the first
+fn open_video(path: &std::path::Path) -> Result<OpenedInput> {
+    let path = std::fs::canonicalize(path).with_context(|| format!("Video not found: {}", path.display()))?;
+    let info = probe_video(&path).context("Failed to open the video")?;
+    info!("{}: {} frames at {:.3} fps", path.display(), info.frames, info.fps());
+    open_single_file(SequenceSource::Video { path, info }, VIDEO_PATTERN)
+}
+
+/// A file holding all frames (video or animation) as the only candidate, at its first frame
+fn open_single_file(source: SequenceSource, pattern: &str) -> Result<OpenedInput> {
+    info!("opening the frames of {}", source.location());
+    let seq = SequenceSpec::from_pattern(pattern, source, 0)?;
+    let dir_config = dir_config_or_default(load_dir_config(&seq, None));
+    Ok(OpenedInput {
+        manifest: None,
+        candidates: vec![(pattern.to_string(), seq)],
+        inferred: false,
+        dir_config,
+        remote_tx: None,
+        remote_range: RemoteRange::new(),
+        remote_stats: RemoteStats::new(),
+    })
+}
+
+/// The directory settings found, if any; a broken file only costs the defaults
+fn dir_config_or_default(found: Result<Option<DirConfig>, ConfigError>) -> Option<DirConfig> {
+    found.unwrap_or_else(|e| {
+        warn!("ignoring {}: {:#}", DIR_CONFIG_FILE, e);
+        None
+    })
+}
+
+/// The worker fetching frames of `source`: the SSH worker, the HTTP worker, or none for local sources.
+pub fn spawn_source_worker(cfg: &Config, source: &SequenceSource, range: RemoteRange, stats: RemoteStats) -> Option<Sender<RemoteWorkerRequest>> {
+    match source {
+        SequenceSource::Remote { user_host, .. } => Some(spawn_worker(cfg, user_host, range, stats)),
+        SequenceSource::Http { base_url } => Some(spawn_http_worker(base_url, range, stats, cfg.remote_channels, cfg.transfer_throttle())),
+        SequenceSource::Local(_) | SequenceSource::Video { .. } | SequenceSource::Animation { .. } => None,
+    }
+}
+
+/// Remote worker for `user_host` (and its configured replicas), with the config's SSH settings.
+pub fn spawn_worker(cfg: &Config, user_host: &str, range: RemoteRange, stats: RemoteStats) -> Sender<RemoteWorkerRequest> {
+    // Primary first, then replicas holding the same dataset
+    let mut names = vec![user_host.to_string()];
+    names.extend(cfg.replicas_for(user_host));
+    let hosts = names
+        .into_iter()
+        .map(|name| {
+            let prefix = cfg.command_prefix_for(&name).map(str::to_string);
+            let target = name.clone();
+            let compression = cfg.remote_compression;
+            RemoteHost::new(name, move || {
+                info!("connecting to {}", target);
+                PersistentSsh::connect_with_prefix(&target, prefix.as_deref()).map(|s| s.with_compression(compression))
+            })
+        })
+        .collect();
+    let slow = (cfg.replica_slow_ms > 0).then(|| Duration::from_millis(cfg.replica_slow_ms));
+    spawn_remote_worker(hosts, range, stats, slow, cfg.stall_timeout(), cfg.remote_channels, cfg.transfer_throttle())
+}
+
+/// Frame to open from a local path (or `file://` URL), `http(s)://` URL or `user@host:path` argument.
+fn input_spec_for(input: &str) -> Result<InputSpec> {
+    let local = !is_http_url(input) && parse_remote_input(input).is_none();
+    if local && !expand_local_path(input).is_file() {
+        return Err(anyhow!(
+            "Input must be an image FILE path. Folder mode is intentionally not supported."
+        ));
+    }
+    frame_spec_for(input)
+}
+
+/// Directory and file name of the frame `input` names, without checking that it exists
+fn frame_spec_for(input: &str) -> Result<InputSpec> {
+    Ok(if is_http_url(input) {
+        let (base_url, file_name) = parse_http_input(input).ok_or_else(|| anyhow!("URL has no file name: {input}"))?;
+        InputSpec {
+            file_name,
+            source: SequenceSource::Http { base_url },
+        }
+    } else if let Some((user_host, remote_path)) = parse_remote_input(input) {
+        let file_name = file_name_from_str_path(&remote_path)?;
+        let dir = std::path::Path::new(&remote_path)
+            .parent()
+            .ok_or_else(|| anyhow!("Remote input has no parent directory"))?
+            .to_string_lossy()
+            .to_string();
+        InputSpec {
+            file_name,
+            source: SequenceSource::Remote { user_host, dir },
+        }
+    } else {
+        let input = expand_local_path(input);
+        let file_name = file_name_from_path(&input)?;
+        let dir = input
+            .parent()
+            .ok_or_else(|| anyhow!("Input has no parent directory"))?
+            .to_path_buf();
+        InputSpec {
+            file_name,
+            source: SequenceSource::Local(dir),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn piped_frames_need_no_file_on_disk() {
+        let dir = std::env::temp_dir().join(format!("zapvis_piped_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir_str = dir.to_str().unwrap();
+        // An empty directory starts at frame 0
+        let spec = piped_frame_spec(dir_str, "f_####.png", None).unwrap();
+        assert_eq!((spec.file_name.as_str(), &spec.source), ("f_0000.png", &SequenceSource::Local(dir.clone())));
+
+        std::fs::write(dir.join("f_0003.png"), b"").unwrap();
+        assert_eq!(piped_frame_spec(dir_str, "f_####.png", None).unwrap().file_name, "f_0003.png");
+        let spec = piped_frame_spec(dir_str, "f_####.png", Some(7)).unwrap();
+        assert_eq!(spec.file_name, "f_0007.png");
+        assert!(input_spec_for(&dir.join("f_0007.png").to_string_lossy()).is_err(), "a missing file is no input");
+
+        let mut cfg = Config {
+            patterns: vec!["f_####.png".to_string()],
+            ..Config::default()
+        };
+        let opened = open_input_spec(spec, None, &mut cfg).unwrap();
+        let (pattern, seq) = &opened.candidates[0];
+        assert_eq!((pattern.as_str(), seq.index), ("f_####.png", 7));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,39 @@
+use image::RgbaImage;
+
+/// Cross-fade standing in for a missing frame: `t` of the way from `before` to `after`
+/// (0 shows `before`). `None` if the frames differ in size.
+pub fn crossfade(before: &RgbaImage, after: &RgbaImage, t: f32) -> Option<RgbaImage> {
+    if before.dimensions() != after.dimensions() {
+        return None;
+    }
+    let t = t.clamp(0.0, 1.0);
+    let mut out = before.clone();
+    for (dst, src) in out.pixels_mut().zip(after.pixels()) {
+        for c in 0..4 {
+            dst[c] = (dst[c] as f32 + (src[c] as f32 - dst[c] as f32) * t).round() as u8;
+        }
+    }
+    Some(out)
+}
+
+/// Position of `idx` between the loaded frames `before` and `after`, for [`crossfade`]
+pub fn fade_position(before: i64, after: i64, idx: i64) -> f32 {
+    (idx - before) as f32 / (after - before).max(1) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn missing_frames_fade_between_their_neighbors() {
+        let before = RgbaImage::from_pixel(2, 2, Rgba([0, 100, 200, 255]));
+        let after = RgbaImage::from_pixel(2, 2, Rgba([100, 100, 0, 255]));
+        // Frame 13 of a sequence with only every 10th frame
+        let t = fade_position(10, 20, 13);
+        assert_eq!(crossfade(&before, &after, t).unwrap().get_pixel(1, 1).0, [30, 100, 140, 255]);
+        assert_eq!(crossfade(&before, &after, 0.0).unwrap(), before);
+        assert!(crossfade(&before, &RgbaImage::new(1, 2), 0.5).is_none());
+    }
+}
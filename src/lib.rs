@@ -1,2 +1,37 @@
+//! zapvis library: pattern-based sequence navigation and persistent-SSH frame access.
+//!
+//! Library users can resolve a sequence with [`sequence::pick_sequence`] and pull the
+//! raw, undecoded frame bytes with [`SequenceSpec::fetch_bytes`], reusing the same
+//! pattern and SSH machinery the viewer uses. [`frame::load_frame_from_bytes`] decodes
+//! them, and [`frame_cache::FrameCache`] keeps decoded frames around a moving index
+//! with the viewer's background loader, previews and eviction.
+pub mod animation;
+pub mod annotations;
+pub mod bookmarks;
+pub mod bounds;
+pub mod color;
+pub mod config;
+pub mod dir_config;
+pub mod error;
+pub mod frame;
+pub mod follow;
+pub mod frame_cache;
+pub mod guides;
+pub mod http_worker;
+pub mod lint;
+pub mod lut;
+pub mod manifest;
+pub mod mirror;
+pub mod orientation;
 pub mod persistent_ssh;
+pub mod radius;
+pub mod recent;
+pub mod region;
+pub mod remote_worker;
+pub mod sequence;
+pub mod session;
+pub mod throttle;
+pub mod video;
+pub mod watchdog;
 pub use persistent_ssh::PersistentSsh;
+pub use sequence::{SequenceSource, SequenceSpec};
@@ -0,0 +1,299 @@
+//! Checks of the config file behind `zapvis config validate`: mistakes that would
+//! otherwise only show at runtime as a pattern that never matches, a sequence opening
+//! with the wrong pattern, or an SSH setting that is silently not applied.
+use serde::Serialize;
+use std::fmt;
+
+use crate::config::Config;
+use crate::sequence::{compile_pattern, match_index, SequenceSource, SequenceSpec};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Works, but probably not as meant
+    Warning,
+    /// Fails to load, or is ignored at runtime
+    Error,
+}
+
+/// One problem found in the config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Line (1-based) and column of the offending value, where it could be located
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "{line}:{column}: ")?,
+            (Some(line), None) => write!(f, "{line}: ")?,
+            _ => {}
+        }
+        let label = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{label}: {}", self.message)
+    }
+}
+
+/// Indices the patterns are tried on when looking for shadowing: a pattern matching the
+/// file names another one gives for all of them hides it
+const SAMPLE_INDICES: [i64; 3] = [0, 7, 42];
+
+/// Check the config file content `text`. A file that does not parse yields that one
+/// finding; otherwise the patterns, the tables keyed by pattern and the SSH settings are
+/// checked. Findings come in file order where located.
+pub fn lint_config(text: &str) -> Vec<Finding> {
+    let cfg: Config = match toml::from_str(text) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            let (line, column) = e.span().map(|span| line_column(text, span.start)).unzip();
+            return vec![Finding {
+                severity: Severity::Error,
+                line,
+                column,
+                message: e.message().trim().to_string(),
+            }];
+        }
+    };
+    let mut lint = Lint { text, findings: Vec::new() };
+    lint.patterns(&cfg);
+    lint.pattern_tables(&cfg);
+    lint.ssh(&cfg);
+    lint.findings.sort_by_key(|f| f.line.unwrap_or(usize::MAX));
+    lint.findings
+}
+
+struct Lint<'a> {
+    text: &'a str,
+    findings: Vec<Finding>,
+}
+
+impl Lint<'_> {
+    /// Report `message` at the `nth` (0-based) occurrence of `needle` in `section`
+    fn report(&mut self, severity: Severity, section: Option<&str>, needle: &str, nth: usize, message: String) {
+        let (line, column) = locate(self.text, section, needle, nth).unzip();
+        self.findings.push(Finding {
+            severity,
+            line,
+            column,
+            message,
+        });
+    }
+
+    fn patterns(&mut self, cfg: &Config) {
+        let mut valid: Vec<&String> = Vec::new();
+        for (i, pat) in cfg.patterns.iter().enumerate() {
+            let quoted = quote(pat);
+            let nth = cfg.patterns[..i].iter().filter(|p| *p == pat).count();
+            if nth > 0 {
+                self.report(Severity::Warning, None, &quoted, nth, format!("pattern {quoted} is listed more than once"));
+            } else if let Err(e) = compile_pattern(pat) {
+                self.report(Severity::Error, None, &quoted, 0, format!("pattern {quoted} never matches: {e}"));
+            } else {
+                valid.push(pat);
+            }
+        }
+        // An earlier pattern (by priority, then config order) that applies everywhere and
+        // matches this one's files is picked first for all of them
+        valid.sort_by_key(|p| std::cmp::Reverse(cfg.pattern_priority(p)));
+        for (i, pat) in valid.iter().enumerate() {
+            let unscoped = |p: &&&String| cfg.pattern_scopes.get(**p).is_none_or(|s| s.dirs.is_empty() && s.hosts.is_empty());
+            if let Some(earlier) = valid[..i].iter().filter(unscoped).find(|earlier| shadows(earlier, pat)) {
+                let message = format!("pattern {} is shadowed by {}, which matches its files and is tried first", quote(pat), quote(earlier));
+                self.report(Severity::Warning, None, &quote(pat), 0, message);
+            }
+        }
+    }
+
+    /// Tables keyed by pattern, whose entries only apply to configured patterns
+    fn pattern_tables(&mut self, cfg: &Config) {
+        let tables = [
+            ("shards", cfg.shards.keys().collect::<Vec<_>>()),
+            ("loader_commands", cfg.loader_commands.keys().collect()),
+            ("pattern_scopes", cfg.pattern_scopes.keys().collect()),
+            ("paired_patterns", cfg.paired_patterns.keys().collect()),
+        ];
+        for (table, keys) in tables {
+            for key in keys.into_iter().filter(|k| !cfg.patterns.contains(k)) {
+                let message = format!("[{table}] entry {} is for a pattern that is not configured", quote(key));
+                self.report(Severity::Warning, Some(table), &quote(key), 0, message);
+            }
+        }
+        for partner in cfg.paired_patterns.values() {
+            if let Err(e) = compile_pattern(partner) {
+                let message = format!("[paired_patterns] partner {} never matches: {e}", quote(partner));
+                self.report(Severity::Error, Some("paired_patterns"), &quote(partner), 0, message);
+            }
+        }
+    }
+
+    fn ssh(&mut self, cfg: &Config) {
+        if cfg.remote_channels == 0 {
+            self.report(Severity::Error, None, "remote_channels", 0, "remote_channels must be at least 1".to_string());
+        }
+        for (key, mb) in [("remote_bandwidth_limit", cfg.remote_bandwidth_limit), ("remote_max_in_flight_mb", cfg.remote_max_in_flight_mb)] {
+            if !mb.is_finite() || mb < 0.0 {
+                self.report(Severity::Error, None, key, 0, format!("{key} must be 0 (no limit) or a positive number of MB, not {mb}"));
+            }
+        }
+        for (host, prefix) in &cfg.remote_command_prefix {
+            if let Some(problem) = host_problem(host) {
+                self.report(Severity::Error, Some("remote_command_prefix"), host, 0, format!("[remote_command_prefix] key {}: {problem}", quote(host)));
+            }
+            if prefix.trim().is_empty() {
+                self.report(Severity::Warning, Some("remote_command_prefix"), host, 0, format!("[remote_command_prefix] prefix for {} is empty", quote(host)));
+            }
+        }
+        for (pattern, scope) in &cfg.pattern_scopes {
+            let section = format!("pattern_scopes.{}", quote(pattern));
+            for host in scope.hosts.iter().filter(|h| *h != "local") {
+                if let Some(problem) = host_problem(host) {
+                    let message = format!("[{section}] host {}: {problem}", quote(host));
+                    self.report(Severity::Error, Some(&section), &quote(host), 0, message);
+                }
+            }
+        }
+        for (primary, replicas) in &cfg.replicas {
+            if let Some(problem) = host_problem(primary) {
+                self.report(Severity::Error, Some("replicas"), primary, 0, format!("[replicas] key {}: {problem}", quote(primary)));
+            }
+            if replicas.is_empty() {
+                self.report(Severity::Warning, Some("replicas"), primary, 0, format!("[replicas] {} lists no replicas", quote(primary)));
+            }
+            for replica in replicas {
+                let quoted = quote(replica);
+                if let Some(problem) = host_problem(replica) {
+                    self.report(Severity::Error, Some("replicas"), &quoted, 0, format!("[replicas] replica {quoted} of {}: {problem}", quote(primary)));
+                } else if replica == primary {
+                    self.report(Severity::Warning, Some("replicas"), &quoted, 0, format!("[replicas] {quoted} is listed as its own replica"));
+                }
+            }
+        }
+    }
+}
+
+/// True if `earlier` matches the file names of `pat` at every sample index
+fn shadows(earlier: &str, pat: &str) -> bool {
+    SAMPLE_INDICES.iter().all(|&idx| {
+        let Ok(seq) = SequenceSpec::from_pattern(pat, SequenceSource::Local(".".into()), idx) else {
+            return false;
+        };
+        matches!(match_index(earlier, &seq.relative_path_for(idx)), Ok(Some(_)))
+    })
+}
+
+/// Why `host` is no `user@host` or bare host name, if it is not
+fn host_problem(host: &str) -> Option<&'static str> {
+    let (user, name) = match host.split_once('@') {
+        Some((user, name)) => (Some(user), name),
+        None => (None, host),
+    };
+    if user.is_some_and(str::is_empty) {
+        Some("the user before @ is empty")
+    } else if name.is_empty() {
+        Some("the host name is empty")
+    } else if name.contains('@') {
+        Some("more than one @")
+    } else if host.contains(|c: char| c.is_whitespace() || c == ':' || c == '/') {
+        Some("expected user@host or a host name, without spaces, paths or ports")
+    } else {
+        None
+    }
+}
+
+/// `s` as TOML writes it in a basic string
+fn quote(s: &str) -> String {
+    format!("{s:?}")
+}
+
+/// 1-based line and column of byte `offset` in `text`
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+/// Line and column of the `nth` occurrence of `needle` after the `[section]` header (in
+/// the top-level keys before any header without a section), ignoring comments. The
+/// headers of its sub-tables (`[section."key"]`) are searched too.
+fn locate(text: &str, section: Option<&str>, needle: &str, nth: usize) -> Option<(usize, usize)> {
+    let header = section.map(|s| format!("[{s}]"));
+    let sub_header = section.map(|s| format!("[{s}."));
+    let mut inside = header.is_none();
+    let mut seen = 0;
+    for (i, line) in text.lines().enumerate() {
+        let code = line.split(" #").next().unwrap_or(line);
+        let trimmed = code.trim();
+        if trimmed.starts_with('[') && !trimmed.starts_with("[\"") && !trimmed.starts_with("[[") && trimmed.ends_with(']') {
+            let sub_table = sub_header.as_deref().is_some_and(|h| trimmed.starts_with(h));
+            inside = header.as_deref() == Some(trimmed) || sub_table;
+            if !sub_table {
+                continue;
+            }
+        }
+        if !inside || trimmed.starts_with('#') {
+            continue;
+        }
+        for (col, _) in code.match_indices(needle) {
+            if seen == nth {
+                return Some((i + 1, code[..col].chars().count() + 1));
+            }
+            seen += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_pattern_and_ssh_mistakes_where_they_are() {
+        let text = r#"patterns = [
+    "frame_#####.png",
+    "frame_#####.png",
+    "frame_00###.png",
+    "render.png",
+    "shot_####.exr",
+]
+remote_channels = 0
+
+[loader_commands]
+"sim_####.dat" = "dat2png {path}"
+
+[replicas]
+"me@nas" = ["me@nas", "backup:22"]
+"#;
+        let findings = lint_config(text);
+        let found: Vec<(usize, Severity)> = findings.iter().map(|f| (f.line.unwrap(), f.severity)).collect();
+        assert_eq!(
+            found,
+            [
+                (3, Severity::Warning),
+                (4, Severity::Warning),
+                (5, Severity::Error),
+                (8, Severity::Error),
+                (11, Severity::Warning),
+                (14, Severity::Warning),
+                (14, Severity::Error),
+            ]
+        );
+        assert!(findings[1].message.contains("shadowed by \"frame_#####.png\""), "{}", findings[1]);
+        assert_eq!(findings[2].column, Some(5));
+        assert!(findings[6].to_string().starts_with("14:23: error: [replicas] replica \"backup:22\""), "{}", findings[6]);
+
+        assert!(lint_config("patterns = [\"f_#.png\"]\n[replicas]\n\"nas\" = [\"nas2\"]\n").is_empty());
+        let scoped = lint_config("patterns = [\"a_#.png\"]\n\n[pattern_scopes.\"b_#.png\"]\nhosts = [\"local\", \"me@\"]\n");
+        let found: Vec<_> = scoped.iter().map(|f| (f.line, f.column, f.severity)).collect();
+        assert_eq!(found, [(Some(3), Some(17), Severity::Warning), (Some(4), Some(19), Severity::Error)]);
+        let broken = lint_config("patterns = [\"f_#.png\"\nskip_gaps = true\n");
+        assert_eq!((broken.len(), broken[0].severity, broken[0].line), (1, Severity::Error, Some(2)));
+    }
+}
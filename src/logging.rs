@@ -0,0 +1,279 @@
+//! Log output: a small `tracing` subscriber printing to stderr.
+//!
+//! What gets printed follows `-v` (warnings and errors by default, then info, debug and
+//! trace) or `ZAPVIS_LOG`, a comma-separated list of `level` and `target=level`
+//! directives such as `info,zapvis::remote_worker=debug`. Spans are timed: closing one
+//! logs how long it was open, which is how SSH requests and decodes are measured.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+/// Environment variable with filter directives, taking precedence over `-v`
+pub const LOG_ENV: &str = "ZAPVIS_LOG";
+
+/// Which events and spans are logged; the longest matching target prefix decides.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    /// Target prefixes (module paths such as `zapvis::frame_cache`) with their level,
+    /// longest first
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    /// The filter for `-v` given `verbose` times
+    pub fn from_verbosity(verbose: u8) -> Self {
+        let default = match verbose {
+            0 => LevelFilter::WARN,
+            1 => LevelFilter::INFO,
+            2 => LevelFilter::DEBUG,
+            _ => LevelFilter::TRACE,
+        };
+        Self {
+            default,
+            targets: Vec::new(),
+        }
+    }
+
+    /// This filter with the directives of `spec` (`ZAPVIS_LOG` syntax) applied on top.
+    pub fn with_directives(mut self, spec: &str) -> Result<Self, String> {
+        let parse_level = |level: &str| {
+            level
+                .trim()
+                .parse::<LevelFilter>()
+                .map_err(|_| format!("bad log level {level:?} (use off, error, warn, info, debug or trace)"))
+        };
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => self.targets.push((target.trim().to_string(), parse_level(level)?)),
+                None => self.default = parse_level(directive)?,
+            }
+        }
+        self.targets.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        Ok(self)
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let matches = |prefix: &str| {
+            target.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        };
+        self.targets
+            .iter()
+            .find(|(prefix, _)| matches(prefix))
+            .map_or(self.default, |&(_, level)| level)
+    }
+
+    pub fn enabled(&self, target: &str, level: &Level) -> bool {
+        *level <= self.level_for(target)
+    }
+
+    /// The most verbose level any target is logged at
+    fn max_level(&self) -> LevelFilter {
+        self.targets.iter().map(|&(_, level)| level).fold(self.default, LevelFilter::max)
+    }
+}
+
+/// Install the stderr logger, filtered by `-v` given `verbose` times unless `ZAPVIS_LOG` is set.
+pub fn init(verbose: u8) {
+    let mut filter = LogFilter::from_verbosity(verbose);
+    if let Ok(spec) = std::env::var(LOG_ENV) {
+        match filter.clone().with_directives(&spec) {
+            Ok(parsed) => filter = parsed,
+            Err(e) => eprintln!("Ignoring {LOG_ENV}: {e}"),
+        }
+    }
+    let logger = StderrLogger {
+        filter,
+        started: Instant::now(),
+        next_id: AtomicU64::new(1),
+        spans: Mutex::new(HashMap::new()),
+    };
+    // Only fails if a subscriber is installed already
+    let _ = tracing::subscriber::set_global_default(logger);
+}
+
+struct SpanData {
+    name: &'static str,
+    target: &'static str,
+    level: Level,
+    /// ` key=value` pairs recorded so far
+    fields: String,
+    opened: Instant,
+    /// Handles to the span still alive; it closes when the last one goes
+    refs: usize,
+}
+
+impl SpanData {
+    fn label(&self) -> String {
+        match self.fields.trim_start() {
+            "" => self.name.to_string(),
+            fields => format!("{}{{{}}}", self.name, fields),
+        }
+    }
+}
+
+struct StderrLogger {
+    filter: LogFilter,
+    started: Instant,
+    next_id: AtomicU64,
+    spans: Mutex<HashMap<u64, SpanData>>,
+}
+
+thread_local! {
+    /// Spans entered on this thread, innermost last
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+impl StderrLogger {
+    fn print(&self, level: &Level, target: &str, text: &str) {
+        let secs = self.started.elapsed().as_secs_f64();
+        eprintln!("{secs:9.3} {level:>5} {target}: {text}");
+    }
+}
+
+impl Subscriber for StderrLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.filter.enabled(metadata.target(), metadata.level())
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(self.filter.max_level())
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let meta = attrs.metadata();
+        let mut fields = FieldWriter::default();
+        attrs.record(&mut fields);
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let span = SpanData {
+            name: meta.name(),
+            target: meta.target(),
+            level: *meta.level(),
+            fields: fields.fields,
+            opened: Instant::now(),
+            refs: 1,
+        };
+        self.spans.lock().unwrap_or_else(PoisonError::into_inner).insert(id, span);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut fields = FieldWriter::default();
+        values.record(&mut fields);
+        if let Some(span) = self.spans.lock().unwrap_or_else(PoisonError::into_inner).get_mut(&span.into_u64()) {
+            span.fields.push_str(&fields.fields);
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = FieldWriter::default();
+        event.record(&mut fields);
+        let mut text = String::new();
+        if let Some(current) = ENTERED.with(|entered| entered.borrow().last().copied()) {
+            if let Some(span) = self.spans.lock().unwrap_or_else(PoisonError::into_inner).get(&current) {
+                let _ = write!(text, "{}: ", span.label());
+            }
+        }
+        text.push_str(&fields.message);
+        text.push_str(&fields.fields);
+        let meta = event.metadata();
+        self.print(meta.level(), meta.target(), &text);
+    }
+
+    fn enter(&self, span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(pos) = entered.iter().rposition(|&id| id == span.into_u64()) {
+                entered.remove(pos);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(data) = self.spans.lock().unwrap_or_else(PoisonError::into_inner).get_mut(&span.into_u64()) {
+            data.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let closed = {
+            let mut spans = self.spans.lock().unwrap_or_else(PoisonError::into_inner);
+            let Some(data) = spans.get_mut(&span.into_u64()) else {
+                return false;
+            };
+            data.refs -= 1;
+            if data.refs > 0 {
+                return false;
+            }
+            spans.remove(&span.into_u64())
+        };
+        if let Some(data) = closed {
+            let ms = data.opened.elapsed().as_secs_f64() * 1000.0;
+            self.print(&data.level, data.target, &format!("{} took {:.1} ms", data.label(), ms));
+        }
+        true
+    }
+}
+
+/// Collects the message and the other fields of an event or span
+#[derive(Default)]
+struct FieldWriter {
+    message: String,
+    fields: String,
+}
+
+impl Visit for FieldWriter {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.fields, " {}={}", field.name(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directives_refine_the_verbosity() {
+        let quiet = LogFilter::from_verbosity(0);
+        assert!(quiet.enabled("zapvis::app", &Level::WARN));
+        assert!(!quiet.enabled("zapvis::app", &Level::INFO));
+        assert!(LogFilter::from_verbosity(2).enabled("zapvis::app", &Level::DEBUG));
+
+        let filter = quiet.with_directives("info, zapvis::remote_worker=trace,zapvis=error").unwrap();
+        assert!(filter.enabled("zapvis::remote_worker", &Level::TRACE));
+        assert!(!filter.enabled("zapvis::frame_cache", &Level::WARN), "zapvis=error covers the rest");
+        assert!(filter.enabled("zapvis::frame_cache", &Level::ERROR));
+        assert!(filter.enabled("eframe", &Level::INFO), "bare level is the default");
+        assert!(filter.enabled("zapvisx", &Level::INFO), "prefixes match whole path segments");
+        assert_eq!(filter.max_level(), LevelFilter::TRACE);
+
+        assert!(LogFilter::from_verbosity(0).with_directives("zapvis=loud").is_err());
+        assert!(!LogFilter::from_verbosity(3).with_directives("off").unwrap().enabled("zapvis", &Level::ERROR));
+    }
+}
@@ -0,0 +1,127 @@
+//! 3D color lookup tables in the `.cube` format (Resolve, Adobe), for previewing a color
+//! pipeline on the shown frames. The viewer applies them on the GPU; [`Lut3d::apply`]
+//! is the same trilinear lookup on the CPU.
+use std::fs;
+use std::path::Path;
+
+use crate::error::LutError;
+
+/// Largest `LUT_3D_SIZE` accepted (the usual ones are 17, 33 and 65)
+pub const MAX_LUT_SIZE: usize = 256;
+
+/// A 3D LUT: `size`³ output colors, red changing fastest, then green, then blue
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut3d {
+    pub title: String,
+    pub size: usize,
+    /// Input values mapped to the first and last entry on each axis
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    pub table: Vec<[f32; 3]>,
+}
+
+impl Lut3d {
+    /// Parse the text of a `.cube` file
+    pub fn parse(text: &str) -> Result<Self, LutError> {
+        let mut lut = Lut3d {
+            title: String::new(),
+            size: 0,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            table: Vec::new(),
+        };
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parse_err = |message: String| LutError::Parse { line: n + 1, message };
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+            let floats = |words: std::str::SplitWhitespace| -> Result<[f32; 3], LutError> {
+                let values: Vec<f32> = words.map(|w| w.parse::<f32>()).collect::<Result<_, _>>().map_err(|e| parse_err(e.to_string()))?;
+                values.try_into().map_err(|_| parse_err("expected three numbers".to_string()))
+            };
+            match keyword {
+                "TITLE" => lut.title = line["TITLE".len()..].trim().trim_matches('"').to_string(),
+                "LUT_3D_SIZE" => {
+                    let size = words.next().and_then(|w| w.parse::<usize>().ok()).ok_or_else(|| parse_err("expected the LUT size".to_string()))?;
+                    if !(2..=MAX_LUT_SIZE).contains(&size) {
+                        return Err(parse_err(format!("LUT size {size} is outside 2..={MAX_LUT_SIZE}")));
+                    }
+                    lut.size = size;
+                    lut.table.reserve(size * size * size);
+                }
+                "LUT_1D_SIZE" => return Err(LutError::Unsupported("1D LUTs are not supported".to_string())),
+                "DOMAIN_MIN" => lut.domain_min = floats(words)?,
+                "DOMAIN_MAX" => lut.domain_max = floats(words)?,
+                // Other keywords (LUT_3D_INPUT_RANGE, ...) do not change the table
+                k if k.starts_with(|c: char| c.is_ascii_alphabetic()) => {}
+                _ => lut.table.push(floats(line.split_whitespace())?),
+            }
+        }
+        if lut.size == 0 {
+            return Err(LutError::Unsupported("no LUT_3D_SIZE".to_string()));
+        }
+        if lut.table.len() != lut.size.pow(3) {
+            return Err(LutError::Unsupported(format!("{} entries for a size of {} (expected {})", lut.table.len(), lut.size, lut.size.pow(3))));
+        }
+        if (0..3).any(|c| lut.domain_max[c] <= lut.domain_min[c]) {
+            return Err(LutError::Unsupported("DOMAIN_MAX must be above DOMAIN_MIN".to_string()));
+        }
+        Ok(lut)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, LutError> {
+        let text = fs::read_to_string(path).map_err(|source| LutError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&text)
+    }
+
+    fn entry(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.table[r + self.size * (g + self.size * b)]
+    }
+
+    /// `rgb` looked up with trilinear interpolation; inputs outside the domain are clamped
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let last = (self.size - 1) as f32;
+        let mut base = [0usize; 3];
+        let mut frac = [0f32; 3];
+        for c in 0..3 {
+            let t = ((rgb[c] - self.domain_min[c]) / (self.domain_max[c] - self.domain_min[c])).clamp(0.0, 1.0) * last;
+            base[c] = (t.floor() as usize).min(self.size - 2);
+            frac[c] = t - base[c] as f32;
+        }
+        let mut out = [0f32; 3];
+        for corner in 0..8 {
+            let bit = |axis: usize| (corner >> axis) & 1;
+            let weight: f32 = (0..3).map(|axis| if bit(axis) == 1 { frac[axis] } else { 1.0 - frac[axis] }).product();
+            let value = self.entry(base[0] + bit(0), base[1] + bit(1), base[2] + bit(2));
+            for c in 0..3 {
+                out[c] += weight * value[c];
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_files_parse_and_interpolate() {
+        // Identity LUT of size 2, except that full red maps to half red
+        let text = "# made by hand\nTITLE \"test\"\nLUT_3D_SIZE 2\n\n0 0 0\n0.5 0 0\n0 1 0\n0.5 1 0\n0 0 1\n0.5 0 1\n0 1 1\n0.5 1 1\n";
+        let lut = Lut3d::parse(text).unwrap();
+        assert_eq!((lut.title.as_str(), lut.size, lut.table.len()), ("test", 2, 8));
+        assert_eq!(lut.apply([1.0, 1.0, 1.0]), [0.5, 1.0, 1.0]);
+        assert_eq!(lut.apply([0.5, 0.25, 2.0]), [0.25, 0.25, 1.0]);
+
+        assert!(matches!(Lut3d::parse("LUT_3D_SIZE 2\n0 0 0\n"), Err(LutError::Unsupported(_))));
+        assert!(matches!(Lut3d::parse("LUT_1D_SIZE 4\n"), Err(LutError::Unsupported(_))));
+        assert!(matches!(Lut3d::parse("LUT_3D_SIZE 2\n0 0 x\n"), Err(LutError::Parse { line: 2, .. })));
+    }
+}
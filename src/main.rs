@@ -1,128 +1,261 @@
+mod annotate;
 mod app;
+mod audio;
+mod blink;
+mod channel_view;
+mod check;
 mod cli;
-mod config;
+mod colormap;
+mod commands;
+mod defects;
+mod encode;
+mod export;
+mod fetch;
+mod grade;
 mod image_cache;
 mod image_util;
-mod remote_worker;
-mod sequence;
+mod input;
+mod interpolate;
+mod logging;
+mod measure;
+mod onion;
+mod pattern_editor;
+mod playback;
+mod quick_open;
+mod samples;
+mod single_instance;
+mod stats;
+mod tiles;
+mod viewport;
 
 use anyhow::{anyhow, Context, Result};
 use clap::Parser;
 use std::fs;
+use tracing::{info, warn};
 use crate::app::ZapVisApp;
-use crate::cli::Args;
-use crate::config::{config_path, load_config, maybe_add_pattern, pattern_matches_file, save_config};
-use crate::remote_worker::{RemoteRange, spawn_remote_worker};
-use crate::sequence::{
-    file_name_from_path, file_name_from_str_path, parse_remote_input, pick_sequence, InputSpec,
-    SequenceSource,
-};
-use zapvis::PersistentSsh;
+use crate::cli::{Args, Command, ViewArgs};
+use crate::input::{open_input, open_input_spec, read_piped_frame, remember_recent, NoPatternMatched, OpenedInput};
+use crate::single_instance::Forwarded;
+use zapvis::bookmarks::{default_author, load_bookmark_file};
+use zapvis::config::{AudioTrack, load_config, save_config};
+use zapvis::frame::{load_frame_from_bytes, load_frame_from_bytes_with_command, DecodeOptions};
+use zapvis::recent::{load_recent_file, recent_path, RecentEntry};
+use zapvis::session::{load_session_file, session_path, SessionState};
 
 fn main() -> Result<()> {
     let args = Args::parse();
-
-    // Handle --config flag
-    if args.config {
-        let path = config_path()?;
-        println!("Config path: {}", path.display());
-        if path.exists() {
-            let content = fs::read_to_string(&path)
-                .context("Failed to read config file")?;
-            println!("\nConfig content:\n{}", content);
-        } else {
-            println!("Config file does not exist.");
-        }
-        return Ok(());
+    logging::init(args.verbose);
+    match args.into_command() {
+        Command::View(args) => view(args),
+        Command::Config(args) => commands::run_config(args),
+        Command::Patterns(args) => commands::run_patterns(args),
+        Command::Export(args) => export::run_export(args),
+        Command::Encode(args) => encode::run_encode(args),
+        Command::Fetch(args) => fetch::run_fetch(args),
+        Command::Check(args) => check::run_check(args),
     }
+}
 
-    // Input is required if not showing config
-    let input = args
-        .input
-        .ok_or_else(|| anyhow!("Input file is required (unless using --config flag)"))?;
-    let input_spec = if let Some((user_host, remote_path)) = parse_remote_input(&input) {
-        let file_name = file_name_from_str_path(&remote_path)?;
-        let dir = std::path::Path::new(&remote_path)
-            .parent()
-            .ok_or_else(|| anyhow!("Remote input has no parent directory"))?
-            .to_string_lossy()
-            .to_string();
-        InputSpec {
-            file_name,
-            source: SequenceSource::Remote { user_host, dir },
-        }
-    } else {
-        let input = std::path::PathBuf::from(&input);
-        if !input.is_file() {
-            return Err(anyhow!(
-                "Input must be an image FILE path. Folder mode is intentionally not supported."
-            ));
-        }
-        let file_name = file_name_from_path(&input)?;
-        let dir = input
-            .parent()
-            .ok_or_else(|| anyhow!("Input has no parent directory"))?
-            .to_path_buf();
-        InputSpec {
-            file_name,
-            source: SequenceSource::Local(dir),
-        }
+/// Open the viewer on a frame file or manifest.
+fn view(args: ViewArgs) -> Result<()> {
+    // Without an input, the recently opened sequences are offered instead
+    let (input, remembered_pattern) = match args.input.clone() {
+        Some(input) => (input, None),
+        None => quick_open::run(recent_entries())?.ok_or_else(|| anyhow!("Input file is required (see `zapvis --help`)"))?,
+    };
+    // `-`: the first frame shown comes from stdin, standing in for a frame in --dir
+    let piped = match (input.as_str(), &args.dir) {
+        ("-", Some(dir)) => Some(read_piped_frame(dir, args.pattern.as_deref().unwrap_or_default(), args.index)?),
+        ("-", None) => return Err(anyhow!("Input `-` needs --dir and --pattern to place the piped frame in its sequence")),
+        (_, Some(_)) => return Err(anyhow!("--dir is only used with `-` as the input")),
+        _ => None,
     };
-
     let mut cfg = load_config().unwrap_or_default();
-
-    // If user provided --pattern, try it first and store it if it works.
-    if let Some(pat) = args.pattern.clone() {
-        if pattern_matches_file(&pat, &input_spec.file_name)? {
-            maybe_add_pattern(&mut cfg, pat);
-            save_config(&cfg).ok(); // ignore save errors (still can run)
-        } else {
-            return Err(anyhow!(
-                "Provided --pattern did not match the input filename. Nothing saved."
-            ));
+    // A running viewer switches to the input, unless this invocation asks for more
+    let listen = cfg.single_instance && !args.new_window;
+    if listen && piped.is_none() && args.compare.is_none() && args.audio.is_none() && args.bookmarks.is_empty() {
+        match single_instance::forward(&input, args.pattern.as_deref()) {
+            Forwarded::Opened => {
+                info!("opened {} in the running viewer", input);
+                return Ok(());
+            }
+            Forwarded::NotRunning => {}
+            Forwarded::Failed(e) => warn!("the running viewer could not open {} ({}); opening a new window", input, e),
         }
     }
-
-    // Establish persistent SSH early if remote (and spawn worker thread)
-    let remote_range = RemoteRange::new();
-    let remote_worker_tx = match &input_spec.source {
-        SequenceSource::Remote { user_host, .. } => {
-            match PersistentSsh::connect(user_host) {
-                Ok(ssh) => {
-                    eprintln!("[SSH] Connected to {}", user_host);
-                    Some(spawn_remote_worker(ssh, remote_range.clone()))
-                }
-                Err(e) => {
-                    eprintln!("Failed to establish persistent SSH: {}", e);
-                    None
-                }
+    // A piped frame's file need not exist; its place in the sequence is all that counts
+    let open = |pattern: Option<String>, cfg: &mut _| match &piped {
+        Some(piped) => open_input_spec(piped.spec.clone(), pattern, cfg),
+        None => open_input(&input, pattern, cfg),
+    };
+    // When no pattern fits, the user builds one in a dialog instead of a CLI round-trip
+    let opened = match open(args.pattern.clone(), &mut cfg) {
+        Err(e) => match e.downcast::<NoPatternMatched>() {
+            Ok(unmatched) => {
+                let pattern = pattern_editor::run(unmatched)?.ok_or_else(|| anyhow!("No sequence pattern matched. Quitting."))?;
+                open(Some(pattern), &mut cfg)?
             }
-        }
-        SequenceSource::Local(_) => None,
+            Err(e) => return Err(e),
+        },
+        opened => opened?,
     };
+    let OpenedInput {
+        manifest,
+        mut candidates,
+        inferred,
+        dir_config,
+        remote_tx: remote_worker_tx,
+        remote_range,
+        remote_stats,
+    } = opened;
 
-    // Determine which pattern to use:
-    let (pattern, seq) = match pick_sequence(&cfg, &input_spec, remote_worker_tx.clone()) {
-        Ok(v) => v,
-        Err(e) => {
-            // Your rule: if no hits, quit. (No interactive prompt here.)
-            eprintln!("{e}");
-            eprintln!("\nKnown patterns in config:");
-            for (i, p) in cfg.patterns.iter().enumerate() {
-                eprintln!("  {}) {}", i + 1, p);
-            }
-            eprintln!("\nTip: run with --pattern \"########_#.png\" to add/try a new one.");
-            return Err(anyhow!("No sequence pattern matched. Quitting."));
-        }
+    if let Some(remembered) = &remembered_pattern {
+        candidates.sort_by_key(|(p, _)| p != remembered);
+    }
+    let (pattern, seq) = candidates[0].clone();
+    // A piped frame may not be on disk, so its sequence is not offered for reopening
+    if piped.is_none() {
+        remember_recent(&input, manifest.is_some(), &pattern, &seq);
+    }
+    let native_fps = seq.source.native_fps();
+    if candidates.len() > 1 {
+        info!(
+            "{} patterns match; using {:?} (pick another in the top bar)",
+            candidates.len(),
+            pattern
+        );
+    }
+
+    // Associate an audio track with this sequence (remembered in the config)
+    let audio = match args.audio.clone() {
+        Some(path) => Some(AudioTrack {
+            path,
+            offset: args.audio_offset.unwrap_or(0.0),
+            sample_rate: args.audio_rate,
+        }),
+        None => manifest.as_ref().and_then(|m| m.audio.clone()),
     };
+    if let Some(mut track) = audio {
+        track.path = fs::canonicalize(&track.path)
+            .with_context(|| format!("Audio file not found: {}", track.path.display()))?;
+        cfg.audio_tracks.insert(seq.sequence_key(&pattern), track);
+        save_config(&cfg).ok(); // ignore save errors (still can run)
+    }
+
+    // Sidecars shared by other reviewers; the app adds the local reviewer's own
+    let author = default_author(cfg.author.as_deref());
+    let mut bookmark_imports = Vec::new();
+    let manifest_bookmarks = manifest.iter().flat_map(|m| &m.bookmarks);
+    for path in args.bookmarks.iter().chain(manifest_bookmarks) {
+        bookmark_imports.push(load_bookmark_file(path)?);
+        info!("merging bookmarks from {}", path.display());
+    }
 
-    let native_options = eframe::NativeOptions::default();
+    // Split view: the second input is matched like the first
+    let compare = args
+        .compare
+        .as_deref()
+        .map(|input| open_input(input, args.pattern.clone(), &mut cfg))
+        .transpose()?;
+
+    // Where the last session on this sequence left off (manifests pick their own frame)
+    let session = (cfg.remember_sessions && manifest.is_none() && piped.is_none())
+        .then(|| last_session(&seq.sequence_key(&pattern)))
+        .flatten();
+    // Decoded like the frames the viewer loads itself
+    let piped_pixels = match piped {
+        Some(piped) => {
+            let decode_opts = DecodeOptions {
+                tone: cfg.tone_map_for(&seq.sequence_key(&pattern)),
+                ..DecodeOptions::from_config(&cfg)
+            };
+            let pixels = match cfg.loader_command_for(&pattern) {
+                Some(command) => load_frame_from_bytes_with_command(command, &piped.bytes, "<stdin>", decode_opts),
+                None => load_frame_from_bytes(&piped.bytes, "<stdin>", decode_opts),
+            };
+            Some(pixels.context("Failed to decode the frame piped in on stdin")?)
+        }
+        None => None,
+    };
+    let mut native_options = eframe::NativeOptions::default();
+    if let Some(s) = &session {
+        if let Some([w, h]) = s.window_size {
+            native_options.viewport = native_options.viewport.with_inner_size([w, h]);
+        }
+        if let Some([x, y]) = s.window_pos {
+            native_options.viewport = native_options.viewport.with_position([x, y]);
+        }
+        native_options.viewport = native_options.viewport.with_maximized(s.fullscreen).with_fullscreen(s.exclusive_fullscreen);
+    }
     eframe::run_native(
         "zapvis",
         native_options,
-        Box::new(|cc| Ok(Box::new(ZapVisApp::new(cc, pattern, seq, remote_worker_tx, remote_range)))),
+        Box::new(|cc| {
+            let app = ZapVisApp::new(
+                cc,
+                cfg,
+                pattern,
+                seq,
+                remote_worker_tx,
+                remote_range,
+                remote_stats,
+            );
+            let mut app = app
+                .with_bookmarks(author, bookmark_imports)
+                .with_pattern_candidates(candidates);
+            if let Some(fps) = native_fps {
+                app = app.with_playback_fps(fps);
+            }
+            if let Some(dir_config) = dir_config {
+                app = app.with_dir_config(dir_config);
+            }
+            if let Some(m) = &manifest {
+                if let Some(fps) = m.fps {
+                    app = app.with_playback_fps(fps);
+                }
+                if let Some(bounds) = m.bounds() {
+                    app = app.with_bounds(bounds);
+                }
+            }
+            app = match compare {
+                Some(compare) => app.with_compare(compare),
+                None => app.with_pair(),
+            };
+            if let Some(session) = session {
+                app = app.with_session(session);
+            }
+            if let Some(rx) = listen.then(|| single_instance::listen(&cc.egui_ctx)).flatten() {
+                app = app.with_forwarded_inputs(rx);
+            }
+            if let Some(pixels) = piped_pixels {
+                app = app.with_initial_frame(pixels);
+            }
+            Ok(Box::new(if inferred { app.with_inferred_pattern() } else { app }))
+        }),
     )
     .map_err(|e| anyhow!(e.to_string()))?;
 
     Ok(())
 }
+
+/// The recent sequences recorded so far (an unreadable file is reported and ignored)
+fn recent_entries() -> Vec<RecentEntry> {
+    match recent_path().and_then(|path| load_recent_file(&path)) {
+        Ok(file) => file.recent,
+        Err(e) => {
+            warn!("could not read the recent sequences: {e}");
+            Vec::new()
+        }
+    }
+}
+
+/// Saved session for a sequence, if any (an unreadable sessions file is reported and ignored)
+fn last_session(sequence_key: &str) -> Option<SessionState> {
+    match session_path().and_then(|path| load_session_file(&path)) {
+        Ok(mut file) => file.sessions.remove(sequence_key),
+        Err(e) => {
+            warn!("could not read the sessions file: {e}");
+            None
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! `.zapseq` manifests: one TOML file describing a sequence (location, pattern, range,
+//! playback rate, audio and bookmark sidecars) so a dataset opens the same way every time.
+//!
+//! Relative paths in a manifest are resolved against the manifest's own directory.
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::bounds::SequenceBounds;
+use crate::config::AudioTrack;
+use crate::error::ConfigError;
+use crate::sequence::{expand_local_path, is_http_url, parse_remote_input, SequenceSource, SequenceSpec, ShardRule};
+
+/// File extension recognized as a manifest on the command line.
+pub const MANIFEST_EXTENSION: &str = "zapseq";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Directory holding the frames: a local path, `user@host:/dir` or an `https://` URL
+    pub source: String,
+    pub pattern: String,
+    /// Frame to open; defaults to the first frame of `range`
+    #[serde(default)]
+    pub start: Option<i64>,
+    /// First and last frame; when given, the ends are not probed
+    #[serde(default)]
+    pub range: Option<[i64; 2]>,
+    /// Playback rate, overriding `playback_fps` from the config
+    #[serde(default)]
+    pub fps: Option<f64>,
+    #[serde(default)]
+    pub audio: Option<AudioTrack>,
+    /// Bookmark sidecars to merge into the view
+    #[serde(default)]
+    pub bookmarks: Vec<PathBuf>,
+    /// Subdirectory layout; `source` is then the folder containing the shards
+    #[serde(default)]
+    pub shard: Option<ShardRule>,
+}
+
+pub fn is_manifest_path(input: &str) -> bool {
+    Path::new(input).extension().is_some_and(|e| e == MANIFEST_EXTENSION)
+}
+
+/// Read a manifest, resolving its relative paths against the manifest's directory.
+pub fn load_manifest(path: &Path) -> Result<Manifest, ConfigError> {
+    let txt = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut manifest: Manifest = toml::from_str(&txt).map_err(|source| ConfigError::Parse {
+        name: format!("manifest {}", path.display()),
+        source,
+    })?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    manifest.resolve_paths(base);
+    Ok(manifest)
+}
+
+impl Manifest {
+    fn resolve_paths(&mut self, base: &Path) {
+        if parse_remote_input(&self.source).is_none() && !is_http_url(&self.source) {
+            self.source = base.join(expand_local_path(&self.source)).display().to_string();
+        }
+        if let Some(audio) = &mut self.audio {
+            audio.path = base.join(&audio.path);
+        }
+        for path in &mut self.bookmarks {
+            *path = base.join(&*path);
+        }
+    }
+
+    /// Bounds given by `range`, if any.
+    pub fn bounds(&self) -> Option<SequenceBounds> {
+        let [first, last] = self.range?;
+        Some(SequenceBounds {
+            first: Some(first),
+            last: Some(last),
+        })
+    }
+
+    /// The sequence the manifest describes, opened at `start`.
+    pub fn sequence(&self) -> Result<SequenceSpec, ConfigError> {
+        let start = self.start.or(self.range.map(|[first, _]| first)).ok_or_else(|| {
+            ConfigError::Invalid("Manifest needs `start` or `range` to know which frame to open".to_string())
+        })?;
+        if let Some([first, last]) = self.range {
+            if !(first..=last).contains(&start) {
+                return Err(ConfigError::Invalid(format!("Manifest start {start} is outside range {first}..={last}")));
+            }
+        }
+        let source = match parse_remote_input(&self.source) {
+            _ if is_http_url(&self.source) => SequenceSource::Http {
+                base_url: self.source.trim_end_matches('/').to_string(),
+            },
+            Some((user_host, dir)) => SequenceSource::Remote { user_host, dir },
+            None => SequenceSource::Local(PathBuf::from(&self.source)),
+        };
+        let mut spec = SequenceSpec::from_pattern(&self.pattern, source, start)?;
+        if self.shard.is_some() {
+            spec.shard = self.shard.clone();
+        }
+        Ok(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_resolves_relative_paths() {
+        let txt = r#"
+            source = "frames"
+            pattern = "frame_####.png"
+            range = [10, 99]
+            fps = 24.0
+            bookmarks = ["review/alice.toml"]
+
+            [audio]
+            path = "take1.wav"
+            offset = -0.5
+        "#;
+        let mut manifest: Manifest = toml::from_str(txt).unwrap();
+        manifest.resolve_paths(Path::new("/data/shot"));
+        assert_eq!(manifest.bookmarks, [PathBuf::from("/data/shot/review/alice.toml")]);
+        assert_eq!(manifest.audio.as_ref().unwrap().path, PathBuf::from("/data/shot/take1.wav"));
+
+        let seq = manifest.sequence().unwrap();
+        assert_eq!(seq.index, 10);
+        assert_eq!(seq.path_display(42), "/data/shot/frames/frame_0042.png");
+        assert_eq!(manifest.bounds().unwrap().frame_count(), Some(90));
+    }
+
+    #[test]
+    fn manifest_needs_a_start_frame() {
+        let mut manifest: Manifest = toml::from_str("source = \"u@h:/data\"\npattern = \"f_##.png\"").unwrap();
+        manifest.resolve_paths(Path::new("/elsewhere"));
+        assert_eq!(manifest.source, "u@h:/data");
+        assert!(manifest.sequence().is_err());
+        manifest.start = Some(7);
+        assert_eq!(manifest.sequence().unwrap().path_display(7), "u@h:/data/f_07.png");
+    }
+}
@@ -0,0 +1,168 @@
+//! Measure mode (Shift+M): distances and angles between points clicked on the frame,
+//! kept while stepping so motion between frames can be read off the overlay.
+use eframe::egui;
+use zapvis::config::MeasureScale;
+
+use crate::annotate::FrameMapping;
+
+const MEASURE_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 200, 0);
+
+/// What the clicks on the frame measure
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MeasureTool {
+    /// Two points: the distance between them
+    #[default]
+    Distance,
+    /// Three points: the angle at the second one
+    Angle,
+}
+
+impl MeasureTool {
+    pub const ALL: [MeasureTool; 2] = [MeasureTool::Distance, MeasureTool::Angle];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MeasureTool::Distance => "Distance",
+            MeasureTool::Angle => "Angle",
+        }
+    }
+
+    fn points(self) -> usize {
+        match self {
+            MeasureTool::Distance => 2,
+            MeasureTool::Angle => 3,
+        }
+    }
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    (b[0] - a[0]).hypot(b[1] - a[1])
+}
+
+/// Angle at `vertex` between the rays to `a` and `b`, in degrees (0..=180)
+fn angle(a: [f32; 2], vertex: [f32; 2], b: [f32; 2]) -> f32 {
+    let (ax, ay) = (a[0] - vertex[0], a[1] - vertex[1]);
+    let (bx, by) = (b[0] - vertex[0], b[1] - vertex[1]);
+    (ax * by - ay * bx).atan2(ax * bx + ay * by).abs().to_degrees()
+}
+
+/// Readout of a finished measurement: its pixel distance (with `scale`, also in real
+/// units) or its angle
+pub fn readout(points: &[[f32; 2]], scale: Option<&MeasureScale>) -> String {
+    match points {
+        [a, b] => {
+            let px = distance(*a, *b);
+            match scale {
+                Some(scale) => format!("{px:.1} px = {}", scale.format(px as f64)),
+                None => format!("{px:.1} px"),
+            }
+        }
+        [a, vertex, b] => format!("{:.1}°", angle(*a, *vertex, *b)),
+        _ => String::new(),
+    }
+}
+
+/// Measurements of the open sequence, in frame pixels, and the one being clicked
+#[derive(Default)]
+pub struct Measurer {
+    pub tool: MeasureTool,
+    /// Points clicked so far for the next measurement
+    pending: Vec<[f32; 2]>,
+    done: Vec<Vec<[f32; 2]>>,
+}
+
+impl Measurer {
+    /// Add a point at `at` (frame pixels); the last point of the tool finishes a measurement
+    pub fn click(&mut self, at: [f32; 2]) {
+        self.pending.push(at);
+        if self.pending.len() >= self.tool.points() {
+            self.done.push(std::mem::take(&mut self.pending));
+        }
+    }
+
+    /// Drop the last clicked point, else the newest measurement (Backspace)
+    pub fn undo(&mut self) {
+        if self.pending.pop().is_none() {
+            self.done.pop();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.done.clear();
+    }
+
+    /// Forget a measurement under way (the tool changed)
+    pub fn cancel_pending(&mut self) {
+        self.pending.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty() && self.done.is_empty()
+    }
+
+    pub fn measurements(&self) -> impl Iterator<Item = &[[f32; 2]]> {
+        self.done.iter().map(Vec::as_slice)
+    }
+}
+
+/// Paint the measurements and the one under way (stretched to `hover`) with their readouts
+pub fn paint_measurements(painter: &egui::Painter, map: &FrameMapping, measurer: &Measurer, hover: Option<[f32; 2]>, scale: Option<&MeasureScale>) {
+    let stroke = egui::Stroke::new(1.5, MEASURE_COLOR);
+    let mut pending = measurer.pending.clone();
+    let preview = !pending.is_empty();
+    pending.extend(hover.filter(|_| preview));
+    for points in measurer.measurements().chain(preview.then_some(pending.as_slice())) {
+        let screen: Vec<egui::Pos2> = points.iter().map(|&p| map.to_screen(p)).collect();
+        painter.add(egui::Shape::line(screen.clone(), stroke));
+        for &p in &screen {
+            painter.circle_stroke(p, 3.0, stroke);
+        }
+        let text = readout(points, scale);
+        if text.is_empty() {
+            continue;
+        }
+        // Distances are labeled at their middle, angles at the vertex
+        let at = match screen.as_slice() {
+            [a, b] => a.lerp(*b, 0.5),
+            [_, vertex, _] => *vertex,
+            _ => continue,
+        };
+        let galley = painter.layout_no_wrap(text, egui::FontId::proportional(13.0), MEASURE_COLOR);
+        let rect = egui::Rect::from_min_size(at + egui::vec2(6.0, 6.0), galley.size() + egui::vec2(8.0, 4.0));
+        painter.rect_filled(rect, 2.0, egui::Color32::from_black_alpha(170));
+        painter.galley(rect.min + egui::vec2(4.0, 2.0), galley, MEASURE_COLOR);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clicks_become_distances_and_angles() {
+        let mut measurer = Measurer::default();
+        measurer.click([0.0, 0.0]);
+        assert_eq!(measurer.measurements().count(), 0, "one point is no distance yet");
+        measurer.click([30.0, 40.0]);
+        let distance = measurer.measurements().next().unwrap();
+        assert_eq!(readout(distance, None), "50.0 px");
+        let scale = MeasureScale { per_pixel: 0.1, unit: "mm".to_string() };
+        assert_eq!(readout(distance, Some(&scale)), "50.0 px = 5.00 mm");
+
+        measurer.tool = MeasureTool::Angle;
+        for p in [[10.0, 0.0], [0.0, 0.0], [0.0, 10.0]] {
+            measurer.click(p);
+        }
+        assert_eq!(readout(measurer.measurements().last().unwrap(), None), "90.0°");
+        assert_eq!(readout(&[[10.0, 0.0], [0.0, 0.0], [-10.0, 0.1]], None), "179.4°");
+
+        measurer.click([1.0, 1.0]);
+        measurer.undo();
+        assert_eq!(measurer.measurements().count(), 2, "undo drops the pending point first");
+        measurer.undo();
+        assert_eq!(measurer.measurements().count(), 1);
+        measurer.clear();
+        assert!(measurer.is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+//! Copying a frame range of a sequence (typically a remote one) to a local directory, so
+//! the frames can be viewed from disk afterwards.
+//!
+//! Files are written with their path relative to the sequence directory (shard folders
+//! included), so the copy opens with the same pattern. Frames already present at the
+//! destination are kept, which lets an interrupted mirror be resumed.
+use serde::Serialize;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+use crate::error::CacheError;
+use crate::remote_worker::RemoteWorkerRequest;
+use crate::sequence::{SequenceSource, SequenceSpec};
+
+/// Counters of a running mirror, updated by its threads and readable from the UI
+#[derive(Clone, Default)]
+pub struct MirrorProgress {
+    done: Arc<AtomicU64>,
+    written: Arc<AtomicU64>,
+    present: Arc<AtomicU64>,
+    missing: Arc<AtomicU64>,
+    failed: Arc<AtomicU64>,
+    bytes: Arc<AtomicU64>,
+    cancelled: Arc<AtomicBool>,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+/// Point-in-time copy of [`MirrorProgress`]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MirrorSnapshot {
+    /// Frames handled so far, whatever the outcome
+    pub done: u64,
+    pub written: u64,
+    /// Frames skipped because the destination already had them
+    pub present: u64,
+    /// Frames that do not exist in the source
+    pub missing: u64,
+    pub failed: u64,
+    pub bytes: u64,
+    pub last_error: Option<String>,
+}
+
+impl MirrorProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop after the frames in flight; the rest of the range is left out
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn snapshot(&self) -> MirrorSnapshot {
+        MirrorSnapshot {
+            done: self.done.load(Ordering::Relaxed),
+            written: self.written.load(Ordering::Relaxed),
+            present: self.present.load(Ordering::Relaxed),
+            missing: self.missing.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            last_error: self.last_error.lock().map(|e| e.clone()).unwrap_or_default(),
+        }
+    }
+
+    fn fail(&self, error: String) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut last) = self.last_error.lock() {
+            *last = Some(error);
+        }
+    }
+}
+
+/// Where frame `idx` of `seq` goes below `dest`
+pub fn mirror_path(seq: &SequenceSpec, dest: &Path, idx: i64) -> PathBuf {
+    dest.join(seq.relative_path_for(idx))
+}
+
+/// `seq` read from its mirror in `dest` instead of its source
+pub fn mirrored_sequence(seq: &SequenceSpec, dest: &Path) -> SequenceSpec {
+    SequenceSpec {
+        source: SequenceSource::Local(dest.to_path_buf()),
+        ..seq.clone()
+    }
+}
+
+/// Copy `frames` of `seq` to `dest` with `threads` parallel fetches, returning the final
+/// counts. Remote frames go through the remote worker behind `request_tx`, whose range
+/// must let the frames through; with one transfer channel per thread they download side
+/// by side. Per-frame failures are counted rather than ending the mirror.
+pub fn mirror_range(
+    seq: &SequenceSpec,
+    frames: RangeInclusive<i64>,
+    dest: &Path,
+    request_tx: Option<&Sender<RemoteWorkerRequest>>,
+    threads: usize,
+    progress: &MirrorProgress,
+) -> MirrorSnapshot {
+    let (first, last) = (*frames.start(), *frames.end());
+    // Next index to hand out; past `last` once the range is exhausted
+    let next = AtomicI64::new(first);
+    std::thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                while !progress.is_cancelled() {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    if idx > last || idx < first {
+                        break;
+                    }
+                    mirror_frame(seq, idx, dest, request_tx, progress);
+                    progress.done.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    progress.snapshot()
+}
+
+fn mirror_frame(
+    seq: &SequenceSpec,
+    idx: i64,
+    dest: &Path,
+    request_tx: Option<&Sender<RemoteWorkerRequest>>,
+    progress: &MirrorProgress,
+) {
+    let target = mirror_path(seq, dest, idx);
+    if target.is_file() {
+        progress.present.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    let bytes = match seq.fetch_bytes(idx, request_tx) {
+        Ok(bytes) => bytes,
+        Err(CacheError::NotFound(_)) => {
+            progress.missing.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        Err(e) => {
+            warn!("frame {}: {}", idx, e);
+            progress.fail(format!("frame {idx}: {e}"));
+            return;
+        }
+    };
+    // Written aside and renamed, so an interrupted mirror leaves no truncated frame
+    // that a resumed one would take as present
+    let mut part = target.clone().into_os_string();
+    part.push(".part");
+    let written = target
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&part, &bytes))
+        .and_then(|()| fs::rename(&part, &target));
+    match written {
+        Ok(()) => {
+            progress.written.fetch_add(1, Ordering::Relaxed);
+            progress.bytes.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        }
+        Err(e) => {
+            warn!("writing {}: {}", target.display(), e);
+            progress.fail(format!("{}: {e}", target.display()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrors_a_range_and_keeps_present_frames() {
+        let root = std::env::temp_dir().join(format!("zapvis_mirror_{}", std::process::id()));
+        let (src, dest) = (root.join("src"), root.join("dest"));
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        let seq = SequenceSpec::from_pattern("f_###.png", SequenceSource::Local(src.clone()), 1).unwrap();
+        for idx in [1, 2, 4, 5] {
+            fs::write(src.join(seq.file_name_for(idx)), format!("frame {idx}")).unwrap();
+        }
+        fs::write(dest.join("f_002.png"), "kept").unwrap();
+
+        let done = mirror_range(&seq, 1..=5, &dest, None, 2, &MirrorProgress::new());
+        assert_eq!((done.done, done.written, done.present, done.missing, done.failed), (5, 3, 1, 1, 0));
+        assert_eq!(fs::read_to_string(dest.join("f_005.png")).unwrap(), "frame 5");
+        assert_eq!(fs::read_to_string(dest.join("f_002.png")).unwrap(), "kept");
+        assert!(!dest.join("f_003.png").exists());
+
+        let local = mirrored_sequence(&seq, &dest);
+        assert_eq!(local.path_display(4), dest.join("f_004.png").display().to_string());
+
+        let cancelled = MirrorProgress::new();
+        cancelled.cancel();
+        assert_eq!(mirror_range(&seq, 1..=5, &dest, None, 1, &cancelled).done, 0);
+        fs::remove_dir_all(&root).ok();
+    }
+}
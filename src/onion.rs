@@ -0,0 +1,99 @@
+use image::RgbaImage;
+
+/// How the other frame is laid over the current one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Mixed in by its opacity
+    Normal,
+    /// Absolute difference, so registration drift shows as bright edges
+    Difference,
+    /// Added on top, saturating
+    Add,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 3] = [BlendMode::Normal, BlendMode::Difference, BlendMode::Add];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BlendMode::Normal => "normal",
+            BlendMode::Difference => "difference",
+            BlendMode::Add => "add",
+        }
+    }
+}
+
+/// Onion skin: another frame of the sequence composited over the current one, to
+/// review animation spacing or drift between frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OnionSkin {
+    /// Frame shown over every frame; `None` for the one a step back
+    pub pinned: Option<i64>,
+    /// 0 shows only the current frame, 1 the other one fully blended
+    pub opacity: f32,
+    pub mode: BlendMode,
+}
+
+impl Default for OnionSkin {
+    fn default() -> Self {
+        Self {
+            pinned: None,
+            opacity: 0.5,
+            mode: BlendMode::Normal,
+        }
+    }
+}
+
+impl OnionSkin {
+    /// The frame laid over `current` when stepping by `step`
+    pub fn other_frame(&self, current: i64, step: u64) -> i64 {
+        self.pinned.unwrap_or_else(|| current.saturating_sub(step as i64))
+    }
+
+    /// `over` blended onto `base`; `None` if the frames differ in size
+    pub fn composite(&self, base: &RgbaImage, over: &RgbaImage) -> Option<RgbaImage> {
+        if base.dimensions() != over.dimensions() {
+            return None;
+        }
+        let opacity = self.opacity.clamp(0.0, 1.0);
+        let mut out = base.clone();
+        for (dst, src) in out.pixels_mut().zip(over.pixels()) {
+            for c in 0..3 {
+                let (b, o) = (dst[c] as f32, src[c] as f32);
+                let blended = match self.mode {
+                    BlendMode::Normal => o,
+                    BlendMode::Difference => (b - o).abs(),
+                    BlendMode::Add => (b + o).min(255.0),
+                };
+                dst[c] = (b + (blended - b) * opacity).round() as u8;
+            }
+            dst[3] = dst[3].max(src[3]);
+        }
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn blends_by_mode_and_opacity() {
+        let base = RgbaImage::from_pixel(2, 1, Rgba([100, 200, 0, 255]));
+        let over = RgbaImage::from_pixel(2, 1, Rgba([200, 100, 255, 255]));
+        let pixel = |mode, opacity| {
+            let onion = OnionSkin { pinned: None, opacity, mode };
+            onion.composite(&base, &over).unwrap().get_pixel(0, 0).0
+        };
+        assert_eq!(pixel(BlendMode::Normal, 0.5), [150, 150, 128, 255]);
+        assert_eq!(pixel(BlendMode::Normal, 0.0), [100, 200, 0, 255]);
+        assert_eq!(pixel(BlendMode::Difference, 1.0), [100, 100, 255, 255]);
+        assert_eq!(pixel(BlendMode::Add, 1.0), [255, 255, 255, 255]);
+        assert!(OnionSkin::default().composite(&base, &RgbaImage::new(1, 1)).is_none());
+
+        let onion = OnionSkin::default();
+        assert_eq!(onion.other_frame(40, 10), 30);
+        assert_eq!(OnionSkin { pinned: Some(7), ..onion }.other_frame(40, 10), 7);
+    }
+}
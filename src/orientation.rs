@@ -0,0 +1,226 @@
+use image::{imageops, ImageBuffer, Pixel};
+use serde::{Deserialize, Serialize};
+
+/// Display orientation of a sequence: clockwise quarter turns followed by flips.
+///
+/// Flips are expressed in display space (applied after the rotation), so "flip
+/// horizontally" always mirrors what is currently on screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Orientation {
+    /// Clockwise quarter turns (0..=3)
+    #[serde(default)]
+    pub rotation: u8,
+    #[serde(default)]
+    pub flip_h: bool,
+    #[serde(default)]
+    pub flip_v: bool,
+}
+
+impl Orientation {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Rotate the displayed image by 90° clockwise (or counter-clockwise).
+    pub fn rotate(&mut self, clockwise: bool) {
+        // Rotating after a display-space flip swaps which axis the flip acts on.
+        if self.flip_h != self.flip_v {
+            std::mem::swap(&mut self.flip_h, &mut self.flip_v);
+        }
+        self.rotation = if clockwise {
+            (self.rotation + 1) % 4
+        } else {
+            (self.rotation + 3) % 4
+        };
+    }
+
+    pub fn toggle_flip_h(&mut self) {
+        self.flip_h = !self.flip_h;
+    }
+
+    pub fn toggle_flip_v(&mut self) {
+        self.flip_v = !self.flip_v;
+    }
+
+    /// True if width and height are swapped on screen.
+    pub fn swaps_axes(&self) -> bool {
+        self.rotation % 2 == 1
+    }
+
+    /// Texture UVs for the displayed corners in order top-left, top-right,
+    /// bottom-right, bottom-left.
+    pub fn corner_uvs(&self) -> [[f32; 2]; 4] {
+        let mut uvs = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        // Each clockwise turn shows the source's previous corner at each display corner.
+        uvs.rotate_right(self.rotation as usize % 4);
+        if self.flip_h {
+            uvs.swap(0, 1);
+            uvs.swap(2, 3);
+        }
+        if self.flip_v {
+            uvs.swap(0, 3);
+            uvs.swap(1, 2);
+        }
+        uvs
+    }
+
+    /// Map a normalized display position (u, v in 0..=1) to normalized source texture coordinates.
+    pub fn display_to_source_uv(&self, u: f32, v: f32) -> [f32; 2] {
+        // Bilinear blend of the corner UVs; exact because the mapping is affine on the unit square.
+        let [tl, tr, br, bl] = self.corner_uvs();
+        let mut out = [0.0; 2];
+        for (i, o) in out.iter_mut().enumerate() {
+            *o = tl[i] * (1.0 - u) * (1.0 - v) + tr[i] * u * (1.0 - v) + br[i] * u * v + bl[i] * (1.0 - u) * v;
+        }
+        out
+    }
+
+    /// Inverse of [`display_to_source_uv`](Self::display_to_source_uv).
+    pub fn source_to_display_uv(&self, s: f32, t: f32) -> [f32; 2] {
+        let [tl, tr, _, bl] = self.corner_uvs();
+        // Display axes map to unit source axes, so the inverse is the transpose
+        let du = [tr[0] - tl[0], tr[1] - tl[1]];
+        let dv = [bl[0] - tl[0], bl[1] - tl[1]];
+        let d = [s - tl[0], t - tl[1]];
+        [d[0] * du[0] + d[1] * du[1], d[0] * dv[0] + d[1] * dv[1]]
+    }
+
+    /// Apply the orientation to a decoded image (e.g. for saving what is displayed).
+    pub fn apply_to_image<P>(&self, img: &ImageBuffer<P, Vec<P::Subpixel>>) -> ImageBuffer<P, Vec<P::Subpixel>>
+    where
+        P: Pixel + 'static,
+    {
+        let mut out = match self.rotation % 4 {
+            1 => imageops::rotate90(img),
+            2 => imageops::rotate180(img),
+            3 => imageops::rotate270(img),
+            _ => img.clone(),
+        };
+        if self.flip_h {
+            imageops::flip_horizontal_in_place(&mut out);
+        }
+        if self.flip_v {
+            imageops::flip_vertical_in_place(&mut out);
+        }
+        out
+    }
+
+    /// Orientation described by an EXIF `Orientation` tag value (1..=8).
+    pub fn from_exif(value: u32) -> Self {
+        let (rotation, flip_h, flip_v) = match value {
+            2 => (0, true, false),
+            3 => (2, false, false),
+            4 => (0, false, true),
+            5 => (1, true, false), // transpose
+            6 => (1, false, false),
+            7 => (1, false, true), // transverse
+            8 => (3, false, false),
+            _ => (0, false, false),
+        };
+        Self {
+            rotation,
+            flip_h,
+            flip_v,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut s = format!("{}°", self.rotation as u32 * 90);
+        if self.flip_h {
+            s.push_str(" H");
+        }
+        if self.flip_v {
+            s.push_str(" V");
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn px(v: u8) -> Rgba<u8> {
+        Rgba([v, v, v, 255])
+    }
+
+    // 2x1 image: [1, 2]
+    fn strip() -> RgbaImage {
+        let mut img = RgbaImage::new(2, 1);
+        img.put_pixel(0, 0, px(1));
+        img.put_pixel(1, 0, px(2));
+        img
+    }
+
+    #[test]
+    fn rotate_cw_turns_row_into_column() {
+        let mut o = Orientation::default();
+        o.rotate(true);
+        let out = o.apply_to_image(&strip());
+        assert_eq!(out.dimensions(), (1, 2));
+        assert_eq!(*out.get_pixel(0, 0), px(1));
+        assert_eq!(*out.get_pixel(0, 1), px(2));
+        assert_eq!(o.corner_uvs()[0], [0.0, 1.0]);
+        // Display top-right comes from source top-left
+        assert_eq!(o.display_to_source_uv(1.0, 0.0), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn rotate_after_flip_keeps_display_flip() {
+        // Flip what is on screen, then rotate: same as rotating then flipping the other axis.
+        let mut a = Orientation::default();
+        a.toggle_flip_h();
+        a.rotate(true);
+
+        let mut b = Orientation::default();
+        b.rotate(true);
+        b.toggle_flip_v();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn exif_orientations_move_the_top_left_corner() {
+        // 3x2 with only the top-left pixel marked; each tag puts it in another corner
+        let mut img = RgbaImage::from_pixel(3, 2, px(0));
+        img.put_pixel(0, 0, px(255));
+        let expected = [
+            (2, (3, 2), (2, 0)), // mirrored horizontally
+            (3, (3, 2), (2, 1)), // rotated 180°
+            (4, (3, 2), (0, 1)), // mirrored vertically
+            (5, (2, 3), (0, 0)), // transposed
+            (6, (2, 3), (1, 0)), // rotated 90° clockwise
+            (7, (2, 3), (1, 2)), // transversed
+            (8, (2, 3), (0, 2)), // rotated 90° counterclockwise
+        ];
+        for (tag, size, corner) in expected {
+            let out = Orientation::from_exif(tag).apply_to_image(&img);
+            assert_eq!(out.dimensions(), size, "orientation {tag}");
+            let marked: Vec<(u32, u32)> = out.enumerate_pixels().filter(|(_, _, p)| **p == px(255)).map(|(x, y, _)| (x, y)).collect();
+            assert_eq!(marked, [corner], "orientation {tag}");
+        }
+        assert!(Orientation::from_exif(1).is_identity());
+    }
+
+    #[test]
+    fn source_to_display_inverts_display_to_source() {
+        let mut o = Orientation::default();
+        for step in 0..8 {
+            if step == 4 {
+                o.toggle_flip_h();
+            }
+            let [s, t] = o.display_to_source_uv(0.25, 0.75);
+            assert_eq!(o.source_to_display_uv(s, t), [0.25, 0.75], "{o:?}");
+            o.rotate(true);
+        }
+    }
+
+    #[test]
+    fn four_turns_are_identity() {
+        let mut o = Orientation::default();
+        for _ in 0..4 {
+            o.rotate(false);
+        }
+        assert!(o.is_identity());
+    }
+}
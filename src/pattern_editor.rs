@@ -0,0 +1,187 @@
+//! Dialog shown instead of quitting when no pattern fits the opened file: the digit runs
+//! of its name are buttons that build the pattern, which is checked for neighbor
+//! evidence as it changes and saved to the config once accepted.
+use anyhow::{anyhow, Result};
+use eframe::egui;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use zapvis::remote_worker::RemoteWorkerRequest;
+use zapvis::sequence::{digit_runs, neighbors_exist, pattern_from_runs, spec_for_pattern, InputSpec, SequenceSpec};
+
+use crate::input::NoPatternMatched;
+
+/// Ask for a pattern for the unmatched input; `None` when the dialog is closed without one.
+pub fn run(unmatched: NoPatternMatched) -> Result<Option<String>> {
+    let mut chosen = None;
+    let mut native_options = eframe::NativeOptions::default();
+    native_options.viewport = native_options.viewport.with_inner_size([560.0, 240.0]);
+    eframe::run_native(
+        "zapvis: new pattern",
+        native_options,
+        Box::new(|cc| Ok(Box::new(PatternEditor::new(&cc.egui_ctx, unmatched, &mut chosen)))),
+    )
+    .map_err(|e| anyhow!(e.to_string()))?;
+    Ok(chosen)
+}
+
+/// What the neighbor check found for the pattern in the editor
+enum Check {
+    /// The pattern is malformed or does not match the file name
+    Invalid(String),
+    Running,
+    Done {
+        seq: SequenceSpec,
+        /// Whether the frames before and after the opened one exist
+        neighbors: (bool, bool),
+    },
+}
+
+struct PatternEditor<'a> {
+    input: InputSpec,
+    request_tx: Option<Sender<RemoteWorkerRequest>>,
+    /// Digit runs of the file name, and which of them count the frames
+    runs: Vec<(usize, usize)>,
+    selected: Vec<bool>,
+    pattern: String,
+    check: Check,
+    /// Neighbor checks run off the UI thread (remote ones wait for the host), tagged with
+    /// their pattern so that results for an edited one are dropped
+    results_tx: Sender<(String, SequenceSpec, (bool, bool))>,
+    results_rx: Receiver<(String, SequenceSpec, (bool, bool))>,
+    chosen: &'a mut Option<String>,
+}
+
+impl<'a> PatternEditor<'a> {
+    fn new(ctx: &egui::Context, unmatched: NoPatternMatched, chosen: &'a mut Option<String>) -> Self {
+        let runs = digit_runs(&unmatched.input.file_name);
+        // The trailing run is the usual frame number, as when inferring
+        let selected = (0..runs.len()).map(|i| i + 1 == runs.len()).collect();
+        let (results_tx, results_rx) = channel();
+        let mut editor = Self {
+            input: unmatched.input,
+            request_tx: unmatched.request_tx,
+            runs,
+            selected,
+            pattern: String::new(),
+            check: Check::Invalid(String::new()),
+            results_tx,
+            results_rx,
+            chosen,
+        };
+        editor.pattern = editor.pattern_from_selection();
+        editor.test(ctx);
+        editor
+    }
+
+    fn pattern_from_selection(&self) -> String {
+        let runs: Vec<(usize, usize)> = self.runs.iter().zip(&self.selected).filter(|(_, &on)| on).map(|(&run, _)| run).collect();
+        pattern_from_runs(&self.input.file_name, &runs)
+    }
+
+    /// Check the pattern in the editor: at once whether it matches the file, then in the
+    /// background whether the neighboring frames exist
+    fn test(&mut self, ctx: &egui::Context) {
+        self.check = match spec_for_pattern(&self.pattern, &self.input) {
+            Err(e) => Check::Invalid(e.to_string()),
+            Ok(None) => Check::Invalid(format!("does not match {}", self.input.file_name)),
+            Ok(Some(seq)) => {
+                let (pattern, tx, results_tx, ctx) = (self.pattern.clone(), self.request_tx.clone(), self.results_tx.clone(), ctx.clone());
+                thread::spawn(move || {
+                    let neighbors = neighbors_exist(&seq, tx);
+                    results_tx.send((pattern, seq, neighbors)).ok();
+                    ctx.request_repaint();
+                });
+                Check::Running
+            }
+        };
+    }
+
+    /// The file name with its digit runs as toggles; true if one was clicked
+    fn file_name_buttons(&mut self, ui: &mut egui::Ui) -> bool {
+        let name = &self.input.file_name;
+        let mut clicked = false;
+        let mut at = 0;
+        ui.horizontal(|ui| {
+            ui.spacing_mut().item_spacing.x = 0.0;
+            for (i, &(start, end)) in self.runs.iter().enumerate() {
+                ui.label(egui::RichText::new(&name[at..start]).monospace());
+                let run = egui::RichText::new(&name[start..end]).monospace().strong();
+                if ui.selectable_label(self.selected[i], run).clicked() {
+                    self.selected[i] = !self.selected[i];
+                    clicked = true;
+                }
+                at = end;
+            }
+            ui.label(egui::RichText::new(&name[at..]).monospace());
+        });
+        clicked
+    }
+
+    fn status(&self, ui: &mut egui::Ui) {
+        match &self.check {
+            Check::Invalid(message) if message.is_empty() => {}
+            Check::Invalid(message) => {
+                ui.colored_label(ui.visuals().error_fg_color, message);
+            }
+            Check::Running => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Looking for the neighboring frames...");
+                });
+            }
+            Check::Done { seq, neighbors: (prev, next) } => {
+                let found = |exists: bool| if exists { "found" } else { "missing" };
+                let previous = match seq.index > seq.min_index() {
+                    true => format!("{} {}", seq.file_name_for(seq.index - 1), found(*prev)),
+                    false => "none before it".to_string(),
+                };
+                ui.label(format!("Frame {}; previous: {previous}; next: {} {}", seq.index, seq.file_name_for(seq.index + 1), found(*next)));
+                if !prev && !next {
+                    ui.colored_label(ui.visuals().warn_fg_color, "No neighboring frame exists, so this pattern finds only the opened file.");
+                }
+            }
+        }
+    }
+}
+
+impl eframe::App for PatternEditor<'_> {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        while let Ok((pattern, seq, neighbors)) = self.results_rx.try_recv() {
+            if pattern == self.pattern {
+                self.check = Check::Done { seq, neighbors };
+            }
+        }
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("No pattern matches this file");
+            if self.runs.is_empty() {
+                ui.label("The file name has no digits to count the frames; type a pattern below.");
+            } else {
+                ui.label("Click the digits that count the frames:");
+            }
+            let mut changed = self.file_name_buttons(ui);
+            if changed {
+                self.pattern = self.pattern_from_selection();
+            }
+            ui.horizontal(|ui| {
+                ui.label("Pattern:");
+                changed |= ui.text_edit_singleline(&mut self.pattern).changed();
+            });
+            if changed {
+                self.test(ctx);
+            }
+            self.status(ui);
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                let usable = matches!(self.check, Check::Done { neighbors: (prev, next), .. } if prev || next);
+                let save = ui.add_enabled(usable, egui::Button::new("Save and open"));
+                if save.clicked() {
+                    *self.chosen = Some(self.pattern.clone());
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+                if ui.button("Cancel").clicked() {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+            });
+        });
+    }
+}
@@ -1,78 +1,253 @@
-use anyhow::{anyhow, Context, Result};
-use std::io::{Read, Write};
-use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::error::SshError;
+use crate::sequence::is_absolute_remote_path;
+
+type Result<T> = std::result::Result<T, SshError>;
+
+/// CAT payloads are read in chunks of this size, reporting progress after each
+const CAT_CHUNK: usize = 64 * 1024;
+
+/// Formats that are compressed already; CATZ would only cost CPU on both ends
+const PRECOMPRESSED_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "gif", "avif", "jxl", "heic"];
+
+/// Compression of CAT transfers on the wire.
+///
+/// Worth it for raw formats (TIFF, PPM, ...) over slow links; already-compressed
+/// formats such as PNG and JPEG are always sent as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    Off,
+    Gzip,
+    Zstd,
+    /// zstd if the remote host has it, else gzip
+    Auto,
+}
+
+/// Shell environment of the remote host, detected when connecting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RemotePlatform {
+    /// `sh` with coreutils: Linux, macOS, BSD, ...
+    #[default]
+    Posix,
+    /// Windows OpenSSH server; the loop runs in PowerShell and paths look like `C:/renders`
+    Windows,
+}
+
+impl RemotePlatform {
+    /// Platform from the output of [`DETECT_COMMAND`]: only cmd.exe expands `%OS%`.
+    fn from_probe(stdout: &str) -> Self {
+        if stdout.contains("Windows_NT") {
+            RemotePlatform::Windows
+        } else {
+            RemotePlatform::Posix
+        }
+    }
+}
 
 /// Persistent SSH session using a single ssh.exe process.
 /// One handshake, many commands.
 ///
 /// Protocol:
 ///   EXISTS <path>\n  -> OK | NO
-///   CAT <path>\n     -> OK <len>\n <raw bytes>
+///   CAT <path>\n     -> OK <len> <md5>\n <raw bytes>
+///   CATZ <codec> <path>\n -> OK <len> <md5> <gzip|zstd>\n <compressed bytes>
+///
+/// The md5 is of the file itself (before compression), or `-` on hosts without md5sum.
+///   PING\n           -> PONG
+///   RESOLVE <dir>\n  -> OK <absolute dir> | NO   (relative to the home directory, `~` expanded)
 ///   QUIT
+///
+/// POSIX hosts run the protocol in a `sh` loop, Windows hosts in a PowerShell one
+/// (see [`RemotePlatform`]), which always answers CATZ with gzip.
 pub struct PersistentSsh {
     child: Child,
     stdin: ChildStdin,
     stdout: ChildStdout,
+    user_host: String,
+    /// Last line ssh printed to stderr, to explain why the session closed
+    stderr_tail: Arc<Mutex<String>>,
+    stderr_thread: Option<JoinHandle<()>>,
+    compression: Compression,
+    platform: RemotePlatform,
+    /// Set once a pipe read/write failed; the protocol stream can't be trusted afterwards.
+    broken: bool,
 }
 
 impl PersistentSsh {
     pub fn connect(user_host: &str) -> Result<Self> {
-        let mut child = Command::new("ssh")
-            .args([
-                "-p",
-                "58022",
-                "-o",
-                "BatchMode=yes",
-                "-o",
-                "ConnectTimeout=5",
-                "-o",
-                "PreferredAuthentications=publickey",
-                "-o",
-                "PasswordAuthentication=no",
-                "-o",
-                "KbdInteractiveAuthentication=no",
-                "-o",
-                "GSSAPIAuthentication=no",
-                user_host,
-                "sh",
-                "-lc",
-                REMOTE_LOOP,
-            ])
+        Self::connect_with_prefix(user_host, None)
+    }
+
+    /// Connect and run every remote file access (EXISTS/CAT) through `command_prefix`,
+    /// e.g. `sudo -n -u capture`, to read directories owned by another account.
+    ///
+    /// SECURITY: the prefix is executed verbatim on the remote host for every request.
+    /// Only characters from a conservative whitelist are accepted, and the prefix should
+    /// be non-interactive (`sudo -n`) because there is no TTY to answer a password prompt.
+    pub fn connect_with_prefix(user_host: &str, command_prefix: Option<&str>) -> Result<Self> {
+        let platform = detect_platform(user_host)?;
+        let mut command = ssh_command(user_host);
+        match platform {
+            RemotePlatform::Posix => {
+                let script = match command_prefix {
+                    Some(prefix) => {
+                        validate_command_prefix(prefix)?;
+                        warn!(
+                            "WARNING: remote file access on {} runs as `{}` (opted in via config)",
+                            user_host, prefix
+                        );
+                        remote_loop(&format!("{} ", prefix.trim()))
+                    }
+                    None => remote_loop(""),
+                };
+                command.args(["sh", "-lc", &script]);
+            }
+            RemotePlatform::Windows => {
+                if let Some(prefix) = command_prefix {
+                    warn!("{} is a Windows host; ignoring the command prefix `{}`", user_host, prefix);
+                }
+                command.args([
+                    "powershell",
+                    "-NoProfile",
+                    "-NonInteractive",
+                    "-EncodedCommand",
+                    &encode_powershell(POWERSHELL_LOOP),
+                ]);
+            }
+        }
+        let mut child = command
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .stderr(Stdio::inherit())
+            .stderr(Stdio::piped())
             .spawn()
-            .with_context(|| format!("Failed to start ssh to {user_host}"))?;
+            .map_err(|source| SshError::Spawn {
+                host: user_host.to_string(),
+                source,
+            })?;
 
-        let stdin = child.stdin.take().ok_or_else(|| anyhow!("ssh stdin missing"))?;
-        let stdout = child.stdout.take().ok_or_else(|| anyhow!("ssh stdout missing"))?;
+        let (Some(stdin), Some(stdout), Some(stderr)) = (child.stdin.take(), child.stdout.take(), child.stderr.take()) else {
+            let _ = child.kill();
+            return Err(SshError::Protocol("ssh pipes missing".to_string()));
+        };
+        let stderr_tail = Arc::new(Mutex::new(String::new()));
+        let stderr_thread = forward_stderr(stderr, stderr_tail.clone());
 
         Ok(Self {
             child,
             stdin,
             stdout,
+            user_host: user_host.to_string(),
+            stderr_tail,
+            stderr_thread: Some(stderr_thread),
+            compression: Compression::Off,
+            platform,
+            broken: false,
         })
     }
 
+    /// Shell environment the remote loop runs in
+    pub fn platform(&self) -> RemotePlatform {
+        self.platform
+    }
+
+    /// Compress CAT transfers of files that are not compressed already.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
     pub fn exists(&mut self, path: &str) -> Result<bool> {
         self.write_line(&format!("EXISTS {}", sanitize(path)))?;
         match self.read_line()?.as_str() {
             "OK" => Ok(true),
             "NO" => Ok(false),
-            other => Err(anyhow!("Unexpected EXISTS response: {other}")),
+            other => Err(SshError::Protocol(format!("EXISTS answered {other:?}"))),
         }
     }
 
     pub fn cat(&mut self, path: &str) -> Result<Vec<u8>> {
-        self.write_line(&format!("CAT {}", sanitize(path)))?;
+        self.cat_with_progress(path, |_, _| {})
+    }
+
+    /// Like [`cat`](Self::cat), calling `progress` with the bytes received so far and the
+    /// transfer size after every chunk. For compressed transfers these are the compressed bytes.
+    ///
+    /// A payload that fails its checksum (or does not decompress) is fetched once more
+    /// before the error is returned.
+    pub fn cat_with_progress(&mut self, path: &str, mut progress: impl FnMut(&[u8], usize)) -> Result<Vec<u8>> {
+        match self.cat_once(path, &mut progress) {
+            Err(e @ (SshError::Checksum { .. } | SshError::Decompress { .. })) => {
+                warn!("{e}; retrying once");
+                self.cat_once(path, &mut progress)
+            }
+            result => result,
+        }
+    }
+
+    fn cat_once(&mut self, path: &str, progress: &mut impl FnMut(&[u8], usize)) -> Result<Vec<u8>> {
+        match wire_codec(self.compression, path) {
+            Some(codec) => self.write_line(&format!("CATZ {} {}", codec, sanitize(path)))?,
+            None => self.write_line(&format!("CAT {}", sanitize(path)))?,
+        }
         let header = self.read_line()?;
         if header == "NO" {
-            return Err(anyhow!("Remote file not found: {path}"));
+            return Err(SshError::NotFound(path.to_string()));
         }
-        let len = parse_len(&header)?;
+        let (len, md5, codec) = parse_header(&header)?;
         let mut buf = vec![0u8; len];
-        self.read_exact(&mut buf)?;
-        Ok(buf)
+        let mut received = 0;
+        while received < len {
+            let end = (received + CAT_CHUNK).min(len);
+            self.read_exact(&mut buf[received..end])?;
+            received = end;
+            progress(&buf[..received], len);
+        }
+        let bytes = match codec {
+            Some(codec) => decompress(codec, &buf)?,
+            None => buf,
+        };
+        verify_md5(path, md5, &bytes)?;
+        Ok(bytes)
+    }
+
+    /// Absolute path of the directory `dir`, which may be relative to the login directory
+    /// (the home directory) or start with `~`. Resolved as the SSH user, without the
+    /// command prefix, like `scp` does. Windows hosts answer with forward slashes
+    /// (`C:/Users/me/renders`).
+    pub fn resolve(&mut self, dir: &str) -> Result<String> {
+        let dir = if dir.is_empty() { "." } else { dir };
+        self.write_line(&format!("RESOLVE {}", sanitize(dir)))?;
+        let line = self.read_line()?;
+        match line.strip_prefix("OK ") {
+            Some(resolved) if is_absolute_remote_path(resolved) => Ok(resolved.to_string()),
+            _ if line == "NO" => Err(SshError::NotFound(dir.to_string())),
+            _ => Err(SshError::Protocol(format!("RESOLVE answered {line:?}"))),
+        }
+    }
+
+    /// Round-trip a PING through the remote loop and return the latency.
+    pub fn ping(&mut self) -> Result<Duration> {
+        let start = Instant::now();
+        self.write_line("PING")?;
+        match self.read_line()?.as_str() {
+            "PONG" => Ok(start.elapsed()),
+            other => Err(SshError::Protocol(format!("PING answered {other:?}"))),
+        }
+    }
+
+    /// True while the ssh process is still running.
+    pub fn is_alive(&mut self) -> bool {
+        !self.broken && matches!(self.child.try_wait(), Ok(None))
     }
 
     pub fn close(mut self) {
@@ -81,8 +256,14 @@ impl PersistentSsh {
     }
 
     fn write_line(&mut self, s: &str) -> Result<()> {
-        self.stdin.write_all(s.as_bytes())?;
-        self.stdin.write_all(b"\n")?;
+        let res = self
+            .stdin
+            .write_all(s.as_bytes())
+            .and_then(|_| self.stdin.write_all(b"\n"));
+        if res.is_err() {
+            self.broken = true;
+        }
+        res?;
         self.stdin.flush().ok();
         Ok(())
     }
@@ -91,42 +272,228 @@ impl PersistentSsh {
         let mut out = Vec::new();
         loop {
             let mut b = [0u8; 1];
-            let n = self.stdout.read(&mut b)?;
+            let n = match self.stdout.read(&mut b) {
+                Ok(n) => n,
+                Err(e) => {
+                    self.broken = true;
+                    return Err(e.into());
+                }
+            };
             if n == 0 {
-                return Err(anyhow!("ssh session closed"));
+                self.broken = true;
+                return Err(self.closed_error());
             }
             if b[0] == b'\n' {
                 break;
             }
             out.push(b[0]);
             if out.len() > 8192 {
-                return Err(anyhow!("header too long"));
+                return Err(SshError::Protocol("header too long".to_string()));
             }
         }
-        Ok(String::from_utf8(out)?.trim_end().to_string())
+        let line = String::from_utf8(out).map_err(|_| SshError::Protocol("header is not UTF-8".to_string()))?;
+        Ok(line.trim_end().to_string())
     }
 
     fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
-        self.stdout.read_exact(buf)?;
+        if let Err(e) = self.stdout.read_exact(buf) {
+            self.broken = true;
+            return Err(e.into());
+        }
         Ok(())
     }
+
+    /// Why stdout hit EOF, from what ssh said on stderr before exiting.
+    fn closed_error(&mut self) -> SshError {
+        // ssh writes its last words just before exiting; give the forwarder a moment to catch them
+        if let Some(handle) = self.stderr_thread.take() {
+            let deadline = Instant::now() + Duration::from_millis(500);
+            while !handle.is_finished() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let message = self.stderr_tail.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        closed_error(&self.user_host, message)
+    }
+}
+
+/// `ssh` to `user_host` with the options of every zapvis session; the remote command is appended.
+fn ssh_command(user_host: &str) -> Command {
+    let mut command = Command::new("ssh");
+    command.args([
+        "-p",
+        "58022",
+        "-o",
+        "BatchMode=yes",
+        "-o",
+        "ConnectTimeout=5",
+        "-o",
+        "PreferredAuthentications=publickey",
+        "-o",
+        "PasswordAuthentication=no",
+        "-o",
+        "KbdInteractiveAuthentication=no",
+        "-o",
+        "GSSAPIAuthentication=no",
+        user_host,
+    ]);
+    command
+}
+
+/// Probe run before the session starts. cmd.exe (directly or from PowerShell, the two
+/// default shells of Windows OpenSSH) prints `Windows_NT`; `sh` has no `cmd` and prints nothing.
+const DETECT_COMMAND: &str = "cmd /c echo %OS%";
+
+/// Find out which loop the host can run, with a short ssh call of its own.
+fn detect_platform(user_host: &str) -> Result<RemotePlatform> {
+    let output = ssh_command(user_host)
+        .arg(DETECT_COMMAND)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|source| SshError::Spawn {
+            host: user_host.to_string(),
+            source,
+        })?;
+    // 255 is ssh's own failure; anything else is the probe's exit status on the host
+    if output.status.code() == Some(255) {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+        return Err(closed_error(user_host, message.to_string()));
+    }
+    let platform = RemotePlatform::from_probe(&String::from_utf8_lossy(&output.stdout));
+    debug!("{} is a {:?} host", user_host, platform);
+    Ok(platform)
+}
+
+/// `-EncodedCommand` argument for `script`: base64 of its UTF-16LE text. Survives any
+/// quoting of cmd.exe or PowerShell as the login shell.
+fn encode_powershell(script: &str) -> String {
+    // Indentation only costs command-line length (cmd.exe allows 8191 characters)
+    let script: Vec<&str> = script.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    let utf16: Vec<u8> = script.join("\n").encode_utf16().flat_map(u16::to_le_bytes).collect();
+    base64(&utf16)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Pass ssh's stderr through to ours, remembering the last non-empty line.
+fn forward_stderr(stderr: ChildStderr, tail: Arc<Mutex<String>>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for line in BufReader::new(stderr).lines().map_while(|l| l.ok()) {
+            warn!("{line}");
+            if !line.trim().is_empty() {
+                *tail.lock().unwrap_or_else(PoisonError::into_inner) = line.trim().to_string();
+            }
+        }
+    })
+}
+
+fn closed_error(user_host: &str, message: String) -> SshError {
+    let host = user_host.to_string();
+    if message.contains("Permission denied") || message.contains("Too many authentication failures") {
+        SshError::AuthFailed { host, message }
+    } else {
+        SshError::Closed { host, message }
+    }
 }
 
 fn sanitize(p: &str) -> String {
-    p.replace('\n', "").replace('\r', "")
+    p.replace(['\n', '\r'], "")
 }
 
-fn parse_len(h: &str) -> Result<usize> {
+/// Codec to request with CATZ for `path`, or `None` to use a plain CAT.
+fn wire_codec(compression: Compression, path: &str) -> Option<&'static str> {
+    let ext = path.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+    if ext.is_some_and(|ext| PRECOMPRESSED_EXTENSIONS.contains(&ext.as_str())) {
+        return None;
+    }
+    match compression {
+        Compression::Off => None,
+        Compression::Gzip => Some("gzip"),
+        Compression::Zstd => Some("zstd"),
+        Compression::Auto => Some("auto"),
+    }
+}
+
+/// Payload length, the file's md5 (`None` if the host could not compute it) and, for
+/// CATZ, the codec the remote end used.
+fn parse_header(h: &str) -> Result<(usize, Option<&str>, Option<&str>)> {
     let mut it = h.split_whitespace();
     if it.next() != Some("OK") {
-        return Err(anyhow!("Unexpected header: {h}"));
+        return Err(SshError::Protocol(format!("unexpected CAT header {h:?}")));
     }
-    let n = it.next().ok_or_else(|| anyhow!("Missing length"))?;
-    Ok(n.parse()?)
+    let (Some(len), Some(md5)) = (it.next().and_then(|n| n.parse().ok()), it.next()) else {
+        return Err(SshError::Protocol(format!("incomplete CAT header {h:?}")));
+    };
+    Ok((len, Some(md5).filter(|m| *m != "-"), it.next()))
+}
+
+fn verify_md5(path: &str, expected: Option<&str>, bytes: &[u8]) -> Result<()> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    let actual: String = Md5::digest(bytes).iter().map(|b| format!("{b:02x}")).collect();
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(SshError::Checksum {
+            path: path.to_string(),
+            expected: expected.to_string(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+fn decompress(codec: &str, bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let result = match codec {
+        "gzip" => flate2::read::GzDecoder::new(bytes).read_to_end(&mut out).map(|_| ()),
+        "zstd" => zstd::stream::copy_decode(bytes, &mut out),
+        other => return Err(SshError::Protocol(format!("unknown CATZ codec {other:?}"))),
+    };
+    result.map_err(|source| SshError::Decompress {
+        codec: codec.to_string(),
+        source,
+    })?;
+    Ok(out)
+}
+
+/// Reject prefixes containing shell metacharacters; the prefix is pasted into the remote loop.
+fn validate_command_prefix(prefix: &str) -> Result<()> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || " _-./=".contains(c);
+    if prefix.trim().is_empty() || !prefix.chars().all(allowed) {
+        return Err(SshError::CommandPrefix(prefix.to_string()));
+    }
+    Ok(())
+}
+
+/// Build the remote loop, running file accesses through `prefix` (empty or ending in a space).
+fn remote_loop(prefix: &str) -> String {
+    REMOTE_LOOP.replace("{P}", prefix)
 }
 
 const REMOTE_LOOP: &str = r#"
 set -eu
+md5() {
+  if command -v md5sum >/dev/null 2>&1; then
+    {P}md5sum -- "$1" | awk '{print $1}'
+  else
+    echo -
+  fi
+}
 while IFS= read -r line; do
   cmd=${line%% *}
   arg=${line#* }
@@ -134,14 +501,46 @@ while IFS= read -r line; do
     QUIT)
       exit 0
       ;;
+    PING)
+      echo PONG
+      ;;
+    RESOLVE)
+      case "$arg" in
+        "~") arg=$HOME ;;
+        "~/"*) arg=$HOME/${arg#"~/"} ;;
+      esac
+      if [ "$arg" != "$line" ] && dir=$(cd -- "$arg" 2>/dev/null && pwd); then
+        echo "OK $dir"
+      else
+        echo NO
+      fi
+      ;;
     EXISTS)
-      [ "$arg" != "$line" ] && [ -f "$arg" ] && echo OK || echo NO
+      [ "$arg" != "$line" ] && {P}test -f "$arg" && echo OK || echo NO
       ;;
     CAT)
-      if [ "$arg" != "$line" ] && [ -f "$arg" ]; then
-        n=$(wc -c < "$arg" | tr -d '[:space:]')
-        echo "OK $n"
-        cat -- "$arg"
+      if [ "$arg" != "$line" ] && {P}test -f "$arg"; then
+        n=$({P}wc -c -- "$arg" | awk '{print $1}')
+        echo "OK $n $(md5 "$arg")"
+        {P}cat -- "$arg"
+      else
+        echo NO
+      fi
+      ;;
+    CATZ)
+      codec=${arg%% *}
+      file=${arg#* }
+      case "$codec" in
+        zstd|auto) if command -v zstd >/dev/null 2>&1; then codec=zstd; else codec=gzip; fi ;;
+        *) codec=gzip ;;
+      esac
+      if [ "$file" != "$arg" ] && {P}test -f "$file"; then
+        z=$(mktemp)
+        {P}cat -- "$file" | $codec -1 -q -c > "$z"
+        n=$(wc -c < "$z" | awk '{print $1}')
+        echo "OK $n $(md5 "$file") $codec"
+        cat -- "$z"
+        rm -f -- "$z"
       else
         echo NO
       fi
@@ -152,3 +551,144 @@ while IFS= read -r line; do
   esac
 done
 "#;
+
+/// The protocol for Windows hosts. Files are read whole and written to the raw stdout
+/// stream; CATZ compresses with .NET's gzip since zstd is not available.
+const POWERSHELL_LOOP: &str = r#"
+$ErrorActionPreference = 'Stop'
+$ProgressPreference = 'SilentlyContinue'
+$in = New-Object IO.StreamReader([Console]::OpenStandardInput(), (New-Object Text.UTF8Encoding $false))
+$out = [Console]::OpenStandardOutput()
+function Send([byte[]]$b) { $out.Write($b, 0, $b.Length); $out.Flush() }
+function Say([string]$s) { Send ([Text.Encoding]::UTF8.GetBytes("$s`n")) }
+function Md5([byte[]]$b) { [BitConverter]::ToString([Security.Cryptography.MD5]::Create().ComputeHash($b)).Replace('-', '') }
+function IsFile([string]$p) { $p -and (Test-Path -LiteralPath $p -PathType Leaf) }
+while ($null -ne ($line = $in.ReadLine())) {
+  $cmd, $arg = $line -split ' ', 2
+  try {
+    switch -CaseSensitive ($cmd) {
+      'QUIT' { exit 0 }
+      'PING' { Say 'PONG' }
+      'RESOLVE' {
+        if ($arg -eq '~') { $arg = $HOME } elseif ($arg -like '~/*') { $arg = Join-Path $HOME $arg.Substring(2) }
+        if ($arg -and (Test-Path -LiteralPath $arg -PathType Container)) {
+          Say ('OK ' + (Resolve-Path -LiteralPath $arg).ProviderPath.Replace('\', '/'))
+        } else { Say 'NO' }
+      }
+      'EXISTS' {
+        if (IsFile $arg) { Say 'OK' } else { Say 'NO' }
+      }
+      'CAT' {
+        if (IsFile $arg) {
+          $b = [IO.File]::ReadAllBytes($arg)
+          Say "OK $($b.Length) $(Md5 $b)"
+          Send $b
+        } else { Say 'NO' }
+      }
+      'CATZ' {
+        $codec, $file = "$arg" -split ' ', 2
+        if (IsFile $file) {
+          $b = [IO.File]::ReadAllBytes($file)
+          $m = New-Object IO.MemoryStream
+          $z = New-Object IO.Compression.GZipStream($m, [IO.Compression.CompressionLevel]::Fastest)
+          $z.Write($b, 0, $b.Length)
+          $z.Close()
+          $c = $m.ToArray()
+          Say "OK $($c.Length) $(Md5 $b) gzip"
+          Send $c
+        } else { Say 'NO' }
+      }
+      default { Say 'NO' }
+    }
+  } catch { Say 'NO' }
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_loop_without_prefix_has_no_placeholders() {
+        let script = remote_loop("");
+        assert!(!script.contains("{P}"));
+        assert!(script.contains("test -f \"$arg\""));
+        assert!(script.contains("RESOLVE)"));
+    }
+
+    #[test]
+    fn remote_loop_applies_prefix() {
+        let script = remote_loop("sudo -n -u capture ");
+        assert!(script.contains("sudo -n -u capture cat -- \"$arg\""));
+        assert!(script.contains("sudo -n -u capture test -f \"$arg\""));
+        assert!(script.contains("sudo -n -u capture cat -- \"$file\" | $codec"));
+    }
+
+    #[test]
+    fn windows_hosts_are_told_apart_by_their_shell() {
+        assert_eq!(RemotePlatform::from_probe("Windows_NT\r\n"), RemotePlatform::Windows);
+        assert_eq!(RemotePlatform::from_probe(""), RemotePlatform::Posix);
+        assert_eq!(RemotePlatform::from_probe("%OS%\n"), RemotePlatform::Posix);
+    }
+
+    #[test]
+    fn powershell_loop_is_sent_as_utf16_base64() {
+        assert_eq!(base64(b"hello"), "aGVsbG8=");
+        assert_eq!(base64(b"hi"), "aGk=");
+        assert_eq!(base64(b"abc"), "YWJj");
+        // "A\nB" as UTF-16LE
+        assert_eq!(encode_powershell("  A\n\n  B  "), base64(&[65, 0, 10, 0, 66, 0]));
+        let encoded = encode_powershell(POWERSHELL_LOOP);
+        assert!(encoded.len() < 8000, "{} characters do not fit a cmd.exe command line", encoded.len());
+        for cmd in ["'EXISTS'", "'CAT'", "'CATZ'", "'PING'", "'RESOLVE'", "'QUIT'"] {
+            assert!(POWERSHELL_LOOP.contains(cmd), "{cmd}");
+        }
+    }
+
+    #[test]
+    fn only_uncompressed_formats_are_sent_compressed() {
+        assert_eq!(wire_codec(Compression::Zstd, "/data/frame_0001.tiff"), Some("zstd"));
+        assert_eq!(wire_codec(Compression::Auto, "/data/frame_0001.PPM"), Some("auto"));
+        assert_eq!(wire_codec(Compression::Gzip, "/data/frame_0001.PNG"), None);
+        assert_eq!(wire_codec(Compression::Off, "/data/frame_0001.tiff"), None);
+    }
+
+    #[test]
+    fn compressed_payloads_round_trip() {
+        use std::io::Write;
+        let raw: Vec<u8> = (0..50_000u32).map(|i| (i / 100) as u8).collect();
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&raw).unwrap();
+        assert_eq!(decompress("gzip", &gz.finish().unwrap()).unwrap(), raw);
+        let zst = zstd::encode_all(&raw[..], 1).unwrap();
+        assert_eq!(decompress("zstd", &zst).unwrap(), raw);
+        assert!(matches!(decompress("zstd", b"garbage"), Err(SshError::Decompress { .. })));
+    }
+
+    #[test]
+    fn payloads_are_checked_against_the_remote_md5() {
+        let md5 = "5d41402abc4b2a76b9719d911017c592"; // md5sum of "hello"
+        assert_eq!(parse_header(&format!("OK 5 {md5} zstd")).unwrap(), (5, Some(md5), Some("zstd")));
+        assert_eq!(parse_header("OK 5 -").unwrap(), (5, None, None));
+        assert!(parse_header("OK 5").is_err());
+        assert!(verify_md5("f", Some(md5), b"hello").is_ok());
+        assert!(verify_md5("f", None, b"anything").is_ok());
+        assert!(matches!(verify_md5("f", Some(md5), b"hellp"), Err(SshError::Checksum { .. })));
+    }
+
+    #[test]
+    fn command_prefix_rejects_shell_metacharacters() {
+        assert!(validate_command_prefix("sudo -n -u capture").is_ok());
+        assert!(validate_command_prefix("sudo -u capture; rm -rf /").is_err());
+        assert!(validate_command_prefix("$(id)").is_err());
+        assert!(validate_command_prefix("  ").is_err());
+    }
+
+    #[test]
+    fn rejected_keys_are_reported_as_auth_failures() {
+        let denied = closed_error("me@host", "me@host: Permission denied (publickey).".to_string());
+        assert!(matches!(denied, SshError::AuthFailed { .. }));
+        let refused = closed_error("me@host", "ssh: connect to host host port 58022: Connection refused".to_string());
+        assert!(matches!(refused, SshError::Closed { .. }));
+    }
+}
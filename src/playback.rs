@@ -0,0 +1,216 @@
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+use zapvis::bookmarks::Bookmarks;
+
+/// Frame clock for playback: the frame to show is derived from wall time since start,
+/// so slow loads drop frames instead of slowing playback (and audio stays in sync).
+pub struct Playback {
+    fps: f64,
+    start_index: i64,
+    started: Instant,
+    /// Frames to play in order when playback is filtered; `None` plays every index
+    playlist: Option<Vec<i64>>,
+    /// First and last frame of the loop region; unfiltered playback wraps at its end
+    looped: Option<(i64, i64)>,
+}
+
+impl Playback {
+    pub fn start(index: i64, fps: f64) -> Self {
+        Self {
+            fps: fps.max(0.001),
+            start_index: index,
+            started: Instant::now(),
+            playlist: None,
+            looped: None,
+        }
+    }
+
+    /// Play only `frames`, one per tick of the frame clock.
+    pub fn start_playlist(frames: Vec<i64>, fps: f64) -> Self {
+        Self {
+            playlist: Some(frames),
+            ..Self::start(0, fps)
+        }
+    }
+
+    /// Hold the first frame for `preroll` so the loader can buffer ahead before the clock runs.
+    pub fn with_preroll(mut self, preroll: Duration) -> Self {
+        self.started += preroll;
+        self
+    }
+
+    /// Play `first..=last` over and over; the start index must be inside it.
+    pub fn looping(mut self, first: i64, last: i64) -> Self {
+        self.looped = Some((first, last));
+        self
+    }
+
+    pub fn is_prerolling(&self) -> bool {
+        self.started > Instant::now()
+    }
+
+    /// Start the clock now, ending the preroll early.
+    pub fn end_preroll(&mut self) {
+        self.started = self.started.min(Instant::now());
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    pub fn is_filtered(&self) -> bool {
+        self.playlist.is_some()
+    }
+
+    /// Frame clock ticks after `elapsed` playback time.
+    fn ticks_after(&self, elapsed: Duration) -> f64 {
+        elapsed.as_secs_f64() * self.fps
+    }
+
+    /// Fractional frame position after `elapsed` playback time (unfiltered playback).
+    pub fn position_after(&self, elapsed: Duration) -> f64 {
+        let position = self.start_index as f64 + self.ticks_after(elapsed);
+        match self.looped {
+            Some((first, last)) if position >= (last + 1) as f64 => {
+                first as f64 + (position - first as f64).rem_euclid((last - first + 1) as f64)
+            }
+            _ => position,
+        }
+    }
+
+    /// Fractional frame position now; for a playlist, the frame being shown.
+    pub fn position(&self) -> f64 {
+        match &self.playlist {
+            Some(_) => self.frame().unwrap_or(0) as f64,
+            None => self.position_after(self.started.elapsed()),
+        }
+    }
+
+    /// Frame due after `elapsed`, or `None` once a playlist is exhausted.
+    pub fn frame_after(&self, elapsed: Duration) -> Option<i64> {
+        match &self.playlist {
+            Some(frames) => frames.get(self.ticks_after(elapsed).floor() as usize).copied(),
+            None => Some(self.position_after(elapsed).floor() as i64),
+        }
+    }
+
+    /// Frame due now.
+    pub fn frame(&self) -> Option<i64> {
+        self.frame_after(self.started.elapsed())
+    }
+
+    /// Time until the next frame is due, for scheduling the repaint.
+    pub fn until_next_frame(&self) -> Duration {
+        let ticks = self.ticks_after(self.started.elapsed());
+        Duration::from_secs_f64((ticks.floor() + 1.0 - ticks) / self.fps)
+    }
+}
+
+/// Which frames playback visits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlaybackFilter {
+    All,
+    /// Frames bookmarked by anyone
+    Bookmarked,
+    /// Frames bookmarked by one reviewer
+    Author(String),
+}
+
+impl PlaybackFilter {
+    pub fn label(&self) -> String {
+        match self {
+            PlaybackFilter::All => "all frames".to_string(),
+            PlaybackFilter::Bookmarked => "bookmarked".to_string(),
+            PlaybackFilter::Author(author) => format!("bookmarked by {author}"),
+        }
+    }
+
+    pub fn matches(&self, bookmarks: &Bookmarks, idx: i64) -> bool {
+        match self {
+            PlaybackFilter::All => true,
+            PlaybackFilter::Bookmarked => bookmarks.at(idx).next().is_some(),
+            PlaybackFilter::Author(author) => bookmarks.at(idx).any(|b| &b.author == author),
+        }
+    }
+
+    /// Matching frames from `from` onwards, or `None` when every frame is played.
+    pub fn playlist(&self, bookmarks: &Bookmarks, from: i64) -> Option<Vec<i64>> {
+        if *self == PlaybackFilter::All {
+            return None;
+        }
+        let frames: BTreeSet<i64> = bookmarks
+            .iter()
+            .map(|b| b.index)
+            .filter(|&idx| idx >= from && self.matches(bookmarks, idx))
+            .collect();
+        Some(frames.into_iter().collect())
+    }
+
+    /// Cycle all -> bookmarked -> each author -> all.
+    pub fn next(&self, bookmarks: &Bookmarks) -> Self {
+        let authors: BTreeSet<&str> = bookmarks.iter().map(|b| b.author.as_str()).collect();
+        let mut options = vec![PlaybackFilter::All, PlaybackFilter::Bookmarked];
+        options.extend(authors.into_iter().map(|a| PlaybackFilter::Author(a.to_string())));
+        let pos = options.iter().position(|o| o == self).unwrap_or(0);
+        options[(pos + 1) % options.len()].clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zapvis::bookmarks::{Bookmark, BookmarkFile};
+
+    #[test]
+    fn position_follows_wall_time() {
+        let p = Playback::start(100, 25.0);
+        assert_eq!(p.position_after(Duration::ZERO), 100.0);
+        assert_eq!(p.position_after(Duration::from_millis(1000)), 125.0);
+        assert_eq!(p.position_after(Duration::from_millis(1020)).floor(), 125.0);
+    }
+
+    #[test]
+    fn looped_playback_wraps_to_the_in_point() {
+        let p = Playback::start(12, 10.0).looping(10, 14);
+        assert_eq!(p.frame_after(Duration::from_millis(250)), Some(14));
+        assert_eq!(p.frame_after(Duration::from_millis(300)), Some(10));
+        assert_eq!(p.frame_after(Duration::from_millis(850)), Some(10));
+        assert_eq!(p.position_after(Duration::from_millis(350)), 10.5);
+    }
+
+    #[test]
+    fn preroll_holds_the_first_frame() {
+        let mut p = Playback::start(100, 25.0).with_preroll(Duration::from_secs(60));
+        assert!(p.is_prerolling());
+        assert_eq!(p.frame(), Some(100));
+        p.end_preroll();
+        assert!(!p.is_prerolling());
+    }
+
+    #[test]
+    fn filtered_playback_visits_only_matching_frames() {
+        let mut marks = Bookmarks::new("alice");
+        marks.toggle(40);
+        marks.toggle(10);
+        marks.merge(BookmarkFile {
+            sequence: String::new(),
+            bookmarks: vec![Bookmark {
+                index: 25,
+                author: "bob".to_string(),
+                note: String::new(),
+            }],
+        });
+
+        let filter = PlaybackFilter::All.next(&marks);
+        assert_eq!(filter, PlaybackFilter::Bookmarked);
+        assert_eq!(filter.playlist(&marks, 20), Some(vec![25, 40]));
+        let alice = filter.next(&marks);
+        assert_eq!(alice.playlist(&marks, 0), Some(vec![10, 40]));
+        assert_eq!(alice.next(&marks).next(&marks), PlaybackFilter::All);
+
+        let p = Playback::start_playlist(vec![25, 40], 10.0);
+        assert_eq!(p.frame_after(Duration::from_millis(50)), Some(25));
+        assert_eq!(p.frame_after(Duration::from_millis(150)), Some(40));
+        assert_eq!(p.frame_after(Duration::from_millis(250)), None);
+    }
+}
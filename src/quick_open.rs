@@ -0,0 +1,69 @@
+//! Dialog shown when the viewer is started without an input: the recently opened
+//! sequences to pick from, and a field to type a frame path, `user@host:path` or URL.
+use anyhow::{anyhow, Result};
+use eframe::egui;
+use zapvis::recent::RecentEntry;
+
+/// Ask which input to open; `None` when the dialog is closed without one. A picked
+/// entry comes back as it was recorded, a typed input without a pattern.
+pub fn run(recent: Vec<RecentEntry>) -> Result<Option<(String, Option<String>)>> {
+    let mut chosen = None;
+    let mut native_options = eframe::NativeOptions::default();
+    native_options.viewport = native_options.viewport.with_inner_size([640.0, 420.0]);
+    eframe::run_native(
+        "zapvis: open",
+        native_options,
+        Box::new(|_cc| {
+            Ok(Box::new(QuickOpen {
+                recent,
+                typed: String::new(),
+                chosen: &mut chosen,
+            }))
+        }),
+    )
+    .map_err(|e| anyhow!(e.to_string()))?;
+    Ok(chosen)
+}
+
+struct QuickOpen<'a> {
+    recent: Vec<RecentEntry>,
+    typed: String,
+    chosen: &'a mut Option<(String, Option<String>)>,
+}
+
+impl eframe::App for QuickOpen<'_> {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let mut open = None;
+        egui::TopBottomPanel::bottom("quick_open_input").show(ctx, |ui| {
+            ui.add_space(4.0);
+            ui.horizontal(|ui| {
+                ui.label("Open:");
+                let field = egui::TextEdit::singleline(&mut self.typed).hint_text("frame path, user@host:path or URL").desired_width(420.0);
+                let response = ui.add(field);
+                let entered = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if (ui.button("Open").clicked() || entered) && !self.typed.trim().is_empty() {
+                    open = Some((self.typed.trim().to_string(), None));
+                }
+            });
+            ui.add_space(4.0);
+        });
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("Recent sequences");
+            if self.recent.is_empty() {
+                ui.label("None yet: sequences opened in the viewer are listed here.");
+            }
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for entry in &self.recent {
+                    let response = ui.selectable_label(false, &entry.input).on_hover_text(format!("Pattern: {}", entry.pattern));
+                    if response.clicked() {
+                        open = Some((entry.input.clone(), Some(entry.pattern.clone())));
+                    }
+                }
+            });
+        });
+        if let Some(input) = open {
+            *self.chosen = Some(input);
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+    }
+}
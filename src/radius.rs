@@ -0,0 +1,70 @@
+//! Sizing of the prefetch window from what loading costs: small frames that decode in a
+//! few milliseconds get a wide window, giant EXRs that take a second each and hundreds
+//! of megabytes get a narrow one. See [`crate::frame_cache::FrameCache::set_adaptive_radius`].
+use std::time::Duration;
+
+/// Narrowest window the adaptation shrinks to, in steps on each side
+pub const MIN_ADAPTIVE_RADIUS: usize = 2;
+
+/// Loader time one side of the window may take to fill; frames further out would rarely
+/// be ready before the index moves on anyway
+const FILL_TIME: Duration = Duration::from_secs(3);
+
+/// What the frames of a sequence have cost so far
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoadCost {
+    /// Mean fetch + decode time of one frame
+    pub mean_load: Duration,
+    /// Mean pixel memory of one decoded frame
+    pub frame_bytes: u64,
+    /// Loader threads working side by side
+    pub threads: usize,
+}
+
+/// Radius for frames costing `cost`, between [`MIN_ADAPTIVE_RADIUS`] and `max`: as many
+/// as the loader fills in [`FILL_TIME`], and no more than `memory_budget` bytes hold.
+pub fn adaptive_radius(cost: LoadCost, memory_budget: u64, max: usize) -> usize {
+    let max = max.max(MIN_ADAPTIVE_RADIUS);
+    let by_time = match cost.mean_load.as_secs_f64() {
+        secs if secs > 0.0 => (FILL_TIME.as_secs_f64() * cost.threads.max(1) as f64 / secs) as usize,
+        _ => max,
+    };
+    // 2 * radius + 1 frames are kept
+    let by_memory = match memory_budget.checked_div(cost.frame_bytes) {
+        Some(frames) => (frames.saturating_sub(1) / 2) as usize,
+        None => max,
+    };
+    by_time.min(by_memory).clamp(MIN_ADAPTIVE_RADIUS, max)
+}
+
+/// Memory the system could still hand out (`MemAvailable`), where it is known (Linux)
+pub fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let kb = meminfo.lines().find_map(|l| l.strip_prefix("MemAvailable:"))?;
+    kb.trim().trim_end_matches("kB").trim().parse::<u64>().ok().map(|kb| kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cheap_frames_widen_the_window_and_costly_ones_narrow_it() {
+        let cost = |ms, mb: u64| LoadCost {
+            mean_load: Duration::from_millis(ms),
+            frame_bytes: mb * 1_000_000,
+            threads: 1,
+        };
+        let gb = 1_000_000_000;
+        // 8 MB PNGs in 10 ms: the time allows 300, memory 62
+        assert_eq!(adaptive_radius(cost(10, 8), gb, 40), 40);
+        assert_eq!(adaptive_radius(cost(10, 8), gb, 100), 62);
+        // 400 MB EXRs in a second: three fit in the time, but only one in memory
+        assert_eq!(adaptive_radius(cost(1000, 400), 10 * gb, 40), 3);
+        assert_eq!(adaptive_radius(cost(1000, 400), gb, 40), MIN_ADAPTIVE_RADIUS);
+        // Four threads fill four times as far
+        let threaded = LoadCost { threads: 4, ..cost(500, 1) };
+        assert_eq!(adaptive_radius(threaded, gb, 40), 24);
+        assert_eq!(adaptive_radius(cost(0, 0), 0, 40), 40);
+    }
+}
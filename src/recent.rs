@@ -0,0 +1,102 @@
+//! Recently opened sequences, newest first, kept in a file under the data directory and
+//! offered for reopening when the viewer is started without an input.
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::ConfigError;
+use crate::session::session_path;
+
+/// Entries kept; older ones drop off the end
+pub const MAX_RECENT: usize = 20;
+
+/// One opened sequence
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentEntry {
+    /// What reopens it, as given on the command line: a frame path, `user@host:path`,
+    /// URL, manifest or video
+    pub input: String,
+    /// Pattern it was viewed with, preferred when several match again
+    pub pattern: String,
+    /// `SequenceSpec::sequence_key`, so that frames of one sequence share an entry
+    pub sequence_key: String,
+}
+
+/// Contents of the recent file (TOML), newest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecentFile {
+    #[serde(default)]
+    pub recent: Vec<RecentEntry>,
+}
+
+/// The recent file, beside the sessions file under the data directory
+pub fn recent_path() -> Result<PathBuf, ConfigError> {
+    Ok(session_path()?.with_file_name("recent.toml"))
+}
+
+/// Read a recent file; a missing one is empty.
+pub fn load_recent_file(path: &Path) -> Result<RecentFile, ConfigError> {
+    if !path.exists() {
+        return Ok(RecentFile::default());
+    }
+    let txt = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    toml::from_str(&txt).map_err(|source| ConfigError::Parse {
+        name: format!("recent sequences {}", path.display()),
+        source,
+    })
+}
+
+pub fn save_recent_file(path: &Path, file: &RecentFile) -> Result<(), ConfigError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    let txt = toml::to_string_pretty(file).map_err(|source| ConfigError::Serialize {
+        name: "recent sequences".to_string(),
+        source,
+    })?;
+    fs::write(path, txt).map_err(|source| ConfigError::Write {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Put `entry` first in the file at `path`, replacing the older entry of its sequence
+pub fn record_recent(path: &Path, entry: RecentEntry) -> Result<(), ConfigError> {
+    let mut file = load_recent_file(path)?;
+    file.recent.retain(|e| e.sequence_key != entry.sequence_key);
+    file.recent.insert(0, entry);
+    file.recent.truncate(MAX_RECENT);
+    save_recent_file(path, &file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reopened_sequences_move_to_the_front() {
+        let path = std::env::temp_dir().join(format!("zapvis_recent_{}.toml", std::process::id()));
+        let entry = |input: &str, key: &str| RecentEntry {
+            input: input.to_string(),
+            pattern: "f_####.png".to_string(),
+            sequence_key: key.to_string(),
+        };
+        assert!(load_recent_file(&path).unwrap().recent.is_empty());
+        record_recent(&path, entry("/shots/a/f_0001.png", "/shots/a|f_####.png")).unwrap();
+        record_recent(&path, entry("me@host:/shots/b/f_0007.png", "me@host:/shots/b|f_####.png")).unwrap();
+        record_recent(&path, entry("/shots/a/f_0450.png", "/shots/a|f_####.png")).unwrap();
+
+        let file = load_recent_file(&path).unwrap();
+        let inputs: Vec<&str> = file.recent.iter().map(|e| e.input.as_str()).collect();
+        assert_eq!(inputs, ["/shots/a/f_0450.png", "me@host:/shots/b/f_0007.png"]);
+
+        for i in 0..MAX_RECENT {
+            record_recent(&path, entry(&format!("/x/{i}.png"), &i.to_string())).unwrap();
+        }
+        assert_eq!(load_recent_file(&path).unwrap().recent.len(), MAX_RECENT);
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,213 @@
+//! Decoding part of a frame: when zoomed into a small region of a huge TIFF, only the
+//! strips or tiles overlapping it are read and decompressed, instead of the whole frame
+//! for every step. Other formats, and TIFFs the region reader does not handle (planar,
+//! float, rotated by an orientation tag the viewer honors), are decoded whole.
+use image::error::{DecodingError, ImageFormatHint};
+use image::{DynamicImage, ImageBuffer, ImageError, ImageFormat};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::path::Path;
+use tiff::decoder::{ChunkType, Decoder, DecodingResult};
+use tiff::tags::Tag;
+use tiff::ColorType;
+use tracing::debug_span;
+
+use crate::error::CacheError;
+use crate::frame::{finish_decode, DecodeOptions, FramePixels};
+use crate::orientation::Orientation;
+
+/// Largest share of a frame's pixels worth decoding as a region; beyond it the whole
+/// frame is decoded, which reads the file front to back
+pub const MAX_REGION_SHARE: f64 = 0.5;
+
+/// Where a region decode lies in its frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRegion {
+    /// `[x0, y0, x1, y1]` in frame pixels
+    pub bounds: [u32; 4],
+    pub full_size: (u32, u32),
+}
+
+impl FrameRegion {
+    /// True if the decoded pixels include all of `bounds` that lies in the frame
+    pub fn contains(&self, bounds: [u32; 4]) -> bool {
+        let [x0, y0, x1, y1] = self.bounds;
+        let (w, h) = self.full_size;
+        bounds[0] >= x0 && bounds[1] >= y0 && bounds[2].min(w) <= x1 && bounds[3].min(h) <= y1
+    }
+}
+
+/// Decode the pixels of the frame at `path` within `want` (`[x0, y0, x1, y1]`).
+/// `Ok(None)` when the frame is not a TIFF the region reader handles, or the region is
+/// most of it; the caller decodes the whole frame then.
+pub fn load_frame_region(path: &Path, want: [u32; 4], opts: DecodeOptions) -> Result<Option<(FramePixels, FrameRegion)>, CacheError> {
+    let _span = debug_span!("decode_region", path = %path.display()).entered();
+    let file = File::open(path).map_err(|source| match source.kind() {
+        std::io::ErrorKind::NotFound => CacheError::NotFound(path.display().to_string()),
+        _ => CacheError::Read {
+            path: path.display().to_string(),
+            source,
+        },
+    })?;
+    decode_region(BufReader::new(file), want, opts, &path.display().to_string())
+}
+
+/// [`load_frame_region`] for a frame fetched into memory
+pub fn load_frame_region_from_bytes(bytes: &[u8], source: &str, want: [u32; 4], opts: DecodeOptions) -> Result<Option<(FramePixels, FrameRegion)>, CacheError> {
+    let _span = debug_span!("decode_region", path = source, bytes = bytes.len()).entered();
+    decode_region(Cursor::new(bytes), want, opts, source)
+}
+
+fn decode_region<R: Read + Seek>(mut reader: R, want: [u32; 4], opts: DecodeOptions, source: &str) -> Result<Option<(FramePixels, FrameRegion)>, CacheError> {
+    let mut magic = [0; 4];
+    if reader.read_exact(&mut magic).is_err() || !matches!(&magic, b"II*\0" | b"MM\0*") {
+        return Ok(None);
+    }
+    reader.rewind().map_err(|e| CacheError::Read {
+        path: source.to_string(),
+        source: e,
+    })?;
+    let failed = |e: tiff::TiffError| CacheError::Decode {
+        path: source.to_string(),
+        source: ImageError::Decoding(DecodingError::new(ImageFormatHint::Exact(ImageFormat::Tiff), e)),
+    };
+    let mut decoder = Decoder::new(reader).map_err(failed)?;
+    let Some((img, region)) = read_region(&mut decoder, want, opts).map_err(failed)? else {
+        return Ok(None);
+    };
+    let icc = if opts.color_manage { decoder.get_tag_u8_vec(Tag::IccProfile).ok() } else { None };
+    Ok(Some((finish_decode(img, Orientation::default(), icc.as_deref(), opts), region)))
+}
+
+/// The chunks of the decoder's image overlapping `want`, cropped to it
+fn read_region<R: Read + Seek>(decoder: &mut Decoder<R>, want: [u32; 4], opts: DecodeOptions) -> tiff::TiffResult<Option<(DynamicImage, FrameRegion)>> {
+    let (w, h) = decoder.dimensions()?;
+    let planar = decoder.find_tag_unsigned::<u16>(Tag::PlanarConfiguration)?.is_some_and(|p| p != 1);
+    let rotated = opts.honor_exif_orientation && decoder.find_tag_unsigned::<u16>(Tag::Orientation)?.is_some_and(|o| o != 1);
+    let downscaled = opts.max_decode_dim > 0 && w.max(h) > opts.max_decode_dim;
+    let [x0, y0, x1, y1] = [want[0].min(w), want[1].min(h), want[2].min(w), want[3].min(h)];
+    let (rw, rh) = (x1.saturating_sub(x0), y1.saturating_sub(y0));
+    let share = (rw as f64 * rh as f64) / (w as f64 * h as f64).max(1.0);
+    let samples = match decoder.colortype()? {
+        ColorType::Gray(8 | 16) => 1,
+        ColorType::GrayA(8 | 16) => 2,
+        ColorType::RGB(8 | 16) => 3,
+        ColorType::RGBA(8 | 16) => 4,
+        _ => return Ok(None),
+    };
+    if planar || rotated || downscaled || rw == 0 || rh == 0 || share > MAX_REGION_SHARE {
+        return Ok(None);
+    }
+    let (cw, ch) = decoder.chunk_dimensions();
+    let across = match decoder.get_chunk_type() {
+        ChunkType::Strip => 1,
+        ChunkType::Tile => w.div_ceil(cw),
+    };
+    let mut u8s = Vec::new();
+    let mut u16s = Vec::new();
+    for row in y0 / ch..y1.div_ceil(ch) {
+        for col in x0 / cw..x1.div_ceil(cw) {
+            let chunk = row * across + col;
+            let (dw, dh) = decoder.chunk_data_dimensions(chunk);
+            let (cx, cy) = (col * cw, row * ch);
+            // Chunk rows and columns inside the region, in frame pixels
+            let within = [x0.max(cx), y0.max(cy), x1.min(cx + dw), y1.min(cy + dh)];
+            let part = ChunkPart {
+                within,
+                chunk: (cx, cy, dw),
+                region: (x0, y0, rw, rh),
+                samples,
+            };
+            match decoder.read_chunk(chunk)? {
+                DecodingResult::U8(data) => part.rows(&data, &mut u8s),
+                DecodingResult::U16(data) => part.rows(&data, &mut u16s),
+                _ => return Ok(None),
+            }
+        }
+    }
+    let img = match (samples, u8s.is_empty()) {
+        (1, false) => ImageBuffer::from_raw(rw, rh, u8s).map(DynamicImage::ImageLuma8),
+        (2, false) => ImageBuffer::from_raw(rw, rh, u8s).map(DynamicImage::ImageLumaA8),
+        (3, false) => ImageBuffer::from_raw(rw, rh, u8s).map(DynamicImage::ImageRgb8),
+        (4, false) => ImageBuffer::from_raw(rw, rh, u8s).map(DynamicImage::ImageRgba8),
+        (1, true) => ImageBuffer::from_raw(rw, rh, u16s).map(DynamicImage::ImageLuma16),
+        (2, true) => ImageBuffer::from_raw(rw, rh, u16s).map(DynamicImage::ImageLumaA16),
+        (3, true) => ImageBuffer::from_raw(rw, rh, u16s).map(DynamicImage::ImageRgb16),
+        _ => ImageBuffer::from_raw(rw, rh, u16s).map(DynamicImage::ImageRgba16),
+    };
+    let region = FrameRegion {
+        bounds: [x0, y0, x1, y1],
+        full_size: (w, h),
+    };
+    Ok(img.map(|img| (img, region)))
+}
+
+/// The part of one chunk that lies in the region
+struct ChunkPart {
+    /// `[x0, y0, x1, y1]` in frame pixels
+    within: [u32; 4],
+    /// Origin and width of the chunk's data
+    chunk: (u32, u32, u32),
+    /// Origin and size of the region
+    region: (u32, u32, u32, u32),
+    samples: u32,
+}
+
+impl ChunkPart {
+    /// Copy the rows of `within` from chunk `src` to region `dst`, allocating it first
+    fn rows<T: Copy + Default>(&self, src: &[T], dst: &mut Vec<T>) {
+        let [from_x, from_y, to_x, to_y] = self.within;
+        let (cx, cy, dw) = self.chunk;
+        let (x0, y0, rw, rh) = self.region;
+        if dst.is_empty() {
+            dst.resize((rw * rh * self.samples) as usize, T::default());
+        }
+        let len = ((to_x - from_x) * self.samples) as usize;
+        for y in from_y..to_y {
+            let s = (((y - cy) * dw + (from_x - cx)) * self.samples) as usize;
+            let d = (((y - y0) * rw + (from_x - x0)) * self.samples) as usize;
+            dst[d..d + len].copy_from_slice(&src[s..s + len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    #[test]
+    fn only_the_region_is_decoded() {
+        let (w, h) = (300u32, 200u32);
+        let value = |x: u32, y: u32| (x + 3 * y) as u16;
+        let data: Vec<u16> = (0..h).flat_map(|y| (0..w).map(move |x| value(x, y))).collect();
+        let mut bytes = Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut bytes).unwrap();
+        let mut image = encoder.new_image::<colortype::Gray16>(w, h).unwrap();
+        image.rows_per_strip(16).unwrap();
+        image.write_data(&data).unwrap();
+        let bytes = bytes.into_inner();
+
+        let opts = DecodeOptions {
+            honor_exif_orientation: true,
+            ..DecodeOptions::raw()
+        };
+        let mut decoder = Decoder::new(Cursor::new(&bytes)).unwrap();
+        let (img, region) = read_region(&mut decoder, [40, 30, 140, 90], opts).unwrap().unwrap();
+        assert_eq!(region.bounds, [40, 30, 140, 90]);
+        assert_eq!(region.full_size, (300, 200));
+        let img = img.as_luma16().unwrap();
+        assert_eq!(img.dimensions(), (100, 60));
+        assert_eq!(img.get_pixel(0, 0).0, [value(40, 30)]);
+        assert_eq!(img.get_pixel(99, 59).0, [value(139, 89)]);
+        assert!(region.contains([50, 40, 140, 80]) && !region.contains([50, 40, 141, 80]));
+        let edge = FrameRegion { bounds: [200, 100, 300, 200], ..region };
+        assert!(edge.contains([250, 150, 400, 400]));
+
+        // Most of the frame, or not a TIFF: decoded whole
+        assert!(load_frame_region_from_bytes(&bytes, "big.tif", [0, 0, 300, 150], opts).unwrap().is_none());
+        assert!(load_frame_region_from_bytes(b"\x89PNG....", "f.png", [0, 0, 10, 10], opts).unwrap().is_none());
+        let (pixels, _) = load_frame_region_from_bytes(&bytes, "big.tif", [0, 0, 10, 10], opts).unwrap().unwrap();
+        assert_eq!(pixels.dimensions(), (10, 10));
+    }
+}
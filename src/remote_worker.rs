@@ -1,36 +1,169 @@
-use anyhow::{anyhow, Result};
 use std::sync::{
-    Arc,
-    atomic::{AtomicU64, Ordering},
-    mpsc::{channel, Sender},
+    Arc, Mutex, PoisonError,
+    atomic::{AtomicI64, AtomicU64, Ordering},
+    mpsc::{channel, Receiver, RecvTimeoutError, Sender},
 };
 use std::thread;
-use zapvis::PersistentSsh;
+use std::time::{Duration, Instant};
+use tracing::{debug, debug_span, info, warn};
+use crate::bounds::{discover_bounds, SequenceBounds};
+use crate::error::SshError;
+use crate::sequence::{build_remote_path, SequenceSource, SequenceSpec};
+use crate::throttle::Throttle;
+use crate::watchdog::Heartbeat;
+use crate::PersistentSsh;
+
+type Result<T> = std::result::Result<T, SshError>;
 
 /// Shared range state for remote worker to check if requests are still needed
 #[derive(Clone)]
 pub struct RemoteRange {
-    min: Arc<AtomicU64>,
-    max: Arc<AtomicU64>,
+    min: Arc<AtomicI64>,
+    max: Arc<AtomicI64>,
+    /// Frames wanted wherever the range is (see `FrameCache::set_pinned`)
+    pinned: Arc<Mutex<Vec<i64>>>,
+    /// Frame on screen, fetched ahead of the transfer limits
+    current: Arc<AtomicI64>,
+}
+
+impl Default for RemoteRange {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RemoteRange {
     pub fn new() -> Self {
         Self {
-            min: Arc::new(AtomicU64::new(0)),
-            max: Arc::new(AtomicU64::new(u64::MAX)),
+            min: Arc::new(AtomicI64::new(i64::MIN)),
+            max: Arc::new(AtomicI64::new(i64::MAX)),
+            pinned: Arc::new(Mutex::new(Vec::new())),
+            current: Arc::new(AtomicI64::new(i64::MIN)),
         }
     }
 
-    pub fn set(&self, min: u64, max: u64) {
+    pub fn set_current(&self, idx: i64) {
+        self.current.store(idx, Ordering::Relaxed);
+    }
+
+    pub fn is_current(&self, idx: i64) -> bool {
+        self.current.load(Ordering::Relaxed) == idx
+    }
+
+    pub fn set(&self, min: i64, max: i64) {
         self.min.store(min, Ordering::Relaxed);
         self.max.store(max, Ordering::Relaxed);
     }
 
-    pub fn contains(&self, idx: u64) -> bool {
+    pub fn set_pinned(&self, frames: &[i64]) {
+        *self.pinned.lock().unwrap_or_else(PoisonError::into_inner) = frames.to_vec();
+    }
+
+    pub fn contains(&self, idx: i64) -> bool {
         let min = self.min.load(Ordering::Relaxed);
         let max = self.max.load(Ordering::Relaxed);
-        idx >= min && idx <= max
+        (idx >= min && idx <= max) || self.pinned.lock().unwrap_or_else(PoisonError::into_inner).contains(&idx)
+    }
+}
+
+/// Connection state of the remote worker's SSH session
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    #[default]
+    Connecting,
+    Connected,
+    Reconnecting,
+    Failed(String),
+}
+
+/// Counters and connection state updated by the remote worker thread, readable from the UI
+#[derive(Clone, Default)]
+pub struct RemoteStats {
+    pub(crate) requests: Arc<AtomicU64>,
+    pub(crate) bytes: Arc<AtomicU64>,
+    pub(crate) errors: Arc<AtomicU64>,
+    pub(crate) skipped: Arc<AtomicU64>,
+    /// Requests routed to a channel and not picked up by a worker yet
+    pub(crate) queued: Arc<AtomicU64>,
+    /// Workers replaced by the watchdog after dying or getting stuck
+    restarts: Arc<AtomicU64>,
+    connection: Arc<Mutex<(ConnectionState, Option<Duration>)>>,
+    /// Host currently serving requests (differs from the primary after a failover)
+    host: Arc<Mutex<String>>,
+    downloads: Arc<Mutex<Vec<Download>>>,
+}
+
+/// A CAT in progress
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Download {
+    pub idx: i64,
+    pub received: usize,
+    pub total: usize,
+}
+
+/// Point-in-time copy of [`RemoteStats`]
+#[derive(Clone, Debug, Default)]
+pub struct RemoteStatsSnapshot {
+    pub requests: u64,
+    pub bytes: u64,
+    pub errors: u64,
+    pub skipped: u64,
+    /// Requests waiting for a free SSH session
+    pub queued: u64,
+    pub restarts: u64,
+    pub state: ConnectionState,
+    /// Round-trip time of the last PING
+    pub latency: Option<Duration>,
+    pub host: String,
+    /// The CATs being received right now, one per busy transfer channel
+    pub downloads: Vec<Download>,
+}
+
+impl RemoteStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> RemoteStatsSnapshot {
+        let (state, latency) = self.connection.lock().map(|c| c.clone()).unwrap_or_default();
+        RemoteStatsSnapshot {
+            requests: self.requests.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+            queued: self.queued.load(Ordering::Relaxed),
+            restarts: self.restarts.load(Ordering::Relaxed),
+            state,
+            latency,
+            host: self.host.lock().map(|h| h.clone()).unwrap_or_default(),
+            downloads: self.downloads.lock().map(|d| d.clone()).unwrap_or_default(),
+        }
+    }
+
+    /// Record the progress of the CAT for `idx` (`None` once it is done)
+    pub(crate) fn set_download(&self, idx: i64, download: Option<Download>) {
+        if let Ok(mut d) = self.downloads.lock() {
+            d.retain(|d| d.idx != idx);
+            d.extend(download);
+        }
+    }
+
+    pub(crate) fn set_state(&self, state: ConnectionState) {
+        if let Ok(mut c) = self.connection.lock() {
+            c.0 = state;
+        }
+    }
+
+    pub(crate) fn set_host(&self, host: &str) {
+        if let Ok(mut h) = self.host.lock() {
+            *h = host.to_string();
+        }
+    }
+
+    pub(crate) fn set_latency(&self, latency: Duration) {
+        if let Ok(mut c) = self.connection.lock() {
+            c.1 = Some(latency);
+        }
     }
 }
 
@@ -41,47 +174,490 @@ pub enum RemoteWorkerRequest {
         response_tx: Sender<Result<bool>>,
     },
     Cat {
-        idx: u64,
+        idx: i64,
         path: String,
         response_tx: Sender<Result<Vec<u8>>>,
+        /// Receives the bytes so far every [`PARTIAL_INTERVAL`] while a large file arrives
+        partial_tx: Option<Sender<PartialCat>>,
+    },
+    /// Find the first and last frame of `seq` around its current index with EXISTS probes
+    /// (see [`discover_bounds`]). Answered from the bounds found before for the same
+    /// frames as long as they contain the index.
+    FindBounds {
+        seq: Box<SequenceSpec>,
+        response_tx: Sender<Result<SequenceBounds>>,
     },
+    /// Absolute form of a directory given relative to the home directory or with `~`
+    Resolve {
+        dir: String,
+        response_tx: Sender<Result<String>>,
+    },
+    /// Drop the current session (if any) and connect again
+    Reconnect,
+}
+
+/// The beginning of a file still being received through CAT
+pub struct PartialCat {
+    pub bytes: Vec<u8>,
+    /// Size of the whole file
+    pub total: usize,
+}
+
+/// Minimum time between two [`PartialCat`]s of one request
+pub const PARTIAL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Idle time after which the worker pings the remote end (latency + liveness check)
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Longest wait between two reconnect attempts while no host answers; the wait starts
+/// at [`KEEPALIVE_INTERVAL`] and doubles with every failed attempt
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// A host able to serve the sequence's files. Replicas hold the same paths as the primary.
+pub struct RemoteHost {
+    pub name: String,
+    connect: Box<dyn Fn() -> Result<PersistentSsh> + Send + Sync>,
+}
+
+impl RemoteHost {
+    pub fn new<F>(name: impl Into<String>, connect: F) -> Self
+    where
+        F: Fn() -> Result<PersistentSsh> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            connect: Box::new(connect),
+        }
+    }
+}
+
+/// The worker's session and which host it is connected to
+struct Session {
+    ssh: Option<PersistentSsh>,
+    active: usize,
+    /// When keepalive may try to connect again while disconnected
+    retry_at: Instant,
+    /// Wait after the next failed attempt
+    retry_delay: Duration,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            ssh: None,
+            active: 0,
+            retry_at: Instant::now(),
+            retry_delay: KEEPALIVE_INTERVAL,
+        }
+    }
+}
+
+/// State shared by the worker threads and the replacements the watchdog starts
+struct Shared {
+    /// EXISTS requests, served by the control channel (worker 0) so they never wait
+    /// behind a large transfer
+    control_rx: Mutex<Receiver<RemoteWorkerRequest>>,
+    /// CAT requests, taken by whichever transfer channel is free
+    transfer_rx: Mutex<Receiver<RemoteWorkerRequest>>,
+    hosts: Vec<RemoteHost>,
+    range: RemoteRange,
+    stats: RemoteStats,
+    slow_latency: Option<Duration>,
+    /// Generation of the current worker in each slot; replaced workers exit when they notice
+    generations: Vec<AtomicU64>,
+    /// Bumped on every [`RemoteWorkerRequest::Reconnect`]; each worker reconnects once it
+    /// sees a new value
+    reconnects: AtomicU64,
+    /// Results of [`RemoteWorkerRequest::FindBounds`], one per sequence
+    bounds: Mutex<Vec<(SequenceSpec, SequenceBounds)>>,
+    throttle: Throttle,
 }
 
-/// Spawn a remote worker thread that exclusively owns the SSH connection
-/// and processes requests serially. Returns the request sender.
-pub fn spawn_remote_worker(ssh: PersistentSsh, range: RemoteRange) -> Sender<RemoteWorkerRequest> {
+/// How often the watchdog looks at the worker's heartbeat
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Spawn the remote worker threads, which exclusively own the SSH sessions. Returns
+/// the request sender.
+///
+/// EXISTS checks go to a control channel with its own session, and CATs are spread over
+/// `transfer_channels` further sessions (at least one), so a jump of the cache window
+/// fetches that many frames in parallel while existence checks keep a low latency.
+///
+/// `hosts` lists the primary first, then replicas holding the same dataset. Each worker
+/// establishes its session itself, reports its state through `stats`, and fails over
+/// to the next host when a session dies (retrying the request there) or, with
+/// `slow_latency` set, when keepalive pings get slower than that. While no host
+/// answers, it keeps trying to connect with a growing delay. All sessions reconnect
+/// on demand after [`RemoteWorkerRequest::Reconnect`].
+///
+/// CATs of frames other than the one on screen are held to the bandwidth and in-flight
+/// limits of `throttle`.
+///
+/// With `stall_timeout` set, a watchdog thread replaces a worker when it panics or
+/// spends longer than that on one request (a hung connection). The replacement opens
+/// its own session and picks up the queued requests; only the request the old worker
+/// was stuck on is lost, and the old thread exits if it ever returns.
+pub fn spawn_remote_worker(
+    hosts: Vec<RemoteHost>,
+    range: RemoteRange,
+    stats: RemoteStats,
+    slow_latency: Option<Duration>,
+    stall_timeout: Option<Duration>,
+    transfer_channels: usize,
+    throttle: Throttle,
+) -> Sender<RemoteWorkerRequest> {
     let (tx, rx) = channel::<RemoteWorkerRequest>();
+    let (control_tx, control_rx) = channel::<RemoteWorkerRequest>();
+    let (transfer_tx, transfer_rx) = channel::<RemoteWorkerRequest>();
+    let shared = Arc::new(Shared {
+        control_rx: Mutex::new(control_rx),
+        transfer_rx: Mutex::new(transfer_rx),
+        hosts,
+        range,
+        stats,
+        slow_latency,
+        generations: (0..=transfer_channels.max(1)).map(|_| AtomicU64::new(0)).collect(),
+        reconnects: AtomicU64::new(0),
+        bounds: Mutex::new(Vec::new()),
+        throttle,
+    });
 
+    // Route each request to its channel; the workers finish once this thread drops the senders
+    let dispatch = shared.clone();
     thread::spawn(move || {
-        let mut ssh = ssh;
         while let Ok(req) = rx.recv() {
-            match req {
-                RemoteWorkerRequest::Exists { path, response_tx } => {
-                    eprintln!("[SSH worker] executing: exists {}", path);
-                    let result = ssh.exists(&path);
-                    let _ = response_tx.send(result);
+            if !matches!(req, RemoteWorkerRequest::Reconnect) {
+                dispatch.stats.queued.fetch_add(1, Ordering::Relaxed);
+            }
+            let routed = match req {
+                RemoteWorkerRequest::Cat { .. } => transfer_tx.send(req),
+                RemoteWorkerRequest::Exists { .. }
+                | RemoteWorkerRequest::FindBounds { .. }
+                | RemoteWorkerRequest::Resolve { .. } => control_tx.send(req),
+                RemoteWorkerRequest::Reconnect => {
+                    dispatch.reconnects.fetch_add(1, Ordering::Relaxed);
+                    // Wake the control channel so the new state shows right away
+                    control_tx.send(req)
                 }
-                RemoteWorkerRequest::Cat { idx, path, response_tx } => {
-                    // Check if idx is still in range before executing expensive cat
-                    if !range.contains(idx) {
-                        eprintln!("[SSH worker] cat SKIP idx={} (out of range)", idx);
-                        let _ = response_tx.send(Err(anyhow!("cancelled: out of range")));
-                        continue;
-                    }
+            };
+            if routed.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut heartbeats: Vec<Heartbeat> = (0..shared.generations.len()).map(|slot| start_worker(&shared, slot, 0)).collect();
+    if let Some(limit) = stall_timeout {
+        thread::spawn(move || loop {
+            thread::sleep(WATCHDOG_INTERVAL);
+            if heartbeats.iter().all(Heartbeat::is_finished) {
+                break;
+            }
+            for (slot, heartbeat) in heartbeats.iter_mut().enumerate() {
+                let Some(stall) = heartbeat.check(limit) else {
+                    continue;
+                };
+                let generation = shared.generations[slot].fetch_add(1, Ordering::Relaxed) + 1;
+                warn!("worker {} {}, starting a new one", slot, stall);
+                shared.stats.restarts.fetch_add(1, Ordering::Relaxed);
+                *heartbeat = start_worker(&shared, slot, generation);
+            }
+        });
+    }
+
+    tx
+}
+
+fn start_worker(shared: &Arc<Shared>, slot: usize, generation: u64) -> Heartbeat {
+    let heartbeat = Heartbeat::default();
+    let (shared, beat) = (shared.clone(), heartbeat.clone());
+    thread::spawn(move || run_worker(&shared, slot, generation, &beat));
+    heartbeat
+}
 
-                    eprintln!("[SSH worker] executing: cat {} (idx={})", path, idx);
-                    let result = ssh.cat(&path);
-                    if let Ok(ref bytes) = result {
-                        eprintln!("[SSH worker] cat result: {} bytes", bytes.len());
-                    } else {
-                        eprintln!("[SSH worker] cat error");
+/// Worker `slot` 0 serves the control channel, the others share the transfers
+fn run_worker(shared: &Shared, slot: usize, generation: u64, heartbeat: &Heartbeat) {
+    let _alive = heartbeat.alive();
+    let Shared { hosts, range, stats, slow_latency, .. } = shared;
+    let rx = if slot == 0 { &shared.control_rx } else { &shared.transfer_rx };
+    let replaced = || shared.generations[slot].load(Ordering::Relaxed) != generation;
+    let mut reconnects = shared.reconnects.load(Ordering::Relaxed);
+    stats.set_state(ConnectionState::Connecting);
+    let mut session = Session::new();
+    heartbeat.busy();
+    establish(hosts, &mut session, stats);
+    heartbeat.idle();
+    loop {
+        if replaced() {
+            info!("replaced by the watchdog, exiting");
+            break;
+        }
+        let next = rx.lock().unwrap_or_else(PoisonError::into_inner).recv_timeout(KEEPALIVE_INTERVAL);
+        heartbeat.busy();
+        let requested = shared.reconnects.load(Ordering::Relaxed);
+        if requested != reconnects {
+            reconnects = requested;
+            info!("manual reconnect");
+            if let Some(old) = session.ssh.take() {
+                old.close();
+            }
+            stats.set_state(ConnectionState::Reconnecting);
+            establish(hosts, &mut session, stats);
+        }
+        let req = match next {
+            Ok(RemoteWorkerRequest::Reconnect) => RemoteWorkerRequest::Reconnect,
+            Ok(req) => {
+                stats.queued.fetch_sub(1, Ordering::Relaxed);
+                req
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                keepalive(hosts, &mut session, stats, *slow_latency);
+                heartbeat.idle();
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                heartbeat.finish();
+                break;
+            }
+        };
+        match req {
+            // Already handled above, through `shared.reconnects`
+            RemoteWorkerRequest::Reconnect => {}
+            RemoteWorkerRequest::Exists { path, response_tx } => {
+                let _span = debug_span!("exists", path = %path).entered();
+                stats.requests.fetch_add(1, Ordering::Relaxed);
+                let result = with_session(hosts, &mut session, stats, |s| s.exists(&path));
+                if result.is_err() {
+                    stats.errors.fetch_add(1, Ordering::Relaxed);
+                }
+                let _ = response_tx.send(result);
+            }
+            RemoteWorkerRequest::Resolve { dir, response_tx } => {
+                let _span = debug_span!("resolve", dir = %dir).entered();
+                stats.requests.fetch_add(1, Ordering::Relaxed);
+                let result = with_session(hosts, &mut session, stats, |s| s.resolve(&dir));
+                if result.is_err() {
+                    stats.errors.fetch_add(1, Ordering::Relaxed);
+                }
+                let _ = response_tx.send(result);
+            }
+            RemoteWorkerRequest::FindBounds { seq, response_tx } => {
+                let _span = debug_span!("find_bounds", index = seq.index).entered();
+                let result = find_bounds(shared, &mut session, heartbeat, &seq);
+                match &result {
+                    Ok(bounds) => debug!("frames {:?}..={:?}", bounds.first, bounds.last),
+                    Err(e) => warn!("bounds of {} not found: {}", seq.source.location(), e),
+                }
+                let _ = response_tx.send(result);
+            }
+            RemoteWorkerRequest::Cat { idx, path, response_tx, partial_tx } => {
+                // Check if idx is still in range before executing expensive cat (also
+                // while waiting for room under the transfer limits)
+                let transfer = shared.throttle.start(range.is_current(idx), || range.contains(idx));
+                let Some(mut transfer) = transfer.filter(|_| range.contains(idx)) else {
+                    debug!("cat SKIP idx={} (out of range)", idx);
+                    stats.skipped.fetch_add(1, Ordering::Relaxed);
+                    let _ = response_tx.send(Err(SshError::Cancelled));
+                    heartbeat.idle();
+                    continue;
+                };
+                let _span = debug_span!("cat", idx, path = %path).entered();
+                stats.requests.fetch_add(1, Ordering::Relaxed);
+                let mut last_partial = Instant::now();
+                let result = with_session(hosts, &mut session, stats, |s| {
+                    s.cat_with_progress(&path, |bytes, total| {
+                        transfer.progress(bytes.len(), total);
+                        stats.set_download(idx, Some(Download {
+                            idx,
+                            received: bytes.len(),
+                            total,
+                        }));
+                        let Some(tx) = &partial_tx else {
+                            return;
+                        };
+                        if bytes.len() < total && last_partial.elapsed() >= PARTIAL_INTERVAL {
+                            last_partial = Instant::now();
+                            let _ = tx.send(PartialCat {
+                                bytes: bytes.to_vec(),
+                                total,
+                            });
+                        }
+                    })
+                });
+                stats.set_download(idx, None);
+                match &result {
+                    Ok(bytes) => {
+                        debug!("received {} bytes", bytes.len());
+                        stats.bytes.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+                    }
+                    Err(SshError::NotFound(_)) => {
+                        debug!("not found");
+                        stats.errors.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(e) => {
+                        warn!("cat {} failed: {}", path, e);
+                        stats.errors.fetch_add(1, Ordering::Relaxed);
                     }
-                    let _ = response_tx.send(result);
                 }
+                let _ = response_tx.send(result);
             }
         }
-        eprintln!("[SSH worker] exiting");
-    });
+        heartbeat.idle();
+    }
+    if let Some(ssh) = session.ssh {
+        ssh.close();
+    }
+    info!("worker exiting");
+}
 
-    tx
+/// Bounds of `seq`, remembered ones if they contain its index, else probed on `session`
+fn find_bounds(shared: &Shared, session: &mut Session, heartbeat: &Heartbeat, seq: &SequenceSpec) -> Result<SequenceBounds> {
+    let remembered = |bounds: &Mutex<Vec<(SequenceSpec, SequenceBounds)>>| {
+        let bounds = bounds.lock().unwrap_or_else(PoisonError::into_inner);
+        bounds.iter().find(|(s, b)| s.same_frames(seq) && b.contains(seq.index)).map(|&(_, b)| b)
+    };
+    if let Some(bounds) = remembered(&shared.bounds) {
+        debug!("known already");
+        return Ok(bounds);
+    }
+    let SequenceSource::Remote { dir, .. } = &seq.source else {
+        return Err(SshError::Protocol("bounds requested for a local sequence".to_string()));
+    };
+    let Shared { hosts, stats, .. } = shared;
+    let found = discover_bounds(seq.index, seq.min_index(), |idx| {
+        // Each probe is progress; a long search on a slow link is not a stall
+        heartbeat.busy();
+        stats.requests.fetch_add(1, Ordering::Relaxed);
+        let path = build_remote_path(dir, &seq.relative_path_for(idx));
+        with_session(hosts, session, stats, |s| s.exists(&path)).inspect_err(|_| {
+            stats.errors.fetch_add(1, Ordering::Relaxed);
+        })
+    })?;
+    let mut bounds = shared.bounds.lock().unwrap_or_else(PoisonError::into_inner);
+    bounds.retain(|(s, _)| !s.same_frames(seq));
+    bounds.push((seq.clone(), found));
+    Ok(found)
+}
+
+/// Connect to one host and measure the initial latency.
+fn connect_host(host: &RemoteHost) -> Result<(PersistentSsh, Duration)> {
+    (host.connect)().and_then(|mut s| s.ping().map(|latency| (s, latency)))
+}
+
+/// Connect, trying the active host first and then the others in order, recording the
+/// outcome in `stats`.
+fn establish(hosts: &[RemoteHost], session: &mut Session, stats: &RemoteStats) {
+    let mut last_err = SshError::NoHosts;
+    for offset in 0..hosts.len() {
+        let i = (session.active + offset) % hosts.len();
+        match connect_host(&hosts[i]) {
+            Ok((ssh, latency)) => {
+                info!("connected to {} ({} ms)", hosts[i].name, latency.as_millis());
+                if i != session.active {
+                    warn!("failed over to replica {}", hosts[i].name);
+                }
+                session.ssh = Some(ssh);
+                session.active = i;
+                session.retry_delay = KEEPALIVE_INTERVAL;
+                stats.set_host(&hosts[i].name);
+                stats.set_latency(latency);
+                stats.set_state(ConnectionState::Connected);
+                return;
+            }
+            Err(e) => {
+                warn!("connection to {} failed: {}", hosts[i].name, e);
+                last_err = e;
+            }
+        }
+    }
+    session.ssh = None;
+    session.retry_at = Instant::now() + session.retry_delay;
+    info!("no host reachable, retrying in {} s", session.retry_delay.as_secs());
+    session.retry_delay = (session.retry_delay * 2).min(MAX_RECONNECT_DELAY);
+    stats.set_state(ConnectionState::Failed(last_err.to_string()));
+}
+
+/// Idle ping: detects dead sessions early and, with replicas, moves off a slow host.
+/// While disconnected, tries to connect again with a growing delay between attempts.
+fn keepalive(hosts: &[RemoteHost], session: &mut Session, stats: &RemoteStats, slow_latency: Option<Duration>) {
+    let Some(ssh) = session.ssh.as_mut() else {
+        if Instant::now() >= session.retry_at {
+            stats.set_state(ConnectionState::Reconnecting);
+            establish(hosts, session, stats);
+        }
+        return;
+    };
+    let latency = match ssh.ping() {
+        Ok(latency) => latency,
+        Err(e) => {
+            warn!("keepalive failed: {}", e);
+            session.ssh = None;
+            stats.set_state(ConnectionState::Reconnecting);
+            establish(hosts, session, stats);
+            return;
+        }
+    };
+    stats.set_latency(latency);
+    let Some(slow) = slow_latency else {
+        return;
+    };
+    if latency <= slow || hosts.len() < 2 {
+        return;
+    }
+    // Try the other hosts; switch to the first that answers faster than the threshold
+    for offset in 1..hosts.len() {
+        let i = (session.active + offset) % hosts.len();
+        match connect_host(&hosts[i]) {
+            Ok((replica, replica_latency)) if replica_latency < slow => {
+                warn!(
+                    "{} is slow ({} ms), switching to {} ({} ms)",
+                    hosts[session.active].name,
+                    latency.as_millis(),
+                    hosts[i].name,
+                    replica_latency.as_millis()
+                );
+                if let Some(old) = session.ssh.replace(replica) {
+                    old.close();
+                }
+                session.active = i;
+                stats.set_host(&hosts[i].name);
+                stats.set_latency(replica_latency);
+                return;
+            }
+            Ok((replica, _)) => replica.close(),
+            Err(e) => warn!("replica {} unavailable: {}", hosts[i].name, e),
+        }
+    }
+}
+
+/// Run `op` on the session, connecting first if there is none. If the session has died,
+/// reconnect (failing over to a replica if the host is gone) and retry once.
+fn with_session<T>(
+    hosts: &[RemoteHost],
+    session: &mut Session,
+    stats: &RemoteStats,
+    mut op: impl FnMut(&mut PersistentSsh) -> Result<T>,
+) -> Result<T> {
+    if session.ssh.is_none() {
+        stats.set_state(ConnectionState::Reconnecting);
+        establish(hosts, session, stats);
+    }
+    let ssh = session
+        .ssh
+        .as_mut()
+        .ok_or(SshError::NotConnected)?;
+    match op(ssh) {
+        Ok(v) => Ok(v),
+        Err(e) if ssh.is_alive() => Err(e),
+        Err(e) => {
+            warn!("session died ({}), reconnecting", e);
+            session.ssh = None;
+            stats.set_state(ConnectionState::Reconnecting);
+            establish(hosts, session, stats);
+            match session.ssh.as_mut() {
+                Some(ssh) => op(ssh),
+                None => Err(e),
+            }
+        }
+    }
 }
@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use zapvis::frame::SourceValue;
+
+/// One picked pixel: frame index, pixel position in the decoded frame and its color
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub index: i64,
+    pub x: u32,
+    pub y: u32,
+    pub rgba: [u8; 4],
+    /// The file's own values for 16-bit and float frames
+    pub value: Option<SourceValue>,
+}
+
+/// Pixels picked with the color picker, in the order they were clicked
+#[derive(Default)]
+pub struct SampleList {
+    samples: Vec<Sample>,
+}
+
+impl SampleList {
+    pub fn push(&mut self, sample: Sample) {
+        self.samples.push(sample);
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Sample> {
+        self.samples.iter()
+    }
+
+    /// CSV with a header row: `index,x,y,r,g,b,a,value` (`value` holds the file's
+    /// channel values separated by spaces, empty for 8-bit frames)
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("index,x,y,r,g,b,a,value\n");
+        for s in &self.samples {
+            let [r, g, b, a] = s.rgba;
+            let value = s.value.map(|v| v.to_string()).unwrap_or_default();
+            let _ = writeln!(csv, "{},{},{},{},{},{},{},{}", s.index, s.x, s.y, r, g, b, a, value);
+        }
+        csv
+    }
+
+    pub fn save_csv(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_csv()).with_context(|| format!("Failed to write samples {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_has_one_row_per_sample() {
+        let mut list = SampleList::default();
+        list.push(Sample {
+            index: 42,
+            x: 3,
+            y: 7,
+            rgba: [255, 128, 0, 255],
+            value: None,
+        });
+        list.push(Sample {
+            index: 43,
+            x: 0,
+            y: 0,
+            rgba: [1, 2, 3, 4],
+            value: Some(SourceValue {
+                values: [0.00342, 54321.0, 0.0, 0.0],
+                channels: 2,
+            }),
+        });
+        assert_eq!(list.to_csv(), "index,x,y,r,g,b,a,value\n42,3,7,255,128,0,255,\n43,0,0,1,2,3,4,0.00342 54321\n");
+        list.clear();
+        assert_eq!(list.to_csv().lines().count(), 1);
+    }
+}
@@ -1,18 +1,102 @@
-use anyhow::{anyhow, Context, Result};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{channel, Sender};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::time::Duration;
+use tracing::{debug, warn};
 
-use crate::remote_worker::RemoteWorkerRequest;
+use crate::animation::{read_animation_frame, AnimationInfo};
+use crate::bounds::{discover_bounds, SequenceBounds};
+use crate::error::{CacheError, PatternError, SshError};
+use crate::remote_worker::{PartialCat, RemoteWorkerRequest};
+use crate::video::{read_video_frame, VideoInfo};
 
 /// Represents a compiled sequence extracted from a filename pattern and a concrete file.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SequenceSource {
     Local(PathBuf),
     Remote { user_host: String, dir: String },
+    /// Directory on an HTTP(S) server, e.g. an nginx static location or a WebDAV share;
+    /// frames are at `base_url/<file name>`
+    Http { base_url: String },
+    /// Local video file whose frames are the sequence, decoded by ffmpeg (see [`crate::video`])
+    Video { path: PathBuf, info: VideoInfo },
+    /// Local animated GIF or PNG, or multi-page TIFF, whose frames are the sequence
+    Animation { path: PathBuf, info: AnimationInfo },
 }
 
-#[derive(Debug, Clone)]
+impl SequenceSource {
+    /// This source with a remote directory given relative to the home directory (or with
+    /// `~`) made absolute by the remote host; local and absolute sources stay as they are.
+    pub fn resolve_remote_dir(self, request_tx: Option<&Sender<RemoteWorkerRequest>>) -> Result<Self, SshError> {
+        match self {
+            SequenceSource::Remote { user_host, dir } if !is_absolute_remote_path(&dir) => {
+                let tx = request_tx.ok_or(SshError::NotConnected)?;
+                let (response_tx, response_rx) = channel();
+                tx.send(RemoteWorkerRequest::Resolve { dir, response_tx }).map_err(|_| SshError::WorkerGone)?;
+                let dir = response_rx.recv().map_err(|_| SshError::WorkerGone)??;
+                Ok(SequenceSource::Remote { user_host, dir })
+            }
+            source => Ok(source),
+        }
+    }
+
+    /// Human-readable location: the local directory, `user@host:dir` or the base URL.
+    pub fn location(&self) -> String {
+        match self {
+            SequenceSource::Local(dir) => dir.display().to_string(),
+            SequenceSource::Remote { user_host, dir } => format!("{}:{}", user_host, dir),
+            SequenceSource::Http { base_url } => base_url.clone(),
+            SequenceSource::Video { path, .. } | SequenceSource::Animation { path, .. } => path.display().to_string(),
+        }
+    }
+
+    /// Whether files are fetched through a worker (SSH or HTTP) rather than read directly.
+    pub fn is_remote(&self) -> bool {
+        matches!(self, SequenceSource::Remote { .. } | SequenceSource::Http { .. })
+    }
+
+    /// Frame rate the frames were recorded at, known for videos and animations
+    pub fn native_fps(&self) -> Option<f64> {
+        match self {
+            SequenceSource::Video { info, .. } => Some(info.fps()),
+            SequenceSource::Animation { info, .. } => info.fps(),
+            _ => None,
+        }
+    }
+
+    /// Where files kept beside the frames (`.zapvis.toml`, sidecars) are read from: the
+    /// source itself, or the directory holding a video or animation.
+    pub fn neighbor_source(&self) -> SequenceSource {
+        match self {
+            SequenceSource::Video { path, .. } | SequenceSource::Animation { path, .. } => SequenceSource::Local(path.parent().map(Path::to_path_buf).unwrap_or_default()),
+            source => source.clone(),
+        }
+    }
+
+    /// What the worker is asked for to get `rel_path`: the remote path or the URL.
+    /// `None` for local sources.
+    pub fn worker_path(&self, rel_path: &str) -> Option<String> {
+        match self {
+            SequenceSource::Local(_) | SequenceSource::Video { .. } | SequenceSource::Animation { .. } => None,
+            SequenceSource::Remote { dir, .. } => Some(build_remote_path(dir, rel_path)),
+            SequenceSource::Http { base_url } => Some(build_url(base_url, rel_path)),
+        }
+    }
+
+    /// Full path or URL of `rel_path`, for messages and the title bar.
+    pub fn path_display(&self, rel_path: &str) -> String {
+        match self {
+            SequenceSource::Local(dir) => dir.join(rel_path).display().to_string(),
+            SequenceSource::Remote { user_host, dir } => format!("{}:{}", user_host, build_remote_path(dir, rel_path)),
+            SequenceSource::Http { base_url } => build_url(base_url, rel_path),
+            SequenceSource::Video { path, .. } | SequenceSource::Animation { path, .. } => format!("{} ({})", path.display(), rel_path),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SequenceSpec {
     pub source: SequenceSource,
     pub prefix: String,
@@ -21,7 +105,115 @@ pub struct SequenceSpec {
     /// Widths of individual `#` groups. Single entry for single-block patterns.
     pub groups: Vec<usize>,
     pub suffix: String,
-    pub index: u64,
+    /// Digits of the frame numbers (decimal unless the pattern has a `%` modifier)
+    pub radix: Radix,
+    /// File names carry a sign before the number (`+` before the `#` run in the
+    /// pattern), so indices can be negative
+    pub signed: bool,
+    pub index: i64,
+    /// Subdirectory layout when frames are split over numbered folders; the source
+    /// directory is then the folder containing the shards.
+    pub shard: Option<ShardRule>,
+}
+
+/// Base of the frame numbers in file names, set by a modifier right after the last `#`
+/// run: `%x` / `%X` for lower / upper case hex (`dump_0x####%x.bin.png`), `%N` for base
+/// N from 2 to 36 with lower case letters (`id_######%36.png`). Without one, decimal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Radix {
+    pub base: u32,
+    /// Letter digits are upper case
+    pub upper: bool,
+}
+
+impl Default for Radix {
+    fn default() -> Self {
+        Self::DECIMAL
+    }
+}
+
+impl Radix {
+    pub const DECIMAL: Radix = Radix { base: 10, upper: false };
+
+    /// `idx` in this base, zero-padded to at least `width` digits
+    pub fn format(self, idx: u64, width: usize) -> String {
+        if self.base == 10 {
+            return format!("{:0width$}", idx, width = width);
+        }
+        let mut digits = Vec::new();
+        let mut n = idx;
+        loop {
+            let d = char::from_digit((n % self.base as u64) as u32, self.base).unwrap_or('0');
+            digits.push(if self.upper { d.to_ascii_uppercase() } else { d });
+            n /= self.base as u64;
+            if n == 0 {
+                break;
+            }
+        }
+        digits.resize(digits.len().max(width), '0');
+        digits.iter().rev().collect()
+    }
+
+    pub fn parse(self, digits: &str) -> Option<u64> {
+        u64::from_str_radix(digits, self.base).ok()
+    }
+
+    /// Regex for one digit
+    fn digit_class(self) -> String {
+        if self.base == 10 {
+            return "\\d".to_string();
+        }
+        let last = char::from_digit(self.base - 1, self.base).unwrap_or('9');
+        let last = if self.upper { last.to_ascii_uppercase() } else { last };
+        if self.base <= 10 {
+            format!("[0-{last}]")
+        } else {
+            format!("[0-9{}-{last}]", if self.upper { 'A' } else { 'a' })
+        }
+    }
+}
+
+/// Largest frame number zapvis works with (and, negated, the smallest of signed
+/// patterns); keeps index arithmetic clear of overflow
+pub const MAX_INDEX: i64 = i64::MAX / 4;
+
+/// Frames split over numbered subdirectories: with `dir = "batch_###"` and
+/// `frames_per_dir = 1000`, frame 12345 lives in `batch_012/`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardRule {
+    /// Subdirectory name with a single `#` run for the shard number
+    pub dir: String,
+    pub frames_per_dir: u64,
+}
+
+impl ShardRule {
+    fn hash_run(&self) -> Result<(usize, usize), PatternError> {
+        match find_hash_runs(&self.dir)[..] {
+            [run] if self.frames_per_dir > 0 => Ok(run),
+            [_] => Err(PatternError::NoFramesPerDir(self.dir.clone())),
+            _ => Err(PatternError::BadShardDir(self.dir.clone())),
+        }
+    }
+
+    /// Subdirectory holding frame `idx`.
+    pub fn dir_for(&self, idx: i64) -> String {
+        let Ok((start, end)) = self.hash_run() else {
+            return self.dir.clone();
+        };
+        format!(
+            "{}{:0width$}{}",
+            &self.dir[..start],
+            idx.div_euclid(self.frames_per_dir as i64),
+            &self.dir[end..],
+            width = end - start
+        )
+    }
+
+    /// Whether `dir_name` is the shard that should hold frame `idx`.
+    pub fn holds(&self, dir_name: &str, idx: i64) -> Result<bool, PatternError> {
+        self.hash_run()?;
+        Ok(self.dir_for(idx) == dir_name)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -31,53 +223,262 @@ pub struct InputSpec {
 }
 
 impl SequenceSpec {
-    pub fn file_name_for(&self, idx: u64) -> String {
+    /// Sequence for `pattern` in `source`, positioned at `index` (no input file needed).
+    /// A folder part in the pattern (`chunk_####/frame_####.png`) becomes the shard
+    /// rule, with `source` taken as the folder containing the shards.
+    pub fn from_pattern(pattern: &str, source: SequenceSource, index: i64) -> Result<Self, PatternError> {
+        let (shard, file_pattern) = split_dir_pattern(pattern)?;
+        let (radix, file_pattern) = split_radix(file_pattern)?;
+        let (signed, _) = split_sign(&file_pattern);
+        let (_, prefix, groups, suffix) = compile_pattern(pattern)?;
+        Ok(Self {
+            source,
+            prefix,
+            width: groups.iter().sum(),
+            groups,
+            suffix,
+            radix,
+            signed,
+            index,
+            shard,
+        })
+    }
+
+    /// Lowest index a file name can have: 0, or [`-MAX_INDEX`](MAX_INDEX) when signed
+    pub fn min_index(&self) -> i64 {
+        if self.signed {
+            -MAX_INDEX
+        } else {
+            0
+        }
+    }
+
+    pub fn file_name_for(&self, idx: i64) -> String {
+        // Unsigned patterns have no names for negative indices; "-" at least never matches a file
+        let sign = match (self.signed, idx < 0) {
+            (_, true) => "-",
+            (true, false) => "+",
+            (false, false) => "",
+        };
+        let idx = idx.unsigned_abs();
         if self.groups.len() <= 1 {
-            format!("{}{:0width$}{}", self.prefix, idx, self.suffix, width = self.width)
+            format!("{}{}{}{}", self.prefix, sign, self.radix.format(idx, self.width), self.suffix)
         } else {
-            // `{:0width$}` always produces at least `self.width` characters, and
+            // The padded number always has at least `self.width` characters, and
             // `self.width == groups.iter().sum()`, so the per-group byte slices are
-            // always in-bounds (all characters are ASCII digits).
-            let full = format!("{:0width$}", idx, width = self.width);
+            // always in-bounds (all characters are ASCII digits). An index too large
+            // for the pattern widens the leading block, like a single `#` run would.
+            let full = self.radix.format(idx, self.width);
+            let extra = full.len() - self.width;
             let mut parts: Vec<&str> = Vec::new();
             let mut offset = 0;
-            for &g in &self.groups {
+            for (i, &g) in self.groups.iter().enumerate() {
+                let g = if i == 0 { g + extra } else { g };
                 parts.push(&full[offset..offset + g]);
                 offset += g;
             }
-            format!("{}{}{}", self.prefix, parts.join("_"), self.suffix)
+            format!("{}{}{}{}", self.prefix, sign, parts.join("_"), self.suffix)
         }
     }
 
-    pub fn path_display(&self, idx: u64) -> String {
-        match &self.source {
-            SequenceSource::Local(dir) => dir.join(self.file_name_for(idx)).display().to_string(),
+    /// Path of frame `idx` relative to the source directory (`shard/file` when sharded).
+    pub fn relative_path_for(&self, idx: i64) -> String {
+        let file_name = self.file_name_for(idx);
+        match &self.shard {
+            Some(rule) => format!("{}/{}", rule.dir_for(idx), file_name),
+            None => file_name,
+        }
+    }
+
+    /// Apply `rule` if the input file's directory is the shard for its index, moving
+    /// the source up to the folder containing the shards. Otherwise the spec is kept.
+    pub fn with_shard(mut self, rule: &ShardRule) -> Result<Self, PatternError> {
+        let (dir_name, root) = match &self.source {
+            SequenceSource::Local(dir) => (
+                dir.file_name().and_then(|s| s.to_str()).map(str::to_string),
+                dir.parent().map(|p| SequenceSource::Local(p.to_path_buf())),
+            ),
             SequenceSource::Remote { user_host, dir } => {
-                let remote_path = build_remote_path(dir, &self.file_name_for(idx));
-                format!("{}:{}", user_host, remote_path)
+                let path = Path::new(dir.trim_end_matches('/'));
+                (
+                    path.file_name().and_then(|s| s.to_str()).map(str::to_string),
+                    path.parent().map(|p| SequenceSource::Remote {
+                        user_host: user_host.clone(),
+                        dir: p.to_string_lossy().to_string(),
+                    }),
+                )
             }
+            SequenceSource::Http { base_url } => match url_parent(base_url) {
+                Some((parent, name)) => (
+                    Some(percent_decode(name)),
+                    Some(SequenceSource::Http {
+                        base_url: parent.to_string(),
+                    }),
+                ),
+                None => (None, None),
+            },
+            SequenceSource::Video { .. } | SequenceSource::Animation { .. } => (None, None),
+        };
+        let (Some(dir_name), Some(root)) = (dir_name, root) else {
+            return Ok(self);
+        };
+        if !rule.holds(&dir_name, self.index)? {
+            warn!(
+                "{:?} is not the shard {:?} for frame {}; not sharding",
+                dir_name,
+                rule.dir_for(self.index),
+                self.index
+            );
+            return Ok(self);
         }
+        self.source = root;
+        self.shard = Some(rule.clone());
+        Ok(self)
+    }
+
+    /// Same frames as `other`: the same files, wherever the current index is
+    pub fn same_frames(&self, other: &SequenceSpec) -> bool {
+        *self == SequenceSpec {
+            index: self.index,
+            ..other.clone()
+        }
+    }
+
+    /// Stable key identifying this sequence (location + pattern) for per-sequence settings.
+    pub fn sequence_key(&self, pattern: &str) -> String {
+        format!("{}|{}", self.source.location(), pattern)
+    }
+
+    pub fn path_display(&self, idx: i64) -> String {
+        self.source.path_display(&self.relative_path_for(idx))
     }
 
-    pub fn exists_with_ssh(&self, idx: u64, request_tx: Option<Sender<RemoteWorkerRequest>>) -> Result<bool> {
+    pub fn exists_with_ssh(&self, idx: i64, request_tx: Option<Sender<RemoteWorkerRequest>>) -> Result<bool, SshError> {
+        let rel_path = self.relative_path_for(idx);
         match &self.source {
-            SequenceSource::Local(dir) => Ok(dir.join(self.file_name_for(idx)).exists()),
-            SequenceSource::Remote { dir, .. } => {
-                let remote_path = build_remote_path(dir, &self.file_name_for(idx));
-                if let Some(tx) = request_tx {
-                    let (response_tx, response_rx) = channel();
-                    eprintln!("[SSH] exists: {}", remote_path);
-                    tx.send(RemoteWorkerRequest::Exists {
-                        path: remote_path,
-                        response_tx,
-                    })?;
-                    response_rx.recv()?
-                } else {
-                    Err(anyhow!("Remote SSH connection not available"))
-                }
+            SequenceSource::Local(dir) => return Ok(dir.join(rel_path).exists()),
+            SequenceSource::Video { info, .. } => return Ok(info.contains(idx)),
+            SequenceSource::Animation { info, .. } => return Ok(info.contains(idx)),
+            SequenceSource::Remote { .. } | SequenceSource::Http { .. } => {}
+        }
+        let (Some(remote_path), Some(tx)) = (self.source.worker_path(&rel_path), request_tx) else {
+            return Err(SshError::NotConnected);
+        };
+        let (response_tx, response_rx) = channel();
+        debug!("exists: {}", remote_path);
+        tx.send(RemoteWorkerRequest::Exists {
+            path: remote_path,
+            response_tx,
+        })
+        .map_err(|_| SshError::WorkerGone)?;
+        response_rx.recv().map_err(|_| SshError::WorkerGone)?
+    }
+
+    /// First and last existing frame around the current index, found by probing single
+    /// indices (see [`discover_bounds`]). Remote sequences are searched by the remote
+    /// worker on its own session, which remembers the result for the next call, HTTP
+    /// ones by the HTTP worker.
+    pub fn find_bounds(&self, request_tx: Option<&Sender<RemoteWorkerRequest>>) -> Result<SequenceBounds, SshError> {
+        match &self.source {
+            SequenceSource::Local(dir) => {
+                discover_bounds(self.index, self.min_index(), |idx| Ok(dir.join(self.relative_path_for(idx)).exists()))
+            }
+            SequenceSource::Video { info: VideoInfo { frames, .. }, .. }
+            | SequenceSource::Animation { info: AnimationInfo { frames, .. }, .. } => Ok(SequenceBounds {
+                first: (*frames > 0).then_some(0),
+                last: frames.checked_sub(1).map(|last| last as i64),
+            }),
+            SequenceSource::Remote { .. } | SequenceSource::Http { .. } => {
+                let tx = request_tx.ok_or(SshError::NotConnected)?;
+                let (response_tx, response_rx) = channel();
+                tx.send(RemoteWorkerRequest::FindBounds {
+                    seq: Box::new(self.clone()),
+                    response_tx,
+                })
+                .map_err(|_| SshError::WorkerGone)?;
+                response_rx.recv().map_err(|_| SshError::WorkerGone)?
             }
         }
     }
+
+    /// Fetch the raw, undecoded bytes of frame `idx` (local read or remote CAT).
+    ///
+    /// This is the passthrough for library users who want to feed frames into their
+    /// own processing instead of zapvis's decoder.
+    pub fn fetch_bytes(
+        &self,
+        idx: i64,
+        request_tx: Option<&Sender<RemoteWorkerRequest>>,
+    ) -> Result<Vec<u8>, CacheError> {
+        fetch_frame_bytes(&self.source, &self.relative_path_for(idx), idx, request_tx)
+    }
+}
+
+/// Fetch the raw bytes of `file_name` (a path relative to the source directory) from `source`.
+/// For remote and HTTP sources the request goes through the worker; `idx` is used for its range check.
+/// Videos and animations have no files: frame `idx` is decoded to PPM or PNG whatever
+/// `file_name` is.
+pub fn fetch_frame_bytes(
+    source: &SequenceSource,
+    file_name: &str,
+    idx: i64,
+    request_tx: Option<&Sender<RemoteWorkerRequest>>,
+) -> Result<Vec<u8>, CacheError> {
+    fetch_frame_bytes_with_partials(source, file_name, idx, request_tx, None)
+}
+
+/// [`fetch_frame_bytes`], handing the beginning of a remote file to `on_partial` now and
+/// then while the rest is still arriving (the newest only, if several queued up).
+pub fn fetch_frame_bytes_with_partials(
+    source: &SequenceSource,
+    file_name: &str,
+    idx: i64,
+    request_tx: Option<&Sender<RemoteWorkerRequest>>,
+    on_partial: Option<&mut dyn FnMut(PartialCat)>,
+) -> Result<Vec<u8>, CacheError> {
+    let remote_path = match source {
+        SequenceSource::Local(dir) => {
+            let path = dir.join(file_name);
+            return fs::read(&path).map_err(|source| match source.kind() {
+                std::io::ErrorKind::NotFound => CacheError::NotFound(path.display().to_string()),
+                _ => CacheError::Read {
+                    path: path.display().to_string(),
+                    source,
+                },
+            });
+        }
+        SequenceSource::Remote { dir, .. } => build_remote_path(dir, file_name),
+        SequenceSource::Http { base_url } => build_url(base_url, file_name),
+        SequenceSource::Video { path, info } => return read_video_frame(path, *info, idx),
+        SequenceSource::Animation { path, info } => return read_animation_frame(path, *info, idx),
+    };
+    let tx = request_tx.ok_or(CacheError::NoConnection)?;
+    let (response_tx, response_rx) = channel();
+    let (partial_tx, partial_rx) = channel();
+    debug!("cat: {} (idx={})", remote_path, idx);
+    tx.send(RemoteWorkerRequest::Cat {
+        idx,
+        path: remote_path,
+        response_tx,
+        partial_tx: on_partial.is_some().then_some(partial_tx),
+    })
+    .map_err(|_| SshError::WorkerGone)?;
+    let bytes = match on_partial {
+        None => response_rx.recv().map_err(|_| SshError::WorkerGone)??,
+        Some(on_partial) => loop {
+            match response_rx.recv_timeout(Duration::from_millis(50)) {
+                Ok(result) => break result?,
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(partial) = partial_rx.try_iter().last() {
+                        on_partial(partial);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(SshError::WorkerGone.into()),
+            }
+        },
+    };
+    debug!("cat received {} bytes (idx={})", bytes.len(), idx);
+    Ok(bytes)
 }
 
 /// Compile a pattern like "image_#####.png" into:
@@ -87,12 +488,19 @@ impl SequenceSpec {
 /// Supports a single contiguous `#` group, or multiple `#` groups separated
 /// by `_` (the only supported inter-block delimiter).  All numeric parts are
 /// concatenated into a single index.
-pub fn compile_pattern(pat: &str) -> Result<(Regex, String, Vec<usize>, String)> {
+///
+/// A folder part (see [`split_dir_pattern`]) is checked and left out: the regex matches
+/// the file name only. A radix modifier (see [`Radix`]) sets which digits the blocks take,
+/// and a `+` right before the first block stands for the sign of signed patterns (left
+/// out of the returned prefix).
+pub fn compile_pattern(pat: &str) -> Result<(Regex, String, Vec<usize>, String), PatternError> {
+    let (_, pat) = split_dir_pattern(pat)?;
+    let (radix, pat) = split_radix(pat)?;
+    let (signed, pat) = split_sign(&pat);
+    let pat = pat.as_str();
     let hash_runs: Vec<(usize, usize)> = find_hash_runs(pat);
     if hash_runs.is_empty() {
-        return Err(anyhow!(
-            "Pattern must contain at least one # run. Got: {pat}"
-        ));
+        return Err(PatternError::NoHashRun(pat.to_string()));
     }
 
     let prefix = &pat[..hash_runs[0].0];
@@ -105,38 +513,94 @@ pub fn compile_pattern(pat: &str) -> Result<(Regex, String, Vec<usize>, String)>
         if i + 1 < hash_runs.len() {
             let between = &pat[end..hash_runs[i + 1].0];
             if between != "_" {
-                return Err(anyhow!(
-                    "Multiple # blocks must be separated by '_'. Got separator: {:?} in {pat}",
-                    between
-                ));
+                return Err(PatternError::BadSeparator {
+                    pattern: pat.to_string(),
+                    separator: between.to_string(),
+                });
             }
         }
     }
 
     // Build regex with one capture group per # block, separated by literal '_'.
     let mut re_str = format!("^{}", regex::escape(prefix));
+    if signed {
+        re_str.push_str("[+-]");
+    }
     for (i, &w) in groups.iter().enumerate() {
         if i > 0 {
             re_str.push('_');
         }
-        re_str.push_str(&format!("(\\d{{{}}})", w));
+        re_str.push_str(&format!("({}{{{}}})", radix.digit_class(), w));
     }
     re_str.push_str(&format!("{}$", regex::escape(suffix)));
 
-    let re = Regex::new(&re_str).context("Failed to compile regex from pattern")?;
+    let re = Regex::new(&re_str)?;
     Ok((re, prefix.to_string(), groups, suffix.to_string()))
 }
 
+/// Whether a (file) pattern is signed, i.e. has a `+` right before its first `#` run
+/// (`t_+####.png` for `t_-0005.png` … `t_+0005.png`), and the pattern without it.
+pub fn split_sign(pat: &str) -> (bool, String) {
+    match find_hash_runs(pat).first() {
+        Some(&(start, _)) if pat[..start].ends_with('+') => (true, format!("{}{}", &pat[..start - 1], &pat[start..])),
+        _ => (false, pat.to_string()),
+    }
+}
+
+/// The radix modifier of a (file) pattern, and the pattern without it.
+pub fn split_radix(pat: &str) -> Result<(Radix, String), PatternError> {
+    let Some(&(_, end)) = find_hash_runs(pat).last() else {
+        return Ok((Radix::DECIMAL, pat.to_string()));
+    };
+    let Some(modifier) = pat[end..].strip_prefix('%') else {
+        return Ok((Radix::DECIMAL, pat.to_string()));
+    };
+    let digits = modifier.bytes().take_while(u8::is_ascii_digit).count();
+    let (radix, len) = match modifier.chars().next() {
+        Some('x') => (Radix { base: 16, upper: false }, 1),
+        Some('X') => (Radix { base: 16, upper: true }, 1),
+        _ => match modifier[..digits].parse() {
+            Ok(base @ 2..=36) => (Radix { base, upper: false }, digits),
+            _ => return Err(PatternError::BadRadix(pat.to_string())),
+        },
+    };
+    Ok((radix, format!("{}{}", &pat[..end], &modifier[len..])))
+}
+
+/// Split a pattern with a folder part into the folder's shard rule and the file pattern.
+///
+/// `chunk_####/frame_####.png` keeps each frame in the folder numbered like it;
+/// `chunk_####:100/frame_####.png` puts 100 frames per folder (folder number = index / 100).
+/// Patterns without a `/` have no folder part.
+pub fn split_dir_pattern(pat: &str) -> Result<(Option<ShardRule>, &str), PatternError> {
+    let Some((dir, file)) = pat.rsplit_once('/') else {
+        return Ok((None, pat));
+    };
+    let (dir, frames_per_dir) = match dir.rsplit_once(':') {
+        Some((dir, n)) => (dir, n.parse().map_err(|_| PatternError::BadDirPattern(pat.to_string()))?),
+        None => (dir, 1),
+    };
+    if dir.contains('/') {
+        return Err(PatternError::BadDirPattern(pat.to_string()));
+    }
+    let rule = ShardRule {
+        dir: dir.to_string(),
+        frames_per_dir,
+    };
+    rule.hash_run()?;
+    Ok((Some(rule), file))
+}
+
 /// Concatenate the text of regex capture groups 1..=`n` into a single string.
 ///
 /// This is used for multi-block `#` patterns where each block is a separate
 /// capture group, and the combined string is parsed as the sequence index.
-fn concat_captures(cap: &regex::Captures<'_>, n: usize) -> Result<String> {
+fn concat_captures(cap: &regex::Captures<'_>, n: usize) -> Result<String, PatternError> {
     (1..=n)
         .map(|i| {
             cap.get(i)
                 .map(|m| m.as_str())
-                .ok_or_else(|| anyhow!("Missing capture group {i}"))
+                .ok_or_else(|| PatternError::BadIndex(cap[0].to_string()))
         })
         .collect()
 }
@@ -165,52 +629,313 @@ pub fn pick_sequence(
     cfg: &crate::config::Config,
     input: &InputSpec,
     request_tx: Option<Sender<RemoteWorkerRequest>>,
-) -> Result<(String, SequenceSpec)> {
+) -> Result<(String, SequenceSpec), PatternError> {
+    matching_sequences(cfg, input, request_tx)?
+        .into_iter()
+        .next()
+        .ok_or(PatternError::NoMatch)
+}
+
+/// All configured patterns that match the input filename, in the order they are tried
+/// (see [`crate::config::Config::patterns_for`]; patterns scoped to other directories or
+/// hosts are left out). Patterns with a shard rule in the config get it applied.
+pub fn matching_sequences(
+    cfg: &crate::config::Config,
+    input: &InputSpec,
+    _request_tx: Option<Sender<RemoteWorkerRequest>>,
+) -> Result<Vec<(String, SequenceSpec)>, PatternError> {
     // If config empty, fail quickly.
     if cfg.patterns.is_empty() {
-        return Err(anyhow!("No patterns configured."));
+        return Err(PatternError::NoPatterns);
     }
 
-    let file_name = input.file_name.clone();
-    let source = input.source.clone();
-
-    for pat in &cfg.patterns {
-        let (re, prefix, groups, suffix) = compile_pattern(pat)?;
-        if let Some(cap) = re.captures(&file_name) {
-            // Concatenate all capture groups to form the combined index string.
-            let idx_str = concat_captures(&cap, groups.len())?;
-            let idx: u64 = idx_str.parse().context("Failed to parse captured index")?;
-            let width: usize = groups.iter().sum();
-
-            let spec = SequenceSpec {
-                source: source.clone(),
-                prefix,
-                width,
-                groups,
-                suffix,
-                index: idx,
-            };
-
+    let mut found = Vec::new();
+    for pat in cfg.patterns_for(&input.source) {
+        if let Some(mut spec) = spec_for_pattern(pat, input)? {
+            if let Some(rule) = cfg.shards.get(pat).filter(|_| spec.shard.is_none()) {
+                spec = spec.with_shard(rule)?;
+            }
             // Skip neigbor check for now
             // Neighbor evidence via stat(): cheap and avoids enumeration.
             //let has_next = spec.exists_with_ssh(idx + 1, request_tx.clone()).unwrap_or(false);
             //let has_prev = idx > 0 && spec.exists_with_ssh(idx - 1, request_tx.clone()).unwrap_or(false);
-            
+
             //if has_next || has_prev {
-            return Ok((pat.clone(), spec));
+            found.push((pat.clone(), spec));
             //}
         }
     }
+    Ok(found)
+}
+
+/// The distinct sequences `patterns` find among the files of the local directory `dir`,
+/// in pattern order, each at its frame closest to `near`. A pattern whose files were
+/// all matched by an earlier one (`frame_000#.png` after `frame_####.png`) adds nothing.
+/// Patterns with a folder part or a shard rule are not looked for.
+pub fn sibling_sequences(patterns: &[String], dir: &Path, near: i64) -> std::io::Result<Vec<(String, SequenceSpec)>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    let mut claimed = std::collections::BTreeSet::new();
+    let mut found = Vec::new();
+    for pat in patterns {
+        let Ok((None, _)) = split_dir_pattern(pat) else {
+            continue;
+        };
+        let matches: Vec<(&String, i64)> = names
+            .iter()
+            .filter_map(|name| Some((name, match_index(pat, name).ok()??)))
+            .collect();
+        if matches.iter().all(|(name, _)| claimed.contains(*name)) {
+            continue;
+        }
+        let Some(&(_, index)) = matches.iter().min_by_key(|(_, idx)| idx.abs_diff(near)) else {
+            continue;
+        };
+        claimed.extend(matches.iter().map(|(name, _)| *name));
+        match SequenceSpec::from_pattern(pat, SequenceSource::Local(dir.to_path_buf()), index) {
+            Ok(spec) => found.push((pat.clone(), spec)),
+            Err(e) => warn!("skipping pattern {:?}: {}", pat, e),
+        }
+    }
+    Ok(found)
+}
+
+/// Frame index `pat` reads from `file_name`, or `None` if the pattern does not match.
+/// With a folder in both (`chunk_0003/frame_0003.png`), the folder has to match too.
+pub fn match_index(pat: &str, file_name: &str) -> Result<Option<i64>, PatternError> {
+    let (re, _, groups, _) = compile_pattern(pat)?;
+    let (radix, file_pattern) = split_radix(split_dir_pattern(pat)?.1)?;
+    let (signed, _) = split_sign(&file_pattern);
+    let (dir, name) = match file_name.rsplit_once('/') {
+        Some((dir, name)) => (Some(dir.rsplit('/').next().unwrap_or(dir)), name),
+        None => (None, file_name),
+    };
+    let Some(cap) = re.captures(name) else {
+        return Ok(None);
+    };
+    // Concatenate all capture groups to form the combined index string.
+    let idx_str = concat_captures(&cap, groups.len())?;
+    let magnitude = radix.parse(&idx_str).and_then(|m| i64::try_from(m).ok()).filter(|&m| m <= MAX_INDEX);
+    let idx = magnitude.ok_or_else(|| PatternError::BadIndex(file_name.to_string()))?;
+    // The sign sits right before the first block
+    let negative = signed && cap.get(1).is_some_and(|m| name[..m.start()].ends_with('-'));
+    let idx = if negative { -idx } else { idx };
+    if let (Some(dir), (Some(rule), _)) = (dir, split_dir_pattern(pat)?) {
+        if !rule.holds(dir, idx)? {
+            return Ok(None);
+        }
+    }
+    Ok(Some(idx))
+}
+
+/// Build the sequence for `pat` if it matches the input filename. A pattern with a
+/// folder part only matches a file inside the folder it names for that index.
+pub fn spec_for_pattern(pat: &str, input: &InputSpec) -> Result<Option<SequenceSpec>, PatternError> {
+    let Some(idx) = match_index(pat, &input.file_name)? else {
+        return Ok(None);
+    };
+    let mut spec = SequenceSpec::from_pattern(pat, input.source.clone(), idx)?;
+    if let Some(rule) = spec.shard.take() {
+        spec = spec.with_shard(&rule)?;
+        if spec.shard.is_none() {
+            return Ok(None);
+        }
+    }
+    Ok(Some(spec))
+}
+
+/// Byte ranges of the digit runs in the stem of `file_name` (the extension is left
+/// alone), left to right: the parts a pattern can turn into `#` blocks.
+pub fn digit_runs(file_name: &str) -> Vec<(usize, usize)> {
+    let stem_len = file_name.rfind('.').filter(|&i| i > 0).unwrap_or(file_name.len());
+    let bytes = file_name.as_bytes();
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    let mut i = 0;
+    while i < stem_len {
+        if bytes[i].is_ascii_digit() {
+            let start = i;
+            while i < stem_len && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            runs.push((start, i));
+        } else {
+            i += 1;
+        }
+    }
+    runs
+}
+
+/// `file_name` with each of the digit `runs` (from [`digit_runs`]) replaced by a `#` block
+/// of its width
+pub fn pattern_from_runs(file_name: &str, runs: &[(usize, usize)]) -> String {
+    let mut runs = runs.to_vec();
+    runs.sort();
+    let mut pattern = String::new();
+    let mut at = 0;
+    for (start, end) in runs {
+        pattern.push_str(&file_name[at..start]);
+        pattern.push_str(&"#".repeat(end - start));
+        at = end;
+    }
+    pattern.push_str(&file_name[at..]);
+    pattern
+}
+
+/// Candidate patterns for a filename that no configured pattern matches.
+///
+/// Each digit run in the file stem (see [`digit_runs`]) becomes a `#` block:
+/// the trailing run is tried first, then the others longest first. Filenames that
+/// already contain `#` yield nothing, since it would be read as a placeholder.
+pub fn infer_patterns(file_name: &str) -> Vec<String> {
+    if file_name.contains('#') {
+        return Vec::new();
+    }
+    let mut runs = digit_runs(file_name);
+    let Some(trailing) = runs.pop() else {
+        return Vec::new();
+    };
+    // Stable sort keeps left-to-right order among runs of equal length
+    runs.sort_by_key(|&(start, end)| std::cmp::Reverse(end - start));
+    std::iter::once(trailing)
+        .chain(runs)
+        .map(|run| pattern_from_runs(file_name, &[run]))
+        .collect()
+}
+
+/// Neighbor evidence for a sequence: whether the frames before and after the current
+/// one exist (a failed check counts as missing).
+pub fn neighbors_exist(spec: &SequenceSpec, request_tx: Option<Sender<RemoteWorkerRequest>>) -> (bool, bool) {
+    let idx = spec.index;
+    let has_prev = idx > spec.min_index() && spec.exists_with_ssh(idx - 1, request_tx.clone()).unwrap_or(false);
+    let has_next = spec.exists_with_ssh(idx + 1, request_tx).unwrap_or(false);
+    (has_prev, has_next)
+}
+
+/// Fallback when no configured pattern matches: infer a pattern from the filename and
+/// accept the first candidate with neighbor evidence (idx+-1 exists).
+pub fn infer_sequence(
+    input: &InputSpec,
+    request_tx: Option<Sender<RemoteWorkerRequest>>,
+) -> Result<(String, SequenceSpec), PatternError> {
+    let candidates = infer_patterns(&input.file_name);
+    if candidates.is_empty() {
+        return Err(PatternError::NothingToInfer(input.file_name.clone()));
+    }
+
+    for pat in candidates {
+        let Some(spec) = spec_for_pattern(&pat, input)? else {
+            continue;
+        };
+        let (has_prev, has_next) = neighbors_exist(&spec, request_tx.clone());
+        if has_next || has_prev {
+            return Ok((pat, spec));
+        }
+    }
 
-    Err(anyhow!("No configured pattern matched with neighbor evidence."))
+    Err(PatternError::NoNeighbors)
 }
 
+/// Split a `user@host:path` input. The path may be absolute, relative to the remote home
+/// directory or start with `~` (see [`SequenceSource::resolve_remote_dir`]).
 pub fn parse_remote_input(input: &str) -> Option<(String, String)> {
-    let re = Regex::new(r"^([^@]+@[^:]+):(/.+)$").ok()?;
+    let re = Regex::new(r"^([^@/]+@[^:/]+):(.+)$").ok()?;
     let caps = re.captures(input)?;
     Some((caps.get(1)?.as_str().to_string(), caps.get(2)?.as_str().to_string()))
 }
 
+/// Local path named by `input`: a `file://` URL (percent-decoded) or a path, with a
+/// leading `~` expanded to the home directory.
+pub fn expand_local_path(input: &str) -> PathBuf {
+    let path = match input.strip_prefix("file://") {
+        Some(url) => percent_decode(url.strip_prefix("localhost").unwrap_or(url)),
+        None => input.to_string(),
+    };
+    let home = || directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+    match path.strip_prefix('~') {
+        Some("") => home().unwrap_or_else(|| PathBuf::from(&path)),
+        Some(rest) if rest.starts_with(['/', std::path::MAIN_SEPARATOR]) => {
+            home().map_or_else(|| PathBuf::from(&path), |home| home.join(&rest[1..]))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Split an `http://` or `https://` input URL into the directory URL (the sequence's
+/// base URL) and the percent-decoded file name. Query strings and fragments are not supported.
+pub fn parse_http_input(input: &str) -> Option<(String, String)> {
+    if !is_http_url(input) {
+        return None;
+    }
+    let (base_url, name) = url_parent(input)?;
+    let name = percent_decode(name);
+    (!name.is_empty()).then(|| (base_url.to_string(), name))
+}
+
+pub fn is_http_url(input: &str) -> bool {
+    let lower = input.get(..8).unwrap_or(input).to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// The URL one level up and the last path segment (still encoded), if the URL has a path.
+fn url_parent(url: &str) -> Option<(&str, &str)> {
+    let url = url.trim_end_matches('/');
+    let path_start = url.find("://").map(|i| i + 3)?;
+    let host_end = url[path_start..].find('/').map(|i| path_start + i)?;
+    let (parent, name) = url.rsplit_once('/')?;
+    (parent.len() >= host_end).then_some((parent, name))
+}
+
+/// URL of `rel_path` under `base_url`, percent-encoding what may not appear in a URL path.
+pub fn build_url(base_url: &str, rel_path: &str) -> String {
+    let mut url = base_url.trim_end_matches('/').to_string();
+    for segment in rel_path.split('/') {
+        url.push('/');
+        for b in segment.bytes() {
+            if b.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&b) {
+                url.push(b as char);
+            } else {
+                url.push_str(&format!("%{b:02X}"));
+            }
+        }
+    }
+    url
+}
+
+/// Decode `%XX` escapes; malformed ones are kept as they are.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| s.get(i + 1..i + 3))
+            .flatten()
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Whether a remote path is absolute: `/data` on POSIX hosts, `C:/data` (or `C:\data`)
+/// on Windows ones.
+pub fn is_absolute_remote_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    let drive = bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && matches!(bytes[2], b'/' | b'\\');
+    path.starts_with('/') || drive
+}
+
 pub fn build_remote_path(dir: &str, file_name: &str) -> String {
     let trimmed = dir.trim_end_matches('/');
     if trimmed.is_empty() {
@@ -220,19 +945,19 @@ pub fn build_remote_path(dir: &str, file_name: &str) -> String {
     }
 }
 
-pub fn file_name_from_path(path: &Path) -> Result<String> {
+pub fn file_name_from_path(path: &Path) -> Result<String, PatternError> {
     path.file_name()
         .and_then(|s| s.to_str())
         .map(|s| s.to_string())
-        .ok_or_else(|| anyhow!("Non-UTF8 filename not supported"))
+        .ok_or(PatternError::NonUtf8Name)
 }
 
-pub fn file_name_from_str_path(path: &str) -> Result<String> {
+pub fn file_name_from_str_path(path: &str) -> Result<String, PatternError> {
     Path::new(path)
         .file_name()
         .and_then(|s| s.to_str())
         .map(|s| s.to_string())
-        .ok_or_else(|| anyhow!("Non-UTF8 filename not supported"))
+        .ok_or(PatternError::NonUtf8Name)
 }
 
 #[cfg(test)]
@@ -263,12 +988,58 @@ mod tests {
 
     #[test]
     fn invalid_separator_errors() {
-        assert!(compile_pattern("frame_####-#.png").is_err());
+        let err = compile_pattern("frame_####-#.png").unwrap_err();
+        assert!(matches!(err, PatternError::BadSeparator { ref separator, .. } if separator == "-"));
+    }
+
+    #[test]
+    fn hex_and_base36_patterns() {
+        let pat = "dump_0x####%x.bin.png";
+        assert_eq!(match_index(pat, "dump_0x00ff.bin.png").unwrap(), Some(255));
+        assert_eq!(match_index(pat, "dump_0x00FF.bin.png").unwrap(), None, "case follows the modifier");
+        let seq = SequenceSpec::from_pattern(pat, SequenceSource::Local(PathBuf::from(".")), 255).unwrap();
+        assert_eq!(seq.file_name_for(256), "dump_0x0100.bin.png");
+        assert_eq!(seq.file_name_for(0x10000), "dump_0x10000.bin.png");
+
+        let upper = SequenceSpec::from_pattern("f_##_##%X.png", SequenceSource::Local(PathBuf::from(".")), 0).unwrap();
+        assert_eq!(upper.file_name_for(0xABC), "f_0A_BC.png");
+        assert_eq!(match_index("f_##_##%X.png", "f_0A_BC.png").unwrap(), Some(0xABC));
+
+        assert_eq!(match_index("id_####%36.png", "id_00zz.png").unwrap(), Some(35 * 36 + 35));
+        assert_eq!(Radix { base: 36, upper: false }.format(36, 4), "0010");
+        assert!(matches!(compile_pattern("id_####%37.png"), Err(PatternError::BadRadix(_))));
+        assert!(matches!(compile_pattern("id_####%q.png"), Err(PatternError::BadRadix(_))));
+    }
+
+    #[test]
+    fn signed_patterns() {
+        let pat = "t_+####.png";
+        assert_eq!(match_index(pat, "t_-0005.png").unwrap(), Some(-5));
+        assert_eq!(match_index(pat, "t_+0012.png").unwrap(), Some(12));
+        assert_eq!(match_index(pat, "t_0012.png").unwrap(), None, "the sign is always written");
+        let seq = SequenceSpec::from_pattern(pat, SequenceSource::Local(PathBuf::from(".")), -1).unwrap();
+        assert_eq!(seq.file_name_for(0), "t_+0000.png");
+        assert_eq!(seq.file_name_for(-1), "t_-0001.png");
+        assert_eq!(seq.min_index(), -MAX_INDEX);
+
+        let unsigned = SequenceSpec::from_pattern("t_####.png", SequenceSource::Local(PathBuf::from(".")), 0).unwrap();
+        assert_eq!(unsigned.min_index(), 0);
+        assert_eq!(match_index("t_####.png", "t_-0005.png").unwrap(), None);
+    }
+
+    #[test]
+    fn frame_numbers_past_max_index_are_rejected() {
+        let pat = "f_###################.png";
+        assert_eq!(match_index(pat, &format!("f_{MAX_INDEX}.png")).unwrap(), Some(MAX_INDEX));
+        let too_large = match_index(pat, "f_9223372036854775807.png");
+        assert!(matches!(too_large, Err(PatternError::BadIndex(_))), "{too_large:?}");
+        let too_small = match_index("f_+###################.png", &format!("f_-{}.png", MAX_INDEX + 1));
+        assert!(matches!(too_small, Err(PatternError::BadIndex(_))), "{too_small:?}");
     }
 
     #[test]
     fn no_hash_errors() {
-        assert!(compile_pattern("frame.png").is_err());
+        assert!(matches!(compile_pattern("frame.png"), Err(PatternError::NoHashRun(_))));
     }
 
     // ── index extraction ─────────────────────────────────────────────────────
@@ -294,7 +1065,7 @@ mod tests {
 
     // ── file_name_for ─────────────────────────────────────────────────────────
 
-    fn make_spec(prefix: &str, groups: Vec<usize>, suffix: &str, index: u64) -> SequenceSpec {
+    fn make_spec(prefix: &str, groups: Vec<usize>, suffix: &str, index: i64) -> SequenceSpec {
         let width = groups.iter().sum();
         SequenceSpec {
             source: SequenceSource::Local(PathBuf::from(".")),
@@ -302,7 +1073,10 @@ mod tests {
             width,
             groups,
             suffix: suffix.to_string(),
+            radix: Radix::DECIMAL,
+            signed: false,
             index,
+            shard: None,
         }
     }
 
@@ -324,4 +1098,244 @@ mod tests {
         let spec = make_spec("frame_", vec![6, 1], ".png", 0);
         assert_eq!(spec.file_name_for(0), "frame_000000_0.png");
     }
+
+    #[test]
+    fn multi_block_step_carries_across_blocks() {
+        let spec = make_spec("frame_", vec![6, 1], ".png", 1239);
+        assert_eq!(spec.file_name_for(1239), "frame_000123_9.png");
+        assert_eq!(spec.file_name_for(1240), "frame_000124_0.png");
+        // Stepping back borrows from the leading block
+        assert_eq!(spec.file_name_for(1229), "frame_000122_9.png");
+    }
+
+    #[test]
+    fn multi_block_round_trip_keeps_block_widths() {
+        let pat = "shot_##_###_#.exr";
+        let (re, prefix, groups, suffix) = compile_pattern(pat).unwrap();
+        let spec = make_spec(&prefix, groups.clone(), &suffix, 0);
+        for idx in [0, 9, 10, 999, 1000, 123_456] {
+            let name = spec.file_name_for(idx);
+            let cap = re.captures(&name).unwrap();
+            let parsed: i64 = concat_captures(&cap, groups.len()).unwrap().parse().unwrap();
+            assert_eq!(parsed, idx, "{name}");
+        }
+        assert_eq!(spec.file_name_for(99_999), "shot_09_999_9.exr");
+    }
+
+    #[test]
+    fn multi_block_overflow_widens_leading_block() {
+        let spec = make_spec("frame_", vec![2, 1], ".png", 0);
+        assert_eq!(spec.file_name_for(1000), "frame_100_0.png");
+    }
+
+    // ── fetch_bytes ───────────────────────────────────────────────────────────
+
+    #[test]
+    fn fetch_bytes_local_returns_raw_file() {
+        let dir = std::env::temp_dir().join(format!("zapvis_fetch_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("frame_0007.png"), b"not really a png").unwrap();
+        let mut spec = make_spec("frame_", vec![4], ".png", 7);
+        spec.source = SequenceSource::Local(dir.clone());
+        assert_eq!(spec.fetch_bytes(7, None).unwrap(), b"not really a png");
+        assert!(spec.fetch_bytes(8, None).unwrap_err().is_not_found());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn find_bounds_probes_local_frames() {
+        let dir = std::env::temp_dir().join(format!("zapvis_bounds_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for idx in 3..=41 {
+            fs::write(dir.join(format!("frame_{idx:04}.png")), b"").unwrap();
+        }
+        let mut spec = make_spec("frame_", vec![4], ".png", 20);
+        spec.source = SequenceSource::Local(dir.clone());
+        let bounds = spec.find_bounds(None).unwrap();
+        assert_eq!((bounds.first, bounds.last), (Some(3), Some(41)));
+
+        let moved = SequenceSpec { index: 5, ..spec.clone() };
+        assert!(moved.same_frames(&spec));
+        assert!(!make_spec("frame_", vec![5], ".png", 20).same_frames(&spec));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn remote_and_local_inputs() {
+        let remote = |s: &str| parse_remote_input(s).map(|(host, path)| format!("{host} {path}"));
+        assert_eq!(remote("me@cam:/data/f_0001.png").as_deref(), Some("me@cam /data/f_0001.png"));
+        assert_eq!(remote("me@cam:renders/f_0001.png").as_deref(), Some("me@cam renders/f_0001.png"));
+        assert_eq!(remote("me@cam:~/f_0001.png").as_deref(), Some("me@cam ~/f_0001.png"));
+        assert_eq!(remote("/mnt/me@cam:a/f_0001.png"), None, "a local path with @ and :");
+        assert_eq!(remote("f_0001.png"), None);
+        assert_eq!(remote("me@win:C:/renders/f_0001.png").as_deref(), Some("me@win C:/renders/f_0001.png"));
+        assert!(is_absolute_remote_path("/data"));
+        assert!(is_absolute_remote_path("C:/renders"));
+        assert!(is_absolute_remote_path("d:\\renders"));
+        assert!(!is_absolute_remote_path("renders"));
+        assert!(!is_absolute_remote_path("~/renders"));
+
+        assert_eq!(expand_local_path("file:///data/my%20shots/f_0001.png"), PathBuf::from("/data/my shots/f_0001.png"));
+        assert_eq!(expand_local_path("file://localhost/data/f_%zz.png"), PathBuf::from("/data/f_%zz.png"));
+        assert_eq!(expand_local_path("data/~f.png"), PathBuf::from("data/~f.png"));
+
+        let http = |s: &str| parse_http_input(s).map(|(base, name)| format!("{base} {name}"));
+        assert_eq!(http("https://renders.lan/job_1/f%2001.png").as_deref(), Some("https://renders.lan/job_1 f 01.png"));
+        assert_eq!(http("HTTP://renders.lan/f_0001.png").as_deref(), Some("HTTP://renders.lan f_0001.png"));
+        assert_eq!(http("https://renders.lan/"), None, "no file name");
+        assert_eq!(http("me@cam:/data/f_0001.png"), None);
+        assert_eq!(build_url("https://renders.lan/job_1/", "shot #1/f 0001.png"), "https://renders.lan/job_1/shot%20%231/f%200001.png");
+        if let Some(dirs) = directories::BaseDirs::new() {
+            assert_eq!(expand_local_path("~/f_0001.png"), dirs.home_dir().join("f_0001.png"));
+        }
+    }
+
+    #[test]
+    fn several_patterns_can_match() {
+        let cfg = crate::config::Config {
+            patterns: vec!["frame_######_#.png".into(), "frame_000123_#.png".into(), "shot_#.png".into()],
+            ..Default::default()
+        };
+        let input = InputSpec {
+            file_name: "frame_000123_4.png".to_string(),
+            source: SequenceSource::Local(PathBuf::from(".")),
+        };
+        let found = matching_sequences(&cfg, &input, None).unwrap();
+        let pats: Vec<&str> = found.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(pats, ["frame_######_#.png", "frame_000123_#.png"]);
+        assert_eq!(found[0].1.file_name_for(1235), "frame_000123_5.png");
+        assert_eq!(found[1].1.file_name_for(5), "frame_000123_5.png");
+        assert_eq!(pick_sequence(&cfg, &input, None).unwrap().0, "frame_######_#.png");
+    }
+
+    #[test]
+    fn sibling_sequences_are_told_apart() {
+        let dir = std::env::temp_dir().join(format!("zapvis_siblings_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["beauty_0001.exr", "beauty_0002.exr", "beauty_0003.exr", "depth_0001.exr", "depth_0002.exr", "notes.txt"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let patterns: Vec<String> = ["beauty_####.exr", "beauty_000#.exr", "shot_####.png", "depth_####.exr"].map(String::from).into();
+        let found = sibling_sequences(&patterns, &dir, 3).unwrap();
+        let seqs: Vec<(&str, i64)> = found.iter().map(|(p, s)| (p.as_str(), s.index)).collect();
+        assert_eq!(seqs, [("beauty_####.exr", 3), ("depth_####.exr", 2)]);
+        assert_eq!(found[1].1.source, SequenceSource::Local(dir.clone()));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    // ── sharding ──────────────────────────────────────────────────────────────
+
+    #[test]
+    fn sharded_paths_cross_folder_boundaries() {
+        let rule = ShardRule {
+            dir: "batch_###".to_string(),
+            frames_per_dir: 1000,
+        };
+        let spec = make_spec("frame_", vec![6], ".png", 12345);
+        let spec = SequenceSpec {
+            source: SequenceSource::Remote {
+                user_host: "u@h".to_string(),
+                dir: "/data/batch_012".to_string(),
+            },
+            ..spec
+        }
+        .with_shard(&rule)
+        .unwrap();
+        assert_eq!(spec.source.location(), "u@h:/data");
+        assert_eq!(spec.relative_path_for(12999), "batch_012/frame_012999.png");
+        assert_eq!(spec.relative_path_for(13000), "batch_013/frame_013000.png");
+        assert_eq!(spec.path_display(11999), "u@h:/data/batch_011/frame_011999.png");
+
+        let mut http = make_spec("frame_", vec![6], ".png", 12345);
+        http.source = SequenceSource::Http {
+            base_url: "https://renders.lan/data/batch_012".to_string(),
+        };
+        let http = http.with_shard(&rule).unwrap();
+        assert_eq!(http.source.location(), "https://renders.lan/data");
+        assert_eq!(http.path_display(13000), "https://renders.lan/data/batch_013/frame_013000.png");
+
+        // A directory that is not the expected shard leaves the spec alone
+        let mut other = make_spec("frame_", vec![6], ".png", 12345);
+        other.source = SequenceSource::Local(PathBuf::from("/data/batch_007"));
+        let other = other.with_shard(&rule).unwrap();
+        assert!(other.shard.is_none());
+        assert_eq!(other.relative_path_for(1), "frame_000001.png");
+    }
+
+    #[test]
+    fn patterns_with_a_folder_part() {
+        let (rule, file) = split_dir_pattern("chunk_####:100/frame_####.png").unwrap();
+        assert_eq!(rule, Some(ShardRule { dir: "chunk_####".to_string(), frames_per_dir: 100 }));
+        assert_eq!(file, "frame_####.png");
+        assert_eq!(split_dir_pattern("chunk_##/f_#.png").unwrap().0.unwrap().frames_per_dir, 1);
+        assert!(matches!(split_dir_pattern("a/chunk_##/f_#.png"), Err(PatternError::BadDirPattern(_))));
+        assert!(matches!(split_dir_pattern("chunk_##:x/f_#.png"), Err(PatternError::BadDirPattern(_))));
+        assert!(split_dir_pattern("chunk/f_#.png").is_err());
+
+        assert_eq!(match_index("chunk_####/frame_####.png", "frame_0007.png").unwrap(), Some(7));
+        assert_eq!(match_index("chunk_####/frame_####.png", "chunk_0007/frame_0007.png").unwrap(), Some(7));
+        assert_eq!(match_index("chunk_####/frame_####.png", "chunk_0008/frame_0007.png").unwrap(), None);
+
+        // The folder holding the opened file becomes the shard, its parent the source
+        let input = InputSpec {
+            file_name: "frame_0250.png".to_string(),
+            source: SequenceSource::Local(PathBuf::from("/data/chunk_0002")),
+        };
+        let spec = spec_for_pattern("chunk_####:100/frame_####.png", &input).unwrap().unwrap();
+        assert_eq!(spec.source.location(), "/data");
+        assert_eq!(spec.relative_path_for(299), "chunk_0002/frame_0299.png");
+        assert_eq!(spec.relative_path_for(300), "chunk_0003/frame_0300.png");
+        let same = spec_for_pattern("chunk_####/frame_####.png", &input).unwrap();
+        assert!(same.is_none(), "frame 250 belongs in chunk_0250");
+    }
+
+    #[test]
+    fn shard_rule_needs_one_hash_run() {
+        let bad = ShardRule {
+            dir: "batch".to_string(),
+            frames_per_dir: 1000,
+        };
+        assert!(bad.holds("batch", 0).is_err());
+    }
+
+    // ── inference ─────────────────────────────────────────────────────────────
+
+    #[test]
+    fn infer_patterns_trailing_run_first() {
+        assert_eq!(
+            infer_patterns("cam2_take13_000042.png"),
+            vec!["cam2_take13_######.png", "cam2_take##_000042.png", "cam#_take13_000042.png"]
+        );
+        // Digits in the extension are not an index
+        assert_eq!(infer_patterns("clip_0001.mp4"), vec!["clip_####.mp4"]);
+        assert!(infer_patterns("notes.txt").is_empty());
+        assert!(infer_patterns("frame#_01.png").is_empty());
+        let name = "cam2_take13_000042.png";
+        assert_eq!(digit_runs(name), [(3, 4), (9, 11), (12, 18)]);
+        assert_eq!(pattern_from_runs(name, &[(12, 18), (9, 11)]), "cam2_take##_######.png");
+    }
+
+    #[test]
+    fn infer_sequence_needs_a_neighbor() {
+        let dir = std::env::temp_dir().join(format!("zapvis_infer_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // The trailing run has no neighbors; the take number does.
+        for name in ["shot01_take3_v1.png", "shot01_take4_v1.png"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let input = InputSpec {
+            file_name: "shot01_take3_v1.png".to_string(),
+            source: SequenceSource::Local(dir.clone()),
+        };
+        let (pat, spec) = infer_sequence(&input, None).unwrap();
+        assert_eq!(pat, "shot01_take#_v1.png");
+        assert_eq!(spec.index, 3);
+
+        let lonely = InputSpec {
+            file_name: "other_0001.png".to_string(),
+            source: SequenceSource::Local(dir.clone()),
+        };
+        assert!(infer_sequence(&lonely, None).is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
 }
@@ -0,0 +1,134 @@
+//! Per-sequence viewing state (last frame, zoom and pan, step size, window), kept in a
+//! sessions file under the data directory and restored when the sequence is opened again.
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::ConfigError;
+
+/// Where the viewer was on one sequence when it was closed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SessionState {
+    pub index: i64,
+    /// Zoom relative to the fitted size (1.0 = fit to window)
+    #[serde(default = "default_zoom")]
+    pub zoom: f32,
+    #[serde(default)]
+    pub pan: [f32; 2],
+    /// Maximized (F)
+    #[serde(default)]
+    pub fullscreen: bool,
+    /// OS fullscreen (F11)
+    #[serde(default)]
+    pub exclusive_fullscreen: bool,
+    #[serde(default = "default_step_size")]
+    pub step_size: u64,
+    /// Inner size of the window when not fullscreen, in points
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_size: Option<[f32; 2]>,
+    /// Outer position of the window when not fullscreen, in points
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_pos: Option<[f32; 2]>,
+    /// In and out points of the loop region (Ctrl+I / Ctrl+O); `export` and `encode`
+    /// default to the frames between them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loop_in: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loop_out: Option<i64>,
+}
+
+fn default_zoom() -> f32 {
+    1.0
+}
+
+fn default_step_size() -> u64 {
+    1
+}
+
+/// Contents of the sessions file (TOML), keyed by `SequenceSpec::sequence_key`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionFile {
+    #[serde(default)]
+    pub sessions: BTreeMap<String, SessionState>,
+}
+
+/// The sessions file under the data directory
+pub fn session_path() -> Result<PathBuf, ConfigError> {
+    let proj = ProjectDirs::from("dev", "zapvis", "zapvis").ok_or(ConfigError::NoConfigDir)?;
+    Ok(proj.data_dir().join("sessions.toml"))
+}
+
+/// Read a sessions file; a missing one is empty.
+pub fn load_session_file(path: &Path) -> Result<SessionFile, ConfigError> {
+    if !path.exists() {
+        return Ok(SessionFile::default());
+    }
+    let txt = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    toml::from_str(&txt).map_err(|source| ConfigError::Parse {
+        name: format!("sessions {}", path.display()),
+        source,
+    })
+}
+
+pub fn save_session_file(path: &Path, file: &SessionFile) -> Result<(), ConfigError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    let txt = toml::to_string_pretty(file).map_err(|source| ConfigError::Serialize {
+        name: "sessions".to_string(),
+        source,
+    })?;
+    fs::write(path, txt).map_err(|source| ConfigError::Write {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Record `state` for `sequence_key` in the file at `path`. The file is read again
+/// first, so viewers closed one after the other keep each other's sequences.
+pub fn record_session(path: &Path, sequence_key: &str, state: SessionState) -> Result<(), ConfigError> {
+    let mut file = load_session_file(path)?;
+    file.sessions.insert(sequence_key.to_string(), state);
+    save_session_file(path, &file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_are_recorded_per_sequence() {
+        let path = std::env::temp_dir().join(format!("zapvis_sessions_{}.toml", std::process::id()));
+        let state = |index| SessionState {
+            index,
+            zoom: 2.5,
+            pan: [10.0, -4.0],
+            fullscreen: false,
+            exclusive_fullscreen: false,
+            step_size: 10,
+            window_size: Some([1280.0, 720.0]),
+            window_pos: Some([1920.0, 40.0]),
+            loop_in: Some(100),
+            loop_out: None,
+        };
+        assert!(load_session_file(&path).unwrap().sessions.is_empty());
+        record_session(&path, "/shots/a|f_####.png", state(120)).unwrap();
+        record_session(&path, "host:/shots/b|f_####.png", state(7)).unwrap();
+        record_session(&path, "/shots/a|f_####.png", state(450)).unwrap();
+
+        let file = load_session_file(&path).unwrap();
+        assert_eq!(file.sessions.len(), 2);
+        assert_eq!(file.sessions["/shots/a|f_####.png"], state(450));
+
+        // Fields missing from older files fall back to the defaults
+        let file: SessionFile = toml::from_str("[sessions.\"k\"]\nindex = 3\n").unwrap();
+        assert_eq!((file.sessions["k"].zoom, file.sessions["k"].step_size), (1.0, 1));
+        assert_eq!(file.sessions["k"].loop_in, None);
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,148 @@
+//! One viewer per user: a later `zapvis <file>` hands its input to the running viewer
+//! over a local socket, and the viewer switches to it, instead of opening another window
+//! with SSH sessions of its own. Unix only; elsewhere every invocation opens a window.
+use eframe::egui;
+use std::sync::mpsc::Receiver;
+
+use crate::input::OpenedInput;
+
+/// Longest a later invocation waits for the viewer to open its input (remote inputs
+/// connect first) before opening a window of its own
+#[cfg(unix)]
+const FORWARD_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// What came of handing an input to the running viewer
+pub enum Forwarded {
+    /// The viewer shows it now
+    Opened,
+    /// No viewer is listening
+    NotRunning,
+    /// The viewer could not open it (no pattern matches, unreachable host, ...)
+    Failed(String),
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use anyhow::anyhow;
+    use directories::ProjectDirs;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use tracing::{info, warn};
+    use zapvis::config::load_config;
+    use zapvis::sequence::{expand_local_path, is_http_url, parse_remote_input};
+
+    use crate::input::{open_input, remember_recent};
+
+    /// Socket of the running viewer, in the runtime directory where there is one
+    fn socket_path() -> Option<PathBuf> {
+        let proj = ProjectDirs::from("dev", "zapvis", "zapvis")?;
+        Some(proj.runtime_dir().unwrap_or(proj.data_dir()).join("viewer.sock"))
+    }
+
+    /// `input` as the viewer, in another working directory, can open it
+    fn absolute_input(input: &str) -> String {
+        if is_http_url(input) || parse_remote_input(input).is_some() {
+            return input.to_string();
+        }
+        let path = expand_local_path(input);
+        fs::canonicalize(&path).unwrap_or(path).display().to_string()
+    }
+
+    pub fn forward(input: &str, pattern: Option<&str>) -> Forwarded {
+        let Some(Ok(mut stream)) = socket_path().map(UnixStream::connect) else {
+            return Forwarded::NotRunning;
+        };
+        stream.set_read_timeout(Some(FORWARD_TIMEOUT)).ok();
+        // One request per connection: the pattern (empty for none), a tab and the input
+        let request = format!("{}\t{}\n", pattern.unwrap_or(""), absolute_input(input));
+        if let Err(e) = stream.write_all(request.as_bytes()) {
+            return Forwarded::Failed(e.to_string());
+        }
+        let mut reply = String::new();
+        if let Err(e) = BufReader::new(stream).read_line(&mut reply) {
+            return Forwarded::Failed(e.to_string());
+        }
+        match reply.trim_end() {
+            "ok" => Forwarded::Opened,
+            reply => Forwarded::Failed(reply.strip_prefix("error ").unwrap_or("no answer").to_string()),
+        }
+    }
+
+    pub fn listen(ctx: &egui::Context) -> Option<Receiver<OpenedInput>> {
+        let path = socket_path()?;
+        if UnixStream::connect(&path).is_ok() {
+            info!("another viewer is listening on {}", path.display());
+            return None;
+        }
+        // Left behind by a viewer that did not exit cleanly
+        fs::remove_file(&path).ok();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).ok();
+        }
+        let listener = match UnixListener::bind(&path) {
+            Ok(listener) => listener,
+            Err(e) => {
+                warn!("not listening for other invocations on {}: {e}", path.display());
+                return None;
+            }
+        };
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).ok();
+        let (tx, rx) = channel();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut request = String::new();
+                if BufReader::new(&stream).read_line(&mut request).is_err() {
+                    continue;
+                }
+                let Some((pattern, input)) = request.trim_end_matches('\n').split_once('\t') else {
+                    continue;
+                };
+                info!("opening {} for another invocation", input);
+                let pattern = (!pattern.is_empty()).then(|| pattern.to_string());
+                let mut cfg = load_config().unwrap_or_default();
+                let opened = open_input(input, pattern, &mut cfg).and_then(|opened| {
+                    let (pattern, seq) = &opened.candidates[0];
+                    remember_recent(input, opened.manifest.is_some(), pattern, seq);
+                    tx.send(opened).map_err(|_| anyhow!("the viewer is closing"))
+                });
+                let reply = match opened {
+                    Ok(()) => "ok\n".to_string(),
+                    Err(e) => format!("error {}\n", format!("{e:#}").replace('\n', " ")),
+                };
+                (&stream).write_all(reply.as_bytes()).ok();
+                ctx.request_repaint();
+            }
+        });
+        Some(rx)
+    }
+}
+
+/// Hand `input` (with its `--pattern`) to the running viewer
+pub fn forward(input: &str, pattern: Option<&str>) -> Forwarded {
+    #[cfg(unix)]
+    return unix::forward(input, pattern);
+    #[cfg(not(unix))]
+    {
+        let _ = (input, pattern);
+        Forwarded::NotRunning
+    }
+}
+
+/// Listen for the inputs of later invocations, opened and ready to switch to. `None` if
+/// another viewer listens already or the socket cannot be made.
+pub fn listen(ctx: &egui::Context) -> Option<Receiver<OpenedInput>> {
+    #[cfg(unix)]
+    return unix::listen(ctx);
+    #[cfg(not(unix))]
+    {
+        let _ = ctx;
+        None
+    }
+}
@@ -0,0 +1,102 @@
+use image::RgbaImage;
+
+/// Summary statistics of one channel over a region
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChannelStats {
+    pub min: u8,
+    pub max: u8,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+/// Pixel rectangle `[x0, x1) x [y0, y1)` in image coordinates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PixelRect {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl PixelRect {
+    pub fn width(&self) -> u32 {
+        self.x1.saturating_sub(self.x0)
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1.saturating_sub(self.y0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width() == 0 || self.height() == 0
+    }
+}
+
+/// Per-channel (R, G, B, A) min/max/mean/stddev over `rect` (clamped to the image).
+pub fn region_stats(img: &RgbaImage, rect: PixelRect) -> Option<[ChannelStats; 4]> {
+    let rect = PixelRect {
+        x0: rect.x0.min(img.width()),
+        y0: rect.y0.min(img.height()),
+        x1: rect.x1.min(img.width()),
+        y1: rect.y1.min(img.height()),
+    };
+    if rect.is_empty() {
+        return None;
+    }
+
+    let mut min = [u8::MAX; 4];
+    let mut max = [u8::MIN; 4];
+    let mut sum = [0u64; 4];
+    let mut sum_sq = [0u64; 4];
+    for y in rect.y0..rect.y1 {
+        for x in rect.x0..rect.x1 {
+            let p = img.get_pixel(x, y);
+            for c in 0..4 {
+                let v = p[c];
+                min[c] = min[c].min(v);
+                max[c] = max[c].max(v);
+                sum[c] += v as u64;
+                sum_sq[c] += (v as u64) * (v as u64);
+            }
+        }
+    }
+
+    let n = rect.width() as f64 * rect.height() as f64;
+    let mut out = [ChannelStats::default(); 4];
+    for c in 0..4 {
+        let mean = sum[c] as f64 / n;
+        let var = (sum_sq[c] as f64 / n - mean * mean).max(0.0);
+        out[c] = ChannelStats {
+            min: min[c],
+            max: max[c],
+            mean,
+            stddev: var.sqrt(),
+        };
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn stats_over_sub_region() {
+        let mut img = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255]));
+        img.put_pixel(1, 1, Rgba([10, 0, 0, 255]));
+        img.put_pixel(2, 1, Rgba([30, 0, 0, 255]));
+        let rect = PixelRect { x0: 1, y0: 1, x1: 3, y1: 2 };
+        let s = region_stats(&img, rect).unwrap();
+        assert_eq!((s[0].min, s[0].max), (10, 30));
+        assert!((s[0].mean - 20.0).abs() < 1e-9);
+        assert!((s[0].stddev - 10.0).abs() < 1e-9);
+        assert_eq!(s[3].stddev, 0.0);
+    }
+
+    #[test]
+    fn empty_region_has_no_stats() {
+        let img = RgbaImage::new(2, 2);
+        assert!(region_stats(&img, PixelRect { x0: 2, y0: 0, x1: 5, y1: 2 }).is_none());
+    }
+}
@@ -0,0 +1,174 @@
+//! Limits on the transfers of a remote worker, shared by its channels: a bandwidth cap
+//! that paces prefetches, and a cap on the bytes of the transfers in flight. The frame
+//! on screen is never held back, but its bytes count against both limits, so prefetches
+//! make room for it.
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest a prefetch waits for room before checking again whether it is still wanted
+const WAIT_SLICE: Duration = Duration::from_millis(250);
+
+/// Transfer limits of one worker; clones share them. The default has no limits.
+#[derive(Clone, Default)]
+pub struct Throttle {
+    inner: Option<Arc<Inner>>,
+}
+
+struct Inner {
+    /// Bytes per second, `None` for no cap
+    rate: Option<f64>,
+    max_in_flight: Option<u64>,
+    state: Mutex<State>,
+    room: Condvar,
+}
+
+struct State {
+    /// When the bytes received so far will have been paid for at the capped rate
+    paid_until: Instant,
+    /// Sizes (or estimates, before their size is known) of the transfers running
+    in_flight: u64,
+    /// Size of the last finished transfer, the estimate for the next one
+    last_size: u64,
+}
+
+impl Throttle {
+    /// Limits of `bytes_per_sec` and `max_in_flight` bytes; 0 disables either.
+    pub fn new(bytes_per_sec: u64, max_in_flight: u64) -> Self {
+        if bytes_per_sec == 0 && max_in_flight == 0 {
+            return Self::default();
+        }
+        Self {
+            inner: Some(Arc::new(Inner {
+                rate: (bytes_per_sec > 0).then_some(bytes_per_sec as f64),
+                max_in_flight: (max_in_flight > 0).then_some(max_in_flight),
+                state: Mutex::new(State {
+                    paid_until: Instant::now(),
+                    in_flight: 0,
+                    last_size: 0,
+                }),
+                room: Condvar::new(),
+            })),
+        }
+    }
+
+    /// Start a transfer. A prefetch (`urgent` false) first waits until the transfers in
+    /// flight leave room for one more the size of the last, unless none is running; it
+    /// gives up and returns `None` once `wanted` turns false.
+    pub fn start(&self, urgent: bool, wanted: impl Fn() -> bool) -> Option<Transfer> {
+        let Some(inner) = &self.inner else {
+            return Some(Transfer::unlimited());
+        };
+        let mut state = inner.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(max) = inner.max_in_flight.filter(|_| !urgent) {
+            while state.in_flight > 0 && state.in_flight + state.last_size > max {
+                if !wanted() {
+                    return None;
+                }
+                state = inner.room.wait_timeout(state, WAIT_SLICE).unwrap_or_else(PoisonError::into_inner).0;
+            }
+        }
+        let reserved = state.last_size;
+        state.in_flight += reserved;
+        Some(Transfer {
+            inner: Some(inner.clone()),
+            urgent,
+            reserved,
+            received: 0,
+        })
+    }
+}
+
+/// A transfer counted against a [`Throttle`] until it is dropped
+pub struct Transfer {
+    inner: Option<Arc<Inner>>,
+    urgent: bool,
+    /// Bytes this transfer holds of the in-flight budget
+    reserved: u64,
+    received: u64,
+}
+
+impl Transfer {
+    fn unlimited() -> Self {
+        Self {
+            inner: None,
+            urgent: true,
+            reserved: 0,
+            received: 0,
+        }
+    }
+
+    /// Account for `received` bytes of `total` so far, sleeping as long as a prefetch
+    /// is ahead of the bandwidth cap.
+    pub fn progress(&mut self, received: usize, total: usize) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        let (received, total) = (received as u64, total as u64);
+        let new_bytes = received.saturating_sub(self.received);
+        self.received = received;
+        let wait = {
+            let mut state = inner.state.lock().unwrap_or_else(PoisonError::into_inner);
+            if total != self.reserved {
+                state.in_flight = state.in_flight - self.reserved + total;
+                self.reserved = total;
+            }
+            let Some(rate) = inner.rate else {
+                return;
+            };
+            let now = Instant::now();
+            state.paid_until = state.paid_until.max(now) + Duration::from_secs_f64(new_bytes as f64 / rate);
+            state.paid_until.saturating_duration_since(now)
+        };
+        if !self.urgent && !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+impl Drop for Transfer {
+    fn drop(&mut self) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        let mut state = inner.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.in_flight -= self.reserved;
+        if self.received > 0 {
+            state.last_size = self.received;
+        }
+        inner.room.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefetches_are_paced_but_the_current_frame_is_not() {
+        let throttle = Throttle::new(1_000_000, 0);
+        let started = Instant::now();
+        let mut urgent = throttle.start(true, || true).unwrap();
+        urgent.progress(100_000, 100_000);
+        assert!(started.elapsed() < Duration::from_millis(50));
+        // The current frame's 100 ms worth of bytes delay the prefetch, as do its own
+        let mut prefetch = throttle.start(false, || true).unwrap();
+        prefetch.progress(50_000, 50_000);
+        assert!(started.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[test]
+    fn prefetches_wait_for_room_in_flight() {
+        let throttle = Throttle::new(0, 1000);
+        let mut first = throttle.start(false, || true).unwrap();
+        first.progress(800, 800);
+        drop(first);
+        // One transfer may always run; a second of the last size does not fit beside it
+        let running = throttle.start(false, || true).unwrap();
+        assert!(throttle.start(false, || false).is_none());
+        assert!(throttle.start(true, || false).is_some());
+        drop(running);
+        assert!(throttle.start(false, || false).is_some());
+        assert!(Throttle::default().start(false, || false).is_some());
+    }
+}
@@ -0,0 +1,348 @@
+//! Display of frames too large for one texture, like stitched microscopy scans of
+//! 30000×30000. A downscaled overview stands in for the whole frame (fit to window,
+//! thumbnails, channel views); where the zoom asks for more detail than it has, tiles
+//! of the mip level matching the zoom are drawn over it. Tiles are rendered from the
+//! decoded frame on a thread of their own, and only the visible ones are uploaded.
+use egui::{ColorImage, TextureHandle, TextureOptions};
+use image::imageops;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread;
+use tracing::debug;
+
+use crate::image_util::frame_to_color_image;
+use zapvis::frame::{downscale_to_max_dim, FramePixels};
+
+/// Frames with a longer side than this are tiled (or than the GPU allows, if less)
+pub const TILED_ABOVE: u32 = 8192;
+
+/// Longest edge of the overview of a tiled frame
+const OVERVIEW_MAX_DIM: u32 = 4096;
+
+/// Edge of a tile in texels, at every level
+const TILE_SIZE: u32 = 1024;
+
+/// Tile textures kept, across frames; the least recently drawn go first
+const MAX_TILES: usize = 48;
+
+/// One tile of the mip pyramid: level L halves the resolution L times, so its tiles
+/// cover `TILE_SIZE << L` frame pixels on each side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    level: u32,
+    col: u32,
+    row: u32,
+}
+
+impl TileKey {
+    /// Frame pixels covered, `[x0, y0, x1, y1]`, clipped to a frame of `size`
+    pub fn bounds(self, size: (u32, u32)) -> [u32; 4] {
+        let span = TILE_SIZE << self.level;
+        let (x0, y0) = (self.col * span, self.row * span);
+        [x0, y0, (x0 + span).min(size.0), (y0 + span).min(size.1)]
+    }
+}
+
+/// Mip level for drawing a frame of `size` at `scale` screen pixels per frame pixel: the
+/// coarsest whose texels are no larger than a screen pixel. `None` while the overview
+/// (of `overview_size`) is detailed enough itself.
+fn level_for(scale: f32, size: (u32, u32), overview_size: (u32, u32)) -> Option<u32> {
+    let level = if scale >= 1.0 { 0 } else { ((1.0 / scale).log2().floor() as u32).min(16) };
+    let overview_step = size.0.max(size.1) as f32 / overview_size.0.max(overview_size.1).max(1) as f32;
+    (((1u32 << level) as f32) < overview_step).then_some(level)
+}
+
+/// Tiles of `level` overlapping `visible` (`[x0, y0, x1, y1]` in frame pixels)
+fn visible_tiles(size: (u32, u32), level: u32, visible: [f32; 4]) -> Vec<TileKey> {
+    let span = TILE_SIZE << level;
+    let (cols, rows) = (size.0.div_ceil(span), size.1.div_ceil(span));
+    let first = |v: f32| (v / span as f32).floor().max(0.0) as u32;
+    let end = |v: f32, n: u32| ((v / span as f32).ceil().max(0.0) as u32).min(n);
+    let mut tiles = Vec::new();
+    for row in first(visible[1])..end(visible[3], rows) {
+        for col in first(visible[0])..end(visible[2], cols) {
+            tiles.push(TileKey { level, col, row });
+        }
+    }
+    tiles
+}
+
+/// Pixels of the tile `key` of `frame`, averaged down to its level
+fn render_tile(frame: &FramePixels, key: TileKey) -> ColorImage {
+    let [x0, y0, x1, y1] = key.bounds(frame.dimensions());
+    let (w, h) = (x1 - x0, y1 - y0);
+    let (tw, th) = ((w >> key.level).max(1), (h >> key.level).max(1));
+    let tile = match frame {
+        FramePixels::Rgba(img) => FramePixels::Rgba(imageops::thumbnail(&*imageops::crop_imm(img, x0, y0, w, h), tw, th)),
+        FramePixels::Luma(img) => FramePixels::Luma(imageops::thumbnail(&*imageops::crop_imm(img, x0, y0, w, h), tw, th)),
+    };
+    frame_to_color_image(&tile)
+}
+
+/// Work for the render thread. The shown frame's overview comes first, then its tiles
+/// (replaced as the view moves), then the overviews of the other cached frames.
+#[derive(Default)]
+struct Queue {
+    /// Frames with the longest edge of their overview
+    overviews: BTreeMap<i64, (Arc<FramePixels>, u32)>,
+    tiles: Vec<(i64, Arc<FramePixels>, TileKey)>,
+    current: Option<i64>,
+    closed: bool,
+}
+
+enum Job {
+    Overview(i64, Arc<FramePixels>, u32),
+    Tile(i64, Arc<FramePixels>, TileKey),
+}
+
+impl Queue {
+    fn pop(&mut self) -> Option<Job> {
+        if let Some((source, max_dim)) = self.current.and_then(|idx| self.overviews.remove(&idx)) {
+            return Some(Job::Overview(self.current?, source, max_dim));
+        }
+        if let Some((idx, source, key)) = self.tiles.pop() {
+            return Some(Job::Tile(idx, source, key));
+        }
+        self.overviews.pop_first().map(|(idx, (source, max_dim))| Job::Overview(idx, source, max_dim))
+    }
+}
+
+enum Rendered {
+    Overview { idx: i64, source: Arc<FramePixels>, overview: Arc<FramePixels> },
+    Tile { idx: i64, source: Arc<FramePixels>, key: TileKey, image: ColorImage },
+}
+
+/// A frame drawn in tiles: its decoded pixels, overview once rendered, and tile textures
+struct TiledFrame {
+    source: Arc<FramePixels>,
+    overview: Option<Arc<FramePixels>>,
+    /// Textures with the clock reading of when they were last drawn
+    tiles: HashMap<TileKey, (TextureHandle, u64)>,
+}
+
+/// The tiled frames of an [`crate::image_cache::ImageCache`] and their render thread
+pub struct TiledFrames {
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+    done_rx: Receiver<Rendered>,
+    frames: BTreeMap<i64, TiledFrame>,
+    /// Counts the calls to `tiles`, for dropping the least recently drawn
+    clock: u64,
+}
+
+impl TiledFrames {
+    pub fn new(ctx: &egui::Context) -> Self {
+        let queue: Arc<(Mutex<Queue>, Condvar)> = Arc::default();
+        let (done_tx, done_rx) = channel();
+        spawn_renderer(queue.clone(), done_tx, ctx.clone());
+        Self {
+            queue,
+            done_rx,
+            frames: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// Track those of the full-resolution `frames` too large for one texture on `ctx`'s
+    /// GPU, dropping the others, and queue the overviews still to render.
+    pub fn sync<'a>(&mut self, ctx: &egui::Context, frames: impl IntoIterator<Item = (i64, &'a Arc<FramePixels>)>) {
+        let limit = TILED_ABOVE.min(ctx.input(|i| i.max_texture_side) as u32);
+        let wanted: BTreeMap<i64, &Arc<FramePixels>> = frames
+            .into_iter()
+            .filter(|(_, pixels)| pixels.width().max(pixels.height()) > limit)
+            .collect();
+        self.frames.retain(|idx, f| wanted.get(idx).is_some_and(|p| Arc::ptr_eq(p, &f.source)));
+        let mut queue = self.queue.0.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.overviews.retain(|idx, (source, _)| wanted.get(idx).is_some_and(|p| Arc::ptr_eq(p, source)));
+        queue.tiles.retain(|(idx, source, _)| wanted.get(idx).is_some_and(|p| Arc::ptr_eq(p, source)));
+        for (idx, source) in wanted {
+            if let Entry::Vacant(slot) = self.frames.entry(idx) {
+                debug!("tiling frame {} ({}x{})", idx, source.width(), source.height());
+                queue.overviews.insert(idx, (source.clone(), OVERVIEW_MAX_DIM.min(limit)));
+                slot.insert(TiledFrame {
+                    source: source.clone(),
+                    overview: None,
+                    tiles: HashMap::new(),
+                });
+            }
+        }
+        self.queue.1.notify_all();
+    }
+
+    /// Render the overview of `idx` (the frame shown) before the others
+    pub fn set_current(&mut self, idx: i64) {
+        self.queue.0.lock().unwrap_or_else(PoisonError::into_inner).current = Some(idx);
+    }
+
+    pub fn is_tiled(&self, idx: i64) -> bool {
+        self.frames.contains_key(&idx)
+    }
+
+    /// Overview of the tiled frame `idx`, once rendered
+    pub fn overview(&self, idx: i64) -> Option<&Arc<FramePixels>> {
+        self.frames.get(&idx)?.overview.as_ref()
+    }
+
+    /// Decoded frames whose overview is rendered, with the overview
+    pub fn overviews(&self) -> impl Iterator<Item = (i64, &Arc<FramePixels>, &Arc<FramePixels>)> {
+        self.frames.iter().filter_map(|(&idx, f)| Some((idx, &f.source, f.overview.as_ref()?)))
+    }
+
+    /// Take in what the render thread finished, uploading tiles with `options`.
+    /// Returns the overviews that came in.
+    pub fn poll(&mut self, ctx: &egui::Context, options: TextureOptions) -> Vec<i64> {
+        let mut overviews = Vec::new();
+        while let Ok(rendered) = self.done_rx.try_recv() {
+            match rendered {
+                Rendered::Overview { idx, source, overview } => {
+                    if let Some(frame) = self.frames.get_mut(&idx).filter(|f| Arc::ptr_eq(&f.source, &source)) {
+                        frame.overview = Some(overview);
+                        overviews.push(idx);
+                    }
+                }
+                Rendered::Tile { idx, source, key, image } => {
+                    if let Some(frame) = self.frames.get_mut(&idx).filter(|f| Arc::ptr_eq(&f.source, &source)) {
+                        let name = format!("zapvis_tile_{idx}_{}_{}_{}", key.level, key.col, key.row);
+                        frame.tiles.insert(key, (ctx.load_texture(name, image, options), self.clock));
+                    }
+                }
+            }
+        }
+        overviews
+    }
+
+    /// Tiles to draw over the overview of `idx` where `visible` (`[x0, y0, x1, y1]` in
+    /// frame pixels) is shown at `scale` screen pixels per frame pixel, with the frame
+    /// pixels each covers. Missing ones are queued for rendering in place of those
+    /// queued for an earlier view.
+    pub fn tiles(&mut self, idx: i64, visible: [f32; 4], scale: f32) -> Vec<([u32; 4], egui::TextureId)> {
+        self.clock += 1;
+        let clock = self.clock;
+        let mut queue = self.queue.0.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.current = Some(idx);
+        queue.tiles.clear();
+        let Some(frame) = self.frames.get_mut(&idx) else {
+            return Vec::new();
+        };
+        let size = frame.source.dimensions();
+        let level = frame.overview.as_ref().and_then(|o| level_for(scale, size, o.dimensions()));
+        let Some(level) = level else {
+            return Vec::new();
+        };
+        let mut drawn = Vec::new();
+        let keys = visible_tiles(size, level, visible);
+        // Popped from the back: the tiles nearest the top-left come first
+        for &key in keys.iter().rev() {
+            match frame.tiles.get_mut(&key) {
+                Some((tex, used)) => {
+                    *used = clock;
+                    drawn.push((key.bounds(size), tex.id()));
+                }
+                None => queue.tiles.push((idx, frame.source.clone(), key)),
+            }
+        }
+        self.queue.1.notify_all();
+        drop(queue);
+        self.trim();
+        drawn
+    }
+
+    /// Drop the least recently drawn tiles beyond [`MAX_TILES`]
+    fn trim(&mut self) {
+        let mut used: Vec<u64> = self.frames.values().flat_map(|f| f.tiles.values().map(|(_, u)| *u)).collect();
+        if used.len() <= MAX_TILES {
+            return;
+        }
+        used.sort_unstable();
+        let cutoff = used[used.len() - MAX_TILES];
+        for frame in self.frames.values_mut() {
+            frame.tiles.retain(|_, (_, u)| *u >= cutoff);
+        }
+    }
+
+    /// Forget the tile textures, e.g. to upload them again with other options
+    pub fn clear_tiles(&mut self) {
+        for frame in self.frames.values_mut() {
+            frame.tiles.clear();
+        }
+    }
+
+    /// GPU memory taken by the tiles, assuming 4 bytes per pixel
+    pub fn texture_bytes(&self) -> usize {
+        let tiles = self.frames.values().flat_map(|f| f.tiles.values());
+        tiles.map(|(tex, _)| tex.size().iter().product::<usize>() * 4).sum()
+    }
+}
+
+impl Drop for TiledFrames {
+    fn drop(&mut self) {
+        self.queue.0.lock().unwrap_or_else(PoisonError::into_inner).closed = true;
+        self.queue.1.notify_all();
+    }
+}
+
+fn spawn_renderer(queue: Arc<(Mutex<Queue>, Condvar)>, done_tx: Sender<Rendered>, ctx: egui::Context) {
+    thread::spawn(move || loop {
+        let job = {
+            let mut q = queue.0.lock().unwrap_or_else(PoisonError::into_inner);
+            loop {
+                if q.closed {
+                    return;
+                }
+                if let Some(job) = q.pop() {
+                    break job;
+                }
+                q = queue.1.wait(q).unwrap_or_else(PoisonError::into_inner);
+            }
+        };
+        let rendered = match job {
+            Job::Overview(idx, source, max_dim) => {
+                let overview = downscale_to_max_dim(&source, max_dim).map_or_else(|| source.clone(), Arc::new);
+                Rendered::Overview { idx, source, overview }
+            }
+            Job::Tile(idx, source, key) => {
+                let image = render_tile(&source, key);
+                Rendered::Tile { idx, source, key, image }
+            }
+        };
+        if done_tx.send(rendered).is_err() {
+            return;
+        }
+        ctx.request_repaint();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GrayImage;
+
+    #[test]
+    fn zoom_picks_the_level_and_the_view_picks_the_tiles() {
+        let size = (30000, 20000);
+        let overview = (4096, 2731);
+        // Fit to a 2000 pixel window: the overview is detailed enough
+        assert_eq!(level_for(2000.0 / 30000.0, size, overview), None);
+        assert_eq!(level_for(0.2, size, overview), Some(2));
+        assert_eq!(level_for(0.5, size, overview), Some(1));
+        assert_eq!(level_for(4.0, size, overview), Some(0));
+
+        let tiles = visible_tiles(size, 1, [3000.0, 0.0, 5000.0, 2100.0]);
+        let at = |col, row| TileKey { level: 1, col, row };
+        assert_eq!(tiles, [at(1, 0), at(2, 0), at(1, 1), at(2, 1)]);
+        // Tiles at the edges are cut to the frame
+        assert_eq!(at(14, 9).bounds(size), [28672, 18432, 30000, 20000]);
+        assert_eq!(visible_tiles(size, 4, [-100.0, -100.0, 1e9, 1e9]).len(), 2 * 2);
+    }
+
+    #[test]
+    fn tiles_are_averaged_down_to_their_level() {
+        let frame = FramePixels::Luma(GrayImage::from_fn(3000, 1000, |x, _| image::Luma([if x < 2048 { 0 } else { 200 }])));
+        let tile = render_tile(&frame, TileKey { level: 1, col: 0, row: 0 });
+        assert_eq!(tile.size, [1024, 500]);
+        let tile = render_tile(&frame, TileKey { level: 0, col: 2, row: 0 });
+        assert_eq!(tile.size, [952, 1000]);
+        assert_eq!(tile.pixels[0], egui::Color32::from_gray(200));
+    }
+}
@@ -0,0 +1,161 @@
+//! Video files opened as sequences: frame N of the video is index N (from 0), decoded
+//! on demand by spawning `ffmpeg`, with `ffprobe` counting the frames when opening.
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tracing::debug_span;
+
+use crate::error::CacheError;
+
+/// File extensions opened as videos rather than matched against frame patterns
+pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "mov", "mkv", "webm", "avi", "mxf"];
+
+/// Pattern naming the frames of a video, e.g. in the title bar and for `zapvis fetch`,
+/// which writes them out as the PPM files they are decoded to
+pub const VIDEO_PATTERN: &str = "frame_######.ppm";
+
+/// Whether `path` has the extension of a video container
+pub fn is_video_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| VIDEO_EXTENSIONS.iter().any(|v| v.eq_ignore_ascii_case(e)))
+}
+
+/// Frame count and rate of a video's first video stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoInfo {
+    pub frames: u64,
+    /// Frames per second as a fraction, e.g. 30000/1001
+    pub rate_num: u32,
+    pub rate_den: u32,
+}
+
+impl VideoInfo {
+    pub fn contains(&self, idx: i64) -> bool {
+        u64::try_from(idx).is_ok_and(|idx| idx < self.frames)
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.rate_num as f64 / self.rate_den.max(1) as f64
+    }
+
+    /// Seek target for frame `idx`: half a frame before its timestamp, so rounding
+    /// never lands on the neighbor (ffmpeg starts at the first frame at or after it).
+    fn seek_seconds(&self, idx: u64) -> f64 {
+        ((idx as f64 - 0.5) / self.fps()).max(0.0)
+    }
+
+    /// Parse `ffprobe -of default=noprint_wrappers=1` output of the stream's
+    /// `nb_frames`, `r_frame_rate` and `duration` and the container's `duration`.
+    /// Containers without a frame count (MKV, WebM) get duration times rate.
+    fn from_probe(stdout: &str) -> Option<Self> {
+        let value = |key: &str| {
+            stdout
+                .lines()
+                .filter_map(|l| l.trim().strip_prefix(key)?.strip_prefix('='))
+                .find(|v| !v.is_empty() && *v != "N/A")
+        };
+        let (num, den) = value("r_frame_rate")?.split_once('/')?;
+        let (rate_num, rate_den) = (num.parse().ok()?, den.parse().ok()?);
+        if rate_num == 0 || rate_den == 0 {
+            return None;
+        }
+        let frames = match value("nb_frames").and_then(|n| n.parse().ok()) {
+            Some(frames) => frames,
+            None => {
+                let duration: f64 = value("duration")?.parse().ok()?;
+                (duration * rate_num as f64 / rate_den as f64).round() as u64
+            }
+        };
+        Some(Self {
+            frames,
+            rate_num,
+            rate_den,
+        })
+    }
+}
+
+/// Count the frames of the video at `path` with ffprobe.
+pub fn probe_video(path: &Path) -> Result<VideoInfo, CacheError> {
+    let output = run(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-select_streams", "v:0"])
+            .args(["-show_entries", "stream=nb_frames,r_frame_rate,duration:format=duration"])
+            .args(["-of", "default=noprint_wrappers=1"])
+            .arg(path),
+        path,
+        "ffprobe",
+    )?;
+    VideoInfo::from_probe(&String::from_utf8_lossy(&output)).ok_or_else(|| CacheError::Video {
+        path: path.display().to_string(),
+        message: "ffprobe found no video stream with a frame rate".to_string(),
+    })
+}
+
+/// Decode frame `idx` of the video at `path` to PPM bytes (what `image` reads back).
+pub fn read_video_frame(path: &Path, info: VideoInfo, idx: i64) -> Result<Vec<u8>, CacheError> {
+    if !info.contains(idx) {
+        return Err(CacheError::NotFound(format!("{} frame {}", path.display(), idx)));
+    }
+    let _span = debug_span!("ffmpeg", path = %path.display(), idx).entered();
+    let seek = format!("{:.6}", info.seek_seconds(idx as u64));
+    let bytes = run(
+        Command::new("ffmpeg")
+            .args(["-v", "error", "-nostdin", "-ss", &seek, "-i"])
+            .arg(path)
+            .args(["-frames:v", "1", "-f", "image2pipe", "-c:v", "ppm", "-pix_fmt", "rgb24", "-"]),
+        path,
+        "ffmpeg",
+    )?;
+    if bytes.is_empty() {
+        // The frame count is an estimate for some containers
+        return Err(CacheError::NotFound(format!("{} frame {}", path.display(), idx)));
+    }
+    Ok(bytes)
+}
+
+/// Run `command` and return its stdout, or its last stderr line as the error
+fn run(command: &mut Command, path: &Path, program: &str) -> Result<Vec<u8>, CacheError> {
+    let failed = |message: String| CacheError::Video {
+        path: path.display().to_string(),
+        message,
+    };
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| failed(format!("could not run {program}: {e}")))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let last_line = stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+        return Err(failed(format!("{program} exited with {}: {last_line}", output.status)));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_counts_come_from_the_stream_or_the_duration() {
+        let mp4 = "r_frame_rate=30000/1001\nduration=2.002000\nnb_frames=60\nduration=2.010000\n";
+        let info = VideoInfo::from_probe(mp4).unwrap();
+        assert_eq!((info.frames, info.rate_num, info.rate_den), (60, 30000, 1001));
+        let mkv = "r_frame_rate=25/1\nduration=N/A\nnb_frames=N/A\nduration=4.000000\n";
+        assert_eq!(VideoInfo::from_probe(mkv).unwrap().frames, 100);
+        assert!(VideoInfo::from_probe("r_frame_rate=0/0\nnb_frames=1\n").is_none());
+    }
+
+    #[test]
+    fn seeks_land_between_frames() {
+        let info = VideoInfo {
+            frames: 100,
+            rate_num: 25,
+            rate_den: 1,
+        };
+        assert_eq!(info.seek_seconds(0), 0.0);
+        assert_eq!(info.seek_seconds(10), 0.38);
+        assert!(info.contains(99) && !info.contains(100) && !info.contains(-1));
+        assert!(is_video_path(Path::new("/clips/take_3.MP4")));
+        assert!(!is_video_path(Path::new("/clips/frame_0001.png")));
+    }
+}
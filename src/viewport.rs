@@ -0,0 +1,122 @@
+use eframe::egui::{Pos2, Rect, Vec2};
+use std::time::{Duration, Instant};
+
+/// Zoom and pan of the image inside the central panel.
+///
+/// `zoom` is relative to the fitted size (1.0 = the default fit-to-window layout),
+/// `pan` offsets the image's top-left corner from the panel's top-left corner.
+pub struct Viewport {
+    pub zoom: f32,
+    pub pan: Vec2,
+    last_change: Option<Instant>,
+}
+
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 256.0;
+
+/// Scroll distance (points) per frame step; one wheel notch on native egui
+const WHEEL_POINTS_PER_STEP: f32 = 40.0;
+/// Horizontal drag distance (points) per frame when scrubbing
+const SCRUB_POINTS_PER_STEP: f32 = 8.0;
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            pan: Vec2::ZERO,
+            last_change: None,
+        }
+    }
+}
+
+impl Viewport {
+    pub fn reset(&mut self) {
+        self.zoom = 1.0;
+        self.pan = Vec2::ZERO;
+        self.touch();
+    }
+
+    /// Screen rect of the image given the panel rect and the fitted image size.
+    pub fn image_rect(&self, panel: Rect, fit_size: Vec2) -> Rect {
+        Rect::from_min_size(panel.min + self.pan, fit_size * self.zoom)
+    }
+
+    /// Zoom by `factor`, keeping the image point under `anchor` fixed on screen.
+    pub fn zoom_at(&mut self, factor: f32, anchor: Pos2, panel: Rect) {
+        let new_zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let actual = new_zoom / self.zoom;
+        let old_min = panel.min + self.pan;
+        let new_min = anchor - (anchor - old_min) * actual;
+        self.pan = new_min - panel.min;
+        self.zoom = new_zoom;
+        self.touch();
+    }
+
+    /// True at the default fit-to-window layout, where dragging scrubs instead of panning.
+    pub fn is_fit(&self) -> bool {
+        self.zoom == 1.0 && self.pan == Vec2::ZERO
+    }
+
+    pub fn pan_by(&mut self, delta: Vec2) {
+        self.pan += delta;
+        self.touch();
+    }
+
+    /// True once zoom/pan has not changed for `quiet` (used to defer expensive recomputation).
+    pub fn settled(&self, quiet: Duration) -> bool {
+        self.last_change.is_none_or(|t| t.elapsed() >= quiet)
+    }
+
+    fn touch(&mut self) {
+        self.last_change = Some(Instant::now());
+    }
+}
+
+/// Turns wheel and drag distances into whole frame steps, carrying the remainder over
+/// so slow trackpad scrolls and drags still add up.
+#[derive(Default)]
+pub struct MouseSteps {
+    wheel: f32,
+    scrub: f32,
+}
+
+impl MouseSteps {
+    /// Steps for a scroll of `delta` points (positive = scrolled up = backwards).
+    pub fn wheel(&mut self, delta: f32) -> i64 {
+        Self::take(&mut self.wheel, -delta, WHEEL_POINTS_PER_STEP)
+    }
+
+    /// Steps for a horizontal drag of `dx` points (positive = right = forwards).
+    pub fn scrub(&mut self, dx: f32) -> i64 {
+        Self::take(&mut self.scrub, dx, SCRUB_POINTS_PER_STEP)
+    }
+
+    pub fn reset_scrub(&mut self) {
+        self.scrub = 0.0;
+    }
+
+    fn take(acc: &mut f32, delta: f32, per_step: f32) -> i64 {
+        *acc += delta;
+        let steps = (*acc / per_step).trunc();
+        *acc -= steps * per_step;
+        steps as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_movements_accumulate_into_steps() {
+        let mut steps = MouseSteps::default();
+        assert_eq!(steps.wheel(-40.0), 1);
+        assert_eq!(steps.wheel(15.0), 0);
+        assert_eq!(steps.wheel(30.0), -1);
+        assert_eq!(steps.scrub(5.0), 0);
+        assert_eq!(steps.scrub(20.0), 3);
+        assert_eq!(steps.scrub(-2.0), 0);
+        steps.reset_scrub();
+        assert_eq!(steps.scrub(-8.0), -1);
+    }
+}
@@ -0,0 +1,114 @@
+//! Liveness tracking for the background threads (frame loader, SSH worker).
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Shared liveness state of one worker thread.
+///
+/// The thread marks itself busy around each unit of work and holds the guard from
+/// [`Heartbeat::alive`] for as long as it runs; a supervisor calls [`Heartbeat::check`]
+/// to find out whether the thread died or has been stuck on one unit for too long.
+/// Waiting for work counts as healthy, however long it takes.
+#[derive(Clone, Default)]
+pub struct Heartbeat(Arc<Mutex<Beat>>);
+
+#[derive(Default)]
+struct Beat {
+    busy_since: Option<Instant>,
+    gone: bool,
+    finished: bool,
+}
+
+/// Why a supervised thread needs replacing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stall {
+    /// The thread ended without finishing (panicked)
+    Dead,
+    /// Busy on one unit of work for this long
+    Stuck(Duration),
+}
+
+impl fmt::Display for Stall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stall::Dead => f.write_str("died"),
+            Stall::Stuck(d) => write!(f, "stuck for {} s", d.as_secs()),
+        }
+    }
+}
+
+/// Marks the thread gone when dropped, including while unwinding from a panic
+pub struct AliveGuard(Heartbeat);
+
+impl Drop for AliveGuard {
+    fn drop(&mut self) {
+        self.0.update(|b| b.gone = true);
+    }
+}
+
+impl Heartbeat {
+    fn update(&self, f: impl FnOnce(&mut Beat)) {
+        f(&mut self.0.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    /// Guard to hold for the lifetime of the thread.
+    pub fn alive(&self) -> AliveGuard {
+        AliveGuard(self.clone())
+    }
+
+    /// Starting a unit of work.
+    pub fn busy(&self) {
+        self.update(|b| b.busy_since = Some(Instant::now()));
+    }
+
+    /// Done with the unit of work, waiting for the next.
+    pub fn idle(&self) {
+        self.update(|b| b.busy_since = None);
+    }
+
+    /// The thread is exiting on purpose (its work source closed); not a failure.
+    pub fn finish(&self) {
+        self.update(|b| b.finished = true);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).finished
+    }
+
+    /// `Some` if the thread died or has been busy for longer than `limit`.
+    pub fn check(&self, limit: Duration) -> Option<Stall> {
+        let beat = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if beat.finished {
+            return None;
+        }
+        if beat.gone {
+            return Some(Stall::Dead);
+        }
+        let busy = beat.busy_since?.elapsed();
+        (busy > limit).then_some(Stall::Stuck(busy))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_stuck_and_dead_threads() {
+        let hb = Heartbeat::default();
+        let guard = hb.alive();
+        assert_eq!(hb.check(Duration::ZERO), None, "idle is healthy");
+        hb.busy();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(matches!(hb.check(Duration::ZERO), Some(Stall::Stuck(_))));
+        assert_eq!(hb.check(Duration::from_secs(60)), None);
+        hb.idle();
+        drop(guard);
+        assert_eq!(hb.check(Duration::from_secs(60)), Some(Stall::Dead));
+
+        let finished = Heartbeat::default();
+        drop(finished.alive());
+        finished.finish();
+        assert_eq!(finished.check(Duration::ZERO), None);
+    }
+}